@@ -106,9 +106,10 @@ pub struct Hub {
     child_hubs: RwLock<Vec<Arc<Mutex<Hub>>>>,
     
     // Message system components
-    subscriptions: RwLock<HashMap<String, BTreeMap<i32, SubscriptionEntry>>>,
-    message_interceptors: RwLock<HashMap<String, BTreeMap<i32, Box<dyn Any + Send + Sync>>>>,
-    method_interceptors: RwLock<HashMap<TypeId, HashMap<String, BTreeMap<i32, Box<dyn Any + Send + Sync>>>>>,
+    // Entries sharing a priority are kept in registration order
+    subscriptions: RwLock<HashMap<String, BTreeMap<i32, Vec<SubscriptionEntry>>>>,
+    message_interceptors: RwLock<HashMap<String, BTreeMap<i32, Vec<Box<dyn Any + Send + Sync>>>>>,
+    method_interceptors: RwLock<HashMap<TypeId, HashMap<String, BTreeMap<i32, Vec<Box<dyn Any + Send + Sync>>>>>>,
 }
 
 impl Hub {
@@ -305,7 +306,7 @@ impl Hub {
             .or_insert_with(BTreeMap::new);
             
         // Use negative priority as key for reverse ordering (highest first)
-        topic_interceptors.insert(-priority, Box::new(interceptor));
+        topic_interceptors.entry(-priority).or_default().push(Box::new(interceptor));
         
         id
     }
@@ -330,7 +331,7 @@ impl Hub {
             .or_insert_with(BTreeMap::new);
             
         // Use negative priority for reverse ordering (highest first)
-        pattern_subscriptions.insert(-priority, subscription);
+        pattern_subscriptions.entry(-priority).or_default().push(subscription);
         
         id
     }
@@ -355,7 +356,7 @@ impl Hub {
             .or_insert_with(BTreeMap::new);
             
         // Use negative priority for reverse order (highest first)
-        method_interceptors.insert(-priority, Box::new(handler));
+        method_interceptors.entry(-priority).or_default().push(Box::new(handler));
         
         id
     }
@@ -400,7 +401,7 @@ impl Hub {
         // Check for exact topic match
         if let Some(topic_interceptors) = interceptors.get(&message.topic) {
             // Iterate through interceptors by priority (highest first due to negative key)
-            for (_neg_priority, interceptor_box) in topic_interceptors.iter() {
+            for interceptor_box in topic_interceptors.values().flatten() {
                 // Try to downcast to the correct interceptor type
                 if let Some(interceptor) = any_as_interceptor_entry::<T, R>(interceptor_box) {
                     // Try this interceptor
@@ -414,7 +415,7 @@ impl Hub {
         // Check for wildcard patterns
         for (pattern, topic_interceptors) in interceptors.iter() {
             if pattern.ends_with('*') && message.topic.starts_with(&pattern[0..pattern.len()-1]) {
-                for (_neg_priority, interceptor_box) in topic_interceptors {
+                for interceptor_box in topic_interceptors.values().flatten() {
                     if let Some(interceptor) = any_as_interceptor_entry::<T, R>(interceptor_box) {
                         if let Some(result) = (interceptor.handler)(message) {
                             return Some(result);
@@ -440,7 +441,7 @@ impl Hub {
         if let Some(type_interceptors) = interceptors.get(&type_id) {
             if let Some(method_interceptors) = type_interceptors.get(method_name) {
                 // Try interceptors by priority (highest first)
-                for (_neg_priority, handler_box) in method_interceptors {
+                for handler_box in method_interceptors.values().flatten() {
                     // In a real implementation, would need proper type casting
                     // For pseudocode, we'll use a helper function
                     if let Some(handler) = any_as_method_handler::<T, A, R>(handler_box) {
@@ -95,9 +95,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let response1 = hub1.handle_request(request1);
     
     // Extract the response data
-    if let Some(message) = response1.data.downcast_ref::<&str>() {
-        println!("Response from Hub 1: {}", message);
-    } else if let Some(message) = response1.data.downcast_ref::<String>() {
+    if let Some(message) = response1.as_str() {
         println!("Response from Hub 1: {}", message);
     } else {
         println!("Received response from Hub 1 with unknown data format");
@@ -115,7 +113,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let response2 = hub2.handle_request(request2);
     
     // Extract the response data
-    if let Some(message) = response2.data.downcast_ref::<String>() {
+    if let Some(message) = response2.as_str() {
         println!("Response from Hub 2: {}", message);
     } else {
         println!("Received response from Hub 2 with unknown data format");
@@ -179,9 +177,7 @@ impl ClientUsingHub1 {
         let response1 = self.hub2.handle_request(request1);
         
         // Extract and display the response
-        if let Some(message) = response1.data.downcast_ref::<&str>() {
-            println!("Client received response via Hub 1 from Hub 2: {}", message);
-        } else if let Some(message) = response1.data.downcast_ref::<String>() {
+        if let Some(message) = response1.as_str() {
             println!("Client received response via Hub 1 from Hub 2: {}", message);
         } else {
             println!("Client received response from Hub 2 with unknown data format");
@@ -205,7 +201,7 @@ impl ClientUsingHub1 {
         let response2 = self.hub1.handle_request(request2);
         
         // Extract and display the response
-        if let Some(message) = response2.data.downcast_ref::<String>() {
+        if let Some(message) = response2.as_str() {
             println!("Client received response via Hub 2 from Hub 1: {}", message);
         } else {
             println!("Client received response from Hub 1 with unknown data format");
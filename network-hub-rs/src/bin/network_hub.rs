@@ -5,6 +5,9 @@ use clap::{Command, Arg, ArgAction};
 use network_hub::{Hub, HubScope, NetworkTransport, TlsConfig};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Initialize tracing
+    tracing_subscriber::fmt::init();
+    
     // Set up command line parsing
     let matches = Command::new("Network Hub")
         .version("0.1.0")
@@ -45,7 +48,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             Arg::new("scope")
                 .short('s')
                 .long("scope")
-                .help("Hub scope (thread, process, machine, network)")
+                .help("Hub scope (thread, process, machine, network, or custom(<level>))")
                 .default_value("network")
                 .action(ArgAction::Set),
         )
@@ -62,16 +65,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let bind_addr = SocketAddr::from_str(bind_address)?;
 
     // Parse scope
-    let scope = match scope_str.to_lowercase().as_str() {
-        "thread" => HubScope::Thread,
-        "process" => HubScope::Process,
-        "machine" => HubScope::Machine,
-        _ => HubScope::Network,
-    };
+    let scope = scope_str.parse().unwrap_or(HubScope::Network);
 
     // Initialize hub
     let hub = Hub::initialize(scope);
-    println!("Hub initialized with ID: {} and scope: {:?}", hub.id, hub.scope);
+    println!("Hub initialized with ID: {} and scope: {}", hub.id, hub.scope);
 
     // Configure TLS
     let tls_config = TlsConfig {
@@ -5,6 +5,9 @@ use clap::{Command, Arg, ArgAction};
 use network_hub::{Hub, HubScope, HttpReverseProxy, TlsConfig};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Initialize tracing
+    tracing_subscriber::fmt::init();
+    
     // Set up command line parsing
     let matches = Command::new("Reverse Proxy")
         .version("0.1.0")
@@ -49,6 +52,13 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .action(ArgAction::Set)
                 .num_args(1..)
         )
+        .arg(
+            Arg::new("routes")
+                .long("routes")
+                .help("Path to a JSON file of saved routes to load on startup")
+                .required(false)
+                .action(ArgAction::Set),
+        )
         .get_matches();
 
     // Get command line arguments
@@ -62,7 +72,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Initialize hub
     let hub = Hub::initialize(HubScope::Network);
-    println!("Hub initialized with ID: {} and scope: {:?}", hub.id, hub.scope);
+    println!("Hub initialized with ID: {} and scope: {}", hub.id, hub.scope);
 
     // Configure TLS - Note: This is a placeholder implementation
     println!("\n==============================================================");
@@ -83,6 +93,14 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Add default routes
     proxy.add_route("/", "https://example.com");
     
+    // Add saved routes
+    if let Some(routes_path) = matches.get_one::<String>("routes") {
+        match proxy.load_routes(routes_path) {
+            Ok(()) => println!("Loaded routes from {}", routes_path),
+            Err(e) => eprintln!("Failed to load routes from {}: {}", routes_path, e),
+        }
+    }
+    
     // Add routes from command line
     if let Some(routes) = matches.get_many::<String>("add-route") {
         for route_str in routes {
@@ -2,13 +2,15 @@ use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
-use network_hub::hub::{Hub, HubScope, ApiRequest, ApiResponse, ResponseStatus};
+use network_hub::hub::{ApiGuard, Hub, HubScope, ApiRequest, ApiResponse, ResponseStatus};
 use network_hub::utils::generate_uuid;
 
 /// A simple calculator service that will register with the hub
 struct CalculatorService {
     hub: Arc<Hub>,
     service_id: String,
+    /// The service's APIs, unregistered when the service is dropped
+    _apis: Vec<ApiGuard>,
 }
 
 impl CalculatorService {
@@ -16,18 +18,19 @@ impl CalculatorService {
         let service_id = format!("calculator-{}", generate_uuid());
         
         // Register APIs with the hub
-        Self::register_apis(Arc::clone(&hub), service_id.clone());
+        let apis = Self::register_apis(Arc::clone(&hub), service_id.clone());
         
         CalculatorService {
             hub,
             service_id,
+            _apis: apis,
         }
     }
     
-    fn register_apis(hub: Arc<Hub>, service_id: String) {
+    fn register_apis(hub: Arc<Hub>, service_id: String) -> Vec<ApiGuard> {
         // Add API
         let service_id_clone = service_id.clone();
-        hub.register_api("/calculator/add", move |request| {
+        let add = hub.register_api_with_guard("/calculator/add", move |request| {
             println!("[{}] Handling add request", service_id_clone);
             
             // Try to extract numbers from the request
@@ -53,7 +56,7 @@ impl CalculatorService {
         
         // Subtract API
         let service_id_clone = service_id.clone();
-        hub.register_api("/calculator/subtract", move |request| {
+        let subtract = hub.register_api_with_guard("/calculator/subtract", move |request| {
             println!("[{}] Handling subtract request", service_id_clone);
             
             // Try to extract numbers from the request
@@ -79,7 +82,7 @@ impl CalculatorService {
         
         // Multiply API
         let service_id_clone = service_id;
-        hub.register_api("/calculator/multiply", move |request| {
+        let multiply = hub.register_api_with_guard("/calculator/multiply", move |request| {
             println!("[{}] Handling multiply request", service_id_clone);
             
             // Try to extract numbers from the request
@@ -102,6 +105,8 @@ impl CalculatorService {
                 }
             }
         }, HashMap::new());
+        
+        vec![add, subtract, multiply]
     }
 }
 
@@ -109,6 +114,8 @@ impl CalculatorService {
 struct MathService {
     hub: Arc<Hub>,
     service_id: String,
+    /// The service's APIs, unregistered when the service is dropped
+    _apis: Vec<ApiGuard>,
 }
 
 impl MathService {
@@ -116,19 +123,20 @@ impl MathService {
         let service_id = format!("math-{}", generate_uuid());
         
         // Register APIs with the hub
-        Self::register_apis(Arc::clone(&hub), service_id.clone());
+        let apis = Self::register_apis(Arc::clone(&hub), service_id.clone());
         
         MathService {
             hub,
             service_id,
+            _apis: apis,
         }
     }
     
-    fn register_apis(hub: Arc<Hub>, service_id: String) {
+    fn register_apis(hub: Arc<Hub>, service_id: String) -> Vec<ApiGuard> {
         // Square API - uses multiply
         let hub_clone = Arc::clone(&hub);
         let service_id_clone = service_id.clone();
-        hub.register_api("/math/square", move |request| {
+        let square = hub.register_api_with_guard("/math/square", move |request| {
             println!("[{}] Handling square request", service_id_clone);
             
             // Try to extract number from the request
@@ -162,7 +170,7 @@ impl MathService {
         // Calculate expression API - uses multiple calculator APIs
         let hub_clone = Arc::clone(&hub);
         let service_id_clone = service_id;
-        hub.register_api("/math/evaluate", move |request| {
+        let evaluate = hub.register_api_with_guard("/math/evaluate", move |request| {
             println!("[{}] Handling expression evaluation request", service_id_clone);
             
             // Try to extract expression from the request
@@ -240,6 +248,8 @@ impl MathService {
                 }
             }
         }, HashMap::new());
+        
+        vec![square, evaluate]
     }
 }
 
@@ -0,0 +1,252 @@
+use std::collections::HashMap;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::error::{HubError, Result};
+use crate::hub::{Hub, HubConfig, HubScope};
+use crate::proxy::HttpReverseProxy;
+use crate::transport::{
+    AuthConfig, NetworkTransport, ReconnectPolicy, TlsConfig, DEFAULT_DISCOVERY_INTERVAL, DEFAULT_DISCOVERY_PORT,
+};
+
+/// Configuration for a hub together with its network transport and reverse proxy
+///
+/// Loaded from TOML, or from JSON for files with a `.json` extension. Every
+/// section is optional and falls back to its defaults.
+///
+/// ```toml
+/// [hub]
+/// scope = "Network"
+/// similarity_threshold = 0.9
+///
+/// [transport]
+/// bind_address = "0.0.0.0:9000"
+/// discovery_port = 9765
+///
+/// [proxy]
+/// bind_address = "0.0.0.0:8443"
+///
+/// [tls]
+/// cert_path = "certs/cert.pem"
+/// key_path = "certs/key.pem"
+///
+/// [routes]
+/// "/api/*" = "http://127.0.0.1:3000"
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Hub scope and routing settings
+    pub hub: HubSection,
+    /// Network transport settings
+    pub transport: TransportSection,
+    /// Reverse proxy settings
+    pub proxy: ProxySection,
+    /// Certificates shared by the transport and proxy
+    pub tls: TlsConfig,
+    /// Proxy routes, from path to backend URL (a path ending in `*` matches every path starting with the rest)
+    pub routes: HashMap<String, String>,
+}
+
+/// Hub section of a configuration file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HubSection {
+    /// Scope level of the hub
+    pub scope: HubScope,
+    /// Routing settings
+    #[serde(flatten)]
+    pub config: HubConfig,
+}
+
+impl Default for HubSection {
+    fn default() -> Self {
+        HubSection {
+            scope: HubScope::Network,
+            config: HubConfig::default(),
+        }
+    }
+}
+
+/// Transport section of a configuration file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TransportSection {
+    /// Address the transport listens on
+    pub bind_address: SocketAddr,
+    /// Number of connection worker threads (the transport's default if unset)
+    pub worker_threads: Option<usize>,
+    /// Number of connections queued before they're accepted (the transport's default if unset)
+    pub listen_backlog: Option<u32>,
+    /// Idle connection timeout in milliseconds (the transport's default if unset)
+    pub idle_timeout_ms: Option<u64>,
+    /// UDP port for hub discovery
+    pub discovery_port: u16,
+    /// Interval between discovery announcements in milliseconds
+    pub discovery_interval_ms: u64,
+    /// How peers whose connection breaks are reconnected (not reconnected if unset)
+    pub reconnect: Option<ReconnectSection>,
+    /// Shared secret peers must know to connect (none if unset)
+    pub auth: Option<AuthConfig>,
+}
+
+impl Default for TransportSection {
+    fn default() -> Self {
+        TransportSection {
+            bind_address: SocketAddr::from(([127, 0, 0, 1], 9000)),
+            worker_threads: None,
+            listen_backlog: None,
+            idle_timeout_ms: None,
+            discovery_port: DEFAULT_DISCOVERY_PORT,
+            discovery_interval_ms: DEFAULT_DISCOVERY_INTERVAL.as_millis() as u64,
+            reconnect: None,
+            auth: None,
+        }
+    }
+}
+
+/// Peer reconnection settings of the transport section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReconnectSection {
+    /// Number of attempts before giving up
+    pub max_retries: u32,
+    /// Time to wait before the first attempt in milliseconds
+    pub base_delay_ms: u64,
+    /// Longest time to wait between attempts in milliseconds
+    pub max_delay_ms: u64,
+}
+
+impl Default for ReconnectSection {
+    fn default() -> Self {
+        let policy = ReconnectPolicy::default();
+        ReconnectSection {
+            max_retries: policy.max_retries,
+            base_delay_ms: policy.base_delay.as_millis() as u64,
+            max_delay_ms: policy.max_delay.as_millis() as u64,
+        }
+    }
+}
+
+impl From<&ReconnectSection> for ReconnectPolicy {
+    fn from(section: &ReconnectSection) -> Self {
+        ReconnectPolicy {
+            max_retries: section.max_retries,
+            base_delay: Duration::from_millis(section.base_delay_ms),
+            max_delay: Duration::from_millis(section.max_delay_ms),
+        }
+    }
+}
+
+/// Proxy section of a configuration file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProxySection {
+    /// Address the proxy listens on
+    pub bind_address: SocketAddr,
+    /// Number of connection worker threads (the proxy's default if unset)
+    pub worker_threads: Option<usize>,
+    /// Number of connections queued before they're accepted (the proxy's default if unset)
+    pub listen_backlog: Option<u32>,
+    /// Whether error responses have a JSON body
+    pub json_errors: bool,
+}
+
+impl Default for ProxySection {
+    fn default() -> Self {
+        ProxySection {
+            bind_address: SocketAddr::from(([127, 0, 0, 1], 8443)),
+            worker_threads: None,
+            listen_backlog: None,
+            json_errors: false,
+        }
+    }
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        TlsConfig {
+            cert_path: "certs/cert.pem".to_string(),
+            key_path: "certs/key.pem".to_string(),
+            ca_path: None,
+        }
+    }
+}
+
+impl Config {
+    /// Load a configuration file, parsed as JSON if it has a `.json` extension and as TOML otherwise
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)?;
+        let config = if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json")) {
+            let config: Self = serde_json::from_str(&contents)?;
+            config.validate()?;
+            config
+        } else {
+            Self::from_toml(&contents)?
+        };
+        info!(path = %path.display(), "Loaded configuration");
+        Ok(config)
+    }
+
+    /// Parse a TOML configuration
+    pub fn from_toml(toml: &str) -> Result<Self> {
+        let config: Self = toml::from_str(toml).map_err(|e| HubError::Config(e.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Check that every route target is a URL the proxy can forward to
+    fn validate(&self) -> Result<()> {
+        for (path, target) in &self.routes {
+            let url = url::Url::parse(target)
+                .map_err(|e| HubError::Config(format!("Invalid target {:?} for route {}: {}", target, path, e)))?;
+            if url.host_str().is_none() {
+                return Err(HubError::Config(format!("Target {:?} for route {} has no host", target, path)));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Create the hub, transport and proxy described by a configuration
+///
+/// Nothing is started; the caller starts the transport and proxy when ready.
+pub fn build_system(config: &Config) -> (Arc<Hub>, NetworkTransport, HttpReverseProxy) {
+    let hub = Arc::new(Hub::with_config(config.hub.scope, config.hub.config.clone()));
+
+    let transport = NetworkTransport::new(Arc::clone(&hub), config.transport.bind_address, config.tls.clone());
+    if let Some(threads) = config.transport.worker_threads {
+        transport.set_worker_threads(threads);
+    }
+    if let Some(backlog) = config.transport.listen_backlog {
+        transport.set_listen_backlog(backlog);
+    }
+    if let Some(timeout_ms) = config.transport.idle_timeout_ms {
+        transport.set_idle_timeout(Duration::from_millis(timeout_ms));
+    }
+    transport.set_discovery_port(config.transport.discovery_port);
+    transport.set_discovery_interval(Duration::from_millis(config.transport.discovery_interval_ms));
+    transport.set_reconnect_policy(config.transport.reconnect.as_ref().map(ReconnectPolicy::from));
+    transport.set_auth(config.transport.auth.clone());
+
+    let proxy = HttpReverseProxy::new(Arc::clone(&hub), config.proxy.bind_address, config.tls.clone());
+    if let Some(threads) = config.proxy.worker_threads {
+        proxy.set_worker_threads(threads);
+    }
+    if let Some(backlog) = config.proxy.listen_backlog {
+        proxy.set_listen_backlog(backlog);
+    }
+    proxy.set_json_errors(config.proxy.json_errors);
+    for (path, target) in &config.routes {
+        proxy.add_route(path, target);
+    }
+
+    (hub, transport, proxy)
+}
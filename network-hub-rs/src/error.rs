@@ -12,6 +12,10 @@ pub enum HubError {
     #[error("Serialization error: {0}")]
     Serialization(#[from] serde_json::Error),
     
+    /// Invalid configuration
+    #[error("Configuration error: {0}")]
+    Config(String),
+    
     /// TLS error
     #[error("TLS error: {0}")]
     Tls(String),
@@ -31,6 +35,26 @@ pub enum HubError {
     /// Invalid state
     #[error("Invalid state: {0}")]
     InvalidState(String),
+    
+    /// An API is already registered at the path
+    #[error("API already registered: {0}")]
+    AlreadyRegistered(String),
+    
+    /// An API handler panicked while handling a request
+    #[error("Handler panicked: {0}")]
+    HandlerPanic(String),
+    
+    /// A peer didn't answer in time
+    #[error("Timed out: {0}")]
+    Timeout(String),
+    
+    /// A peer is refused by the transport's peer filter
+    #[error("Peer not allowed: {0}")]
+    PeerNotAllowed(String),
+    
+    /// A peer refused the connection for not knowing its shared secret
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
 }
 
 pub type Result<T> = std::result::Result<T, HubError>;
\ No newline at end of file
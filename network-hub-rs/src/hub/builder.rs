@@ -0,0 +1,146 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::hub::{Hub, HubScope};
+
+/// Default number of retries a request may take across the whole hierarchy
+const DEFAULT_RETRY_BUDGET: usize = 3;
+
+/// Runtime configuration for a hub
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HubConfig {
+    /// Minimum similarity for a path to be used as an approximation
+    pub similarity_threshold: f64,
+    /// Maximum number of parent escalations for a request (`None` for unlimited)
+    pub max_hops: Option<usize>,
+    /// Whether request metrics are collected
+    pub metrics_enabled: bool,
+    /// Whether failed requests may be retried at the API's registered fallback
+    pub enable_fallback: bool,
+    /// Whether unmatched requests may be routed to a similar path
+    pub enable_approximation: bool,
+    /// Whether concurrent identical requests share one handler execution
+    pub singleflight: bool,
+    /// Number of requests handled at once, with the rest queued fairly (0 for no limit)
+    pub request_workers: usize,
+    /// Number of times a failed request is retried at this hub
+    pub max_retries: usize,
+    /// Number of retries a request made to this hub may take across the hierarchy
+    pub retry_budget: usize,
+}
+
+impl Default for HubConfig {
+    fn default() -> Self {
+        HubConfig {
+            similarity_threshold: 0.8,
+            max_hops: None,
+            metrics_enabled: false,
+            enable_fallback: true,
+            enable_approximation: true,
+            singleflight: false,
+            request_workers: 0,
+            max_retries: 0,
+            retry_budget: DEFAULT_RETRY_BUDGET,
+        }
+    }
+}
+
+/// Builder for configuring a hub before it is created
+pub struct HubBuilder {
+    /// Scope level of the hub
+    scope: HubScope,
+    /// Hub configuration
+    config: HubConfig,
+    /// Whether to discover and connect to parent hubs on build
+    discovery: bool,
+}
+
+impl HubBuilder {
+    /// Create a new builder for a hub with the specified scope
+    pub fn new(scope: HubScope) -> Self {
+        HubBuilder {
+            scope,
+            config: HubConfig::default(),
+            discovery: false,
+        }
+    }
+
+    /// Set the scope of the hub
+    pub fn scope(mut self, scope: HubScope) -> Self {
+        self.scope = scope;
+        self
+    }
+
+    /// Enable request metrics collection
+    pub fn enable_metrics(mut self) -> Self {
+        self.config.metrics_enabled = true;
+        self
+    }
+
+    /// Set the minimum similarity used for path approximation
+    pub fn similarity_threshold(mut self, threshold: f64) -> Self {
+        self.config.similarity_threshold = threshold;
+        self
+    }
+
+    /// Set the maximum number of parent escalations for a request
+    pub fn max_hops(mut self, max_hops: usize) -> Self {
+        self.config.max_hops = Some(max_hops);
+        self
+    }
+
+    /// Enable or disable retrying failed requests at the API's registered fallback
+    pub fn enable_fallback(mut self, enabled: bool) -> Self {
+        self.config.enable_fallback = enabled;
+        self
+    }
+
+    /// Enable or disable routing unmatched requests to a similar path
+    pub fn enable_approximation(mut self, enabled: bool) -> Self {
+        self.config.enable_approximation = enabled;
+        self
+    }
+
+    /// Enable or disable sharing one handler execution between concurrent identical requests
+    pub fn singleflight(mut self, enabled: bool) -> Self {
+        self.config.singleflight = enabled;
+        self
+    }
+
+    /// Limit the number of requests handled at once, queueing the rest fairly
+    pub fn request_workers(mut self, workers: usize) -> Self {
+        self.config.request_workers = workers;
+        self
+    }
+
+    /// Set the number of times a failed request is retried at the hub
+    pub fn max_retries(mut self, retries: usize) -> Self {
+        self.config.max_retries = retries;
+        self
+    }
+
+    /// Set the number of retries a request made to the hub may take across the hierarchy
+    pub fn retry_budget(mut self, budget: usize) -> Self {
+        self.config.retry_budget = budget;
+        self
+    }
+
+    /// Discover and connect to parent hubs when the hub is built (as `Hub::initialize` does)
+    pub fn discovery(mut self, enabled: bool) -> Self {
+        self.discovery = enabled;
+        self
+    }
+
+    /// Build the hub
+    pub fn build(self) -> Arc<Hub> {
+        let hub = Arc::new(Hub::with_config(self.scope, self.config));
+
+        if self.discovery {
+            Hub::discover_parents(&hub);
+        }
+
+        hub
+    }
+}
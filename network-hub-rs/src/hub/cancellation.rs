@@ -0,0 +1,62 @@
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+thread_local! {
+    /// Token of the request being handled on this thread, if it can be cancelled
+    static CURRENT: RefCell<Option<CancellationToken>> = const { RefCell::new(None) };
+}
+
+/// Token a caller fires to cancel a request in flight
+///
+/// Passed to `Hub::handle_request_cancellable`. Clones share the same state, so
+/// the caller keeps one to cancel with while the request is handled. Handlers
+/// check `CancellationToken::current` to stop early; handlers that don't still
+/// run to completion, but their response is replaced by a `Cancelled` one.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    /// Whether the token has fired
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    /// Create a token that hasn't fired
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fire the token, cancelling the requests it was passed with
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the token has fired
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Get the token of the cancellable request being handled on this thread
+    ///
+    /// `None` within requests made without a token.
+    pub fn current() -> Option<CancellationToken> {
+        CURRENT.with(|current| current.borrow().clone())
+    }
+
+    /// Make this the current token until the returned guard is dropped
+    pub(crate) fn enter(&self) -> CurrentTokenGuard {
+        let previous = CURRENT.with(|current| current.replace(Some(self.clone())));
+        CurrentTokenGuard { previous }
+    }
+}
+
+/// Restores the token that was current before `CancellationToken::enter` when dropped
+pub(crate) struct CurrentTokenGuard {
+    /// Token current before, for requests made while handling another
+    previous: Option<CancellationToken>,
+}
+
+impl Drop for CurrentTokenGuard {
+    fn drop(&mut self) {
+        CURRENT.with(|current| *current.borrow_mut() = self.previous.take());
+    }
+}
@@ -1,18 +1,54 @@
 use std::any::{Any, TypeId};
+use std::cmp::Reverse;
 use std::collections::{HashMap, BTreeMap};
 use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicU64, Ordering};
 
-use crate::utils::generate_uuid;
+use crate::utils::{generate_uuid, matches_pattern};
 use crate::hub::types::{Message, ApiRequest, ApiResponse, Interceptor};
 
+/// Method interceptor handler, as stored (type-erased) in the manager
+type MethodHandler<T, A, R> = Box<dyn Fn(&T, &A) -> Option<R> + Send + Sync>;
+
+/// Request mutator, as stored in the manager
+type RequestMutator = Box<dyn Fn(&mut ApiRequest) + Send + Sync>;
+
+/// Response interceptor, as stored in the manager
+type ResponseInterceptor = Box<dyn Fn(&ApiRequest, &mut ApiResponse) + Send + Sync>;
+
+/// Ordering key of an interceptor: highest priority first, then in registration order
+type InterceptorKey = (Reverse<i32>, u64);
+
+/// Where a registered interceptor is stored, for removal by ID
+enum Registration {
+    /// Message interceptor for a topic
+    Message(String, InterceptorKey),
+    /// Method interceptor for a type and method name
+    Method(TypeId, String, InterceptorKey),
+    /// API interceptor for a path
+    Api(String, InterceptorKey),
+    /// Request mutator for a path
+    Mutator(String, InterceptorKey),
+    /// Response interceptor for a path
+    Response(String, InterceptorKey),
+}
+
 /// Manager for message and API interceptors
 pub struct InterceptorManager {
     /// Message interceptors by topic
-    message_interceptors: RwLock<HashMap<String, BTreeMap<i32, Box<dyn Any + Send + Sync>>>>,
+    message_interceptors: RwLock<HashMap<String, BTreeMap<InterceptorKey, Box<dyn Any + Send + Sync>>>>,
     /// Method interceptors by type ID and method name
-    method_interceptors: RwLock<HashMap<TypeId, HashMap<String, BTreeMap<i32, Box<dyn Any + Send + Sync>>>>>,
+    method_interceptors: RwLock<HashMap<TypeId, HashMap<String, BTreeMap<InterceptorKey, Box<dyn Any + Send + Sync>>>>>,
     /// API interceptors by path
-    api_interceptors: RwLock<HashMap<String, BTreeMap<i32, Box<dyn Fn(&ApiRequest) -> Option<ApiResponse> + Send + Sync>>>>,
+    api_interceptors: RwLock<HashMap<String, BTreeMap<InterceptorKey, Box<dyn Fn(&ApiRequest) -> Option<ApiResponse> + Send + Sync>>>>,
+    /// Request mutators by path
+    request_mutators: RwLock<HashMap<String, BTreeMap<InterceptorKey, RequestMutator>>>,
+    /// Response interceptors by path
+    response_interceptors: RwLock<HashMap<String, BTreeMap<InterceptorKey, ResponseInterceptor>>>,
+    /// Sequence number of the next registered interceptor
+    next_seq: AtomicU64,
+    /// Location of every registered interceptor by ID
+    registrations: RwLock<HashMap<String, Registration>>,
 }
 
 impl InterceptorManager {
@@ -22,9 +58,20 @@ impl InterceptorManager {
             message_interceptors: RwLock::new(HashMap::new()),
             method_interceptors: RwLock::new(HashMap::new()),
             api_interceptors: RwLock::new(HashMap::new()),
+            request_mutators: RwLock::new(HashMap::new()),
+            response_interceptors: RwLock::new(HashMap::new()),
+            next_seq: AtomicU64::new(0),
+            registrations: RwLock::new(HashMap::new()),
         }
     }
     
+    /// Get the ordering key for a newly registered interceptor
+    ///
+    /// Interceptors sharing a priority all run, in the order they were registered.
+    fn next_key(&self, priority: i32) -> InterceptorKey {
+        (Reverse(priority), self.next_seq.fetch_add(1, Ordering::Relaxed))
+    }
+    
     /// Register a message interceptor
     pub fn register<T, R, F>(&self, topic: &str, handler: F, priority: i32) -> String
     where
@@ -45,8 +92,9 @@ impl InterceptorManager {
             .entry(topic.to_string())
             .or_insert_with(BTreeMap::new);
         
-        // Use negative priority for reverse ordering (highest first)
-        topic_interceptors.insert(-priority, Box::new(interceptor));
+        let key = self.next_key(priority);
+        topic_interceptors.insert(key, Box::new(interceptor));
+        self.registrations.write().unwrap().insert(id.clone(), Registration::Message(topic.to_string(), key));
         
         id
     }
@@ -63,29 +111,131 @@ impl InterceptorManager {
             .entry(path.to_string())
             .or_insert_with(BTreeMap::new);
         
-        // Use negative priority for reverse ordering (highest first)
-        path_interceptors.insert(-priority, Box::new(handler));
+        let key = self.next_key(priority);
+        path_interceptors.insert(key, Box::new(handler));
+        self.registrations.write().unwrap().insert(id.clone(), Registration::Api(path.to_string(), key));
+        
+        id
+    }
+    
+    /// Register a request mutator, which changes API requests before they are intercepted or looked up
+    pub fn register_request_mutator<F>(&self, path: &str, mutator: F, priority: i32) -> String
+    where
+        F: Fn(&mut ApiRequest) + Send + Sync + 'static,
+    {
+        let id = generate_uuid();
+        
+        let mut mutators = self.request_mutators.write().unwrap();
+        let path_mutators = mutators
+            .entry(path.to_string())
+            .or_insert_with(BTreeMap::new);
+        
+        let key = self.next_key(priority);
+        path_mutators.insert(key, Box::new(mutator));
+        self.registrations.write().unwrap().insert(id.clone(), Registration::Mutator(path.to_string(), key));
+        
+        id
+    }
+    
+    /// Apply the request mutators registered for a request's path, exactly or
+    /// through a wildcard pattern, in priority order
+    ///
+    /// The mutators to apply are chosen by the path the request had before any of
+    /// them ran, so a rewritten path doesn't pick up further mutators.
+    pub fn mutate_api_request(&self, request: &mut ApiRequest) {
+        let mutators = self.request_mutators.read().unwrap();
+        if mutators.is_empty() {
+            return;
+        }
+        
+        let mut matching: Vec<_> = mutators
+            .iter()
+            .filter(|(pattern, _)| matches_pattern(pattern, &request.path))
+            .flat_map(|(_, path_mutators)| path_mutators.iter())
+            .collect();
+        matching.sort_by_key(|(key, _)| **key);
+        
+        for (_key, mutator) in matching {
+            mutator(request);
+        }
+    }
+    
+    /// Register a response interceptor, which changes the responses to API requests once they're resolved
+    ///
+    /// Response interceptors for a path run in the order they were registered.
+    pub fn register_response_interceptor<F>(&self, path: &str, interceptor: F) -> String
+    where
+        F: Fn(&ApiRequest, &mut ApiResponse) + Send + Sync + 'static,
+    {
+        let id = generate_uuid();
+        
+        let mut interceptors = self.response_interceptors.write().unwrap();
+        let path_interceptors = interceptors
+            .entry(path.to_string())
+            .or_insert_with(BTreeMap::new);
+        
+        let key = self.next_key(0);
+        path_interceptors.insert(key, Box::new(interceptor));
+        self.registrations.write().unwrap().insert(id.clone(), Registration::Response(path.to_string(), key));
         
         id
     }
     
+    /// Apply the response interceptors registered for a request's path, exactly or
+    /// through a wildcard pattern, in the order they were registered
+    pub fn intercept_api_response(&self, request: &ApiRequest, response: &mut ApiResponse) {
+        let interceptors = self.response_interceptors.read().unwrap();
+        if interceptors.is_empty() {
+            return;
+        }
+        
+        let mut matching: Vec<_> = interceptors
+            .iter()
+            .filter(|(pattern, _)| matches_pattern(pattern, &request.path))
+            .flat_map(|(_, path_interceptors)| path_interceptors.iter())
+            .collect();
+        matching.sort_by_key(|(key, _)| **key);
+        
+        for (_key, interceptor) in matching {
+            interceptor(request, response);
+        }
+    }
+    
+    /// Whether any response interceptors are registered for a path, exactly or through a wildcard pattern
+    pub fn has_response_interceptors(&self, path: &str) -> bool {
+        let interceptors = self.response_interceptors.read().unwrap();
+        interceptors.keys().any(|pattern| matches_pattern(pattern, path))
+    }
+    
+    /// Whether any request mutators are registered for a path, exactly or through a wildcard pattern
+    pub fn has_request_mutators(&self, path: &str) -> bool {
+        let mutators = self.request_mutators.read().unwrap();
+        mutators.keys().any(|pattern| matches_pattern(pattern, path))
+    }
+    
+    /// Whether any API interceptors are registered for a path, exactly or through a wildcard pattern
+    pub fn has_api_interceptors(&self, path: &str) -> bool {
+        let interceptors = self.api_interceptors.read().unwrap();
+        interceptors.keys().any(|pattern| matches_pattern(pattern, path))
+    }
+    
     /// Try to intercept an API request
     pub fn try_intercept_api_request(&self, request: &ApiRequest) -> Option<ApiResponse> {
         let interceptors = self.api_interceptors.read().unwrap();
         
         // Check for exact path match
         if let Some(path_interceptors) = interceptors.get(&request.path) {
-            for (_neg_priority, handler) in path_interceptors.iter() {
+            for (_key, handler) in path_interceptors.iter() {
                 if let Some(response) = handler(request) {
                     return Some(response);
                 }
             }
         }
         
-        // Check for wildcard patterns
+        // Check for wildcard patterns (see `matches_pattern`)
         for (pattern, path_interceptors) in interceptors.iter() {
-            if pattern.ends_with('*') && request.path.starts_with(&pattern[0..pattern.len()-1]) {
-                for (_neg_priority, handler) in path_interceptors.iter() {
+            if pattern.contains('*') && matches_pattern(pattern, &request.path) {
+                for (_key, handler) in path_interceptors.iter() {
                     if let Some(response) = handler(request) {
                         return Some(response);
                     }
@@ -106,7 +256,7 @@ impl InterceptorManager {
         
         // Check for exact topic match
         if let Some(topic_interceptors) = interceptors.get(&message.topic) {
-            for (_neg_priority, interceptor_box) in topic_interceptors.iter() {
+            for (_key, interceptor_box) in topic_interceptors.iter() {
                 // We need to cast based on our message wrapper and expected response type
                 let interceptor_ref = interceptor_box.downcast_ref::<Interceptor<Message<T>, R>>();
                 if let Some(interceptor) = interceptor_ref {
@@ -117,10 +267,10 @@ impl InterceptorManager {
             }
         }
         
-        // Check for wildcard patterns
+        // Check for wildcard patterns (see `matches_pattern`)
         for (pattern, topic_interceptors) in interceptors.iter() {
-            if pattern.ends_with('*') && message.topic.starts_with(&pattern[0..pattern.len()-1]) {
-                for (_neg_priority, interceptor_box) in topic_interceptors.iter() {
+            if pattern.contains('*') && matches_pattern(pattern, &message.topic) {
+                for (_key, interceptor_box) in topic_interceptors.iter() {
                     let interceptor_ref = interceptor_box.downcast_ref::<Interceptor<Message<T>, R>>();
                     if let Some(interceptor) = interceptor_ref {
                         if let Some(result) = (interceptor.handler)(message) {
@@ -134,10 +284,9 @@ impl InterceptorManager {
         None
     }
     
-    /// Register a method interceptor
+    /// Register a method interceptor for a method of type `T` taking arguments `A`
     pub fn register_method_interceptor<T, A, R, F>(
         &self,
-        class_type: TypeId,
         method_name: &str,
         handler: F,
         priority: i32,
@@ -150,22 +299,32 @@ impl InterceptorManager {
     {
         let id = generate_uuid();
         
+        // Stored as the same trait object type that try_intercept_method downcasts to
+        let handler: MethodHandler<T, A, R> = Box::new(handler);
+        
         let mut interceptors = self.method_interceptors.write().unwrap();
         let type_interceptors = interceptors
-            .entry(class_type)
+            .entry(TypeId::of::<T>())
             .or_insert_with(HashMap::new);
         
         let method_interceptors = type_interceptors
             .entry(method_name.to_string())
             .or_insert_with(BTreeMap::new);
         
-        // Use negative priority for reverse ordering (highest first)
-        method_interceptors.insert(-priority, Box::new(handler));
+        let key = self.next_key(priority);
+        method_interceptors.insert(key, Box::new(handler));
+        self.registrations.write().unwrap().insert(
+            id.clone(),
+            Registration::Method(TypeId::of::<T>(), method_name.to_string(), key),
+        );
         
         id
     }
     
     /// Try to intercept a method call
+    ///
+    /// Interceptors registered for the same method with different argument or
+    /// return types are skipped.
     pub fn try_intercept_method<T, A, R>(
         &self,
         target: &T,
@@ -178,22 +337,117 @@ impl InterceptorManager {
         R: 'static + Send + Sync,
     {
         let interceptors = self.method_interceptors.read().unwrap();
-        let type_id = TypeId::of::<T>();
-        
-        if let Some(type_interceptors) = interceptors.get(&type_id) {
-            if let Some(method_interceptors) = type_interceptors.get(method_name) {
-                for (_neg_priority, handler_box) in method_interceptors.iter() {
-                    // In real code, we'd need a better way to handle this casting
-                    // This is a placeholder - would need proper trait objects and dynamic dispatch
-                    if let Some(handler) = handler_box.downcast_ref::<Box<dyn Fn(&T, &A) -> Option<R> + Send + Sync>>() {
-                        if let Some(result) = handler(target, args) {
-                            return Some(result);
+        let method_interceptors = interceptors.get(&TypeId::of::<T>())?.get(method_name)?;
+        
+        method_interceptors
+            .values()
+            .filter_map(|handler_box| handler_box.downcast_ref::<MethodHandler<T, A, R>>())
+            .find_map(|handler| handler(target, args))
+    }
+    
+    /// Remove an interceptor by the ID returned when it was registered
+    ///
+    /// Returns whether an interceptor was removed.
+    pub fn unregister(&self, id: &str) -> bool {
+        let Some(registration) = self.registrations.write().unwrap().remove(id) else {
+            return false;
+        };
+        
+        match registration {
+            Registration::Message(topic, key) => {
+                let mut interceptors = self.message_interceptors.write().unwrap();
+                if let Some(topic_interceptors) = interceptors.get_mut(&topic) {
+                    topic_interceptors.remove(&key);
+                    if topic_interceptors.is_empty() {
+                        interceptors.remove(&topic);
+                    }
+                }
+            }
+            Registration::Method(type_id, method_name, key) => {
+                let mut interceptors = self.method_interceptors.write().unwrap();
+                if let Some(type_interceptors) = interceptors.get_mut(&type_id) {
+                    if let Some(method_interceptors) = type_interceptors.get_mut(&method_name) {
+                        method_interceptors.remove(&key);
+                        if method_interceptors.is_empty() {
+                            type_interceptors.remove(&method_name);
                         }
                     }
+                    if type_interceptors.is_empty() {
+                        interceptors.remove(&type_id);
+                    }
+                }
+            }
+            Registration::Api(path, key) => {
+                let mut interceptors = self.api_interceptors.write().unwrap();
+                if let Some(path_interceptors) = interceptors.get_mut(&path) {
+                    path_interceptors.remove(&key);
+                    if path_interceptors.is_empty() {
+                        interceptors.remove(&path);
+                    }
+                }
+            }
+            Registration::Mutator(path, key) => {
+                let mut mutators = self.request_mutators.write().unwrap();
+                if let Some(path_mutators) = mutators.get_mut(&path) {
+                    path_mutators.remove(&key);
+                    if path_mutators.is_empty() {
+                        mutators.remove(&path);
+                    }
+                }
+            }
+            Registration::Response(path, key) => {
+                let mut interceptors = self.response_interceptors.write().unwrap();
+                if let Some(path_interceptors) = interceptors.get_mut(&path) {
+                    path_interceptors.remove(&key);
+                    if path_interceptors.is_empty() {
+                        interceptors.remove(&path);
+                    }
                 }
             }
         }
         
-        None
+        true
     }
-}
\ No newline at end of file
+    
+    /// Remove every registered interceptor
+    ///
+    /// Guards for the removed interceptors become no-ops.
+    pub fn clear(&self) {
+        self.message_interceptors.write().unwrap().clear();
+        self.method_interceptors.write().unwrap().clear();
+        self.api_interceptors.write().unwrap().clear();
+        self.request_mutators.write().unwrap().clear();
+        self.response_interceptors.write().unwrap().clear();
+        self.registrations.write().unwrap().clear();
+    }
+}
+
+/// Guard that unregisters an interceptor when dropped
+///
+/// Returned by the hub's scoped registration methods, for hooks that should only
+/// last as long as a block (such as a test case).
+#[must_use = "the interceptor is unregistered as soon as the guard is dropped"]
+pub struct InterceptorGuard {
+    /// Manager the interceptor is registered with
+    interceptors: Arc<InterceptorManager>,
+    /// ID of the interceptor
+    id: String,
+}
+
+impl InterceptorGuard {
+    /// Create a guard for an interceptor registered with a manager
+    pub(crate) fn new(interceptors: Arc<InterceptorManager>, id: String) -> Self {
+        InterceptorGuard { interceptors, id }
+    }
+    
+    /// Get the ID of the guarded interceptor
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+impl Drop for InterceptorGuard {
+    fn drop(&mut self) {
+        self.interceptors.unregister(&self.id);
+    }
+}
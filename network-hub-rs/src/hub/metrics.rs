@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use dashmap::DashMap;
+use serde::Serialize;
+
+use crate::hub::types::ResponseStatus;
+
+/// Snapshot of the request metrics collected by a hub
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct HubStats {
+    /// Total number of requests handled
+    pub total_requests: u64,
+    /// Number of requests per path, broken down by response status
+    pub requests: HashMap<String, HashMap<ResponseStatus, u64>>,
+    /// Number of requests resolved by each resolution step
+    pub resolutions: ResolutionStats,
+    /// Distribution of the time taken to handle requests
+    pub durations: DurationStats,
+    /// Number of requests waiting for one of the hub's request workers
+    pub queue_depth: usize,
+}
+
+/// Upper bounds of the request duration histogram buckets, in seconds
+const DURATION_BUCKETS: [f64; 10] = [0.0005, 0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.5, 1.0, 5.0];
+
+/// Histogram of the time a hub took to handle requests
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct DurationStats {
+    /// Upper bound of each bucket in seconds, with the number of requests taking
+    /// at most that long (so the counts are cumulative)
+    pub buckets: Vec<(f64, u64)>,
+    /// Total time taken by all requests, in seconds
+    pub sum_seconds: f64,
+    /// Number of requests timed
+    pub count: u64,
+}
+
+/// Number of requests a hub resolved by each step of request resolution
+///
+/// Every request is counted once by each hub it passes through, by the step
+/// that resolved it there: a request escalated to the parent counts as `parent`
+/// here and by its own step at the parent.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ResolutionStats {
+    /// Requests answered by an interceptor
+    pub intercepted: u64,
+    /// Requests answered by an API registered with the hub
+    pub local: u64,
+    /// Requests retried at the fallback of a failing API
+    pub fallback: u64,
+    /// Requests routed down to a child hub
+    pub child: u64,
+    /// Requests escalated to the parent hub
+    pub parent: u64,
+    /// Requests redirected to an API with a similar path
+    pub approximated: u64,
+    /// Requests nothing matched
+    pub not_found: u64,
+}
+
+/// Step of request resolution counted in `ResolutionStats`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Resolution {
+    Intercepted,
+    Local,
+    Fallback,
+    Child,
+    Parent,
+    Approximated,
+    NotFound,
+}
+
+/// Event emitted for each request handled by a hub
+#[derive(Debug, Clone, Serialize)]
+pub struct HubEvent {
+    /// Requested API path
+    pub path: String,
+    /// Status of the response
+    pub status: ResponseStatus,
+    /// ID of the request sender
+    pub sender_id: String,
+    /// Time taken to handle the request, in milliseconds
+    pub elapsed_ms: u64,
+}
+
+/// Request counters for a hub
+pub struct HubMetrics {
+    /// Total number of requests handled
+    total_requests: AtomicU64,
+    /// Request counts by path and response status
+    requests: DashMap<String, HashMap<ResponseStatus, u64>>,
+    /// Request counts by resolution step, indexed by `Resolution`
+    resolutions: [AtomicU64; 7],
+    /// Request counts by duration, indexed like `DURATION_BUCKETS` with a last
+    /// bucket for longer requests
+    duration_buckets: [AtomicU64; DURATION_BUCKETS.len() + 1],
+    /// Total time taken by all requests, in microseconds
+    duration_sum_micros: AtomicU64,
+}
+
+impl HubMetrics {
+    /// Create an empty set of counters
+    pub fn new() -> Self {
+        HubMetrics {
+            total_requests: AtomicU64::new(0),
+            requests: DashMap::new(),
+            resolutions: Default::default(),
+            duration_buckets: Default::default(),
+            duration_sum_micros: AtomicU64::new(0),
+        }
+    }
+
+    /// Record a handled request
+    pub fn record(&self, path: &str, status: ResponseStatus) {
+        self.total_requests.fetch_add(1, Ordering::Relaxed);
+        *self.requests
+            .entry(path.to_string())
+            .or_default()
+            .entry(status)
+            .or_insert(0) += 1;
+    }
+
+    /// Record the step that resolved a request
+    pub(crate) fn record_resolution(&self, resolution: Resolution) {
+        self.resolutions[resolution as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record the time taken to handle a request
+    pub(crate) fn record_duration(&self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        let bucket = DURATION_BUCKETS.iter().position(|bound| seconds <= *bound).unwrap_or(DURATION_BUCKETS.len());
+        self.duration_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.duration_sum_micros.fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Take a snapshot of the current counters
+    pub fn snapshot(&self) -> HubStats {
+        HubStats {
+            total_requests: self.total_requests.load(Ordering::Relaxed),
+            requests: self.requests
+                .iter()
+                .map(|entry| (entry.key().clone(), entry.value().clone()))
+                .collect(),
+            resolutions: ResolutionStats {
+                intercepted: self.resolution_count(Resolution::Intercepted),
+                local: self.resolution_count(Resolution::Local),
+                fallback: self.resolution_count(Resolution::Fallback),
+                child: self.resolution_count(Resolution::Child),
+                parent: self.resolution_count(Resolution::Parent),
+                approximated: self.resolution_count(Resolution::Approximated),
+                not_found: self.resolution_count(Resolution::NotFound),
+            },
+            durations: self.duration_stats(),
+            queue_depth: 0,
+        }
+    }
+
+    fn duration_stats(&self) -> DurationStats {
+        let mut cumulative = 0;
+        let buckets = DURATION_BUCKETS
+            .iter()
+            .zip(&self.duration_buckets)
+            .map(|(bound, count)| {
+                cumulative += count.load(Ordering::Relaxed);
+                (*bound, cumulative)
+            })
+            .collect();
+        DurationStats {
+            buckets,
+            sum_seconds: self.duration_sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0,
+            count: cumulative + self.duration_buckets[DURATION_BUCKETS.len()].load(Ordering::Relaxed),
+        }
+    }
+
+    fn resolution_count(&self, resolution: Resolution) -> u64 {
+        self.resolutions[resolution as usize].load(Ordering::Relaxed)
+    }
+}
+
+impl HubStats {
+    /// Render the counters in the Prometheus text exposition format
+    ///
+    /// Exports `hub_requests_total` by path and status, `hub_resolutions_total`
+    /// by resolution step, and the `hub_request_duration_seconds` histogram.
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+
+        out.push_str("# HELP hub_requests_total Requests handled by the hub.\n");
+        out.push_str("# TYPE hub_requests_total counter\n");
+        let mut paths: Vec<_> = self.requests.iter().collect();
+        paths.sort_by_key(|(path, _)| *path);
+        for (path, statuses) in paths {
+            let mut statuses: Vec<_> = statuses.iter().map(|(status, count)| (status_label(*status), count)).collect();
+            statuses.sort();
+            for (status, count) in statuses {
+                let _ = writeln!(out, "hub_requests_total{{path=\"{}\",status=\"{}\"}} {}", escape_label(path), status, count);
+            }
+        }
+
+        out.push_str("# HELP hub_resolutions_total Requests resolved by each step of request resolution.\n");
+        out.push_str("# TYPE hub_resolutions_total counter\n");
+        let resolutions = &self.resolutions;
+        for (step, count) in [
+            ("intercepted", resolutions.intercepted),
+            ("local", resolutions.local),
+            ("fallback", resolutions.fallback),
+            ("child", resolutions.child),
+            ("parent", resolutions.parent),
+            ("approximated", resolutions.approximated),
+            ("not_found", resolutions.not_found),
+        ] {
+            let _ = writeln!(out, "hub_resolutions_total{{step=\"{}\"}} {}", step, count);
+        }
+
+        out.push_str("# HELP hub_request_duration_seconds Time taken to handle requests.\n");
+        out.push_str("# TYPE hub_request_duration_seconds histogram\n");
+        for (bound, count) in &self.durations.buckets {
+            let _ = writeln!(out, "hub_request_duration_seconds_bucket{{le=\"{}\"}} {}", bound, count);
+        }
+        let _ = writeln!(out, "hub_request_duration_seconds_bucket{{le=\"+Inf\"}} {}", self.durations.count);
+        let _ = writeln!(out, "hub_request_duration_seconds_sum {}", self.durations.sum_seconds);
+        let _ = writeln!(out, "hub_request_duration_seconds_count {}", self.durations.count);
+
+        out.push_str("# HELP hub_queue_depth Requests waiting for a request worker.\n");
+        out.push_str("# TYPE hub_queue_depth gauge\n");
+        let _ = writeln!(out, "hub_queue_depth {}", self.queue_depth);
+
+        out
+    }
+}
+
+/// Prometheus label value of a response status
+fn status_label(status: ResponseStatus) -> &'static str {
+    match status {
+        ResponseStatus::Success => "success",
+        ResponseStatus::NotFound => "not_found",
+        ResponseStatus::Error => "error",
+        ResponseStatus::Intercepted => "intercepted",
+        ResponseStatus::Approximated => "approximated",
+        ResponseStatus::BadRequest => "bad_request",
+        ResponseStatus::Cancelled => "cancelled",
+        ResponseStatus::ShuttingDown => "shutting_down",
+    }
+}
+
+/// Escape a Prometheus label value
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+impl Default for HubMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,27 +1,60 @@
 mod types;
 mod registry;
 mod interceptor;
+mod builder;
+mod metrics;
+mod recorder;
+mod cancellation;
+mod scheduler;
+mod validation;
 
 pub use types::{
     HubScope, 
     Message, 
     ApiRequest, 
     ApiResponse, 
+    ResponseData,
     ResponseStatus,
+    ResponseStream,
+    ResolutionStep,
+    ResolutionTrace,
     Subscription,
+    SyncSubscriber,
+    AsyncSubscriber,
     Interceptor,
 };
-pub use interceptor::InterceptorManager;
-pub use registry::ApiRegistry;
+use types::SubscriptionQueue;
+pub use interceptor::{InterceptorGuard, InterceptorManager};
+pub use registry::{ApiGuard, ApiRegistry, AsyncHandler, RegistryDiff, RegistryEntry};
+use registry::{block_on_async_runtime, ApiEntry};
+pub use builder::{HubBuilder, HubConfig};
+pub use metrics::{DurationStats, HubEvent, HubMetrics, HubStats, ResolutionStats};
+pub use recorder::{RecordedRequest, RequestRecorder};
+pub use cancellation::CancellationToken;
+use metrics::Resolution;
+use scheduler::{RequestClass, RequestScheduler};
+use validation::RequestValidator;
 
 use crate::error::{HubError, Result};
-use crate::utils::{generate_uuid, current_time_millis};
+use crate::utils::{generate_uuid, current_time_millis, matches_pattern};
 
-use std::sync::{Arc, RwLock, Mutex, Weak};
-use std::collections::HashMap;
+use std::sync::{mpsc, Arc, RwLock, Mutex, Weak};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::collections::{HashMap, VecDeque};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 use std::any::Any;
+use std::future::Future;
+use std::ops::Deref;
+use std::panic::{self, AssertUnwindSafe};
 use std::thread;
+use std::time::{Duration, Instant};
 use dashmap::DashMap;
+use dashmap::mapref::entry::Entry;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use tokio::sync::broadcast;
+use tracing::{debug, error, info, warn};
 
 /// The central hub that manages routing and discovery
 pub struct Hub {
@@ -39,11 +72,231 @@ pub struct Hub {
     interceptors: Arc<InterceptorManager>,
     /// Active subscriptions
     subscriptions: Arc<DashMap<String, Vec<Subscription>>>,
+    /// Runtime configuration
+    config: RwLock<HubConfig>,
+    /// Request metrics (collected when enabled in the config)
+    metrics: Arc<HubMetrics>,
+    /// Channel of request events for live observers
+    events: broadcast::Sender<HubEvent>,
+    /// Requests being handled in singleflight mode, keyed by path and metadata,
+    /// with the channels of the identical requests waiting for their response
+    in_flight: Arc<DashMap<String, Vec<mpsc::Sender<Option<ApiResponse>>>>>,
+    /// Most recent requests that couldn't be routed, oldest first
+    dead_letters: Arc<Mutex<VecDeque<RecordedRequest>>>,
+    /// Handler called with every request that couldn't be routed
+    dead_letter_handler: Arc<RwLock<Option<DeadLetterHandler>>>,
+    /// Queue of requests waiting for a request worker
+    scheduler: Arc<RequestScheduler>,
+    /// Whether the hub is shutting down, rejecting new requests
+    shutting_down: Arc<AtomicBool>,
+    /// Number of handler calls in progress
+    active_handlers: Arc<AtomicUsize>,
+    /// Hooks called before and after every request made to the hub
+    request_hooks: Arc<RwLock<RequestHooks>>,
+}
+
+/// Handler of requests that couldn't be routed
+type DeadLetterHandler = Arc<dyn Fn(&ApiRequest) + Send + Sync>;
+
+/// A subscription matching a message's topic, as collected for delivery
+struct Subscriber {
+    id: String,
+    handler: SyncSubscriber,
+    async_handler: Option<AsyncSubscriber>,
+    queue: Option<SubscriptionQueue>,
+}
+
+/// Hook called with every request made to a hub before it is handled
+type BeforeRequestHook = Arc<dyn Fn(&ApiRequest) + Send + Sync>;
+
+/// Hook called with every request made to a hub, its response and how long it took
+type AfterRequestHook = Arc<dyn Fn(&ApiRequest, &ApiResponse, Duration) + Send + Sync>;
+
+/// Hooks of a hub's requests, in the order they were added
+#[derive(Clone, Default)]
+struct RequestHooks {
+    before: Vec<BeforeRequestHook>,
+    after: Vec<AfterRequestHook>,
+}
+
+/// Direction a request is travelling through the hub hierarchy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    /// Handled at this hub first, so it may go down to a child or up to the parent
+    Any,
+    /// Escalated from a child, so it is never routed back down
+    Up,
+    /// Delegated by the parent, so it is never escalated back up
+    Down,
+}
+
+/// A request being routed, which is only borrowed when handled by `Hub::handle_request_ref`
+enum RoutedRequest<'a> {
+    /// Owned request, whose data can be moved into a redirected request
+    Owned(ApiRequest),
+    /// Borrowed request, which can't be redirected
+    Borrowed(&'a ApiRequest),
+}
+
+impl Deref for RoutedRequest<'_> {
+    type Target = ApiRequest;
+    
+    fn deref(&self) -> &ApiRequest {
+        match self {
+            RoutedRequest::Owned(request) => request,
+            RoutedRequest::Borrowed(request) => request,
+        }
+    }
+}
+
+/// Scopes a request has spanned on its way through the hub hierarchy, checked
+/// against the scope limits of the APIs it reaches
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Reach {
+    /// Scope of the hub the request was first handled at
+    origin: HubScope,
+    /// Widest scope of the hubs it has passed through
+    widest: HubScope,
+}
+
+impl Reach {
+    /// The reach of a request first handled at a hub of the given scope
+    fn new(scope: HubScope) -> Self {
+        Reach { origin: scope, widest: scope }
+    }
+    
+    /// The reach of the request once escalated to a hub of the given scope
+    fn through(self, scope: HubScope) -> Self {
+        Reach { widest: self.widest.max(scope), ..self }
+    }
+    
+    /// Whether the request may reach an API with the given metadata (see `MIN_SCOPE_KEY` and `MAX_SCOPE_KEY`)
+    fn allows(&self, metadata: &HashMap<String, String>) -> bool {
+        scope_limit(metadata, MIN_SCOPE_KEY).is_none_or(|min| self.origin >= min)
+            && scope_limit(metadata, MAX_SCOPE_KEY).is_none_or(|max| self.widest <= max)
+    }
+}
+
+/// Get a scope limit from API metadata, ignoring values that aren't a scope
+fn scope_limit(metadata: &HashMap<String, String>, key: &str) -> Option<HubScope> {
+    metadata.get(key)?.parse().ok()
+}
+
+/// Metadata key of the narrowest scope an API may be reached from
+///
+/// Requests first handled at a hub of a narrower scope don't reach the API,
+/// even once escalated to hubs of a wide enough scope.
+pub const MIN_SCOPE_KEY: &str = "min_scope";
+
+/// Metadata key of the widest scope an API may be reached from
+///
+/// Requests that have passed through a hub of a wider scope don't reach the API,
+/// and its route isn't propagated to ancestors of a wider scope. For example, an
+/// API registered with a thread hub with a `max_scope` of `Process` can be called
+/// from its process, but never through the machine or network hubs.
+pub const MAX_SCOPE_KEY: &str = "max_scope";
+
+/// Metadata key of the JSON list of paths tried while resolving a redirected request
+///
+/// Set on requests redirected to a fallback or an approximated path, and on their
+/// responses, in the order the paths were tried (starting with the original path).
+pub const ATTEMPTED_PATHS_KEY: &str = "attempted_paths";
+
+/// Metadata key of a message's time to live in milliseconds
+///
+/// A message whose timestamp is further in the past than its time to live is
+/// dropped instead of being delivered.
+pub const TTL_KEY: &str = "ttl_ms";
+
+/// Metadata key of the number of retries a request may still take across the hierarchy
+///
+/// Set by the first hub to retry a request, from its retry budget, and decremented
+/// by every hub retrying it afterwards, so failures deep in the hierarchy aren't
+/// retried over and over by every hub on the way. Responses carry what's left of
+/// it back down. Requests made with the key set start with that budget instead.
+pub const RETRY_BUDGET_KEY: &str = "retry_budget";
+
+/// Number of request events buffered for each observer before it starts missing events
+const EVENT_CHANNEL_CAPACITY: usize = 1024;
+
+/// Number of unroutable requests kept by each hub before the oldest are dropped
+const DEAD_LETTER_CAPACITY: usize = 256;
+
+/// Time `Hub::shutdown` waits for handler calls in progress to finish
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Counts a handler call as in progress until dropped
+struct ActiveHandler<'a>(&'a AtomicUsize);
+
+impl<'a> ActiveHandler<'a> {
+    fn new(active_handlers: &'a AtomicUsize) -> Self {
+        active_handlers.fetch_add(1, Ordering::SeqCst);
+        ActiveHandler(active_handlers)
+    }
+}
+
+impl Drop for ActiveHandler<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Retries taken and left for a request, shared by the sync and async retry loops
+struct Retries {
+    /// Retries this hub makes at most
+    max_retries: usize,
+    /// Retries this hub has made
+    retries: usize,
+    /// Retries left across the hierarchy
+    budget: usize,
+}
+
+impl Retries {
+    /// Start retrying a request, with the budget in its metadata or else the hub's own
+    fn new(request: &ApiRequest, max_retries: usize, default_budget: usize) -> Self {
+        let budget = Self::budget_in(&request.metadata).unwrap_or(default_budget);
+        Retries { max_retries, retries: 0, budget }
+    }
+    
+    /// Write the budget left into the request and copy it for an attempt that can
+    /// still be retried, or return `None` if the request itself is the last attempt
+    fn next_attempt(&self, request: &mut ApiRequest) -> Option<ApiRequest> {
+        request.metadata.insert(RETRY_BUDGET_KEY.to_string(), self.budget.to_string());
+        if self.retries < self.max_retries && self.budget > 0 { request.try_clone() } else { None }
+    }
+    
+    /// Take the budget left by hubs further along out of ours, and report it in the response
+    fn settle(&mut self, response: &mut ApiResponse) {
+        self.budget = Self::budget_in(&response.metadata).unwrap_or(self.budget).min(self.budget);
+        response.metadata.insert(RETRY_BUDGET_KEY.to_string(), self.budget.to_string());
+    }
+    
+    /// Settle the response to an attempt, taking a retry if it failed and the budget allows
+    fn retry(&mut self, response: &mut ApiResponse) -> bool {
+        self.settle(response);
+        if response.status != ResponseStatus::Error || self.budget == 0 {
+            return false;
+        }
+        self.retries += 1;
+        self.budget -= 1;
+        true
+    }
+    
+    /// Get the retry budget in a request's or response's metadata, if any
+    fn budget_in(metadata: &HashMap<String, String>) -> Option<usize> {
+        metadata.get(RETRY_BUDGET_KEY)?.parse().ok()
+    }
 }
 
 impl Hub {
     /// Create a new hub with the specified scope
     pub fn new(scope: HubScope) -> Self {
+        Self::with_config(scope, HubConfig::default())
+    }
+    
+    /// Create a new hub with the specified scope and configuration
+    pub fn with_config(scope: HubScope, config: HubConfig) -> Self {
+        let scheduler = Arc::new(RequestScheduler::new(config.request_workers));
         Hub {
             id: generate_uuid(),
             scope,
@@ -52,33 +305,67 @@ impl Hub {
             child_hubs: RwLock::new(Vec::new()),
             interceptors: Arc::new(InterceptorManager::new()),
             subscriptions: Arc::new(DashMap::new()),
+            config: RwLock::new(config),
+            metrics: Arc::new(HubMetrics::new()),
+            events: broadcast::channel(EVENT_CHANNEL_CAPACITY).0,
+            in_flight: Arc::new(DashMap::new()),
+            dead_letters: Arc::new(Mutex::new(VecDeque::new())),
+            dead_letter_handler: Arc::new(RwLock::new(None)),
+            scheduler,
+            shutting_down: Arc::new(AtomicBool::new(false)),
+            active_handlers: Arc::new(AtomicUsize::new(0)),
+            request_hooks: Arc::new(RwLock::new(RequestHooks::default())),
+        }
+    }
+    
+    /// Create a new hub around an existing API registry and interceptor manager
+    ///
+    /// Hubs sharing a registry serve the same APIs (and the same routes to child
+    /// hubs), and hubs sharing a manager run the same interceptors, whichever hub
+    /// they were registered through. A registry can also be filled in before the
+    /// hub is created, e.g. with mock APIs for a test.
+    pub fn with_components(scope: HubScope, registry: Arc<ApiRegistry>, interceptors: Arc<InterceptorManager>) -> Self {
+        Hub {
+            registry,
+            interceptors,
+            ..Self::new(scope)
         }
     }
     
+    /// Create a builder for configuring a hub
+    pub fn builder(scope: HubScope) -> HubBuilder {
+        HubBuilder::new(scope)
+    }
+    
     /// Initialize a hub at the appropriate scope and connect to parent hubs
     pub fn initialize(scope: HubScope) -> Arc<Self> {
         let hub = Arc::new(Hub::new(scope));
-        
-        // Discover and connect to parent hubs based on the scope level
-        match scope {
+        Self::discover_parents(&hub);
+        hub
+    }
+    
+    /// Discover and connect to parent hubs based on the scope level
+    fn discover_parents(hub: &Arc<Hub>) {
+        match hub.scope {
             HubScope::Thread => {
                 // Thread-level hubs look for process-level hubs in the same process
-                Self::discover_and_connect_process_hub(Arc::clone(&hub));
+                Self::discover_and_connect_process_hub(Arc::clone(hub));
             },
             HubScope::Process => {
                 // Process-level hubs look for machine-level hubs on the same machine
-                Self::discover_and_connect_machine_hub(Arc::clone(&hub));
+                Self::discover_and_connect_machine_hub(Arc::clone(hub));
             },
             HubScope::Machine => {
                 // Machine-level hubs look for network-level hubs on the network
-                Self::discover_and_connect_network_hub(Arc::clone(&hub));
+                Self::discover_and_connect_network_hub(Arc::clone(hub));
             },
             HubScope::Network => {
                 // Network-level hubs are the top level, so they don't need to connect to parents
             }
+            HubScope::Custom(_) => {
+                // Custom tiers have no well-known parent, so they are connected explicitly
+            }
         }
-        
-        hub
     }
     
     /// Discover and connect to a process-level hub in the same process
@@ -101,9 +388,9 @@ impl Hub {
             let process_hubs = PROCESS_HUBS.read().unwrap();
             for (_, process_hub) in process_hubs.iter() {
                 if let Err(e) = thread_hub.connect_to_parent(Arc::clone(process_hub)) {
-                    eprintln!("Error connecting to process hub: {}", e);
+                    error!(error = %e, "Error connecting to process hub");
                 } else {
-                    println!("Thread hub {} connected to process hub {}", thread_hub.id, process_hub.id);
+                    info!(thread_hub = %thread_hub.id, process_hub = %process_hub.id, "Thread hub connected to process hub");
                     connected = true;
                     break;
                 }
@@ -112,7 +399,7 @@ impl Hub {
         
         // If no process hub found, create one
         if !connected {
-            println!("No process hub found, creating one...");
+            debug!("No process hub found, creating one");
             let process_hub = Arc::new(Hub::new(HubScope::Process));
             
             // Register the process hub
@@ -123,9 +410,9 @@ impl Hub {
             
             // Connect the thread hub to the process hub
             if let Err(e) = thread_hub.connect_to_parent(Arc::clone(&process_hub)) {
-                eprintln!("Error connecting to new process hub: {}", e);
+                error!(error = %e, "Error connecting to new process hub");
             } else {
-                println!("Thread hub {} connected to new process hub {}", thread_hub.id, process_hub.id);
+                info!(thread_hub = %thread_hub.id, process_hub = %process_hub.id, "Thread hub connected to new process hub");
             }
             
             // If we're the first process hub, start discovery of machine hubs
@@ -155,7 +442,7 @@ impl Hub {
             // Create the directory if it doesn't exist
             if !hub_dir.exists() {
                 if let Err(e) = std::fs::create_dir_all(hub_dir) {
-                    eprintln!("Error creating hub directory: {}", e);
+                    error!(error = %e, "Error creating hub directory");
                     return;
                 }
             }
@@ -166,7 +453,7 @@ impl Hub {
             
             if machine_hub_socket.exists() {
                 // Try to connect to the existing machine hub
-                println!("Found existing machine hub socket, connecting...");
+                debug!("Found existing machine hub socket, connecting");
                 
                 // In a real implementation, would connect to the Unix socket
                 // For this example, we'll just create a new machine hub
@@ -174,9 +461,9 @@ impl Hub {
                 
                 // Connect the process hub to the machine hub
                 if let Err(e) = process_hub.connect_to_parent(Arc::clone(&machine_hub)) {
-                    eprintln!("Error connecting to machine hub: {}", e);
+                    error!(error = %e, "Error connecting to machine hub");
                 } else {
-                    println!("Process hub {} connected to machine hub {}", process_hub.id, machine_hub.id);
+                    info!(process_hub = %process_hub.id, machine_hub = %machine_hub.id, "Process hub connected to machine hub");
                     connected = true;
                     
                     // Register the machine hub
@@ -189,15 +476,15 @@ impl Hub {
             
             // If no machine hub found or connection failed, create one
             if !connected {
-                println!("No machine hub found, creating one...");
+                debug!("No machine hub found, creating one");
                 let machine_hub = Arc::new(Hub::new(HubScope::Machine));
                 
                 // Create the socket file
                 let socket_file = std::fs::File::create(&machine_hub_socket);
                 if let Err(e) = socket_file {
-                    eprintln!("Error creating machine hub socket file: {}", e);
+                    error!(error = %e, "Error creating machine hub socket file");
                 } else {
-                    println!("Created machine hub socket file: {:?}", machine_hub_socket);
+                    debug!(path = ?machine_hub_socket, "Created machine hub socket file");
                 }
                 
                 // Register the machine hub
@@ -208,9 +495,9 @@ impl Hub {
                 
                 // Connect the process hub to the machine hub
                 if let Err(e) = process_hub.connect_to_parent(Arc::clone(&machine_hub)) {
-                    eprintln!("Error connecting to new machine hub: {}", e);
+                    error!(error = %e, "Error connecting to new machine hub");
                 } else {
-                    println!("Process hub {} connected to new machine hub {}", process_hub.id, machine_hub.id);
+                    info!(process_hub = %process_hub.id, machine_hub = %machine_hub.id, "Process hub connected to new machine hub");
                 }
                 
                 // If we're the first machine hub, start discovery of network hubs
@@ -227,7 +514,7 @@ impl Hub {
         // This will be triggered when the transport is started
         // The transport will automatically discover network hubs and connect to them
         
-        println!("Starting network hub discovery for machine hub {}", machine_hub.id);
+        info!(machine_hub = %machine_hub.id, "Starting network hub discovery");
         
         // In a real implementation, we would register the machine hub with a network transport
         // For now, we'll just create a network hub and connect to it directly
@@ -235,15 +522,18 @@ impl Hub {
         
         // Connect the machine hub to the network hub
         if let Err(e) = machine_hub.connect_to_parent(Arc::clone(&network_hub)) {
-            eprintln!("Error connecting to network hub: {}", e);
+            error!(error = %e, "Error connecting to network hub");
         } else {
-            println!("Machine hub {} connected to network hub {}", machine_hub.id, network_hub.id);
+            info!(machine_hub = %machine_hub.id, network_hub = %network_hub.id, "Machine hub connected to network hub");
         }
     }
     
     /// Connect to a parent hub
-    pub fn connect_to_parent(&self, parent: Arc<Hub>) -> Result<()> {
-        if parent.scope <= self.scope {
+    ///
+    /// The APIs reachable through this hub are registered with the parent (and its
+    /// ancestors) as remote routes, so requests handled there are routed down here.
+    pub fn connect_to_parent(self: &Arc<Self>, parent: Arc<Hub>) -> Result<()> {
+        if !parent.scope.is_parent_of(&self.scope) {
             return Err(HubError::InvalidState(
                 format!("Parent hub scope ({:?}) must be greater than child hub scope ({:?})",
                         parent.scope, self.scope)
@@ -251,223 +541,1679 @@ impl Hub {
         }
         
         // Set parent reference - store a weak reference to avoid circular ref
-        let mut parent_lock = self.parent_hub.write().unwrap();
-        *parent_lock = Some(Arc::downgrade(&parent));
-        
+        // (the lock is released before cloning, since clone() reads it again)
+        {
+            let mut parent_lock = self.parent_hub.write().unwrap();
+            *parent_lock = Some(Arc::downgrade(&parent));
+        }
+
         // Add this hub as a child of the parent - store a weak reference to avoid circular ref
-        let self_arc = Arc::new(self.clone());
-        let weak_self = Arc::downgrade(&self_arc);
-        
-        let mut parent_children = parent.child_hubs.write().unwrap();
-        parent_children.push(weak_self);
+        parent.child_hubs.write().unwrap().push(Arc::downgrade(self));
         
+        // Let the parent route to the APIs registered before connecting
+        for path in self.registry.paths().into_iter().chain(self.registry.remote_paths()) {
+            self.propagate_route(&path);
+        }
+
         Ok(())
     }
+
+    /// Disconnect from the parent hub, if any
+    pub fn disconnect_from_parent(&self) {
+        // Take the parent reference, leaving this hub without a parent
+        let weak_parent = self.parent_hub.write().unwrap().take();
+
+        // Remove this hub from the parent's children (dropping any dead references too)
+        if let Some(parent) = weak_parent.and_then(|weak| weak.upgrade()) {
+            let mut parent_children = parent.child_hubs.write().unwrap();
+            parent_children.retain(|child| {
+                child.upgrade().is_some_and(|child| child.id != self.id)
+            });
+        }
+    }
     
-    /// Register an API endpoint with the hub
+    /// Register an API endpoint with the hub, replacing any APIs already registered at the path
     pub fn register_api<F>(&self, path: &str, handler: F, metadata: HashMap<String, String>) 
     where
         F: Fn(&ApiRequest) -> ApiResponse + Send + Sync + 'static,
     {
-        self.registry.register(path, handler, metadata.clone());
-        
-        // Propagate to parent if exists
-        if let Some(weak_parent) = self.parent_hub.read().unwrap().as_ref() {
-            if let Some(parent) = weak_parent.upgrade() {
-                let parent_metadata = metadata.clone();
-                // Register this API with the parent hub as a remote API
-                parent.register_remote_api(path, self.id.clone(), parent_metadata);
-            }
-            // If the weak reference couldn't be upgraded, the parent hub no longer exists
-        }
+        self.registry.register(path, handler, metadata);
+        self.propagate_route(path);
     }
     
-    /// Register a remote API endpoint with this hub
-    pub fn register_remote_api(&self, path: &str, source_id: String, metadata: HashMap<String, String>) {
-        let source_id_clone = source_id.clone();
-        let metadata_clone = metadata.clone();
-        
-        // Create a handler that will forward requests to the source hub
-        self.registry.register(path, move |_request: &ApiRequest| {
-            // In a real implementation, this would forward the request to the source hub
-            // For now, this is a placeholder indicating the registration worked
-            ApiResponse {
-                data: Box::new(format!("Remote API from hub {}", source_id_clone)),
-                metadata: metadata_clone.clone(),
-                status: ResponseStatus::Success,
+    /// Register an API whose request data is checked against a JSON schema before the handler runs
+    ///
+    /// Request data is read as JSON from a string, a `serde_json::Value`, a typed
+    /// payload or a proxied HTTP request's body. Requests whose data isn't JSON or
+    /// doesn't match the schema are answered with `BadRequest` and a JSON string
+    /// listing the errors, without calling the handler. Fails with
+    /// `HubError::Config` if the schema isn't a valid JSON schema.
+    pub fn register_api_validated<F>(&self, path: &str, schema: &serde_json::Value, handler: F, metadata: HashMap<String, String>) -> Result<()>
+    where
+        F: Fn(&ApiRequest) -> ApiResponse + Send + Sync + 'static,
+    {
+        let validator = RequestValidator::compile(path, schema)?;
+        self.register_api(path, move |request: &ApiRequest| {
+            match validator.validate(request) {
+                Ok(()) => handler(request),
+                Err(response) => {
+                    debug!(path = %request.path, "Rejecting request, data does not match the schema");
+                    response
+                }
             }
         }, metadata);
+        Ok(())
     }
     
-    /// Handle an API request with cascading search and interception
-    pub fn handle_request(&self, request: ApiRequest) -> ApiResponse {
-        // 1. Check for interception
-        if let Some(intercepted) = self.interceptors.try_intercept_api_request(&request) {
-            let mut response = intercepted;
-            response.metadata.insert("intercepted".to_string(), "true".to_string());
-            response.status = ResponseStatus::Intercepted;
-            return response;
-        }
-        
-        // 2. Check local registry
-        if let Some(api) = self.registry.lookup(&request.path) {
-            return (api.handler)(&request);
-        }
-        
-        // 3. Escalate to parent hub if available
-        if let Some(weak_parent) = self.parent_hub.read().unwrap().as_ref() {
-            if let Some(parent) = weak_parent.upgrade() {
-                return parent.handle_request(request);
-            }
-            // If the weak reference couldn't be upgraded, the parent hub no longer exists
-        }
-        
-        // 4. Try fallback
-        if let Some((fallback_path, _)) = self.registry.lookup_fallback(&request.path) {
-            let mut fallback_request = ApiRequest {
-                path: fallback_path.clone(),
-                data: request.data,
-                metadata: request.metadata.clone(),
-                sender_id: request.sender_id.clone(),
-            };
-            fallback_request.metadata.insert("original_path".to_string(), request.path.clone());
-            return self.handle_request(fallback_request);
-        }
-        
-        // 5. Try approximation
-        if let Some((similar_path, _)) = self.registry.lookup_similar(&request.path, 0.8) {
-            let mut approx_request = ApiRequest {
-                path: similar_path.clone(),
-                data: request.data,
-                metadata: request.metadata.clone(),
-                sender_id: request.sender_id.clone(),
-            };
-            approx_request.metadata.insert("original_path".to_string(), request.path.clone());
-            let mut response = self.handle_request(approx_request);
-            response.metadata.insert("approximated".to_string(), "true".to_string());
-            response.status = ResponseStatus::Approximated;
-            return response;
+    /// Register an API with this hub and every hub below it in the hierarchy
+    ///
+    /// Handlers can't be cloned, so `handler_factory` is called once per hub to
+    /// create the handler registered with it. Each hub then answers requests for
+    /// the path itself rather than routing them to a child. Hubs connected as
+    /// children later don't get the API.
+    pub fn register_api_broadcast<M, F>(&self, path: &str, handler_factory: M, metadata: HashMap<String, String>)
+    where
+        M: Fn() -> F,
+        F: Fn(&ApiRequest) -> ApiResponse + Send + Sync + 'static,
+    {
+        self.register_api_in_subtree(path, &handler_factory, &metadata);
+    }
+    
+    /// Register an API created by `handler_factory` with the hubs below this one, then this one
+    fn register_api_in_subtree<F>(&self, path: &str, handler_factory: &dyn Fn() -> F, metadata: &HashMap<String, String>)
+    where
+        F: Fn(&ApiRequest) -> ApiResponse + Send + Sync + 'static,
+    {
+        let children: Vec<Arc<Hub>> = self.child_hubs.read().unwrap().iter().filter_map(Weak::upgrade).collect();
+        for child in children {
+            child.register_api_in_subtree(path, handler_factory, metadata);
         }
         
-        // 6. Not found
-        ApiResponse {
-            data: Box::new(()),
-            metadata: HashMap::new(),
-            status: ResponseStatus::NotFound,
-        }
+        debug!(hub = %self.id, path, "Registering broadcast API");
+        self.register_api(path, handler_factory(), metadata.clone());
     }
     
-    /// Register a message interceptor for a specific topic
-    pub fn register_interceptor<T, R, F>(&self, topic: &str, handler: F, priority: i32) -> String
+    /// Register an async API endpoint with the hub, replacing any APIs already registered at the path
+    ///
+    /// The handler reads what it needs from the request and returns a future of
+    /// the response, so it can await I/O. `handle_request_async` awaits the future
+    /// directly; `handle_request` and requests routed from other hubs block until
+    /// it completes on a runtime shared by every hub.
+    pub fn register_api_async<F, Fut>(&self, path: &str, handler: F, metadata: HashMap<String, String>)
     where
-        T: 'static + Send + Sync,
-        R: 'static + Send + Sync,
-        F: Fn(&Message<T>) -> Option<R> + Send + Sync + 'static,
+        F: Fn(&ApiRequest) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ApiResponse> + Send + 'static,
     {
-        self.interceptors.register(topic, handler, priority)
+        self.registry.register_async(path, handler, metadata);
+        self.propagate_route(path);
     }
     
-    /// Register an API interceptor for a specific path
-    pub fn register_api_interceptor<F>(&self, path: &str, handler: F, priority: i32) -> String
+    /// Add an API handler to the chain of handlers at a path
+    ///
+    /// Requests for the path try each handler, highest priority first, until one
+    /// returns something other than `NotFound`. Handlers with equal priorities are
+    /// tried in the order they were registered; `register_api` replaces the whole
+    /// chain with a handler at priority 0.
+    pub fn register_api_with_priority<F>(&self, path: &str, handler: F, metadata: HashMap<String, String>, priority: i32)
     where
-        F: Fn(&ApiRequest) -> Option<ApiResponse> + Send + Sync + 'static,
+        F: Fn(&ApiRequest) -> ApiResponse + Send + Sync + 'static,
     {
-        self.interceptors.register_api_interceptor(path, handler, priority)
+        self.registry.register_with_priority(path, handler, metadata, priority);
+        self.propagate_route(path);
     }
     
-    /// Subscribe to messages matching a pattern
-    pub fn subscribe<F>(&self, pattern: &str, callback: F, priority: i32) -> String
+    /// Register an API endpoint with the hub, failing if the path is already taken
+    ///
+    /// Unlike `register_api`, which replaces an existing handler, this returns
+    /// `HubError::AlreadyRegistered` so two services can't silently claim the same path.
+    pub fn try_register_api<F>(&self, path: &str, handler: F, metadata: HashMap<String, String>) -> Result<()>
     where
-        F: Fn(&Message<Box<dyn Any + Send + Sync>>) -> Option<Box<dyn Any + Send + Sync>> + Send + Sync + 'static,
+        F: Fn(&ApiRequest) -> ApiResponse + Send + Sync + 'static,
     {
-        let id = generate_uuid();
-        let subscription = Subscription {
-            id: id.clone(),
-            priority,
-            handler: Arc::new(Mutex::new(Box::new(callback))),
-        };
-        
-        self.subscriptions
-            .entry(pattern.to_string())
-            .or_default()
-            .push(subscription);
-        
-        // Sort subscriptions by priority (highest first)
-        if let Some(mut subs) = self.subscriptions.get_mut(pattern) {
-            subs.sort_by(|a, b| b.priority.cmp(&a.priority));
-        }
-        
-        id
+        self.registry.try_register(path, handler, metadata)?;
+        self.propagate_route(path);
+        Ok(())
     }
     
-    /// Publish a message with interception capability
-    pub fn publish<T, R>(&self, topic: &str, data: T, metadata: HashMap<String, String>) -> Option<R>
+    /// Register an API endpoint that is unregistered when the returned guard is dropped
+    ///
+    /// Like `register_api`, this replaces any APIs already registered at the path.
+    pub fn register_api_with_guard<F>(&self, path: &str, handler: F, metadata: HashMap<String, String>) -> ApiGuard
     where
-        T: 'static + Send + Sync + Clone,
-        R: 'static + Send + Sync,
+        F: Fn(&ApiRequest) -> ApiResponse + Send + Sync + 'static,
     {
-        let message = Message {
-            topic: topic.to_string(),
-            data: data.clone(),
-            metadata: metadata.clone(),
-            sender_id: self.id.clone(), // Set the sender ID to this hub's ID
-            timestamp: current_time_millis(),
+        self.register_api(path, handler, metadata);
+        ApiGuard::new(Arc::clone(&self.registry), path.to_string())
+    }
+    
+    /// Remove every API handler registered at a path pattern
+    ///
+    /// Returns whether an API was removed. Ancestors keep routing the path to
+    /// this hub, which answers `NotFound` until an API is registered there again.
+    pub fn unregister_api(&self, path: &str) -> bool {
+        self.registry.unregister(path)
+    }
+    
+    /// Record a path reachable through this hub as a remote route in every ancestor
+    fn propagate_route(&self, path: &str) {
+        let parent = self.parent_hub.read().unwrap().as_ref().and_then(|weak| weak.upgrade());
+        // If the weak reference couldn't be upgraded, the parent hub no longer exists
+        let Some(parent) = parent else {
+            return;
         };
         
-        // Try to intercept the message
-        if let Some(result) = self.interceptors.try_intercept_message::<T, R>(&message) {
-            return Some(result);
+        // APIs limited to a narrower scope aren't routed to from wider hubs
+        if let Some(max_scope) = self.route_max_scope(path).filter(|max_scope| parent.scope > *max_scope) {
+            debug!(path, parent = %parent.id, %max_scope, "Not propagating route beyond its scope");
+            return;
         }
         
-        // Create an Any-boxed version of the message for subscriptions
-        let any_message = Message {
-            topic: message.topic.clone(),
-            data: Box::new(data.clone()) as Box<dyn std::any::Any + Send + Sync>,
-            metadata: message.metadata.clone(),
-            sender_id: message.sender_id.clone(),
-            timestamp: message.timestamp,
-        };
-        
-        // Dispatch to any matching subscriptions
-        let matching_topics: Vec<_> = self.subscriptions
-            .iter()
-            .filter(|entry| Self::match_topic_pattern(&entry.key(), topic))
-            .map(|entry| entry.key().clone())
-            .collect();
-            
-        for topic_pattern in matching_topics {
-            if let Some(subs) = self.subscriptions.get(&topic_pattern) {
-                for subscription in subs.iter() {
-                    let handler = subscription.handler.lock().unwrap();
-                    let _ = handler(&any_message);
+        // The parent holds the weak reference to this hub registered when connecting
+        let weak_self = parent.child_hubs.read().unwrap().iter()
+            .find(|child| child.upgrade().is_some_and(|child| child.id == self.id))
+            .cloned();
+        if let Some(weak_self) = weak_self {
+            debug!(path, parent = %parent.id, "Propagating route to parent");
+            parent.registry.register_remote(path, weak_self);
+            parent.propagate_route(path);
+        }
+    }
+    
+    /// Get the widest scope the API at a path may be reached from, asking the child
+    /// serving the path if it isn't registered with this hub
+    fn route_max_scope(&self, path: &str) -> Option<HubScope> {
+        match self.registry.get_metadata(path) {
+            Some(metadata) => {
+                let max_scope = scope_limit(&metadata, MAX_SCOPE_KEY);
+                if max_scope.is_none() && metadata.contains_key(MAX_SCOPE_KEY) {
+                    warn!(path, max_scope = %metadata[MAX_SCOPE_KEY], "Ignoring invalid scope limit");
                 }
+                max_scope
             }
+            None => self.registry.lookup_remote(path)?.route_max_scope(path),
+        }
+    }
+    
+    /// Remove every API, interceptor and subscription registered with this hub
+    ///
+    /// Links to the parent and child hubs are kept, as are the routes to APIs
+    /// registered with the children, so a shared hub can be reset between test
+    /// cases without rebuilding the hierarchy.
+    pub fn clear(&self) {
+        self.registry.clear();
+        self.interceptors.clear();
+        self.subscriptions.clear();
+        debug!(hub = %self.id, "Cleared hub registrations");
+    }
+    
+    /// Get the metadata of an API registered with this hub, without invoking it
+    pub fn api_metadata(&self, path: &str) -> Option<HashMap<String, String>> {
+        self.registry.get_metadata(path)
+    }
+    
+    /// Get the APIs registered with this hub without their handlers, to restart with
+    ///
+    /// Serialize the entries before the service stops and pass them to
+    /// `import_registry` once it restarts.
+    pub fn export_registry(&self) -> Vec<RegistryEntry> {
+        self.registry.export()
+    }
+    
+    /// Record the APIs a restarted hub is expected to serve, from `export_registry`
+    ///
+    /// Handlers can't be restored, so nothing is registered; `registry_diff` tells
+    /// which expected paths still have to be registered again.
+    pub fn import_registry(&self, entries: Vec<RegistryEntry>) {
+        debug!(hub = %self.id, apis = entries.len(), "Imported registry snapshot");
+        self.registry.import(entries);
+    }
+    
+    /// Get the paths recorded by `import_registry`, sorted
+    pub fn expected_paths(&self) -> Vec<String> {
+        self.registry.expected_paths()
+    }
+    
+    /// Compare the paths registered with this hub against those recorded by `import_registry`
+    pub fn registry_diff(&self) -> RegistryDiff {
+        self.registry.diff()
+    }
+    
+    /// Get a copy of the hub's configuration
+    pub fn config(&self) -> HubConfig {
+        self.config.read().unwrap().clone()
+    }
+    
+    /// Set the minimum similarity for a path to be used as an approximation
+    ///
+    /// A threshold of `1.0` effectively disables fuzzy matching, while lower values
+    /// allow looser matches.
+    pub fn set_similarity_threshold(&self, threshold: f64) {
+        self.config.write().unwrap().similarity_threshold = threshold;
+    }
+    
+    /// Enable or disable retrying failed requests at the API's registered fallback
+    pub fn set_fallback_enabled(&self, enabled: bool) {
+        self.config.write().unwrap().enable_fallback = enabled;
+    }
+    
+    /// Enable or disable routing unmatched requests to a similar path
+    ///
+    /// Disabling both fallback and approximation gives strict exact-match routing,
+    /// which is safer for security-sensitive APIs.
+    pub fn set_approximation_enabled(&self, enabled: bool) {
+        self.config.write().unwrap().enable_approximation = enabled;
+    }
+    
+    /// Enable or disable sharing one handler execution between concurrent identical requests
+    ///
+    /// Requests with the same path and metadata that arrive while one of them is
+    /// being handled wait for its response and get a copy of it. Request data
+    /// isn't compared, so only enable this for APIs whose response doesn't depend
+    /// on it. Responses whose data can't be cloned (see `ApiResponse::try_clone`)
+    /// can't be shared, and the waiting requests are handled on their own.
+    pub fn set_singleflight_enabled(&self, enabled: bool) {
+        self.config.write().unwrap().singleflight = enabled;
+    }
+    
+    /// Limit the number of requests this hub handles at once, queueing the rest
+    ///
+    /// Waiting requests made to this hub and those escalated from its children are
+    /// admitted in turn, so a flood of escalations can't starve local requests.
+    /// Requests still run on the thread that made them, and async ones wait without
+    /// blocking it; those delegated by the parent and those made while handling a
+    /// request at this hub aren't queued.
+    /// Defaults to 0, for no limit.
+    pub fn set_request_workers(&self, workers: usize) {
+        self.config.write().unwrap().request_workers = workers;
+        self.scheduler.set_workers(workers);
+    }
+    
+    /// Set the number of times this hub retries a request answered with `Error`
+    ///
+    /// Applies to requests made to this hub and to those routed through it, until
+    /// the request's retry budget (see `set_retry_budget`) runs out. Requests are
+    /// retried at once, and only if their data can be copied (see
+    /// `ApiRequest::try_clone`); those handled by `handle_request_ref` aren't
+    /// retried. Defaults to 0, for no retries.
+    pub fn set_max_retries(&self, retries: usize) {
+        self.config.write().unwrap().max_retries = retries;
+    }
+    
+    /// Set the number of retries a request made to this hub may take across the hierarchy (3 by default)
+    ///
+    /// The budget is shared by every hub the request passes through, carried in its
+    /// `RETRY_BUDGET_KEY` metadata entry, so the total number of attempts stays
+    /// bounded however many hubs retry it.
+    pub fn set_retry_budget(&self, budget: usize) {
+        self.config.write().unwrap().retry_budget = budget;
+    }
+    
+    /// Get a snapshot of the request metrics collected by this hub
+    pub fn stats(&self) -> HubStats {
+        HubStats {
+            queue_depth: self.scheduler.depth(),
+            ..self.metrics.snapshot()
         }
+    }
+    
+    /// Render the request metrics collected by this hub in the Prometheus text format
+    ///
+    /// Serve it from a `/metrics` endpoint for Prometheus to scrape. The counters
+    /// stay at zero unless metrics are enabled.
+    pub fn export_prometheus(&self) -> String {
+        self.stats().to_prometheus()
+    }
+    
+    /// Subscribe to an event for every request this hub handles
+    ///
+    /// Each subscriber gets its own copy of the events. A subscriber that falls
+    /// more than 1024 events behind misses the oldest ones.
+    pub fn subscribe_events(&self) -> broadcast::Receiver<HubEvent> {
+        self.events.subscribe()
+    }
+    
+    /// Stop accepting requests and wait for the handler calls in progress to finish
+    ///
+    /// Requests made to the hub from now on, including those escalated from its
+    /// children or delegated by its parent, are answered with `ShuttingDown`
+    /// without being handled. Waits at most 30 seconds, and returns whether every
+    /// handler call finished in time.
+    pub fn shutdown(&self) -> bool {
+        self.shutdown_with_timeout(SHUTDOWN_TIMEOUT)
+    }
+    
+    /// Stop accepting requests and wait at most `timeout` for the handler calls in progress to finish
+    ///
+    /// Returns whether every handler call finished in time.
+    pub fn shutdown_with_timeout(&self, timeout: Duration) -> bool {
+        self.shutting_down.store(true, Ordering::SeqCst);
+        info!(hub = %self.id, "Shutting down hub");
         
-        // If not intercepted and we have a parent, propagate to parent
-        if let Some(weak_parent) = self.parent_hub.read().unwrap().as_ref() {
-            if let Some(parent) = weak_parent.upgrade() {
-                // In a real implementation, we would need to serialize data for transport
-                // For now, we simply clone and forward the message to the parent
-                // Note: This won't actually work because type parameters are lost,
-                // but in a real impl this would use serialization to preserve type info
-                let _result = parent.publish::<T, R>(topic, data, metadata);
+        let started = Instant::now();
+        loop {
+            let active = self.active_handlers.load(Ordering::SeqCst);
+            if active == 0 {
+                debug!(hub = %self.id, "Hub drained");
+                return true;
             }
-            // If the weak reference couldn't be upgraded, the parent hub no longer exists
+            if started.elapsed() >= timeout {
+                warn!(hub = %self.id, active, "Timed out waiting for handlers to finish");
+                return false;
+            }
+            thread::sleep(Duration::from_millis(10));
         }
-        
-        None
     }
     
-    /// Helper function to match a topic against a pattern
-    fn match_topic_pattern(pattern: &str, topic: &str) -> bool {
-        // Simple pattern matching implementation
-        // In a real system, this would handle wildcards, etc.
-        // For now, just check for exact match or wildcard
-        if pattern == "#" || pattern == "*" {
-            return true;
+    /// Whether the hub is shutting down (see `shutdown`)
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::SeqCst)
+    }
+    
+    /// Response to a request made once the hub is shutting down
+    fn shutting_down_response(&self, request: &ApiRequest) -> ApiResponse {
+        debug!(hub = %self.id, path = %request.path, "Rejecting request, hub is shutting down");
+        ApiResponse {
+            data: Box::new("Hub is shutting down".to_string()),
+            metadata: HashMap::new(),
+            status: ResponseStatus::ShuttingDown,
+        }
+    }
+    
+    /// Handle an API request with cascading search and interception
+    ///
+    /// The request is consumed, since a failing API's fallback and approximated
+    /// paths are tried with a rewritten request that takes over its data. Use
+    /// `handle_request_ref` to keep the request, e.g. to retry it or send it on.
+    pub fn handle_request(&self, request: ApiRequest) -> ApiResponse {
+        let hooked = self.before_request(&request);
+        let started = Instant::now();
+        let response = self.handle_unhooked(request);
+        if let Some(request) = hooked {
+            self.after_request(&request, &response, started.elapsed());
+        }
+        response
+    }
+    
+    /// Handle an API request like `handle_request`, without calling the request hooks
+    fn handle_unhooked(&self, request: ApiRequest) -> ApiResponse {
+        let intercepted = self.response_interception(&request);
+        let mut response = self.dispatch_request(RoutedRequest::Owned(request), None, Direction::Any, Reach::new(self.scope));
+        if let Some(request) = intercepted {
+            self.interceptors.intercept_api_response(&request, &mut response);
+        }
+        response
+    }
+    
+    /// Handle an API request without consuming it
+    ///
+    /// The request is intercepted and routed to a matching API in this hub, a child
+    /// or the parent just like with `handle_request`. Fallbacks and approximation
+    /// need to take over the request's data, so they are skipped: a failing API's
+    /// response is returned as is, and a path without a matching API is `NotFound`.
+    pub fn handle_request_ref(&self, request: &ApiRequest) -> ApiResponse {
+        let hooked = self.before_request(request).is_some();
+        let started = Instant::now();
+        let mut response = self.dispatch_request(RoutedRequest::Borrowed(request), None, Direction::Any, Reach::new(self.scope));
+        self.interceptors.intercept_api_response(request, &mut response);
+        if hooked {
+            self.after_request(request, &response, started.elapsed());
+        }
+        response
+    }
+    
+    /// Add a hook called with every request made to this hub, before it is handled
+    ///
+    /// Hooks fire once per call to `handle_request` (or any of its variants),
+    /// whatever the path and whether or not an API is found, but not for requests
+    /// reaching the hub from its children or parent. Unlike interceptors they
+    /// can't change the request or its response, which makes them suited to
+    /// auditing and tracing. Hooks run on the thread handling the request, in the
+    /// order they were added, so they should be quick.
+    pub fn on_before_request<F>(&self, hook: F)
+    where
+        F: Fn(&ApiRequest) + Send + Sync + 'static,
+    {
+        self.request_hooks.write().unwrap().before.push(Arc::new(hook));
+    }
+    
+    /// Add a hook called with every request made to this hub once it is answered
+    ///
+    /// The hook gets the response the caller gets and the time taken to handle
+    /// the request. Requests handled by `handle_request_ref` are passed as is;
+    /// others were consumed by their handler, so the hook gets their path,
+    /// metadata and sender without their data. See `on_before_request`.
+    pub fn on_after_request<F>(&self, hook: F)
+    where
+        F: Fn(&ApiRequest, &ApiResponse, Duration) + Send + Sync + 'static,
+    {
+        self.request_hooks.write().unwrap().after.push(Arc::new(hook));
+    }
+    
+    /// Call the before-request hooks, returning the request to pass to the after-request hooks if there are any
+    fn before_request(&self, request: &ApiRequest) -> Option<ApiRequest> {
+        // Cloned so hooks can add hooks without deadlocking
+        let hooks = self.request_hooks.read().unwrap().clone();
+        for hook in &hooks.before {
+            hook(request);
+        }
+        (!hooks.after.is_empty()).then(|| Self::without_data(request))
+    }
+    
+    /// Call the after-request hooks with an answered request
+    fn after_request(&self, request: &ApiRequest, response: &ApiResponse, elapsed: Duration) {
+        let hooks = self.request_hooks.read().unwrap().after.clone();
+        for hook in &hooks {
+            hook(request, response, elapsed);
+        }
+    }
+    
+    /// Copy a request's path, metadata and sender, without its data
+    fn without_data(request: &ApiRequest) -> ApiRequest {
+        ApiRequest {
+            path: request.path.clone(),
+            data: Box::new(()),
+            metadata: request.metadata.clone(),
+            sender_id: request.sender_id.clone(),
+        }
+    }
+    
+    /// Get the request to pass to the response interceptors for a request's path, if there are any
+    ///
+    /// The request's data goes to the handler, so the copy has the path, metadata
+    /// and sender of the request but no data.
+    fn response_interception(&self, request: &ApiRequest) -> Option<ApiRequest> {
+        self.interceptors.has_response_interceptors(&request.path).then(|| Self::without_data(request))
+    }
+    
+    /// Handle an API request from async code
+    ///
+    /// When the first of the APIs registered with this hub for the path is async,
+    /// the request is intercepted and the APIs are tried in priority order on the
+    /// current task, awaiting async handlers rather than blocking on them. Any
+    /// other request, including one with request mutators, is handled by
+    /// `handle_request` on tokio's blocking thread pool, so sync handlers and
+    /// routing through other hubs don't stall the runtime, as are fallbacks.
+    /// Must be called within a tokio runtime.
+    pub async fn handle_request_async(&self, request: ApiRequest) -> ApiResponse {
+        let hooked = self.before_request(&request);
+        let started = Instant::now();
+        let response = self.handle_unhooked_async(request).await;
+        if let Some(request) = hooked {
+            self.after_request(&request, &response, started.elapsed());
+        }
+        response
+    }
+    
+    /// Handle an API request like `handle_request_async`, without calling the request hooks
+    async fn handle_unhooked_async(&self, request: ApiRequest) -> ApiResponse {
+        if self.is_shutting_down() {
+            return self.shutting_down_response(&request);
+        }
+        let reach = Reach::new(self.scope);
+        let chain: Vec<ApiEntry> = self.registry.lookup_chain(&request.path)
+            .into_iter()
+            .filter(|api| reach.allows(&api.metadata))
+            .collect();
+        // Shared handler executions are only tracked on the blocking path
+        let awaitable = chain.first().is_some_and(|api| api.async_handler.is_some())
+            && !self.interceptors.has_request_mutators(&request.path)
+            && !self.config.read().unwrap().singleflight;
+        if !awaitable {
+            let hub = self.clone();
+            return Self::run_blocking(move || hub.handle_unhooked(request)).await;
+        }
+        
+        self.scheduler.run_async(RequestClass::Local, async move {
+            let path = request.path.clone();
+            let sender_id = request.sender_id.clone();
+            let intercepted = self.response_interception(&request);
+            let (max_retries, retry_budget) = {
+                let config = self.config.read().unwrap();
+                (config.max_retries, config.retry_budget)
+            };
+            let started = Instant::now();
+            let mut response = if max_retries == 0 {
+                self.route_request_async(request, chain, reach).await
+            } else {
+                self.route_with_retries_async(request, chain, reach, max_retries, retry_budget).await
+            };
+            self.observe(path, sender_id, response.status, started);
+            if let Some(request) = intercepted {
+                self.interceptors.intercept_api_response(&request, &mut response);
+            }
+            response
+        }).await
+    }
+    
+    /// Route a request from async code, retrying it like `route_with_retries`
+    async fn route_with_retries_async(&self, mut request: ApiRequest, chain: Vec<ApiEntry>, reach: Reach, max_retries: usize, default_budget: usize) -> ApiResponse {
+        let mut retries = Retries::new(&request, max_retries, default_budget);
+        loop {
+            let Some(attempt) = retries.next_attempt(&mut request) else {
+                let mut response = self.route_request_async(request, chain, reach).await;
+                retries.settle(&mut response);
+                return response;
+            };
+            
+            let mut response = self.route_request_async(attempt, chain.clone(), reach).await;
+            if !retries.retry(&mut response) {
+                return response;
+            }
+            debug!(hub = %self.id, path = %request.path, retries = retries.retries, budget = retries.budget, "Request failed, retrying");
+        }
+    }
+    
+    /// Route a request from async code, given the reachable APIs registered for
+    /// its path, recording which step resolved it
+    async fn route_request_async(&self, request: ApiRequest, chain: Vec<ApiEntry>, reach: Reach) -> ApiResponse {
+        let (response, resolution) = self.resolve_request_async(request, chain, reach).await;
+        if self.config.read().unwrap().metrics_enabled {
+            self.metrics.record_resolution(resolution);
+        }
+        response
+    }
+    
+    /// Resolve a request at this hub from async code, given the reachable APIs registered for its path
+    ///
+    /// Mirrors the interception and local registry steps of `resolve_request`.
+    async fn resolve_request_async(&self, request: ApiRequest, chain: Vec<ApiEntry>, reach: Reach) -> (ApiResponse, Resolution) {
+        if let Some(mut response) = self.interceptors.try_intercept_api_request(&request) {
+            response.metadata.insert("intercepted".to_string(), "true".to_string());
+            response.status = ResponseStatus::Intercepted;
+            return (response, Resolution::Intercepted);
+        }
+        
+        let mut answered = None;
+        for api in chain {
+            let _active = ActiveHandler::new(&self.active_handlers);
+            let response = Self::invoke_handler_async(&api, &request).await;
+            let found = response.status != ResponseStatus::NotFound;
+            answered = Some((api, response));
+            if found {
+                break;
+            }
+        }
+        let Some((api, response)) = answered else {
+            unreachable!("requests are only resolved asynchronously when an async API is registered for them");
+        };
+        
+        let config = self.config();
+        let fallback_path = api.fallback_path.filter(|_| {
+            config.enable_fallback
+                && response.status == ResponseStatus::Error
+                && !request.metadata.contains_key("fallback_from")
+        });
+        let Some(fallback_path) = fallback_path else {
+            return (response, Resolution::Local);
+        };
+        
+        debug!(path = %request.path, fallback = %fallback_path, "API failed, trying its fallback");
+        let failed_path = request.path.clone();
+        let mut fallback_request = Self::redirect_request(request, fallback_path);
+        fallback_request.metadata.insert("fallback_from".to_string(), failed_path);
+        let hub = self.clone();
+        let admitted = self.scheduler.is_admitted();
+        let response = Self::run_blocking(move || {
+            hub.scheduler.run_on_thread(admitted, || hub.route_redirected(fallback_request, None, Direction::Any, reach))
+        }).await;
+        (response, Resolution::Fallback)
+    }
+    
+    /// Handle a request on tokio's blocking thread pool, passing on a panic
+    async fn run_blocking<F>(handle: F) -> ApiResponse
+    where
+        F: FnOnce() -> ApiResponse + Send + 'static,
+    {
+        match tokio::task::spawn_blocking(handle).await {
+            Ok(response) => response,
+            Err(e) => panic::resume_unwind(e.into_panic()),
+        }
+    }
+    
+    /// Handle an API request that the caller can cancel while it's in flight
+    ///
+    /// The request is handled like with `handle_request`, on the calling thread.
+    /// Handlers get the token from `CancellationToken::current` and can return
+    /// early once it fires. If the token has fired by the time the request is
+    /// answered (or before it's handled at all), the response is `Cancelled`.
+    pub fn handle_request_cancellable(&self, request: ApiRequest, token: &CancellationToken) -> ApiResponse {
+        let path = request.path.clone();
+        if !token.is_cancelled() {
+            let _current = token.enter();
+            let response = self.handle_request(request);
+            if !token.is_cancelled() {
+                return response;
+            }
+        }
+        
+        debug!(path = %path, "Request cancelled");
+        ApiResponse {
+            data: Box::new("Request cancelled".to_string()),
+            metadata: HashMap::new(),
+            status: ResponseStatus::Cancelled,
+        }
+    }
+    
+    /// Handle several API requests, returning the responses in the same order
+    ///
+    /// Requests are split across a small pool of scoped worker threads (one per
+    /// available core), so a slow handler doesn't hold up the rest of the batch.
+    pub fn handle_batch(&self, requests: Vec<ApiRequest>) -> Vec<ApiResponse> {
+        let workers = thread::available_parallelism()
+            .map_or(1, |n| n.get())
+            .min(requests.len());
+        
+        if workers <= 1 {
+            return requests.into_iter().map(|request| self.handle_request(request)).collect();
+        }
+        
+        // Split into contiguous chunks so concatenating the results preserves order
+        let chunk_size = requests.len().div_ceil(workers);
+        let mut chunks = Vec::with_capacity(workers);
+        let mut requests = requests.into_iter();
+        loop {
+            let chunk: Vec<ApiRequest> = requests.by_ref().take(chunk_size).collect();
+            if chunk.is_empty() {
+                break;
+            }
+            chunks.push(chunk);
+        }
+        
+        thread::scope(|scope| {
+            let handles: Vec<_> = chunks
+                .into_iter()
+                .map(|chunk| {
+                    scope.spawn(move || {
+                        chunk.into_iter().map(|request| self.handle_request(request)).collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap_or_else(|e| panic::resume_unwind(e)))
+                .collect()
+        })
+    }
+    
+    /// Handle a request with a remaining escalation budget, recording metrics
+    fn dispatch_request(&self, request: RoutedRequest, hops_left: Option<usize>, direction: Direction, reach: Reach) -> ApiResponse {
+        if self.is_shutting_down() {
+            return self.shutting_down_response(&request);
+        }
+        
+        // Requests delegated by the parent already hold one of its workers, which
+        // could wait on this hub's workers for good if they had to queue here
+        let _admission = match direction {
+            Direction::Any => self.scheduler.admit(RequestClass::Local),
+            Direction::Up => self.scheduler.admit(RequestClass::Escalated),
+            Direction::Down => None,
+        };
+        let (metrics_enabled, singleflight, max_retries, retry_budget) = {
+            let config = self.config.read().unwrap();
+            (config.metrics_enabled, config.singleflight, config.max_retries, config.retry_budget)
+        };
+        let observed = self.events.receiver_count() > 0;
+        let route_once = |request| if singleflight {
+            self.route_shared(request, hops_left, direction, reach)
+        } else {
+            self.route_request(request, hops_left, direction, reach)
+        };
+        let route = |request| if max_retries == 0 {
+            route_once(request)
+        } else {
+            self.route_with_retries(request, max_retries, retry_budget, route_once)
+        };
+        if !metrics_enabled && !observed {
+            return route(request);
+        }
+        
+        let path = request.path.clone();
+        let sender_id = request.sender_id.clone();
+        let started = Instant::now();
+        let response = route(request);
+        self.observe(path, sender_id, response.status, started);
+        response
+    }
+    
+    /// Route a request, retrying it while it fails and its retry budget allows
+    ///
+    /// Every attempt but the last routes a copy of the request, carrying the budget
+    /// left in its metadata. Hubs further along take their own retries out of it
+    /// and report what's left in their response, so it's taken into account here.
+    fn route_with_retries<'a, F>(&self, request: RoutedRequest<'a>, max_retries: usize, default_budget: usize, route: F) -> ApiResponse
+    where
+        F: Fn(RoutedRequest<'a>) -> ApiResponse,
+    {
+        // Borrowed requests can't carry the budget
+        let RoutedRequest::Owned(mut request) = request else {
+            return route(request);
+        };
+        
+        let mut retries = Retries::new(&request, max_retries, default_budget);
+        loop {
+            // The last attempt takes the request over
+            let Some(attempt) = retries.next_attempt(&mut request) else {
+                let mut response = route(RoutedRequest::Owned(request));
+                retries.settle(&mut response);
+                return response;
+            };
+            
+            let mut response = route(RoutedRequest::Owned(attempt));
+            if !retries.retry(&mut response) {
+                return response;
+            }
+            debug!(hub = %self.id, path = %request.path, retries = retries.retries, budget = retries.budget, "Request failed, retrying");
+        }
+    }
+    
+    /// Record a handled request in the metrics and send it to event observers,
+    /// if either is enabled
+    fn observe(&self, path: String, sender_id: String, status: ResponseStatus, started: Instant) {
+        let elapsed = started.elapsed();
+        if self.config.read().unwrap().metrics_enabled {
+            self.metrics.record(&path, status);
+            self.metrics.record_duration(elapsed);
+        }
+        if self.events.receiver_count() > 0 {
+            // Sending only fails if every observer has unsubscribed in the meantime
+            let _ = self.events.send(HubEvent {
+                path,
+                status,
+                sender_id,
+                elapsed_ms: elapsed.as_millis() as u64,
+            });
+        }
+    }
+    
+    /// Route a request, sharing the response with identical requests that arrive
+    /// while it is being handled
+    fn route_shared(&self, request: RoutedRequest, hops_left: Option<usize>, direction: Direction, reach: Reach) -> ApiResponse {
+        let key = Self::singleflight_key(&request, reach);
+        
+        // The map entry is locked while a waiter is added, so the handling request
+        // can't miss a waiter when it removes the entry
+        match self.in_flight.entry(key.clone()) {
+            Entry::Occupied(mut waiting) => {
+                let (tx, rx) = mpsc::channel();
+                waiting.get_mut().push(tx);
+                drop(waiting);
+                
+                // Nothing is received if the response couldn't be cloned, or if
+                // handling it panicked; handle the request separately then
+                if let Ok(Some(response)) = rx.recv() {
+                    debug!(path = %request.path, "Shared response of an identical request");
+                    return response;
+                }
+                return self.route_request(request, hops_left, direction, reach);
+            }
+            Entry::Vacant(vacant) => {
+                vacant.insert(Vec::new());
+            }
+        }
+        
+        // Removes the entry even if routing panics, so waiters aren't left hanging
+        struct Flight<'a> {
+            in_flight: &'a DashMap<String, Vec<mpsc::Sender<Option<ApiResponse>>>>,
+            key: String,
+            finished: bool,
+        }
+        impl Flight<'_> {
+            fn finish(mut self) -> Vec<mpsc::Sender<Option<ApiResponse>>> {
+                self.finished = true;
+                self.in_flight.remove(&self.key).map(|(_, waiters)| waiters).unwrap_or_default()
+            }
+        }
+        impl Drop for Flight<'_> {
+            fn drop(&mut self) {
+                if !self.finished {
+                    self.in_flight.remove(&self.key);
+                }
+            }
+        }
+        
+        let flight = Flight { in_flight: &self.in_flight, key, finished: false };
+        let response = self.route_request(request, hops_left, direction, reach);
+        for waiter in flight.finish() {
+            let _ = waiter.send(response.try_clone());
+        }
+        response
+    }
+    
+    /// Key identifying identical requests for singleflight mode: the path and a
+    /// hash of the metadata and the scopes the request has spanned
+    fn singleflight_key(request: &ApiRequest, reach: Reach) -> String {
+        let mut metadata: Vec<_> = request.metadata.iter().collect();
+        metadata.sort();
+        let mut hasher = DefaultHasher::new();
+        metadata.hash(&mut hasher);
+        reach.hash(&mut hasher);
+        format!("{}#{:016x}", request.path, hasher.finish())
+    }
+    
+    /// Resolve a request, counting the step that resolved it when metrics are enabled
+    fn route_request(&self, request: RoutedRequest, hops_left: Option<usize>, direction: Direction, reach: Reach) -> ApiResponse {
+        let (response, resolution) = self.resolve_request(request, hops_left, direction, reach);
+        if self.config.read().unwrap().metrics_enabled {
+            self.metrics.record_resolution(resolution);
+        }
+        response
+    }
+    
+    /// Resolve a request through interception, the local registry (and a failing
+    /// API's fallback), child hubs, the parent hub and approximation, returning the
+    /// response and the step that resolved it
+    fn resolve_request(&self, request: RoutedRequest, hops_left: Option<usize>, direction: Direction, reach: Reach) -> (ApiResponse, Resolution) {
+        let config = self.config();
+        
+        // The tighter of the incoming hop budget and this hub's own limit applies
+        let hops_left = match (hops_left, config.max_hops) {
+            (Some(incoming), Some(own)) => Some(incoming.min(own)),
+            (incoming, own) => incoming.or(own),
+        };
+        
+        // Let request mutators change the request first; a borrowed request can't be changed
+        let request = match request {
+            RoutedRequest::Owned(mut request) => {
+                self.interceptors.mutate_api_request(&mut request);
+                RoutedRequest::Owned(request)
+            }
+            borrowed => borrowed,
+        };
+        
+        // 1. Check for interception
+        if let Some(intercepted) = self.interceptors.try_intercept_api_request(&request) {
+            let mut response = intercepted;
+            response.metadata.insert("intercepted".to_string(), "true".to_string());
+            response.status = ResponseStatus::Intercepted;
+            return (response, Resolution::Intercepted);
+        }
+        
+        // 2. Check local registry, trying each handler in priority order until one
+        // answers with something other than `NotFound`, and retrying at the
+        // answering API's fallback if it fails. APIs limited to other scopes than the
+        // request has spanned are skipped.
+        let chain = self.registry.lookup_chain(&request.path).into_iter().filter(|api| reach.allows(&api.metadata));
+        let mut answered = None;
+        for api in chain {
+            let response = {
+                let _active = ActiveHandler::new(&self.active_handlers);
+                Self::invoke_handler(&api, &request)
+            };
+            let found = response.status != ResponseStatus::NotFound;
+            answered = Some((api, response));
+            if found {
+                break;
+            }
+        }
+        if let Some((api, response)) = answered {
+            // Fallbacks are followed once, so two APIs falling back to each other can't loop
+            let fallback_path = api.fallback_path.filter(|_| {
+                config.enable_fallback
+                    && response.status == ResponseStatus::Error
+                    && !request.metadata.contains_key("fallback_from")
+            });
+            // A borrowed request can't hand its data over to the fallback request
+            let (Some(fallback_path), RoutedRequest::Owned(request)) = (fallback_path, request) else {
+                return (response, Resolution::Local);
+            };
+            
+            debug!(path = %request.path, fallback = %fallback_path, "API failed, trying its fallback");
+            let failed_path = request.path.clone();
+            let mut fallback_request = Self::redirect_request(request, fallback_path);
+            fallback_request.metadata.insert("fallback_from".to_string(), failed_path);
+            return (self.route_redirected(fallback_request, hops_left, direction, reach), Resolution::Fallback);
+        }
+        
+        // 3. Route down to the child hub serving the path (unless the request came up from a child)
+        if direction != Direction::Up && hops_left != Some(0) {
+            if let Some(child) = self.registry.lookup_remote(&request.path) {
+                // Skip routes left behind by a child that has since disconnected
+                let connected = child.parent_hub.read().unwrap().as_ref()
+                    .and_then(|weak| weak.upgrade())
+                    .is_some_and(|parent| parent.id == self.id);
+                if connected {
+                    return (child.dispatch_request(request, hops_left.map(|hops| hops - 1), Direction::Down, reach), Resolution::Child);
+                }
+            }
+        }
+        
+        // 4. Escalate to parent hub if available (and the hop limit allows it)
+        if direction != Direction::Down && hops_left != Some(0) {
+            let parent = self.parent_hub.read().unwrap().as_ref().and_then(|weak| weak.upgrade());
+            if let Some(parent) = parent {
+                let reach = reach.through(parent.scope);
+                return (parent.dispatch_request(request, hops_left.map(|hops| hops - 1), Direction::Up, reach), Resolution::Parent);
+            }
+            // If the weak reference couldn't be upgraded, the parent hub no longer exists
+        }
+        
+        // 5. Try approximation
+        let similar = if config.enable_approximation {
+            self.registry.similar_path_where(&request.path, config.similarity_threshold, |api| reach.allows(&api.metadata))
+        } else {
+            None
+        };
+        let request = match (similar, request) {
+            (Some((similar_path, _)), RoutedRequest::Owned(request)) => {
+                let approx_request = Self::redirect_request(request, similar_path);
+                let mut response = self.route_redirected(approx_request, hops_left, direction, reach);
+                response.metadata.insert("approximated".to_string(), "true".to_string());
+                response.status = ResponseStatus::Approximated;
+                return (response, Resolution::Approximated);
+            }
+            (_, request) => request,
+        };
+        
+        // 6. Not found, at the hub where the search ended
+        self.dead_letter(&request);
+        let response = ApiResponse {
+            data: Box::new(()),
+            metadata: HashMap::new(),
+            status: ResponseStatus::NotFound,
+        };
+        (response, Resolution::NotFound)
+    }
+    
+    /// Keep a request that couldn't be routed and pass it to the dead-letter handler
+    fn dead_letter(&self, request: &ApiRequest) {
+        debug!(path = %request.path, sender_id = %request.sender_id, "No route for request");
+        {
+            let mut dead_letters = self.dead_letters.lock().unwrap();
+            if dead_letters.len() == DEAD_LETTER_CAPACITY {
+                dead_letters.pop_front();
+            }
+            dead_letters.push_back(RecordedRequest::from(request));
+        }
+        
+        // The handler is called without holding the lock, so it may use this hub
+        let handler = self.dead_letter_handler.read().unwrap().clone();
+        if let Some(handler) = handler {
+            handler(request);
+        }
+    }
+    
+    /// Set a handler to be called with every request this hub can't route
+    ///
+    /// A request is a dead letter of the hub where the search for its API ended
+    /// (usually the top of the hierarchy), once interception, the registries and
+    /// approximation have all failed. The handler replaces any previous one.
+    pub fn set_dead_letter_handler<F>(&self, handler: F)
+    where
+        F: Fn(&ApiRequest) + Send + Sync + 'static,
+    {
+        *self.dead_letter_handler.write().unwrap() = Some(Arc::new(handler));
+    }
+    
+    /// Get the most recent requests this hub couldn't route, oldest first
+    ///
+    /// Only the last 256 are kept.
+    pub fn dead_letters(&self) -> Vec<RecordedRequest> {
+        self.dead_letters.lock().unwrap().iter().cloned().collect()
+    }
+    
+    /// Explain how a request would be resolved, without invoking any handler
+    ///
+    /// Follows the same steps as `handle_request` (into child and parent hubs too)
+    /// and reports the one that matches, the chosen path and, for approximated
+    /// paths, the similarity score. Interceptors aren't run, and neither are
+    /// fallbacks, since whether they apply depends on what the handler returns.
+    pub fn explain(&self, request: &ApiRequest) -> ResolutionTrace {
+        self.explain_route(&request.path, None, Direction::Any, Reach::new(self.scope))
+    }
+    
+    /// Whether a request for the path would reach a registered API, without invoking any handler
+    ///
+    /// Follows the same steps as `explain`, so APIs of child hubs and of parents
+    /// within the hop limit count. Paths that would only be approximated don't.
+    pub fn can_handle(&self, path: &str) -> bool {
+        self.explain_route(path, None, Direction::Any, Reach::new(self.scope)).last().step == ResolutionStep::Local
+    }
+    
+    /// Explain the resolution of a path with a remaining escalation budget,
+    /// mirroring `route_request`
+    fn explain_route(&self, path: &str, hops_left: Option<usize>, direction: Direction, reach: Reach) -> ResolutionTrace {
+        let config = self.config();
+        let hops_left = match (hops_left, config.max_hops) {
+            (Some(incoming), Some(own)) => Some(incoming.min(own)),
+            (incoming, own) => incoming.or(own),
+        };
+        
+        let mut trace = ResolutionTrace {
+            hub_id: self.id.clone(),
+            step: ResolutionStep::NotFound,
+            path: None,
+            similarity: None,
+            fallback_path: None,
+            interceptable: self.interceptors.has_api_interceptors(path),
+            next: None,
+        };
+        
+        let reachable = self.registry.lookup_chain(path).iter().any(|api| reach.allows(&api.metadata));
+        if let Some(matched) = self.registry.matching_path(path).filter(|_| reachable) {
+            trace.step = ResolutionStep::Local;
+            trace.fallback_path = self.registry.lookup_fallback(path).filter(|_| config.enable_fallback);
+            trace.path = Some(matched);
+            return trace;
+        }
+        
+        if direction != Direction::Up && hops_left != Some(0) {
+            if let Some(child) = self.registry.lookup_remote(path) {
+                let connected = child.parent_hub.read().unwrap().as_ref()
+                    .and_then(|weak| weak.upgrade())
+                    .is_some_and(|parent| parent.id == self.id);
+                if connected {
+                    trace.step = ResolutionStep::Child;
+                    trace.next = Some(Box::new(child.explain_route(path, hops_left.map(|hops| hops - 1), Direction::Down, reach)));
+                    return trace;
+                }
+            }
+        }
+        
+        if direction != Direction::Down && hops_left != Some(0) {
+            let parent = self.parent_hub.read().unwrap().as_ref().and_then(|weak| weak.upgrade());
+            if let Some(parent) = parent {
+                trace.step = ResolutionStep::Parent;
+                trace.next = Some(Box::new(parent.explain_route(path, hops_left.map(|hops| hops - 1), Direction::Up, reach.through(parent.scope))));
+                return trace;
+            }
+        }
+        
+        let similar = if config.enable_approximation {
+            self.registry.similar_path_where(path, config.similarity_threshold, |api| reach.allows(&api.metadata))
+        } else {
+            None
+        };
+        if let Some((similar_path, similarity)) = similar {
+            trace.step = ResolutionStep::Approximated;
+            trace.fallback_path = self.registry.lookup_fallback(&similar_path).filter(|_| config.enable_fallback);
+            trace.path = Some(similar_path);
+            trace.similarity = Some(similarity);
+        }
+        
+        trace
+    }
+    
+    /// Build a request for another path, recording the path it was redirected from
+    /// and adding the new path to the trail of attempted paths
+    fn redirect_request(request: ApiRequest, path: String) -> ApiRequest {
+        let mut attempted_paths: Vec<String> = request.metadata.get(ATTEMPTED_PATHS_KEY)
+            .and_then(|trail| serde_json::from_str(trail).ok())
+            .unwrap_or_else(|| vec![request.path.clone()]);
+        attempted_paths.push(path.clone());
+        
+        let mut metadata = request.metadata;
+        metadata.insert("original_path".to_string(), request.path);
+        metadata.insert(ATTEMPTED_PATHS_KEY.to_string(), serde_json::json!(attempted_paths).to_string());
+        
+        ApiRequest {
+            path,
+            data: request.data,
+            metadata,
+            sender_id: request.sender_id,
+        }
+    }
+    
+    /// Route a redirected request, returning the trail of attempted paths in the response
+    ///
+    /// A response that already carries a trail came from a further redirect, whose
+    /// trail is the longer one.
+    ///
+    /// The redirect isn't counted as a resolution of its own, since the request was
+    /// already counted as falling back or being approximated.
+    fn route_redirected(&self, request: ApiRequest, hops_left: Option<usize>, direction: Direction, reach: Reach) -> ApiResponse {
+        let attempted_paths = request.metadata.get(ATTEMPTED_PATHS_KEY).cloned().unwrap_or_default();
+        let (mut response, _) = self.resolve_request(RoutedRequest::Owned(request), hops_left, direction, reach);
+        response.metadata.entry(ATTEMPTED_PATHS_KEY.to_string()).or_insert(attempted_paths);
+        response
+    }
+    
+    /// Invoke an API handler, converting a panic into an error response
+    ///
+    /// The registry lock is released before this is called, so a panicking handler
+    /// cannot poison it.
+    fn invoke_handler(api: &ApiEntry, request: &ApiRequest) -> ApiResponse {
+        panic::catch_unwind(AssertUnwindSafe(|| (api.handler)(request)))
+            .unwrap_or_else(|payload| Self::panic_response(request, payload))
+    }
+    
+    /// Invoke an API handler from async code, awaiting an async handler's response
+    /// and converting a panic into an error response
+    async fn invoke_handler_async(api: &ApiEntry, request: &ApiRequest) -> ApiResponse {
+        let Some(handler) = &api.async_handler else {
+            return Self::invoke_handler(api, request);
+        };
+        let response = match panic::catch_unwind(AssertUnwindSafe(|| handler(request))) {
+            Ok(future) => AssertUnwindSafe(future).catch_unwind().await,
+            Err(payload) => Err(payload),
+        };
+        response.unwrap_or_else(|payload| Self::panic_response(request, payload))
+    }
+    
+    /// Error response for a request whose handler panicked
+    fn panic_response(request: &ApiRequest, payload: Box<dyn Any + Send>) -> ApiResponse {
+        let message = payload.downcast_ref::<&str>().map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        error!(path = %request.path, panic = %message, "API handler panicked");
+        
+        ApiResponse {
+            data: Box::new(HubError::HandlerPanic(message)),
+            metadata: HashMap::from([("panicked".to_string(), "true".to_string())]),
+            status: ResponseStatus::Error,
+        }
+    }
+    
+    /// Register a message interceptor for a specific topic
+    pub fn register_interceptor<T, R, F>(&self, topic: &str, handler: F, priority: i32) -> String
+    where
+        T: 'static + Send + Sync,
+        R: 'static + Send + Sync,
+        F: Fn(&Message<T>) -> Option<R> + Send + Sync + 'static,
+    {
+        self.interceptors.register(topic, handler, priority)
+    }
+    
+    /// Register an API interceptor for a specific path
+    pub fn register_api_interceptor<F>(&self, path: &str, handler: F, priority: i32) -> String
+    where
+        F: Fn(&ApiRequest) -> Option<ApiResponse> + Send + Sync + 'static,
+    {
+        self.interceptors.register_api_interceptor(path, handler, priority)
+    }
+    
+    /// Register an API interceptor that is unregistered when the returned guard is dropped
+    pub fn register_api_interceptor_scoped<F>(&self, path: &str, handler: F, priority: i32) -> InterceptorGuard
+    where
+        F: Fn(&ApiRequest) -> Option<ApiResponse> + Send + Sync + 'static,
+    {
+        let id = self.interceptors.register_api_interceptor(path, handler, priority);
+        InterceptorGuard::new(Arc::clone(&self.interceptors), id)
+    }
+    
+    /// Register a request mutator for a specific path (or wildcard pattern, see `matches_pattern`)
+    ///
+    /// Unlike an API interceptor, a mutator can't answer a request: it changes the
+    /// request (e.g. to add auth metadata or rewrite the path), which is then
+    /// intercepted and routed as usual. Mutators run in priority order before any
+    /// interceptor, and are skipped for requests handled by `handle_request_ref`.
+    /// Remove it with `unregister_interceptor`.
+    pub fn register_request_mutator<F>(&self, path: &str, mutator: F, priority: i32) -> String
+    where
+        F: Fn(&mut ApiRequest) + Send + Sync + 'static,
+    {
+        self.interceptors.register_request_mutator(path, mutator, priority)
+    }
+    
+    /// Register a response interceptor for a path (or wildcard pattern, see `matches_pattern`)
+    ///
+    /// Response interceptors run on the response to every request this hub handles
+    /// for the path once it's resolved, however it was: by an API here, a child or
+    /// parent hub, a fallback or an approximated path. They can add metadata, redact
+    /// data or record the status in one place. Interceptors run in the order they
+    /// were registered, and get the request as it was passed in, without its data
+    /// (which goes to the handler) unless it was handled by `handle_request_ref`.
+    /// Remove it with `unregister_interceptor`.
+    pub fn register_response_interceptor<F>(&self, path: &str, interceptor: F) -> String
+    where
+        F: Fn(&ApiRequest, &mut ApiResponse) + Send + Sync + 'static,
+    {
+        self.interceptors.register_response_interceptor(path, interceptor)
+    }
+    
+    /// Record the requests this hub handles for a path (or wildcard pattern, see `matches_pattern`) from now on
+    ///
+    /// Recording is done by an API interceptor of the highest priority that lets
+    /// every request through, so handlers see no difference. Requests answered by
+    /// an interceptor registered for the exact path are missed by a wildcard recorder.
+    pub fn record(&self, path_pattern: &str) -> RequestRecorder {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&requests);
+        let guard = self.register_api_interceptor_scoped(path_pattern, move |request: &ApiRequest| {
+            recorded.lock().unwrap().push(RecordedRequest::from(request));
+            None
+        }, i32::MAX);
+        RequestRecorder::new(requests, guard)
+    }
+    
+    /// Remove a message, method or API interceptor or a request mutator by the ID returned when it was registered
+    ///
+    /// Returns whether an interceptor was removed.
+    pub fn unregister_interceptor(&self, id: &str) -> bool {
+        self.interceptors.unregister(id)
+    }
+    
+    /// Register an interceptor for calls to a method of type `T` taking arguments `A`
+    ///
+    /// Code calling the method asks the hub first with `try_intercept_method`, and
+    /// uses the interceptor's return value instead of calling the method when one
+    /// is returned.
+    pub fn intercept_method<T, A, R, F>(&self, method_name: &str, handler: F, priority: i32) -> String
+    where
+        T: 'static + Send + Sync,
+        A: 'static + Send + Sync,
+        R: 'static + Send + Sync,
+        F: Fn(&T, &A) -> Option<R> + Send + Sync + 'static,
+    {
+        self.interceptors.register_method_interceptor(method_name, handler, priority)
+    }
+    
+    /// Try to intercept a method call, checking this hub's interceptors and then its parent's
+    pub fn try_intercept_method<T, A, R>(&self, target: &T, method_name: &str, args: &A) -> Option<R>
+    where
+        T: 'static + Send + Sync,
+        A: 'static + Send + Sync,
+        R: 'static + Send + Sync,
+    {
+        if let Some(result) = self.interceptors.try_intercept_method(target, method_name, args) {
+            return Some(result);
+        }
+        
+        let parent = self.parent_hub.read().unwrap().as_ref().and_then(|weak| weak.upgrade());
+        parent.and_then(|parent| parent.try_intercept_method(target, method_name, args))
+    }
+    
+    /// Subscribe to messages matching a pattern
+    ///
+    /// The pattern is a topic, or may contain `*` and `**` wildcards as described
+    /// for `matches_pattern`. Returns the subscription ID, which can be passed to
+    /// `unsubscribe`.
+    pub fn subscribe<F>(&self, pattern: &str, callback: F, priority: i32) -> String
+    where
+        F: Fn(&Message<Box<dyn Any + Send + Sync>>) -> Option<Box<dyn Any + Send + Sync>> + Send + Sync + 'static,
+    {
+        let id = generate_uuid();
+        self.add_subscription(pattern, id.clone(), callback, priority);
+        id
+    }
+    
+    /// Subscribe to messages matching a pattern with an async callback
+    ///
+    /// The callback reads what it needs from the message and returns a future of
+    /// its result, so it can await I/O. `publish_async` awaits the future directly;
+    /// `publish` and the other sync publishing methods block until it completes on
+    /// a runtime shared by every hub. Returns the subscription ID, which can be
+    /// passed to `unsubscribe`.
+    pub fn subscribe_async<F, Fut>(&self, pattern: &str, callback: F, priority: i32) -> String
+    where
+        F: Fn(&Message<Box<dyn Any + Send + Sync>>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Option<Box<dyn Any + Send + Sync>>> + Send + 'static,
+    {
+        let id = generate_uuid();
+        let async_handler: AsyncSubscriber = Arc::new(move |message| Box::pin(callback(message)));
+        let blocking_handler = Arc::clone(&async_handler);
+        let subscription = Subscription {
+            id: id.clone(),
+            priority,
+            handler: Arc::new(Mutex::new(Box::new(move |message: &Message<Box<dyn Any + Send + Sync>>| {
+                block_on_async_runtime(blocking_handler(message))
+            }))),
+            async_handler: Some(async_handler),
+            queue: None,
+        };
+        self.insert_subscription(pattern, subscription);
+        id
+    }
+    
+    /// Subscribe to messages matching a pattern, handling them on a thread of their own
+    ///
+    /// Publishing only queues a message for the callback, so a slow callback doesn't
+    /// hold up the publisher. Up to `capacity` messages wait in the queue; while it
+    /// is full, further messages are dropped and counted (see `dropped_messages`).
+    /// The callback's results are discarded. Returns the subscription ID, which can
+    /// be passed to `unsubscribe`; the thread exits once the queue is drained.
+    pub fn subscribe_queued<F>(&self, pattern: &str, callback: F, capacity: usize) -> String
+    where
+        F: Fn(&Message<Box<dyn Any + Send + Sync>>) -> Option<Box<dyn Any + Send + Sync>> + Send + Sync + 'static,
+    {
+        let id = generate_uuid();
+        let (sender, receiver) = mpsc::sync_channel::<Arc<Message<Box<dyn Any + Send + Sync>>>>(capacity.max(1));
+        let subscription = Subscription {
+            id: id.clone(),
+            priority: 0,
+            handler: Arc::new(Mutex::new(Box::new(callback))),
+            async_handler: None,
+            queue: Some(SubscriptionQueue { sender, dropped: Arc::new(AtomicU64::new(0)) }),
+        };
+        
+        // Stops once the subscription, and with it the sender, is gone
+        let handler = Arc::clone(&subscription.handler);
+        thread::spawn(move || {
+            for message in receiver {
+                let handler = handler.lock().unwrap();
+                handler(&message);
+            }
+        });
+        
+        self.insert_subscription(pattern, subscription);
+        id
+    }
+    
+    /// Get the number of messages a subscription made with `subscribe_queued` has
+    /// dropped because its queue was full
+    ///
+    /// Returns `None` if there is no such subscription.
+    pub fn dropped_messages(&self, id: &str) -> Option<u64> {
+        self.subscriptions.iter().find_map(|entry| {
+            entry.value().iter()
+                .find(|subscription| subscription.id == id)
+                .and_then(|subscription| subscription.queue.as_ref())
+                .map(|queue| queue.dropped.load(Ordering::Relaxed))
+        })
+    }
+    
+    /// Subscribe to the next message matching a pattern
+    ///
+    /// The subscription is removed as soon as a matching message is delivered, so
+    /// the callback runs at most once. Returns the subscription ID, which can be
+    /// passed to `unsubscribe` to cancel it before a message arrives.
+    pub fn subscribe_once<F>(&self, pattern: &str, callback: F) -> String
+    where
+        F: Fn(&Message<Box<dyn Any + Send + Sync>>) -> Option<Box<dyn Any + Send + Sync>> + Send + Sync + 'static,
+    {
+        let id = generate_uuid();
+        // Weak, since the subscription is stored in the map it removes itself from
+        let subscriptions = Arc::downgrade(&self.subscriptions);
+        let subscription_id = id.clone();
+        let fired = AtomicBool::new(false);
+        
+        self.add_subscription(pattern, id.clone(), move |message: &Message<Box<dyn Any + Send + Sync>>| {
+            // Two concurrent publishes may both reach the callback before it is removed
+            if fired.swap(true, Ordering::SeqCst) {
+                return None;
+            }
+            if let Some(subscriptions) = subscriptions.upgrade() {
+                Self::remove_subscription(&subscriptions, &subscription_id);
+            }
+            callback(message)
+        }, 0);
+        id
+    }
+    
+    /// Remove a subscription by the ID returned when it was created
+    ///
+    /// Returns whether a subscription was removed.
+    pub fn unsubscribe(&self, id: &str) -> bool {
+        Self::remove_subscription(&self.subscriptions, id)
+    }
+    
+    /// Remove every subscription made with exactly this pattern
+    ///
+    /// Returns the number of subscriptions removed. Subscriptions whose patterns
+    /// merely match it (like `#`) are kept.
+    pub fn unsubscribe_all(&self, pattern: &str) -> usize {
+        self.subscriptions.remove(pattern).map_or(0, |(_, subs)| subs.len())
+    }
+    
+    /// Get the patterns that have subscriptions, sorted
+    pub fn subscribed_patterns(&self) -> Vec<String> {
+        let mut patterns: Vec<String> = self.subscriptions.iter().map(|entry| entry.key().clone()).collect();
+        patterns.sort();
+        patterns
+    }
+    
+    /// Remove a subscription from a subscription map, dropping its pattern once it has none left
+    fn remove_subscription(subscriptions: &DashMap<String, Vec<Subscription>>, id: &str) -> bool {
+        let pattern = subscriptions.iter_mut().find_map(|mut entry| {
+            let position = entry.value().iter().position(|subscription| subscription.id == id)?;
+            entry.value_mut().remove(position);
+            Some(entry.key().clone())
+        });
+        
+        match pattern {
+            Some(pattern) => {
+                subscriptions.remove_if(&pattern, |_, subs| subs.is_empty());
+                true
+            }
+            None => false,
+        }
+    }
+    
+    /// Store a subscription under a pattern
+    fn add_subscription<F>(&self, pattern: &str, id: String, callback: F, priority: i32)
+    where
+        F: Fn(&Message<Box<dyn Any + Send + Sync>>) -> Option<Box<dyn Any + Send + Sync>> + Send + Sync + 'static,
+    {
+        let subscription = Subscription {
+            id,
+            priority,
+            handler: Arc::new(Mutex::new(Box::new(callback))),
+            async_handler: None,
+            queue: None,
+        };
+        self.insert_subscription(pattern, subscription);
+    }
+    
+    /// Store a subscription under a pattern, keeping the pattern's subscriptions in priority order
+    fn insert_subscription(&self, pattern: &str, subscription: Subscription) {
+        self.subscriptions
+            .entry(pattern.to_string())
+            .or_default()
+            .push(subscription);
+        
+        // Sort subscriptions by priority (highest first); the sort is stable, so
+        // subscriptions sharing a priority keep their registration order
+        if let Some(mut subs) = self.subscriptions.get_mut(pattern) {
+            subs.sort_by(|a, b| b.priority.cmp(&a.priority));
+        }
+    }
+    
+    /// Publish a message with interception capability
+    pub fn publish<T, R>(&self, topic: &str, data: T, metadata: HashMap<String, String>) -> Option<R>
+    where
+        T: 'static + Send + Sync + Clone,
+        R: 'static + Send + Sync,
+    {
+        self.publish_message(Message {
+            topic: topic.to_string(),
+            data,
+            metadata,
+            sender_id: self.id.clone(), // Set the sender ID to this hub's ID
+            timestamp: current_time_millis(),
+        })
+    }
+    
+    /// Publish a message from async code
+    ///
+    /// The message reaches interceptors, subscribers and the parent hub just like
+    /// with `publish`, but the futures of async subscribers are awaited on the
+    /// current task rather than blocked on. Sync subscribers are still called
+    /// directly, so they should be quick.
+    pub async fn publish_async<T, R>(&self, topic: &str, data: T, metadata: HashMap<String, String>) -> Option<R>
+    where
+        T: 'static + Send + Sync + Clone,
+        R: 'static + Send + Sync,
+    {
+        self.dispatch_message_async(Message {
+            topic: topic.to_string(),
+            data,
+            metadata,
+            sender_id: self.id.clone(),
+            timestamp: current_time_millis(),
+        }).await
+    }
+    
+    /// Publish a message that was created earlier, e.g. one received from a peer
+    ///
+    /// The message keeps its sender and timestamp, so a message that has outlived
+    /// its time to live (see `TTL_KEY`) is dropped without reaching interceptors
+    /// or subscribers.
+    pub fn publish_message<T, R>(&self, message: Message<T>) -> Option<R>
+    where
+        T: 'static + Send + Sync + Clone,
+        R: 'static + Send + Sync,
+    {
+        self.dispatch_message(message, false).pop()
+    }
+    
+    /// Publish a message and collect the result of every subscriber that returns one
+    ///
+    /// Results are collected in the order the subscribers are called, followed by
+    /// those of the parent hub's subscribers; results that aren't an `R` are skipped.
+    /// An intercepted message doesn't reach any subscriber, so only the
+    /// interceptor's result is returned.
+    pub fn publish_collect<T, R>(&self, topic: &str, data: T, metadata: HashMap<String, String>) -> Vec<R>
+    where
+        T: 'static + Send + Sync + Clone,
+        R: 'static + Send + Sync,
+    {
+        self.dispatch_message(Message {
+            topic: topic.to_string(),
+            data,
+            metadata,
+            sender_id: self.id.clone(),
+            timestamp: current_time_millis(),
+        }, true)
+    }
+    
+    /// Deliver a message to interceptors, subscribers and the parent hub
+    ///
+    /// Returns the interceptor's result if intercepted, and otherwise the
+    /// subscribers' results if `collect` is set.
+    fn dispatch_message<T, R>(&self, message: Message<T>, collect: bool) -> Vec<R>
+    where
+        T: 'static + Send + Sync + Clone,
+        R: 'static + Send + Sync,
+    {
+        if message.is_expired() {
+            debug!(topic = %message.topic, timestamp = message.timestamp, "Dropping expired message");
+            return Vec::new();
+        }
+        let topic = message.topic.as_str();
+        
+        // Try to intercept the message
+        if let Some(result) = self.interceptors.try_intercept_message::<T, R>(&message) {
+            return vec![result];
+        }
+        
+        let any_message = Self::any_message(&message);
+        let mut results = Vec::new();
+        for subscriber in self.matching_subscribers(topic) {
+            // Queued subscriptions are handled on their own thread
+            if let Some(queue) = subscriber.queue {
+                Self::enqueue(&subscriber.id, &queue, &any_message);
+                continue;
+            }
+            
+            let handler = subscriber.handler.lock().unwrap();
+            let result = handler(&any_message);
+            if collect {
+                results.extend(result.and_then(|result| result.downcast::<R>().ok()).map(|result| *result));
+            }
+        }
+        
+        // If not intercepted and we have a parent, propagate to parent
+        if let Some(weak_parent) = self.parent_hub.read().unwrap().as_ref() {
+            if let Some(parent) = weak_parent.upgrade() {
+                // In a real implementation, we would need to serialize data for transport
+                // For now, we simply clone and forward the message to the parent
+                // Note: This won't actually work because type parameters are lost,
+                // but in a real impl this would use serialization to preserve type info
+                let parent_results = parent.dispatch_message::<T, R>(message, collect);
+                if collect {
+                    results.extend(parent_results);
+                }
+            }
+            // If the weak reference couldn't be upgraded, the parent hub no longer exists
+        }
+        
+        results
+    }
+    
+    /// Deliver a message like `dispatch_message`, awaiting async subscribers
+    ///
+    /// Returns the interceptor's result if intercepted.
+    fn dispatch_message_async<T, R>(&self, message: Message<T>) -> BoxFuture<'_, Option<R>>
+    where
+        T: 'static + Send + Sync + Clone,
+        R: 'static + Send + Sync,
+    {
+        Box::pin(async move {
+            if message.is_expired() {
+                debug!(topic = %message.topic, timestamp = message.timestamp, "Dropping expired message");
+                return None;
+            }
+            
+            if let Some(result) = self.interceptors.try_intercept_message::<T, R>(&message) {
+                return Some(result);
+            }
+            
+            let any_message = Self::any_message(&message);
+            for subscriber in self.matching_subscribers(&message.topic) {
+                if let Some(queue) = subscriber.queue {
+                    Self::enqueue(&subscriber.id, &queue, &any_message);
+                    continue;
+                }
+                
+                match subscriber.async_handler {
+                    Some(async_handler) => {
+                        async_handler(&any_message).await;
+                    }
+                    None => {
+                        let handler = subscriber.handler.lock().unwrap();
+                        handler(&any_message);
+                    }
+                }
+            }
+            
+            let parent = self.parent_hub.read().unwrap().as_ref().and_then(Weak::upgrade);
+            if let Some(parent) = parent {
+                parent.dispatch_message_async::<T, R>(message).await;
+            }
+            None
+        })
+    }
+    
+    /// Create an Any-boxed version of a message for subscriptions
+    fn any_message<T>(message: &Message<T>) -> Arc<Message<Box<dyn Any + Send + Sync>>>
+    where
+        T: 'static + Send + Sync + Clone,
+    {
+        Arc::new(Message {
+            topic: message.topic.clone(),
+            data: Box::new(message.data.clone()) as Box<dyn Any + Send + Sync>,
+            metadata: message.metadata.clone(),
+            sender_id: message.sender_id.clone(),
+            timestamp: message.timestamp,
+        })
+    }
+    
+    /// Get the subscriptions whose patterns match a topic, in the order they're delivered to
+    ///
+    /// The handlers are collected before any is called, so a handler can
+    /// unsubscribe without deadlocking on the subscription map.
+    fn matching_subscribers(&self, topic: &str) -> Vec<Subscriber> {
+        let matching_topics: Vec<_> = self.subscriptions
+            .iter()
+            .filter(|entry| matches_pattern(entry.key(), topic))
+            .map(|entry| entry.key().clone())
+            .collect();
+        
+        let mut subscribers = Vec::new();
+        for topic_pattern in matching_topics {
+            if let Some(subs) = self.subscriptions.get(&topic_pattern) {
+                subscribers.extend(subs.iter().map(|subscription| Subscriber {
+                    id: subscription.id.clone(),
+                    handler: Arc::clone(&subscription.handler),
+                    async_handler: subscription.async_handler.clone(),
+                    queue: subscription.queue.clone(),
+                }));
+            }
+        }
+        subscribers
+    }
+    
+    /// Queue a message for a subscription handled on its own thread, dropping it if the queue is full
+    fn enqueue(id: &str, queue: &SubscriptionQueue, message: &Arc<Message<Box<dyn Any + Send + Sync>>>) {
+        if queue.sender.try_send(Arc::clone(message)).is_err() {
+            let topic = message.topic.as_str();
+            let dropped = queue.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+            if dropped == 1 {
+                warn!(subscription_id = %id, topic, "Subscriber queue full, dropping messages");
+            } else {
+                debug!(subscription_id = %id, topic, dropped, "Subscriber queue full, dropped message");
+            }
         }
-        pattern == topic
     }
 }
 
@@ -481,6 +2227,16 @@ impl Clone for Hub {
             child_hubs: RwLock::new(self.child_hubs.read().unwrap().clone()),
             interceptors: Arc::clone(&self.interceptors),
             subscriptions: Arc::clone(&self.subscriptions),
+            config: RwLock::new(self.config()),
+            metrics: Arc::clone(&self.metrics),
+            events: self.events.clone(),
+            in_flight: Arc::clone(&self.in_flight),
+            dead_letters: Arc::clone(&self.dead_letters),
+            dead_letter_handler: Arc::clone(&self.dead_letter_handler),
+            scheduler: Arc::clone(&self.scheduler),
+            shutting_down: Arc::clone(&self.shutting_down),
+            active_handlers: Arc::clone(&self.active_handlers),
+            request_hooks: Arc::clone(&self.request_hooks),
         }
     }
 }
\ No newline at end of file
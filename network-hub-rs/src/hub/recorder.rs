@@ -0,0 +1,53 @@
+use std::collections::HashMap;
+use std::mem;
+use std::sync::{Arc, Mutex};
+
+use crate::hub::interceptor::InterceptorGuard;
+use crate::hub::types::ApiRequest;
+
+/// A request captured by a `RequestRecorder`
+///
+/// Request data is opaque and can't be cloned, so only the routing details are kept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedRequest {
+    /// Path the request was made to
+    pub path: String,
+    /// Metadata of the request
+    pub metadata: HashMap<String, String>,
+    /// ID of the sender
+    pub sender_id: String,
+}
+
+impl From<&ApiRequest> for RecordedRequest {
+    fn from(request: &ApiRequest) -> Self {
+        RecordedRequest {
+            path: request.path.clone(),
+            metadata: request.metadata.clone(),
+            sender_id: request.sender_id.clone(),
+        }
+    }
+}
+
+/// Records the requests a hub handles for a path pattern, without changing how they're handled
+///
+/// Returned by `Hub::record`, for tests asserting which requests flowed through a
+/// hub. Recording stops when the recorder is dropped.
+#[must_use = "recording stops as soon as the recorder is dropped"]
+pub struct RequestRecorder {
+    /// Requests recorded since the last `take`
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+    /// Guard of the recording interceptor
+    _guard: InterceptorGuard,
+}
+
+impl RequestRecorder {
+    /// Create a recorder for the requests an interceptor pushes into `requests`
+    pub(crate) fn new(requests: Arc<Mutex<Vec<RecordedRequest>>>, guard: InterceptorGuard) -> Self {
+        RequestRecorder { requests, _guard: guard }
+    }
+
+    /// Take the requests recorded so far, in the order they were handled
+    pub fn take(&self) -> Vec<RecordedRequest> {
+        mem::take(&mut *self.requests.lock().unwrap())
+    }
+}
@@ -1,82 +1,477 @@
 use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
+use std::future::Future;
+use std::panic;
+use std::sync::{Arc, OnceLock, RwLock, Weak};
 
-use crate::utils::find_similar_path;
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use tokio::runtime::Runtime;
+
+use crate::error::{HubError, Result};
+use crate::utils::find_similar;
 use crate::hub::types::ApiRequest;
 use crate::hub::types::ApiResponse;
+use crate::hub::Hub;
+
+/// Handler of an async API, returning the future of its response
+pub type AsyncHandler = Arc<dyn Fn(&ApiRequest) -> BoxFuture<'static, ApiResponse> + Send + Sync>;
 
 /// A registered API handler
 pub struct ApiEntry {
     /// The handler function
+    ///
+    /// For an async API, this blocks until the future of its response completes.
     pub handler: Arc<dyn Fn(&ApiRequest) -> ApiResponse + Send + Sync>,
+    /// The handler of an async API, awaited directly by `Hub::handle_request_async`
+    pub async_handler: Option<AsyncHandler>,
     /// Metadata about the API
     pub metadata: HashMap<String, String>,
-    /// Optional fallback path if this API is not available
+    /// Optional path to retry a request at if this API fails, taken from the
+    /// `fallback` metadata entry
     pub fallback_path: Option<String>,
+    /// Priority among the handlers registered at the same path (higher priorities are tried first)
+    pub priority: i32,
+}
+
+impl ApiEntry {
+    fn new<F>(handler: F, metadata: HashMap<String, String>, priority: i32) -> Self
+    where
+        F: Fn(&ApiRequest) -> ApiResponse + Send + Sync + 'static,
+    {
+        ApiEntry {
+            handler: Arc::new(handler),
+            async_handler: None,
+            fallback_path: metadata.get("fallback").cloned(),
+            metadata,
+            priority,
+        }
+    }
+    
+    fn new_async<F, Fut>(handler: F, metadata: HashMap<String, String>, priority: i32) -> Self
+    where
+        F: Fn(&ApiRequest) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ApiResponse> + Send + 'static,
+    {
+        let async_handler: AsyncHandler = Arc::new(move |request| Box::pin(handler(request)));
+        let blocking_handler = Arc::clone(&async_handler);
+        ApiEntry {
+            async_handler: Some(async_handler),
+            ..ApiEntry::new(move |request| block_on_async_runtime(blocking_handler(request)), metadata, priority)
+        }
+    }
+}
+
+/// A registered API without its handler, as exported for a hub to restart with
+///
+/// Every handler in a path's chain has its own entry.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegistryEntry {
+    /// Path (or pattern) the API is registered at
+    pub path: String,
+    /// Metadata the API is registered with
+    pub metadata: HashMap<String, String>,
+    /// Priority of the handler in the path's chain
+    pub priority: i32,
+    /// Whether the handler is async
+    pub is_async: bool,
+}
+
+/// Paths registered differently than a registry snapshot expects
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RegistryDiff {
+    /// Expected paths without any API registered, yet to be re-registered
+    pub missing: Vec<String>,
+    /// Paths with an API registered that the snapshot doesn't list
+    pub unexpected: Vec<String>,
+}
+
+/// Run the future of an async API's response (or async subscriber's result) to
+/// completion, blocking the calling thread
+///
+/// The future is spawned on a runtime shared by every hub, so this works from
+/// threads with or without a runtime of their own. A panic in the future is
+/// passed on to the caller.
+pub(crate) fn block_on_async_runtime<T: Send + 'static>(future: BoxFuture<'static, T>) -> T {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    let runtime = RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .thread_name("hub-async")
+            .enable_all()
+            .build()
+            .expect("failed to start the runtime for async APIs")
+    });
+    
+    match futures::executor::block_on(runtime.spawn(future)) {
+        Ok(response) => response,
+        Err(e) => panic::resume_unwind(e.into_panic()),
+    }
+}
+
+/// A node in the path-segment trie of registered APIs
+struct RouteNode<T> {
+    /// Value registered at the path ending at this node
+    entry: Option<(String, T)>,
+    /// Children for literal segments
+    children: HashMap<String, RouteNode<T>>,
+    /// Child for a parameter segment (`:name`), matching any single segment
+    param: Option<Box<RouteNode<T>>>,
+    /// Value registered with a trailing `*` segment, matching any remaining segments
+    wildcard: Option<(String, T)>,
+}
+
+impl<T> Default for RouteNode<T> {
+    fn default() -> Self {
+        RouteNode {
+            entry: None,
+            children: HashMap::new(),
+            param: None,
+            wildcard: None,
+        }
+    }
+}
+
+impl<T> RouteNode<T> {
+    /// Find the value matching the given path segments
+    ///
+    /// Literal segments take precedence over parameters, which take precedence
+    /// over wildcards. A branch that doesn't lead to a match is backtracked.
+    fn find(&self, segments: &[&str]) -> Option<&(String, T)> {
+        let Some((segment, rest)) = segments.split_first() else {
+            return self.entry.as_ref();
+        };
+        
+        self.children
+            .get(*segment)
+            .and_then(|child| child.find(rest))
+            .or_else(|| {
+                self.param
+                    .as_ref()
+                    .filter(|_| !segment.is_empty())
+                    .and_then(|param| param.find(rest))
+            })
+            .or(self.wildcard.as_ref())
+    }
+    
+    /// Visit every registered value in the subtree
+    fn for_each<'a>(&'a self, visit: &mut impl FnMut(&'a str, &'a T)) {
+        for (path, entry) in self.entry.iter().chain(self.wildcard.iter()) {
+            visit(path, entry);
+        }
+        for child in self.children.values().chain(self.param.as_deref()) {
+            child.for_each(visit);
+        }
+    }
+    
+    /// Get the slot a path pattern is registered in, creating nodes as needed
+    fn slot_mut(&mut self, path: &str) -> &mut Option<(String, T)> {
+        let segments: Vec<&str> = path.split('/').collect();
+        let mut node = self;
+        
+        for (i, segment) in segments.iter().enumerate() {
+            if *segment == "*" && i == segments.len() - 1 {
+                return &mut node.wildcard;
+            }
+            node = if is_param(segment) {
+                node.param.get_or_insert_with(Default::default)
+            } else {
+                node.children.entry(segment.to_string()).or_default()
+            };
+        }
+        
+        &mut node.entry
+    }
+    
+    /// Get the value registered under exactly this path pattern
+    fn get(&self, path: &str) -> Option<&T> {
+        let segments: Vec<&str> = path.split('/').collect();
+        let mut node = self;
+        
+        for (i, segment) in segments.iter().enumerate() {
+            if *segment == "*" && i == segments.len() - 1 {
+                return node.wildcard.as_ref().map(|(_, entry)| entry);
+            }
+            node = if is_param(segment) {
+                node.param.as_deref()?
+            } else {
+                node.children.get(*segment)?
+            };
+        }
+        
+        node.entry.as_ref().map(|(_, entry)| entry)
+    }
+    
+    /// Remove the value registered under exactly this path pattern
+    fn remove(&mut self, path: &str) -> Option<T> {
+        let segments: Vec<&str> = path.split('/').collect();
+        let mut node = self;
+        
+        for (i, segment) in segments.iter().enumerate() {
+            if *segment == "*" && i == segments.len() - 1 {
+                return node.wildcard.take().map(|(_, entry)| entry);
+            }
+            node = if is_param(segment) {
+                node.param.as_deref_mut()?
+            } else {
+                node.children.get_mut(*segment)?
+            };
+        }
+        
+        node.entry.take().map(|(_, entry)| entry)
+    }
+}
+
+/// Whether a path segment is a parameter (`:name`)
+fn is_param(segment: &str) -> bool {
+    segment.len() > 1 && segment.starts_with(':')
 }
 
 /// Registry of API endpoints
+///
+/// Paths are stored in a trie of `/`-separated segments, so a lookup costs time
+/// proportional to the path length rather than the number of registered APIs.
+/// Besides literal paths, a registered path may contain `:name` segments that
+/// match any single segment, and may end in a `*` segment that matches
+/// everything below it.
+///
+/// Several handlers may be registered at one path, forming a chain that is
+/// tried in priority order.
+///
+/// Alongside its own APIs, the registry records remote routes: paths registered
+/// with a child hub, mapped to the child that serves them.
 pub struct ApiRegistry {
-    /// Map of API paths to handlers
-    entries: RwLock<HashMap<String, ApiEntry>>,
+    /// Trie of registered paths, split on `/`, each holding its handlers in priority order
+    routes: RwLock<RouteNode<Vec<ApiEntry>>>,
+    /// Paths served by child hubs
+    remote_routes: RwLock<RouteNode<Weak<Hub>>>,
+    /// APIs expected to be registered, as imported from a snapshot
+    expected: RwLock<Vec<RegistryEntry>>,
 }
 
 impl ApiRegistry {
     /// Create a new API registry
     pub fn new() -> Self {
         ApiRegistry {
-            entries: RwLock::new(HashMap::new()),
+            routes: RwLock::new(RouteNode::default()),
+            remote_routes: RwLock::new(RouteNode::default()),
+            expected: RwLock::new(Vec::new()),
         }
     }
     
-    /// Register an API handler
+    /// Register an API handler, replacing every handler registered at the path
     pub fn register<F>(&self, path: &str, handler: F, metadata: HashMap<String, String>)
     where
         F: Fn(&ApiRequest) -> ApiResponse + Send + Sync + 'static,
     {
-        let fallback_path = metadata.get("fallback").cloned();
-        
-        let entry = ApiEntry {
-            handler: Arc::new(handler),
-            metadata,
-            fallback_path,
-        };
+        let entry = ApiEntry::new(handler, metadata, 0);
         
-        let mut entries = self.entries.write().unwrap();
-        entries.insert(path.to_string(), entry);
+        let mut routes = self.routes.write().unwrap();
+        *routes.slot_mut(path) = Some((path.to_string(), vec![entry]));
     }
     
-    /// Look up an API handler by path
-    pub fn lookup(&self, path: &str) -> Option<ApiEntry> {
-        let entries = self.entries.read().unwrap();
-        entries.get(path).cloned()
+    /// Register an async API handler, replacing every handler registered at the path
+    pub fn register_async<F, Fut>(&self, path: &str, handler: F, metadata: HashMap<String, String>)
+    where
+        F: Fn(&ApiRequest) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ApiResponse> + Send + 'static,
+    {
+        let entry = ApiEntry::new_async(handler, metadata, 0);
+        
+        let mut routes = self.routes.write().unwrap();
+        *routes.slot_mut(path) = Some((path.to_string(), vec![entry]));
     }
     
-    /// Look up a fallback path for an API
-    pub fn lookup_fallback(&self, path: &str) -> Option<(String, ApiEntry)> {
-        let entries = self.entries.read().unwrap();
+    /// Add an API handler to the chain of handlers registered at the path
+    ///
+    /// Handlers with equal priorities are tried in the order they were registered.
+    pub fn register_with_priority<F>(&self, path: &str, handler: F, metadata: HashMap<String, String>, priority: i32)
+    where
+        F: Fn(&ApiRequest) -> ApiResponse + Send + Sync + 'static,
+    {
+        let entry = ApiEntry::new(handler, metadata, priority);
         
-        for (api_path, entry) in entries.iter() {
-            if let Some(fallback) = &entry.fallback_path {
-                if fallback == path {
-                    return Some((api_path.clone(), entry.clone()));
-                }
-            }
+        let mut routes = self.routes.write().unwrap();
+        let (_, chain) = routes.slot_mut(path).get_or_insert_with(|| (path.to_string(), Vec::new()));
+        let position = chain.partition_point(|existing| existing.priority >= priority);
+        chain.insert(position, entry);
+    }
+    
+    /// Register an API handler unless an API is already registered at the path
+    ///
+    /// Paths differing only in parameter names (`/users/:id` and `/users/:name`)
+    /// count as the same path.
+    pub fn try_register<F>(&self, path: &str, handler: F, metadata: HashMap<String, String>) -> Result<()>
+    where
+        F: Fn(&ApiRequest) -> ApiResponse + Send + Sync + 'static,
+    {
+        let mut routes = self.routes.write().unwrap();
+        let slot = routes.slot_mut(path);
+        if let Some((existing, _)) = slot {
+            return Err(HubError::AlreadyRegistered(existing.clone()));
         }
         
-        None
+        *slot = Some((path.to_string(), vec![ApiEntry::new(handler, metadata, 0)]));
+        Ok(())
+    }
+    
+    /// Look up the highest-priority API handler by path
+    ///
+    /// The entry is cloned out so the registry lock is released before the caller
+    /// invokes the handler. Handlers may therefore call back into the hub (or
+    /// register new APIs) without deadlocking.
+    pub fn lookup(&self, path: &str) -> Option<ApiEntry> {
+        self.lookup_chain(path).into_iter().next()
+    }
+    
+    /// Look up every API handler registered at the path matching a path, in priority order
+    ///
+    /// Like `lookup`, the entries are cloned out of the registry.
+    pub fn lookup_chain(&self, path: &str) -> Vec<ApiEntry> {
+        let segments: Vec<&str> = path.split('/').collect();
+        let routes = self.routes.read().unwrap();
+        routes.find(&segments).map(|(_, chain)| chain.clone()).unwrap_or_default()
+    }
+    
+    /// Get the registered path (or pattern) of the API matching a path
+    pub fn matching_path(&self, path: &str) -> Option<String> {
+        let segments: Vec<&str> = path.split('/').collect();
+        let routes = self.routes.read().unwrap();
+        routes.find(&segments).map(|(api_path, _)| api_path.clone())
+    }
+    
+    /// Get the metadata registered for an API without invoking its handler
+    pub fn get_metadata(&self, path: &str) -> Option<HashMap<String, String>> {
+        let routes = self.routes.read().unwrap();
+        routes.get(path).and_then(|chain| chain.first()).map(|entry| entry.metadata.clone())
+    }
+    
+    /// Look up the fallback path registered for the API matching a path
+    pub fn lookup_fallback(&self, path: &str) -> Option<String> {
+        let segments: Vec<&str> = path.split('/').collect();
+        let routes = self.routes.read().unwrap();
+        routes.find(&segments).and_then(|(_, chain)| chain.first()).and_then(|entry| entry.fallback_path.clone())
     }
     
     /// Look up an API with a similar path
     pub fn lookup_similar(&self, path: &str, threshold: f64) -> Option<(String, ApiEntry)> {
-        let entries = self.entries.read().unwrap();
-        let entries_map = entries.iter().map(|(k, v)| (k.clone(), v.clone())).collect::<HashMap<_, _>>();
+        let routes = self.routes.read().unwrap();
         
-        if let Some((similar_path, _)) = find_similar_path(&entries_map, path, threshold) {
-            return entries.get(&similar_path).map(|entry| (similar_path, entry.clone()));
-        }
+        let mut candidates = Vec::new();
+        routes.for_each(&mut |api_path, entry| candidates.push((api_path, entry)));
         
-        None
+        let (similar_path, _) = find_similar(candidates.iter().map(|(api_path, _)| *api_path), path, threshold)?;
+        candidates
+            .into_iter()
+            .find(|(api_path, _)| *api_path == similar_path)
+            .and_then(|(api_path, chain)| Some((api_path.to_string(), chain.first()?.clone())))
+    }
+    
+    /// Find a registered path similar to a path, with its similarity score
+    pub fn similar_path(&self, path: &str, threshold: f64) -> Option<(String, f64)> {
+        self.similar_path_where(path, threshold, |_| true)
+    }
+    
+    /// Find the most similar registered path among those with an API accepted by `filter`
+    pub(crate) fn similar_path_where(&self, path: &str, threshold: f64, filter: impl Fn(&ApiEntry) -> bool) -> Option<(String, f64)> {
+        let routes = self.routes.read().unwrap();
+        let mut candidates = Vec::new();
+        routes.for_each(&mut |api_path, chain| {
+            if chain.iter().any(&filter) {
+                candidates.push(api_path);
+            }
+        });
+        find_similar(candidates, path, threshold)
+    }
+    
+    /// Get the paths of every registered API
+    pub fn paths(&self) -> Vec<String> {
+        let routes = self.routes.read().unwrap();
+        let mut paths = Vec::new();
+        routes.for_each(&mut |api_path, _| paths.push(api_path.to_string()));
+        paths
+    }
+    
+    /// Get every registered API without its handler, ordered by path and then priority
+    pub fn export(&self) -> Vec<RegistryEntry> {
+        let routes = self.routes.read().unwrap();
+        let mut entries = Vec::new();
+        routes.for_each(&mut |api_path, chain| {
+            entries.extend(chain.iter().map(|entry| RegistryEntry {
+                path: api_path.to_string(),
+                metadata: entry.metadata.clone(),
+                priority: entry.priority,
+                is_async: entry.async_handler.is_some(),
+            }));
+        });
+        // Chains are already in priority order, which a stable sort keeps
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        entries
+    }
+    
+    /// Record the APIs expected to be registered, replacing any recorded before
+    ///
+    /// Nothing is registered: handlers can't be exported, so the service registers
+    /// them itself, using `diff` to find those still missing.
+    pub fn import(&self, entries: Vec<RegistryEntry>) {
+        *self.expected.write().unwrap() = entries;
+    }
+    
+    /// Get the paths of the APIs expected to be registered, sorted and without duplicates
+    pub fn expected_paths(&self) -> Vec<String> {
+        let mut paths: Vec<String> = self.expected.read().unwrap().iter().map(|entry| entry.path.clone()).collect();
+        paths.sort();
+        paths.dedup();
+        paths
+    }
+    
+    /// Compare the registered paths with those expected
+    pub fn diff(&self) -> RegistryDiff {
+        let expected = self.expected_paths();
+        let mut registered = self.paths();
+        registered.sort();
+        RegistryDiff {
+            missing: expected.iter().filter(|path| !registered.contains(path)).cloned().collect(),
+            unexpected: registered.into_iter().filter(|path| !expected.contains(path)).collect(),
+        }
+    }
+    
+    /// Remove every API handler registered at a path pattern
+    ///
+    /// Returns whether anything was registered there.
+    pub fn unregister(&self, path: &str) -> bool {
+        self.routes.write().unwrap().remove(path).is_some()
+    }
+    
+    /// Remove every registered API, keeping the remote routes
+    pub fn clear(&self) {
+        *self.routes.write().unwrap() = RouteNode::default();
+    }
+    
+    /// Record that a path is served by a child hub, replacing any previous route
+    pub fn register_remote(&self, path: &str, child: Weak<Hub>) {
+        let mut remote_routes = self.remote_routes.write().unwrap();
+        *remote_routes.slot_mut(path) = Some((path.to_string(), child));
+    }
+    
+    /// Look up the child hub serving a path, if it is still alive
+    pub fn lookup_remote(&self, path: &str) -> Option<Arc<Hub>> {
+        let segments: Vec<&str> = path.split('/').collect();
+        let remote_routes = self.remote_routes.read().unwrap();
+        remote_routes.find(&segments).and_then(|(_, child)| child.upgrade())
+    }
+    
+    /// Get the paths of every remote route
+    pub fn remote_paths(&self) -> Vec<String> {
+        let remote_routes = self.remote_routes.read().unwrap();
+        let mut paths = Vec::new();
+        remote_routes.for_each(&mut |api_path, _| paths.push(api_path.to_string()));
+        paths
+    }
+}
+
+impl Default for ApiRegistry {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -84,8 +479,40 @@ impl Clone for ApiEntry {
     fn clone(&self) -> Self {
         ApiEntry {
             handler: Arc::clone(&self.handler),
+            async_handler: self.async_handler.clone(),
             metadata: self.metadata.clone(),
             fallback_path: self.fallback_path.clone(),
+            priority: self.priority,
         }
     }
-}
\ No newline at end of file
+}
+
+/// Guard that unregisters an API when dropped
+///
+/// Returned by `Hub::register_api_with_guard`, for services that come and go:
+/// a service holding the guards of its APIs takes them down with it.
+#[must_use = "the API is unregistered as soon as the guard is dropped"]
+pub struct ApiGuard {
+    /// Registry the API is registered with
+    registry: Arc<ApiRegistry>,
+    /// Path the API is registered at
+    path: String,
+}
+
+impl ApiGuard {
+    /// Create a guard for an API registered with a registry
+    pub(crate) fn new(registry: Arc<ApiRegistry>, path: String) -> Self {
+        ApiGuard { registry, path }
+    }
+    
+    /// Get the path of the guarded API
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+impl Drop for ApiGuard {
+    fn drop(&mut self) {
+        self.registry.unregister(&self.path);
+    }
+}
@@ -0,0 +1,285 @@
+use std::cell::RefCell;
+use std::collections::{HashSet, VecDeque};
+use std::future::Future;
+use std::sync::{Condvar, Mutex};
+
+use tokio::sync::Notify;
+
+thread_local! {
+    /// Schedulers that admitted the request being handled on this thread
+    static ADMITTED: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+}
+
+tokio::task_local! {
+    /// Schedulers that admitted the async request being handled by this task
+    static ADMITTED_ASYNC: Vec<usize>;
+}
+
+/// Where a request waiting for a worker came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RequestClass {
+    /// Made to the hub itself
+    Local = 0,
+    /// Escalated from a child hub
+    Escalated = 1,
+}
+
+/// Fair queue limiting the number of requests a hub handles at once
+///
+/// Requests past the limit wait for a worker, and waiting local and escalated
+/// requests are admitted in turn, so a flood of escalations from the children
+/// can't hold up requests made to the hub itself. Admitted requests still run on
+/// the thread (or task) that made them.
+pub(crate) struct RequestScheduler {
+    /// Workers, running requests and waiting requests
+    state: Mutex<SchedulerState>,
+    /// Wakes waiting requests when some are admitted
+    admitted: Condvar,
+    /// Wakes waiting async requests when some are admitted
+    admitted_async: Notify,
+}
+
+#[derive(Default)]
+struct SchedulerState {
+    /// Number of requests handled at once (0 for no limit)
+    workers: usize,
+    /// Number of requests being handled
+    running: usize,
+    /// Tickets of the requests waiting, by class
+    queues: [VecDeque<u64>; 2],
+    /// Tickets admitted whose requests haven't woken up yet
+    ready: HashSet<u64>,
+    /// Ticket of the next request to wait
+    next_ticket: u64,
+    /// Class admitted first when a worker frees up
+    next_class: usize,
+}
+
+impl SchedulerState {
+    /// Admit waiting requests while there are free workers, alternating between classes
+    fn admit(&mut self) -> bool {
+        let mut admitted = false;
+        while self.workers == 0 || self.running < self.workers {
+            let class = (0..self.queues.len())
+                .map(|offset| (self.next_class + offset) % self.queues.len())
+                .find(|class| !self.queues[*class].is_empty());
+            let Some(class) = class else {
+                break;
+            };
+            let ticket = self.queues[class].pop_front().unwrap();
+            self.ready.insert(ticket);
+            self.running += 1;
+            self.next_class = (class + 1) % self.queues.len();
+            admitted = true;
+        }
+        admitted
+    }
+}
+
+impl RequestScheduler {
+    /// Create a scheduler with a number of workers (0 for no limit)
+    pub(crate) fn new(workers: usize) -> Self {
+        RequestScheduler {
+            state: Mutex::new(SchedulerState { workers, ..SchedulerState::default() }),
+            admitted: Condvar::new(),
+            admitted_async: Notify::new(),
+        }
+    }
+
+    /// Change the number of workers, admitting waiting requests if there are more
+    pub(crate) fn set_workers(&self, workers: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.workers = workers;
+        if state.admit() {
+            self.wake();
+        }
+    }
+
+    /// Wake the requests waiting for a worker, so the admitted ones can run
+    fn wake(&self) {
+        self.admitted.notify_all();
+        self.admitted_async.notify_waiters();
+    }
+
+    /// Free a worker, admitting the next waiting request
+    fn release(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.running -= 1;
+        if state.admit() {
+            self.wake();
+        }
+    }
+
+    /// Identifies the scheduler in the lists of schedulers that admitted a request
+    fn id(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    /// Whether the request being handled on this thread or task was admitted by this scheduler
+    pub(crate) fn is_admitted(&self) -> bool {
+        let id = self.id();
+        ADMITTED.with(|admitted| admitted.borrow().contains(&id))
+            || ADMITTED_ASYNC.try_with(|admitted| admitted.contains(&id)).unwrap_or(false)
+    }
+
+    /// Number of requests waiting for a worker
+    pub(crate) fn depth(&self) -> usize {
+        self.state.lock().unwrap().queues.iter().map(VecDeque::len).sum()
+    }
+
+    /// Wait for a worker to handle a request, which is freed when the returned guard is dropped
+    ///
+    /// Returns `None` without waiting if there's no limit, or if the request is
+    /// made while this scheduler's thread or task is handling another one (e.g. by a
+    /// handler calling its own hub), which would otherwise wait on itself.
+    pub(crate) fn admit(&self, class: RequestClass) -> Option<AdmissionGuard<'_>> {
+        let id = self.id();
+        if self.is_admitted() {
+            return None;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        if state.workers == 0 {
+            return None;
+        }
+        if state.running < state.workers && state.queues.iter().all(VecDeque::is_empty) {
+            state.running += 1;
+        } else {
+            let ticket = state.next_ticket;
+            state.next_ticket += 1;
+            state.queues[class as usize].push_back(ticket);
+            let mut state = self.admitted.wait_while(state, |state| !state.ready.contains(&ticket)).unwrap();
+            state.ready.remove(&ticket);
+        }
+
+        ADMITTED.with(|admitted| admitted.borrow_mut().push(id));
+        Some(AdmissionGuard { scheduler: self })
+    }
+
+    /// Handle a request from async code once a worker is free, like `admit` but
+    /// waiting without blocking the thread
+    ///
+    /// Requests the future makes to the same scheduler, from its task or from the
+    /// thread polling it, aren't queued behind it. A request dropped while it waits
+    /// gives up its place in the queue.
+    pub(crate) async fn run_async<F: Future>(&self, class: RequestClass, request: F) -> F::Output {
+        if self.is_admitted() {
+            return request.await;
+        }
+
+        let _worker = self.admit_async(class).await;
+        let mut admitted = ADMITTED_ASYNC.try_with(Vec::clone).unwrap_or_default();
+        admitted.push(self.id());
+        ADMITTED_ASYNC.scope(admitted, request).await
+    }
+
+    /// Run part of a request on another thread, sharing the worker of the task
+    /// handling it if it was admitted
+    pub(crate) fn run_on_thread<T>(&self, admitted: bool, handle: impl FnOnce() -> T) -> T {
+        if !admitted {
+            return handle();
+        }
+
+        let id = self.id();
+        ADMITTED.with(|admitted| admitted.borrow_mut().push(id));
+        let _admitted = ThreadAdmission { id };
+        handle()
+    }
+
+    /// Wait for a worker without blocking the thread, for `run_async`
+    async fn admit_async(&self, class: RequestClass) -> Option<AsyncAdmissionGuard<'_>> {
+        let ticket = {
+            let mut state = self.state.lock().unwrap();
+            if state.workers == 0 {
+                return None;
+            }
+            if state.running < state.workers && state.queues.iter().all(VecDeque::is_empty) {
+                state.running += 1;
+                return Some(AsyncAdmissionGuard { scheduler: self });
+            }
+            let ticket = state.next_ticket;
+            state.next_ticket += 1;
+            state.queues[class as usize].push_back(ticket);
+            ticket
+        };
+
+        let mut waiting = WaitingTicket { scheduler: self, ticket: Some(ticket) };
+        loop {
+            // Listen before checking, so an admission in between isn't missed
+            let admitted = self.admitted_async.notified();
+            tokio::pin!(admitted);
+            admitted.as_mut().enable();
+            if self.state.lock().unwrap().ready.remove(&ticket) {
+                waiting.ticket = None;
+                return Some(AsyncAdmissionGuard { scheduler: self });
+            }
+            admitted.await;
+        }
+    }
+}
+
+/// Frees a worker of a `RequestScheduler` when dropped
+pub(crate) struct AdmissionGuard<'a> {
+    scheduler: &'a RequestScheduler,
+}
+
+impl Drop for AdmissionGuard<'_> {
+    fn drop(&mut self) {
+        drop(ThreadAdmission { id: self.scheduler.id() });
+        self.scheduler.release();
+    }
+}
+
+/// Marks this thread as handling a request admitted by a scheduler, until dropped
+struct ThreadAdmission {
+    /// Id of the scheduler
+    id: usize,
+}
+
+impl Drop for ThreadAdmission {
+    fn drop(&mut self) {
+        ADMITTED.with(|admitted| {
+            let mut admitted = admitted.borrow_mut();
+            if let Some(position) = admitted.iter().rposition(|admitted| *admitted == self.id) {
+                admitted.remove(position);
+            }
+        });
+    }
+}
+
+/// Frees a worker of a `RequestScheduler` taken by an async request when dropped
+struct AsyncAdmissionGuard<'a> {
+    scheduler: &'a RequestScheduler,
+}
+
+impl Drop for AsyncAdmissionGuard<'_> {
+    fn drop(&mut self) {
+        self.scheduler.release();
+    }
+}
+
+/// Place of an async request in a `RequestScheduler`'s queue, given up if the
+/// request is dropped before it's admitted
+struct WaitingTicket<'a> {
+    scheduler: &'a RequestScheduler,
+    /// The ticket, until the request is admitted
+    ticket: Option<u64>,
+}
+
+impl Drop for WaitingTicket<'_> {
+    fn drop(&mut self) {
+        let Some(ticket) = self.ticket else {
+            return;
+        };
+
+        let mut state = self.scheduler.state.lock().unwrap();
+        for queue in &mut state.queues {
+            queue.retain(|waiting| *waiting != ticket);
+        }
+        // Admitted in the meantime, so its worker goes to the next request
+        if state.ready.remove(&ticket) {
+            drop(state);
+            self.scheduler.release();
+        }
+    }
+}
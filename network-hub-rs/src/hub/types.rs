@@ -1,10 +1,29 @@
-use std::any::Any;
+use std::any::{Any, TypeId};
+use std::cmp::Ordering;
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::fmt;
+use std::io::Read;
+use std::str::FromStr;
+use std::sync::{mpsc, Arc, Mutex, RwLock};
+use std::sync::atomic::AtomicU64;
+use futures::future::BoxFuture;
 use serde::{Serialize, Deserialize};
 
+use crate::error::HubError;
+use crate::utils::current_time_millis;
+
 /// Represents a scope level of the hub
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+///
+/// Scopes are ordered from narrowest to widest:
+/// `Thread < Process < Machine < Network`. A hub can only be the parent
+/// of hubs with a strictly narrower scope.
+///
+/// Intermediate tiers (such as a rack or a datacenter) use `Custom` with a
+/// numeric level. The named scopes sit at levels 0 (`Thread`), 100 (`Process`),
+/// 200 (`Machine`) and 300 (`Network`), so e.g. `Custom(250)` ranks between
+/// `Machine` and `Network`. A custom scope at the same level as a named one
+/// ranks just above it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum HubScope {
     /// Thread-level scope (within a single thread)
     Thread,
@@ -14,6 +33,80 @@ pub enum HubScope {
     Machine,
     /// Network-level scope (across machines on a network)
     Network,
+    /// Custom scope at a numeric level relative to the named scopes
+    Custom(u32),
+}
+
+impl HubScope {
+    /// Rank of the scope in the hierarchy (wider scopes rank higher)
+    pub fn level(&self) -> u32 {
+        match self {
+            HubScope::Thread => 0,
+            HubScope::Process => 100,
+            HubScope::Machine => 200,
+            HubScope::Network => 300,
+            HubScope::Custom(level) => *level,
+        }
+    }
+    
+    /// Check whether a hub at this scope can be the parent of a hub at `child` scope
+    pub fn is_parent_of(&self, child: &HubScope) -> bool {
+        self > child
+    }
+}
+
+impl PartialOrd for HubScope {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for HubScope {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let is_custom = |scope: &HubScope| matches!(scope, HubScope::Custom(_));
+        self.level()
+            .cmp(&other.level())
+            .then_with(|| is_custom(self).cmp(&is_custom(other)))
+    }
+}
+
+impl fmt::Display for HubScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HubScope::Custom(level) => write!(f, "Custom({})", level),
+            named => write!(f, "{:?}", named),
+        }
+    }
+}
+
+impl FromStr for HubScope {
+    type Err = HubError;
+    
+    /// Parse a scope name (case-insensitive), or `Custom(<level>)`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.trim().to_lowercase();
+        match lower.as_str() {
+            "thread" => Ok(HubScope::Thread),
+            "process" => Ok(HubScope::Process),
+            "machine" => Ok(HubScope::Machine),
+            "network" => Ok(HubScope::Network),
+            _ => lower
+                .strip_prefix("custom(")
+                .and_then(|rest| rest.strip_suffix(')'))
+                .and_then(|level| level.trim().parse().ok())
+                .map(HubScope::Custom)
+                .ok_or_else(|| HubError::InvalidState(format!("Unknown hub scope: {}", s))),
+        }
+    }
+}
+
+impl TryFrom<&str> for HubScope {
+    type Error = HubError;
+    
+    /// Parse a scope name, like `str::parse`
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
 }
 
 /// Message with typed data
@@ -30,6 +123,18 @@ pub struct Message<T> {
     pub timestamp: u64,
 }
 
+impl<T> Message<T> {
+    /// Whether the message has outlived the time to live in its `ttl_ms` metadata entry
+    ///
+    /// Messages without a (valid) time to live never expire.
+    pub fn is_expired(&self) -> bool {
+        self.metadata
+            .get(crate::hub::TTL_KEY)
+            .and_then(|ttl| ttl.parse::<u64>().ok())
+            .is_some_and(|ttl| current_time_millis() > self.timestamp.saturating_add(ttl))
+    }
+}
+
 /// Request to an API endpoint
 pub struct ApiRequest {
     /// API path
@@ -42,6 +147,21 @@ pub struct ApiRequest {
     pub sender_id: String,
 }
 
+impl ApiRequest {
+    /// Copy the request, if its data is of a registered type (see `ApiResponse::register_data_type`)
+    pub fn try_clone(&self) -> Option<ApiRequest> {
+        let cloner = *DATA_CLONERS.read().unwrap().get(&(*self.data).type_id())?;
+        let data = cloner(self.data.as_ref())?;
+        
+        Some(ApiRequest {
+            path: self.path.clone(),
+            data,
+            metadata: self.metadata.clone(),
+            sender_id: self.sender_id.clone(),
+        })
+    }
+}
+
 /// Response from an API endpoint
 pub struct ApiResponse {
     /// Response data
@@ -52,8 +172,166 @@ pub struct ApiResponse {
     pub status: ResponseStatus,
 }
 
+impl ApiResponse {
+    /// Create a successful response whose body is read incrementally from `reader`
+    ///
+    /// Use this for large payloads that shouldn't be held in memory at once.
+    pub fn stream(reader: Box<dyn Read + Send>) -> Self {
+        ApiResponse {
+            data: Box::new(ResponseStream(Mutex::new(reader))),
+            metadata: HashMap::new(),
+            status: ResponseStatus::Success,
+        }
+    }
+    
+    /// Create a successful response whose data can be copied by `try_clone`
+    pub fn cloneable<T: ResponseData>(data: T) -> Self {
+        Self::register_data_type::<T>();
+        ApiResponse {
+            data: Box::new(data),
+            metadata: HashMap::new(),
+            status: ResponseStatus::Success,
+        }
+    }
+    
+    /// Let `try_clone` copy responses whose data is a `T`
+    ///
+    /// Strings, numbers, booleans, `()`, byte and string vectors, string maps and
+    /// JSON values are registered already.
+    pub fn register_data_type<T: ResponseData>() {
+        let type_id = TypeId::of::<T>();
+        if !DATA_CLONERS.read().unwrap().contains_key(&type_id) {
+            DATA_CLONERS.write().unwrap().insert(type_id, clone_data::<T>);
+        }
+    }
+    
+    /// Copy the response, if its data is of a registered type (see `register_data_type`)
+    ///
+    /// Data of any other type, such as a stream, can't be copied.
+    pub fn try_clone(&self) -> Option<ApiResponse> {
+        let cloner = *DATA_CLONERS.read().unwrap().get(&(*self.data).type_id())?;
+        let data = cloner(self.data.as_ref())?;
+        
+        Some(ApiResponse {
+            data,
+            metadata: self.metadata.clone(),
+            status: self.status,
+        })
+    }
+    
+    /// Get the response data as a string, if it is a `String` or a `&str`
+    pub fn as_str(&self) -> Option<&str> {
+        self.data
+            .downcast_ref::<String>()
+            .map(String::as_str)
+            .or_else(|| self.data.downcast_ref::<&str>().copied())
+    }
+    
+    /// Take the response data as a string, if it is a `String` or a `&str`
+    pub fn into_string(self) -> Option<String> {
+        match self.data.downcast::<String>() {
+            Ok(data) => Some(*data),
+            Err(data) => data.downcast_ref::<&str>().map(|data| data.to_string()),
+        }
+    }
+    
+    /// Convert the response data to JSON
+    ///
+    /// Supports strings (which become JSON strings, without being parsed), numbers,
+    /// booleans, vectors of strings, numbers or booleans, and JSON values.
+    pub fn as_json(&self) -> Option<serde_json::Value> {
+        fn to_json<T: Clone + Into<serde_json::Value> + 'static>(data: &(dyn Any + Send + Sync)) -> Option<serde_json::Value> {
+            data.downcast_ref::<T>().cloned().map(Into::into)
+        }
+        
+        if let Some(data) = self.as_str() {
+            return Some(data.into());
+        }
+        let data = self.data.as_ref();
+        to_json::<serde_json::Value>(data)
+            .or_else(|| to_json::<bool>(data))
+            .or_else(|| to_json::<i32>(data))
+            .or_else(|| to_json::<i64>(data))
+            .or_else(|| to_json::<u32>(data))
+            .or_else(|| to_json::<u64>(data))
+            .or_else(|| to_json::<usize>(data))
+            .or_else(|| to_json::<f64>(data))
+            .or_else(|| to_json::<Vec<String>>(data))
+            .or_else(|| to_json::<Vec<i32>>(data))
+            .or_else(|| to_json::<Vec<i64>>(data))
+            .or_else(|| to_json::<Vec<f64>>(data))
+            .or_else(|| to_json::<Vec<bool>>(data))
+    }
+    
+    /// Check whether the response body is streamed
+    pub fn is_stream(&self) -> bool {
+        self.data.is::<ResponseStream>()
+    }
+    
+    /// Take the body reader out of a streaming response
+    ///
+    /// Returns the response unchanged if its body is not a stream.
+    pub fn into_stream(self) -> Result<Box<dyn Read + Send>, ApiResponse> {
+        match self.data.downcast::<ResponseStream>() {
+            Ok(stream) => Ok(stream.0.into_inner().unwrap_or_else(|e| e.into_inner())),
+            Err(data) => Err(ApiResponse {
+                data,
+                metadata: self.metadata,
+                status: self.status,
+            }),
+        }
+    }
+}
+
+/// Response data that can be copied
+///
+/// Implemented for every `Clone` type. Responses are only copied if the type
+/// of their data is registered with `ApiResponse::register_data_type` (or the
+/// response was created with `ApiResponse::cloneable`), so data that can't be
+/// cloned can still be returned as a plain `Box<dyn Any>`.
+pub trait ResponseData: Any + Send + Sync {
+    /// Copy the data into a new box
+    fn clone_box(&self) -> Box<dyn ResponseData>;
+}
+
+impl<T: Any + Clone + Send + Sync> ResponseData for T {
+    fn clone_box(&self) -> Box<dyn ResponseData> {
+        Box::new(self.clone())
+    }
+}
+
+/// Copies response data known to be of a particular type
+type DataCloner = fn(&(dyn Any + Send + Sync)) -> Option<Box<dyn Any + Send + Sync>>;
+
+fn clone_data<T: ResponseData>(data: &(dyn Any + Send + Sync)) -> Option<Box<dyn Any + Send + Sync>> {
+    data.downcast_ref::<T>().map(|data| T::clone_box(data) as Box<dyn Any + Send + Sync>)
+}
+
+lazy_static::lazy_static! {
+    /// Data types that `ApiResponse::try_clone` and `ApiRequest::try_clone` can copy
+    static ref DATA_CLONERS: RwLock<HashMap<TypeId, DataCloner>> = {
+        let mut cloners: HashMap<TypeId, DataCloner> = HashMap::new();
+        macro_rules! register {
+            ($($data_type:ty),*) => {
+                $(cloners.insert(TypeId::of::<$data_type>(), clone_data::<$data_type>);)*
+            };
+        }
+        register!(
+            String, &'static str, (), bool,
+            i8, i16, i32, i64, isize, u8, u16, u32, u64, usize, f32, f64,
+            Vec<u8>, Vec<String>, HashMap<String, String>, serde_json::Value
+        );
+        RwLock::new(cloners)
+    };
+}
+
+/// Body of a streaming response
+///
+/// The reader is wrapped in a mutex so the response data stays `Sync`.
+pub struct ResponseStream(Mutex<Box<dyn Read + Send>>);
+
 /// Status of an API response
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ResponseStatus {
     /// Success
     Success,
@@ -65,8 +343,71 @@ pub enum ResponseStatus {
     Intercepted,
     /// Approximated
     Approximated,
+    /// The request could not be decoded
+    BadRequest,
+    /// The caller cancelled the request before it was answered
+    Cancelled,
+    /// The hub is shutting down and no longer accepts requests
+    ShuttingDown,
 }
 
+/// Step of request resolution that resolves a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ResolutionStep {
+    /// An API registered with the hub matches the path
+    Local,
+    /// The request is routed down to the child hub serving the path
+    Child,
+    /// The request is escalated to the parent hub
+    Parent,
+    /// An API registered with the hub has a similar path
+    Approximated,
+    /// Nothing matches the path
+    NotFound,
+}
+
+/// How a hub would resolve a request, as reported by `Hub::explain`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResolutionTrace {
+    /// ID of the hub the trace was taken at
+    pub hub_id: String,
+    /// Step that resolves the request at this hub
+    pub step: ResolutionStep,
+    /// Registered path (or pattern) of the API the request resolves to, for
+    /// `Local` and `Approximated` steps
+    pub path: Option<String>,
+    /// Similarity of the requested path to the chosen one, for `Approximated` steps
+    pub similarity: Option<f64>,
+    /// Path the request would be retried at if the chosen API fails
+    pub fallback_path: Option<String>,
+    /// Whether API interceptors are registered for the path
+    ///
+    /// Interceptors aren't run when explaining, so any of them may still
+    /// intercept the request before it reaches the step reported here.
+    pub interceptable: bool,
+    /// Trace at the child or parent hub, for `Child` and `Parent` steps
+    pub next: Option<Box<ResolutionTrace>>,
+}
+
+impl ResolutionTrace {
+    /// Trace at the hub where resolution ends, following `next`
+    pub fn last(&self) -> &ResolutionTrace {
+        let mut trace = self;
+        while let Some(next) = &trace.next {
+            trace = next;
+        }
+        trace
+    }
+}
+
+/// Handler of a subscription, returning its result
+pub type SyncSubscriber =
+    Arc<Mutex<Box<dyn Fn(&Message<Box<dyn Any + Send + Sync>>) -> Option<Box<dyn Any + Send + Sync>> + Send + Sync>>>;
+
+/// Handler of an async subscription, returning the future of its result
+pub type AsyncSubscriber =
+    Arc<dyn Fn(&Message<Box<dyn Any + Send + Sync>>) -> BoxFuture<'static, Option<Box<dyn Any + Send + Sync>>> + Send + Sync>;
+
 /// A subscription to messages
 pub struct Subscription {
     /// Subscription ID
@@ -74,7 +415,22 @@ pub struct Subscription {
     /// Priority (higher priorities are checked first)
     pub priority: i32,
     /// Message handler function
-    pub handler: Arc<Mutex<Box<dyn Fn(&Message<Box<dyn Any + Send + Sync>>) -> Option<Box<dyn Any + Send + Sync>> + Send + Sync>>>,
+    ///
+    /// For an async subscription, this blocks until the future of its result completes.
+    pub handler: SyncSubscriber,
+    /// The handler of an async subscription, awaited directly by `Hub::publish_async`
+    pub(crate) async_handler: Option<AsyncSubscriber>,
+    /// Queue feeding the handler, if it runs on a thread of its own
+    pub(crate) queue: Option<SubscriptionQueue>,
+}
+
+/// Bounded queue feeding a subscription's handler on a thread of its own
+#[derive(Clone)]
+pub(crate) struct SubscriptionQueue {
+    /// Sender of the messages to handle
+    pub sender: mpsc::SyncSender<Arc<Message<Box<dyn Any + Send + Sync>>>>,
+    /// Number of messages dropped because the queue was full
+    pub dropped: Arc<AtomicU64>,
 }
 
 /// An interceptor for messages or API requests
@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+
+use jsonschema::JSONSchema;
+use serde_json::{json, Value};
+
+use crate::error::{HubError, Result};
+use crate::hub::types::{ApiRequest, ApiResponse, ResponseStatus};
+use crate::proxy::HttpRequestParts;
+use crate::transport::SerdePayload;
+
+/// JSON schema the data of an API's requests is checked against before its handler runs
+pub(crate) struct RequestValidator {
+    /// Compiled schema
+    schema: JSONSchema,
+}
+
+impl RequestValidator {
+    /// Compile a JSON schema, failing with `HubError::Config` if it isn't a valid schema
+    pub(crate) fn compile(path: &str, schema: &Value) -> Result<Self> {
+        let schema = JSONSchema::compile(schema)
+            .map_err(|e| HubError::Config(format!("Invalid schema for {}: {}", path, e)))?;
+        Ok(RequestValidator { schema })
+    }
+
+    /// Check a request's data against the schema
+    ///
+    /// Returns the `BadRequest` response to answer with if the data isn't JSON or
+    /// doesn't match. Its data is a JSON string with an `error` message and the
+    /// `errors` found, each with the JSON pointer to the offending value.
+    pub(crate) fn validate(&self, request: &ApiRequest) -> std::result::Result<(), ApiResponse> {
+        let instance = match request_json(request) {
+            Ok(instance) => instance,
+            Err(e) => return Err(bad_request(format!("Request data is not valid JSON: {}", e), Vec::new())),
+        };
+
+        if let Err(errors) = self.schema.validate(&instance) {
+            let errors = errors
+                .map(|e| json!({ "path": e.instance_path.to_string(), "message": e.to_string() }))
+                .collect();
+            return Err(bad_request("Request data does not match the API's schema".to_string(), errors));
+        }
+        Ok(())
+    }
+}
+
+/// Get the JSON a request carries: a string, a JSON value, a typed payload or an HTTP request's body
+fn request_json(request: &ApiRequest) -> serde_json::Result<Value> {
+    let data = &*request.data;
+    if let Some(value) = data.downcast_ref::<Value>() {
+        return Ok(value.clone());
+    }
+    if let Some(payload) = data.downcast_ref::<SerdePayload>() {
+        return Ok(payload.value().clone());
+    }
+    if let Some(http_request) = data.downcast_ref::<HttpRequestParts>() {
+        return serde_json::from_slice(&http_request.body);
+    }
+    let text = data
+        .downcast_ref::<String>()
+        .map(String::as_str)
+        .or_else(|| data.downcast_ref::<&str>().copied())
+        .unwrap_or("");
+    serde_json::from_str(text)
+}
+
+/// Response to a request whose data failed validation
+fn bad_request(error: String, errors: Vec<Value>) -> ApiResponse {
+    ApiResponse {
+        data: Box::new(json!({ "error": error, "errors": errors }).to_string()),
+        metadata: HashMap::from([("content_type".to_string(), "application/json".to_string())]),
+        status: ResponseStatus::BadRequest,
+    }
+}
@@ -4,11 +4,14 @@ pub mod hub;
 pub mod transport;
 /// Reverse proxy implementation
 pub mod proxy;
+/// Configuration files for a hub, its transport and proxy
+pub mod config;
 /// Common error types
 pub mod error;
 /// Common utilities
 pub mod utils;
 
-pub use hub::{Hub, HubScope, Message, ApiRequest, ApiResponse, ResponseStatus};
+pub use hub::{Hub, HubBuilder, HubScope, Message, ApiRequest, ApiResponse, ResponseStatus};
 pub use transport::{NetworkTransport, TlsConfig};
-pub use proxy::HttpReverseProxy;
\ No newline at end of file
+pub use proxy::HttpReverseProxy;
+pub use config::Config;
\ No newline at end of file
@@ -0,0 +1,50 @@
+use std::io::Write;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use tracing::warn;
+
+/// Record of a request the proxy answered
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AccessLog {
+    /// Time the request was received, in milliseconds since the Unix epoch
+    pub timestamp: u64,
+    /// Address of the client
+    pub client: SocketAddr,
+    /// HTTP method of the request
+    pub method: String,
+    /// Path of the request, with its query
+    pub path: String,
+    /// Route target the request was forwarded to, if it was proxied
+    pub upstream: Option<String>,
+    /// HTTP status code of the response
+    pub status: u16,
+    /// Size of the response body sent to the client, in bytes
+    pub bytes: u64,
+    /// Time taken to answer the request, in milliseconds
+    pub latency_ms: u64,
+}
+
+/// Handler of the access log records of a proxy
+pub(crate) type AccessLogHandler = Arc<dyn Fn(&AccessLog) + Send + Sync>;
+
+impl AccessLog {
+    /// Create a handler writing each record to `writer` as a line of JSON
+    ///
+    /// Pass it to `HttpReverseProxy::set_access_log`. Records are written whole
+    /// even when several connections complete requests at once.
+    pub fn json_lines<W: Write + Send + 'static>(writer: W) -> impl Fn(&AccessLog) + Send + Sync {
+        let writer = Mutex::new(writer);
+        move |record| {
+            let Ok(mut line) = serde_json::to_vec(record) else {
+                return;
+            };
+            line.push(b'\n');
+            let mut writer = writer.lock().unwrap();
+            if let Err(e) = writer.write_all(&line).and_then(|_| writer.flush()) {
+                warn!(error = %e, "Error writing access log");
+            }
+        }
+    }
+}
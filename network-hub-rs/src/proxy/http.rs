@@ -0,0 +1,208 @@
+use std::io::{self, Write};
+
+/// An HTTP request received by the proxy, parsed once when it's read
+///
+/// Carried as the data of the `ApiRequest` the proxy sends through the hub, so
+/// handlers and the forwarding code don't re-parse the raw request.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HttpRequestParts {
+    /// Request method, e.g. `GET`
+    pub method: String,
+    /// Request target, with its query
+    pub path: String,
+    /// Protocol version, e.g. `HTTP/1.1`; empty if the request line has none
+    pub version: String,
+    /// Headers in the order they were sent, with their names as sent
+    pub headers: Vec<(String, String)>,
+    /// Request body
+    pub body: Vec<u8>,
+}
+
+impl HttpRequestParts {
+    /// Parse a request from its raw bytes
+    ///
+    /// Everything after the blank line ending the headers is taken as the body;
+    /// a request without one has an empty body. Returns `None` if the request
+    /// line has no method or path.
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let (head, body) = split_head(bytes);
+        let head = String::from_utf8_lossy(head);
+        let mut lines = head.lines();
+
+        let mut request_line = lines.next()?.split_whitespace();
+        let method = request_line.next()?.to_string();
+        let path = request_line.next()?.to_string();
+        let version = request_line.next().unwrap_or("").to_string();
+
+        Some(HttpRequestParts {
+            method,
+            path,
+            version,
+            headers: parse_headers(lines),
+            body: body.to_vec(),
+        })
+    }
+
+    /// Get the value of a header, whatever the case of its name
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+
+    /// Whether the client wants the connection kept open after this request
+    ///
+    /// HTTP/1.1 connections are persistent unless the client sends `Connection: close`;
+    /// older versions must opt in with `Connection: keep-alive`.
+    pub fn keep_alive(&self) -> bool {
+        match self.header("connection").map(|value| value.to_ascii_lowercase()).as_deref() {
+            Some("close") => false,
+            Some("keep-alive") => true,
+            _ => self.version == "HTTP/1.1",
+        }
+    }
+
+    /// Whether the client accepts gzip-encoded responses
+    pub fn accepts_gzip(&self) -> bool {
+        self.header("accept-encoding").is_some_and(|value| {
+            value.split(',').any(|encoding| {
+                let mut params = encoding.split(';').map(str::trim);
+                let name = params.next().unwrap_or("");
+                let rejected = params.any(|param| {
+                    param.strip_prefix("q=").and_then(|q| q.parse::<f32>().ok()) == Some(0.0)
+                });
+                (name.eq_ignore_ascii_case("gzip") || name == "*") && !rejected
+            })
+        })
+    }
+
+    /// Serialize the request as HTTP/1.1, headers as they are
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut request = format!("{} {} {}\r\n", self.method, self.path, self.version).into_bytes();
+        write_headers(&mut request, &self.headers);
+        request.extend_from_slice(&self.body);
+        request
+    }
+}
+
+/// An HTTP response, as the proxy writes it to a client
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HttpResponseParts {
+    /// Status code, e.g. `200`
+    pub status: u16,
+    /// Reason phrase, e.g. `OK`
+    pub reason: String,
+    /// Headers in the order they are sent
+    pub headers: Vec<(String, String)>,
+    /// Response body, without any transfer encoding
+    pub body: Vec<u8>,
+}
+
+impl HttpResponseParts {
+    /// Create a plain text response whose body is its reason phrase, closing the connection
+    pub fn plain(status: u16, reason: &str) -> Self {
+        HttpResponseParts {
+            status,
+            reason: reason.to_string(),
+            headers: vec![
+                ("Content-Type".to_string(), "text/plain".to_string()),
+                ("Content-Length".to_string(), reason.len().to_string()),
+                ("Connection".to_string(), "close".to_string()),
+            ],
+            body: reason.as_bytes().to_vec(),
+        }
+    }
+
+    /// Parse a response from its raw bytes, joining the chunks of a chunked body
+    ///
+    /// Returns `None` if the status line has no valid status code.
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let (head, body) = split_head(bytes);
+        let head = String::from_utf8_lossy(head);
+        let mut lines = head.lines();
+
+        let (_, status_and_reason) = lines.next()?.split_once(' ')?;
+        let (status, reason) = status_and_reason.split_once(' ').unwrap_or((status_and_reason, ""));
+        let headers = parse_headers(lines);
+
+        let chunked = find_header(&headers, "transfer-encoding").is_some_and(|value| value.eq_ignore_ascii_case("chunked"));
+        Some(HttpResponseParts {
+            status: status.trim().parse().ok()?,
+            reason: reason.trim().to_string(),
+            headers,
+            body: if chunked { decode_chunked(body) } else { body.to_vec() },
+        })
+    }
+
+    /// Get the value of a header, whatever the case of its name
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+
+    /// Write the status line and headers
+    pub fn write_head<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut head = format!("HTTP/1.1 {} {}\r\n", self.status, self.reason).into_bytes();
+        write_headers(&mut head, &self.headers);
+        writer.write_all(&head)
+    }
+
+    /// Write the whole response, head and body
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.write_head(writer)?;
+        writer.write_all(&self.body)
+    }
+}
+
+/// Split a message into its head (without the blank line ending it) and body
+fn split_head(bytes: &[u8]) -> (&[u8], &[u8]) {
+    match find(bytes, b"\r\n\r\n") {
+        Some(head_end) => (&bytes[..head_end], &bytes[head_end + 4..]),
+        None => (bytes, &[]),
+    }
+}
+
+/// Parse the header lines following a request or status line
+fn parse_headers<'a>(lines: impl Iterator<Item = &'a str>) -> Vec<(String, String)> {
+    lines
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect()
+}
+
+/// Append header lines and the blank line ending them
+fn write_headers(message: &mut Vec<u8>, headers: &[(String, String)]) {
+    for (name, value) in headers {
+        message.extend_from_slice(format!("{}: {}\r\n", name, value).as_bytes());
+    }
+    message.extend_from_slice(b"\r\n");
+}
+
+/// Find the value of a header, whatever the case of its name
+fn find_header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(header, _)| header.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+/// Join the chunks of a chunked body
+fn decode_chunked(mut body: &[u8]) -> Vec<u8> {
+    let mut decoded = Vec::new();
+    while let Some(line_end) = find(body, b"\r\n") {
+        let size_line = String::from_utf8_lossy(&body[..line_end]);
+        let Ok(size) = usize::from_str_radix(size_line.split(';').next().unwrap_or("").trim(), 16) else {
+            break;
+        };
+        body = &body[line_end + 2..];
+        if size == 0 || body.len() < size {
+            break;
+        }
+        decoded.extend_from_slice(&body[..size]);
+        body = body.get(size + 2..).unwrap_or(&[]);
+    }
+    decoded
+}
+
+/// Find the position of `needle` in `haystack`
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
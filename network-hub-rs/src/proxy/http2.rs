@@ -0,0 +1,297 @@
+use std::convert::Infallible;
+use std::future::Future;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use hyper::body::HttpBody;
+use hyper::header::HOST;
+use hyper::http::request::Parts;
+use hyper::server::conn::Http;
+use hyper::service::service_fn;
+use hyper::{Body, Request, Response, StatusCode};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::runtime::Runtime;
+use tokio_rustls::TlsAcceptor;
+use tracing::{debug, error, Span};
+
+use super::{HttpReverseProxy, HttpRequestParts, HttpResponseParts};
+use crate::error::{HubError, Result};
+use crate::transport::create_server_config;
+
+/// ALPN protocols offered to clients, most preferred first
+const ALPN_PROTOCOLS: [&[u8]; 2] = [b"h2", b"http/1.1"];
+
+/// Headers of an HTTP/1.1 response that are specific to its connection, which HTTP/2 forbids
+const CONNECTION_HEADERS: [&str; 5] = ["connection", "keep-alive", "proxy-connection", "transfer-encoding", "upgrade"];
+
+/// Serve a connection whose client negotiates HTTP/2 or HTTP/1.1 with ALPN
+///
+/// HTTP/2 streams are translated into the HTTP/1.1 requests the proxy already
+/// answers, so they're routed and forwarded the same way.
+pub(super) fn serve_connection(
+    proxy: &HttpReverseProxy,
+    stream: TcpStream,
+    client_addr: SocketAddr,
+    span: &Span,
+) -> Result<()> {
+    let idle_timeout = proxy.idle_timeout();
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(HubError::Io)?;
+
+    let mut server_config = create_server_config(&proxy.tls_config)?;
+    server_config.alpn_protocols = ALPN_PROTOCOLS.iter().map(|protocol| protocol.to_vec()).collect();
+    let acceptor = TlsAcceptor::from(Arc::new(server_config));
+
+    stream.set_nonblocking(true).map_err(HubError::Io)?;
+    let tls_stream = runtime.block_on(async {
+        let stream = tokio::net::TcpStream::from_std(stream)?;
+        with_timeout(idle_timeout, acceptor.accept(stream)).await
+    });
+    let tls_stream = match tls_stream {
+        Ok(tls_stream) => tls_stream,
+        Err(e) => {
+            error!(error = %e, "TLS setup error");
+            return Err(HubError::Tls(format!("TLS handshake failed: {}", e)));
+        }
+    };
+
+    if tls_stream.get_ref().1.alpn_protocol() != Some(b"h2") {
+        let mut stream = BlockingStream { runtime: &runtime, stream: tls_stream, timeout: idle_timeout };
+        let result = proxy.serve_http1(&mut stream, client_addr, span);
+        let _ = runtime.block_on(stream.stream.shutdown());
+        return result;
+    }
+
+    debug!("Serving HTTP/2");
+    let activity = Arc::new(Activity::new());
+    let service = {
+        let activity = Arc::clone(&activity);
+        service_fn(move |request| {
+            let proxy = proxy.clone();
+            let span = span.clone();
+            let activity = Arc::clone(&activity);
+            async move {
+                activity.begin();
+                let response = serve_stream(proxy, request, client_addr, span).await;
+                activity.end();
+                Ok::<_, Infallible>(response)
+            }
+        })
+    };
+
+    runtime.block_on(async {
+        let connection = Http::new().http2_only(true).serve_connection(tls_stream, service);
+        tokio::pin!(connection);
+        loop {
+            tokio::select! {
+                result = &mut connection => return result,
+                _ = tokio::time::sleep(idle_timeout), if !idle_timeout.is_zero() => {
+                    // Close connections without streams in flight for the idle timeout
+                    if activity.idle_for() >= idle_timeout {
+                        debug!(timeout_ms = idle_timeout.as_millis() as u64, "Closing idle connection");
+                        connection.as_mut().graceful_shutdown();
+                        return connection.await;
+                    }
+                }
+            }
+        }
+    })
+    .or_else(|e| {
+        if is_disconnect(&e) {
+            debug!("Client closed connection");
+            Ok(())
+        } else {
+            error!(error = %e, "HTTP/2 connection error");
+            Err(HubError::Network(format!("HTTP/2 connection error: {}", e)))
+        }
+    })
+}
+
+/// Whether an HTTP/2 connection failed only because the client went away
+fn is_disconnect(error: &hyper::Error) -> bool {
+    let mut source = std::error::Error::source(error);
+    while let Some(e) = source {
+        if let Some(e) = e.downcast_ref::<io::Error>() {
+            return matches!(
+                e.kind(),
+                io::ErrorKind::NotConnected | io::ErrorKind::ConnectionReset | io::ErrorKind::BrokenPipe
+            );
+        }
+        source = e.source();
+    }
+    false
+}
+
+/// Answer one HTTP/2 stream
+async fn serve_stream(
+    proxy: HttpReverseProxy,
+    request: Request<Body>,
+    client_addr: SocketAddr,
+    span: Span,
+) -> Response<Body> {
+    let (parts, mut body) = request.into_parts();
+
+    let max_body_size = proxy.max_body_size();
+    let mut content = Vec::new();
+    while let Some(chunk) = body.data().await {
+        match chunk {
+            Ok(chunk) if content.len() + chunk.len() > max_body_size => {
+                error!(max_body_size, "Rejecting request, body too large");
+                return plain_response(StatusCode::PAYLOAD_TOO_LARGE, "Payload Too Large");
+            }
+            Ok(chunk) => content.extend_from_slice(&chunk),
+            Err(e) => {
+                error!(error = %e, "Error reading request body");
+                return plain_response(StatusCode::BAD_REQUEST, "Bad Request");
+            }
+        }
+    }
+
+    // Handlers block, so they run off the connection's runtime
+    let request = request_parts(&parts, content);
+    let request_size = request.to_bytes().len();
+    let response = tokio::task::spawn_blocking(move || {
+        let _enter = span.enter();
+        let mut response = Vec::new();
+        proxy
+            .serve_request(&mut response, Some(request), request_size, client_addr, &span)
+            .map(|_| response)
+    })
+    .await;
+
+    match response {
+        Ok(Ok(response)) => http2_response(&response),
+        Ok(Err(e)) => {
+            error!(error = %e, "Error handling HTTP/2 request");
+            plain_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error")
+        }
+        Err(e) => {
+            error!(error = %e, "HTTP/2 request handler failed");
+            plain_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error")
+        }
+    }
+}
+
+/// Get the parts of an HTTP/2 request, as the proxy answers them
+fn request_parts(parts: &Parts, body: Vec<u8>) -> HttpRequestParts {
+    let mut headers = Vec::new();
+
+    // HTTP/2 carries the host in the :authority pseudo-header
+    if !parts.headers.contains_key(HOST) {
+        if let Some(authority) = parts.uri.authority() {
+            headers.push(("Host".to_string(), authority.to_string()));
+        }
+    }
+    for (name, value) in &parts.headers {
+        headers.push((name.to_string(), String::from_utf8_lossy(value.as_bytes()).into_owned()));
+    }
+
+    HttpRequestParts {
+        method: parts.method.to_string(),
+        path: parts.uri.path_and_query().map(|path| path.to_string()).unwrap_or_else(|| "/".to_string()),
+        version: "HTTP/2.0".to_string(),
+        headers,
+        body,
+    }
+}
+
+/// Build the HTTP/2 response equivalent to an HTTP/1.1 one written by the proxy
+fn http2_response(response: &[u8]) -> Response<Body> {
+    let Some(response) = HttpResponseParts::parse(response) else {
+        error!("Malformed response to HTTP/2 request");
+        return plain_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error");
+    };
+
+    let mut builder = Response::builder()
+        .status(StatusCode::from_u16(response.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR));
+    for (name, value) in &response.headers {
+        if !CONNECTION_HEADERS.iter().any(|header| name.eq_ignore_ascii_case(header)) {
+            builder = builder.header(name, value);
+        }
+    }
+    builder
+        .body(Body::from(response.body))
+        .unwrap_or_else(|_| plain_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error"))
+}
+
+/// Build a plain text response the proxy answers with itself
+fn plain_response(status: StatusCode, body: &'static str) -> Response<Body> {
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = status;
+    response
+}
+
+/// Run an I/O operation, failing with `TimedOut` if it takes longer than `timeout`
+///
+/// A zero timeout waits indefinitely.
+async fn with_timeout<T>(timeout: Duration, operation: impl Future<Output = io::Result<T>>) -> io::Result<T> {
+    if timeout.is_zero() {
+        return operation.await;
+    }
+    tokio::time::timeout(timeout, operation)
+        .await
+        .unwrap_or_else(|_| Err(io::ErrorKind::TimedOut.into()))
+}
+
+/// Streams in flight on an HTTP/2 connection, to tell when it's idle
+struct Activity {
+    /// Number of streams being answered
+    in_flight: AtomicUsize,
+    /// When the last stream was answered, or the connection was accepted
+    last_active: Mutex<Instant>,
+}
+
+impl Activity {
+    fn new() -> Self {
+        Activity { in_flight: AtomicUsize::new(0), last_active: Mutex::new(Instant::now()) }
+    }
+
+    fn begin(&self) {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn end(&self) {
+        *self.last_active.lock().unwrap() = Instant::now();
+        self.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// How long the connection has had no streams in flight
+    fn idle_for(&self) -> Duration {
+        if self.in_flight.load(Ordering::Relaxed) > 0 {
+            return Duration::ZERO;
+        }
+        self.last_active.lock().unwrap().elapsed()
+    }
+}
+
+/// Blocking reads and writes over an async stream, to serve HTTP/1.1 to
+/// clients that didn't negotiate HTTP/2
+struct BlockingStream<'a, S> {
+    /// Runtime driving the stream
+    runtime: &'a Runtime,
+    /// The async stream
+    stream: S,
+    /// Time a read or write may wait before failing with `TimedOut`
+    timeout: Duration,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Read for BlockingStream<'_, S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.runtime.block_on(with_timeout(self.timeout, self.stream.read(buf)))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Write for BlockingStream<'_, S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.runtime.block_on(with_timeout(self.timeout, self.stream.write(buf)))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.runtime.block_on(with_timeout(self.timeout, self.stream.flush()))
+    }
+}
@@ -0,0 +1,81 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+/// Requests received from a client IP address in the current metrics window
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ClientTraffic {
+    /// Number of requests received
+    pub requests: u64,
+    /// Total size of the requests received, headers and body, in bytes
+    pub bytes: u64,
+}
+
+/// Counters for the requests received from each client IP address
+///
+/// The counters are reset once a window has passed, so they reflect recent
+/// traffic rather than everything since the proxy started.
+pub(crate) struct ClientMetrics {
+    /// Length of a window, in milliseconds
+    window_ms: AtomicU64,
+    /// Counters for the current window
+    current: Mutex<ClientWindow>,
+}
+
+/// Counters collected since the start of a window
+struct ClientWindow {
+    /// When the window started
+    started: Instant,
+    /// Traffic by client IP address
+    clients: HashMap<IpAddr, ClientTraffic>,
+}
+
+impl ClientMetrics {
+    /// Create empty counters reset every `window`
+    pub(crate) fn new(window: Duration) -> Self {
+        ClientMetrics {
+            window_ms: AtomicU64::new(window.as_millis() as u64),
+            current: Mutex::new(ClientWindow {
+                started: Instant::now(),
+                clients: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Set the length of a window, applying from the next reset
+    pub(crate) fn set_window(&self, window: Duration) {
+        self.window_ms.store(window.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// Get the length of a window
+    pub(crate) fn window(&self) -> Duration {
+        Duration::from_millis(self.window_ms.load(Ordering::Relaxed))
+    }
+
+    /// Record a request received from a client
+    pub(crate) fn record(&self, client: IpAddr, bytes: usize) {
+        let mut current = self.current_window();
+        let traffic = current.clients.entry(client).or_default();
+        traffic.requests += 1;
+        traffic.bytes += bytes as u64;
+    }
+
+    /// Take a snapshot of the traffic of every client in the current window
+    pub(crate) fn snapshot(&self) -> HashMap<IpAddr, ClientTraffic> {
+        self.current_window().clients.clone()
+    }
+
+    /// Lock the counters, starting a new window if the current one has passed
+    fn current_window(&self) -> std::sync::MutexGuard<'_, ClientWindow> {
+        let mut current = self.current.lock().unwrap();
+        if current.started.elapsed() >= self.window() {
+            current.started = Instant::now();
+            current.clients.clear();
+        }
+        current
+    }
+}
@@ -1,12 +1,149 @@
+mod access_log;
+mod http;
+mod http2;
+mod metrics;
+
+pub use access_log::AccessLog;
+pub use http::{HttpRequestParts, HttpResponseParts};
+pub use metrics::ClientTraffic;
+
 use std::collections::HashMap;
-use std::net::{TcpListener, TcpStream, SocketAddr};
-use std::sync::{Arc, RwLock};
+use std::fs;
+use std::net::{IpAddr, TcpListener, TcpStream, SocketAddr};
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
 use std::thread;
+use std::time::{Duration, Instant};
 use std::io::{Read, Write};
+use std::path::Path;
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use tracing::{debug, error, info, info_span, warn};
 
 use crate::error::{HubError, Result};
 use crate::hub::{Hub, ApiRequest, ApiResponse, ResponseStatus};
 use crate::transport::{TlsConfig, create_server_tls_stream};
+use crate::utils::{
+    bind_listener, connect_tcp, current_time_millis, is_timeout, set_idle_timeout, WorkerPool, DEFAULT_LISTEN_BACKLOG,
+    DEFAULT_WORKER_THREADS,
+};
+
+use access_log::AccessLogHandler;
+use metrics::ClientMetrics;
+
+/// Size of the chunks used when streaming a response body to the client
+const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Maximum size of an HTTP request line plus headers
+const MAX_HEADER_SIZE: usize = 64 * 1024;
+
+/// Default time a client connection may sit idle before it is closed
+const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Default maximum size of a request or backend response body
+const DEFAULT_MAX_BODY_SIZE: usize = 10 * 1024 * 1024;
+
+/// Default time after which the per-client request counters are reset
+const DEFAULT_CLIENT_METRICS_WINDOW: Duration = Duration::from_secs(60);
+
+/// Smallest response body worth compressing
+const MIN_COMPRESSION_SIZE: usize = 256;
+
+/// Maximum number of idle connections kept open to each backend server
+const MAX_IDLE_BACKEND_CONNECTIONS: usize = 4;
+
+/// Time spent answering a connection rejected because every worker is busy
+const REJECT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Maximum number of request paths whose resolved route target is cached
+const ROUTE_CACHE_CAPACITY: usize = 256;
+
+/// Metadata key of the status code a target answered a forwarded request with
+const UPSTREAM_STATUS_KEY: &str = "upstream_status";
+
+/// Metadata key of proxied responses giving the route target the request was forwarded to
+const UPSTREAM_KEY: &str = "upstream";
+
+/// Metadata keys a response's content type is read from, in order of preference
+///
+/// Responses forwarded from a target carry its `content-type` header; hub
+/// handlers set `content_type`.
+const CONTENT_TYPE_KEYS: &[&str] = &["content-type", "content_type"];
+
+/// Client headers that aren't forwarded to the target
+///
+/// Besides the hop-by-hop headers, the proxy sets its own `Host` and `Content-Length`,
+/// and drops `Accept-Encoding` because it compresses responses to clients itself.
+const UNFORWARDED_HEADERS: &[&str] = &[
+    "host",
+    "content-length",
+    "accept-encoding",
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Outcome of reading a request from a client connection
+enum HttpRead {
+    /// A complete request, headers and body
+    Request(Vec<u8>),
+    /// The client closed the connection before sending another request
+    Closed,
+    /// The request headers exceed `MAX_HEADER_SIZE`
+    HeadersTooLarge,
+    /// The declared body length exceeds the maximum body size
+    BodyTooLarge(usize),
+}
+
+/// Least-recently-used cache of request paths to the route target they resolved to
+struct RouteCache {
+    /// Cached targets with the tick at which they were last used
+    entries: HashMap<String, (String, u64)>,
+    /// Counter advanced on every access
+    tick: u64,
+}
+
+impl RouteCache {
+    fn new() -> Self {
+        RouteCache {
+            entries: HashMap::new(),
+            tick: 0,
+        }
+    }
+    
+    /// Get the cached target for a path, marking it as recently used
+    fn get(&mut self, path: &str) -> Option<String> {
+        self.tick += 1;
+        let tick = self.tick;
+        self.entries.get_mut(path).map(|(target, last_used)| {
+            *last_used = tick;
+            target.clone()
+        })
+    }
+    
+    /// Cache the target for a path, evicting the least recently used entry when full
+    fn insert(&mut self, path: String, target: String) {
+        if self.entries.len() >= ROUTE_CACHE_CAPACITY && !self.entries.contains_key(&path) {
+            let oldest = self.entries
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(path, _)| path.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        
+        self.tick += 1;
+        self.entries.insert(path, (target, self.tick));
+    }
+}
 
 /// HTTP reverse proxy using the hub
 #[derive(Clone)]
@@ -19,6 +156,32 @@ pub struct HttpReverseProxy {
     bind_address: SocketAddr,
     /// Map of path patterns to target URLs
     route_map: Arc<RwLock<HashMap<String, String>>>,
+    /// Targets already resolved for request paths, cleared whenever the routes change
+    route_cache: Arc<Mutex<RouteCache>>,
+    /// Number of times the route map has been scanned to resolve a path
+    route_scans: Arc<AtomicU64>,
+    /// Idle keep-alive connections to backend servers, keyed by `host:port`
+    backend_pool: Arc<Mutex<HashMap<String, Vec<TcpStream>>>>,
+    /// Whether to gzip compressible responses for clients that accept it
+    compression: Arc<AtomicBool>,
+    /// Whether error responses have a JSON body describing the failure
+    json_errors: Arc<AtomicBool>,
+    /// Whether HTTP/2 is offered to clients during the TLS handshake
+    http2: Arc<AtomicBool>,
+    /// Number of worker threads serving connections
+    worker_threads: Arc<AtomicUsize>,
+    /// Number of connections the OS queues before they're accepted
+    listen_backlog: Arc<AtomicU32>,
+    /// Maximum size of a request or backend response body, in bytes
+    max_body_size: Arc<AtomicUsize>,
+    /// Time a client connection may sit idle before it is closed, in milliseconds
+    idle_timeout_ms: Arc<AtomicU64>,
+    /// Requests and bytes received from each client IP address
+    client_metrics: Arc<ClientMetrics>,
+    /// Handler of the access log record of each answered request
+    access_log: Arc<RwLock<Option<AccessLogHandler>>>,
+    /// HTTP status codes and reason phrases responses are sent with, overriding the defaults
+    status_mapping: Arc<RwLock<HashMap<ResponseStatus, (u16, String)>>>,
 }
 
 impl HttpReverseProxy {
@@ -29,6 +192,19 @@ impl HttpReverseProxy {
             tls_config,
             bind_address,
             route_map: Arc::new(RwLock::new(HashMap::new())),
+            route_cache: Arc::new(Mutex::new(RouteCache::new())),
+            route_scans: Arc::new(AtomicU64::new(0)),
+            backend_pool: Arc::new(Mutex::new(HashMap::new())),
+            compression: Arc::new(AtomicBool::new(true)),
+            json_errors: Arc::new(AtomicBool::new(false)),
+            http2: Arc::new(AtomicBool::new(false)),
+            worker_threads: Arc::new(AtomicUsize::new(DEFAULT_WORKER_THREADS)),
+            listen_backlog: Arc::new(AtomicU32::new(DEFAULT_LISTEN_BACKLOG)),
+            max_body_size: Arc::new(AtomicUsize::new(DEFAULT_MAX_BODY_SIZE)),
+            idle_timeout_ms: Arc::new(AtomicU64::new(DEFAULT_IDLE_TIMEOUT.as_millis() as u64)),
+            client_metrics: Arc::new(ClientMetrics::new(DEFAULT_CLIENT_METRICS_WINDOW)),
+            access_log: Arc::new(RwLock::new(None)),
+            status_mapping: Arc::new(RwLock::new(HashMap::new())),
         };
         
         // Register APIs
@@ -37,50 +213,120 @@ impl HttpReverseProxy {
         proxy
     }
     
-    /// Start the HTTP reverse proxy
+    /// Start the HTTP reverse proxy on its configured address
     pub fn start(&self) -> Result<()> {
+        self.start_multi(&[self.bind_address])
+    }
+    
+    /// Start the HTTP reverse proxy listening on several addresses (e.g. IPv4 and IPv6)
+    ///
+    /// Every address is served by its own accept loop sharing the same hub, route
+    /// map and worker pool. All addresses are bound before any connection is accepted,
+    /// so a bind failure is reported without starting anything. Blocks until every
+    /// accept loop exits.
+    pub fn start_multi(&self, addresses: &[SocketAddr]) -> Result<()> {
         // Start the HTTP server
-        let listener = TcpListener::bind(self.bind_address)
-            .map_err(|e| HubError::Io(e))?;
-            
-        println!("HTTP reverse proxy listening on {}", self.bind_address);
+        let backlog = self.listen_backlog();
+        let listeners = addresses
+            .iter()
+            .map(|address| bind_listener(*address, backlog).map_err(HubError::Io))
+            .collect::<Result<Vec<_>>>()?;
+        
+        // Connections are served by a bounded pool, with as many waiting as there are workers
+        let worker_threads = self.worker_threads();
+        let proxy = self.clone();
+        let pool = Arc::new(WorkerPool::new(worker_threads, worker_threads, move |stream: TcpStream| {
+            if let Err(e) = proxy.handle_connection(stream) {
+                error!(error = %e, "Error handling HTTP connection");
+            }
+        }));
         
         // Handle incoming connections
+        let accept_loops: Vec<_> = listeners
+            .into_iter()
+            .map(|listener| {
+                let proxy = self.clone();
+                let pool = Arc::clone(&pool);
+                thread::spawn(move || proxy.accept_connections(listener, &pool))
+            })
+            .collect();
+        
+        for accept_loop in accept_loops {
+            let _ = accept_loop.join();
+        }
+        
+        Ok(())
+    }
+    
+    /// Accept connections on a listener, handing each to the worker pool
+    fn accept_connections(&self, listener: TcpListener, pool: &WorkerPool<TcpStream>) {
+        if let Ok(address) = listener.local_addr() {
+            info!(address = %address, "HTTP reverse proxy listening");
+        }
+        
         for stream in listener.incoming() {
             match stream {
                 Ok(stream) => {
-                    let hub = Arc::clone(&self.hub);
-                    let tls_config = self.tls_config.clone();
-                    let route_map = Arc::clone(&self.route_map);
-                    
-                    thread::spawn(move || {
-                        if let Err(e) = Self::handle_http_connection(hub, stream, &tls_config, route_map) {
-                            eprintln!("Error handling HTTP connection: {}", e);
-                        }
-                    });
+                    if let Err(stream) = pool.try_submit(stream) {
+                        warn!("All workers busy, rejecting HTTP connection");
+                        self.reject_connection(stream);
+                    }
                 }
                 Err(e) => {
-                    eprintln!("Connection error: {}", e);
+                    warn!(error = %e, "Connection error");
                 }
             }
         }
+    }
+    
+    /// Answer a connection the worker pool has no room for with 503 Service Unavailable
+    fn reject_connection(&self, stream: TcpStream) {
+        // Bound the time the accept loop spends on the rejected client
+        let _ = stream.set_read_timeout(Some(REJECT_TIMEOUT));
+        let _ = stream.set_write_timeout(Some(REJECT_TIMEOUT));
         
-        Ok(())
+        let mut tls_stream = match create_server_tls_stream(stream, &self.tls_config) {
+            Ok(tls_stream) => tls_stream,
+            Err(e) => {
+                warn!(error = %e, "Error setting up TLS for rejected connection");
+                return;
+            }
+        };
+        
+        // Consume the request so closing the connection doesn't reset it before the reply is read
+        let _ = Self::read_http_request(&mut tls_stream, &mut Vec::new(), self.max_body_size());
+        
+        let mut response = HttpResponseParts::plain(503, "Service Unavailable");
+        response.headers.push(("Retry-After".to_string(), "1".to_string()));
+        if let Err(e) = response.write_to(&mut tls_stream) {
+            warn!(error = %e, "Error writing 503 response");
+        }
+    }
+    
+    /// Handle a single HTTP connection accepted by the caller
+    pub fn handle_connection(&self, stream: TcpStream) -> Result<()> {
+        self.handle_http_connection(stream)
+    }
+    
+    /// Serve HTTP/1.1 over a TLS connection another listener accepted, e.g. a
+    /// network transport sharing its port with the proxy
+    pub(crate) fn serve_tls_stream<S: Read + Write>(&self, tls_stream: &mut S, client_addr: SocketAddr) -> Result<()> {
+        let span = info_span!("http_connection", client = %client_addr, path = tracing::field::Empty);
+        let _enter = span.enter();
+        debug!("Client connected");
+        self.serve_http1(tls_stream, client_addr, &span)
     }
     
     /// Register proxy APIs with the hub
     fn register_proxy_apis(&self) {
         // Register a handler for configuring proxy routes
-        let route_map = Arc::clone(&self.route_map);
+        let this = self.clone();
         
         let register_handler = move |request: &ApiRequest| {
             // Extract path and target from request
             if let Some(path) = request.data.downcast_ref::<String>() {
                 if let Some(target) = request.metadata.get("target") {
-                    let mut map = route_map.write().unwrap();
-                    map.insert(path.clone(), target.clone());
-                    
-                    println!("Registered proxy route: {} -> {}", path, target);
+                    this.add_route(path, target);
                     
                     return ApiResponse {
                         data: Box::new(true),
@@ -99,32 +345,45 @@ impl HttpReverseProxy {
         
         self.hub.register_api("/proxy/register", register_handler, HashMap::new());
         
-        // Register a wildcard API for handling all HTTP requests
-        let route_map = Arc::clone(&self.route_map);
-        let _hub = Arc::clone(&self.hub);
+        // Register a handler reporting the traffic of each client, as JSON
+        let client_metrics = Arc::clone(&self.client_metrics);
+        let metrics_handler = move |_: &ApiRequest| {
+            let clients: HashMap<String, ClientTraffic> = client_metrics
+                .snapshot()
+                .into_iter()
+                .map(|(client, traffic)| (client.to_string(), traffic))
+                .collect();
+            let metrics = serde_json::json!({
+                "window_ms": client_metrics.window().as_millis() as u64,
+                "clients": clients,
+            });
+            
+            ApiResponse {
+                data: Box::new(metrics.to_string()),
+                metadata: HashMap::from([("content_type".to_string(), "application/json".to_string())]),
+                status: ResponseStatus::Success,
+            }
+        };
         
+        self.hub.register_api("/proxy/metrics", metrics_handler, HashMap::new());
+        
+        // Register a wildcard API for handling all HTTP requests
         // Move a clone of self into the closure to avoid the lifetime issue
         let this = self.clone();
         
         let http_handler = move |request: &ApiRequest| {
-            // Extract the path from the request
-            let path = &request.path[6..]; // Remove "/http/" prefix
-            
-            println!("HTTP Handler called with path: {}", request.path);
-            println!("After prefix removal: {}", path);
-            
-            // Get additional metadata
-            if let Some(method) = request.metadata.get("method") {
-                println!("Request method: {}", method);
-            }
-            
-            if let Some(meta_path) = request.metadata.get("path") {
-                println!("Path from metadata: {}", meta_path);
-            }
+            // Extract the path from the request, keeping its leading slash
+            let path = match request.path.strip_prefix("/http") {
+                Some("") => "/",
+                Some(path) => path,
+                None => request.path.as_str(),
+            };
             
-            // Look up the target
-            let map = route_map.read().unwrap();
-            let mut target = None;
+            debug!(
+                path = %request.path,
+                method = ?request.metadata.get("method"),
+                "HTTP handler called"
+            );
             
             // Get the actual path from metadata - this is what the test is sending
             // The test includes metadata with the actual path after /http/
@@ -134,59 +393,14 @@ impl HttpReverseProxy {
                 path.to_string()
             };
             
-            println!("Routes available:");
-            for (k, v) in map.iter() {
-                println!("  {} -> {}", k, v);
-            }
-            
-            println!("Looking for route matching: {}", actual_path);
-            
-            // First try root path for the empty or "/" paths
-            if actual_path == "/" || actual_path.is_empty() {
-                if let Some(t) = map.get("/") {
-                    println!("Found root match: / -> {}", t);
-                    target = Some(t.clone());
-                }
-            } 
-            
-            // Try exact match if we haven't found a target yet
-            if target.is_none() {
-                if let Some(t) = map.get(&actual_path) {
-                    println!("Found exact match: {} -> {}", actual_path, t);
-                    target = Some(t.clone());
-                } else {
-                    // Check for wildcard patterns
-                    for (pattern, t) in map.iter() {
-                        if pattern.ends_with('*') && actual_path.starts_with(&pattern[0..pattern.len()-1]) {
-                            println!("Found wildcard match: {} matches pattern {}", actual_path, pattern);
-                            target = Some(t.clone());
-                            break;
-                        }
-                    }
-                }
-            }
-            
-            // Use default fallbacks if needed
-            if target.is_none() {
-                // Try root as fallback
-                if let Some(t) = map.get("/") {
-                    println!("Using root as fallback for {}", actual_path);
-                    target = Some(t.clone());
-                } else if let Some(t) = map.get("*") {
-                    // Try wildcard as fallback
-                    println!("Using '*' as fallback for {}", actual_path);
-                    target = Some(t.clone());
-                }
+            if let Some(target) = this.resolve_target(&actual_path) {
+                // Forward the request to the target, noting where it went for the access log
+                let mut response = this.forward_request(target.clone(), &actual_path, request);
+                response.metadata.insert(UPSTREAM_KEY.to_string(), target);
+                return response;
             }
             
-            if let Some(target) = target {
-                println!("Found target: {}", target);
-                
-                // Forward the request to the target
-                return this.forward_request(target, &actual_path, request);
-            }
-            
-            println!("No proxy target found for {}", actual_path);
+            warn!(path = %actual_path, "No proxy target found");
             ApiResponse {
                 data: Box::new(format!("No proxy target found for path: {}", actual_path)),
                 metadata: HashMap::new(),
@@ -197,150 +411,750 @@ impl HttpReverseProxy {
         self.hub.register_api("/http/*", http_handler, HashMap::new());
     }
     
+    /// Resolve the route target for a request path, using the route cache when possible
+    fn resolve_target(&self, path: &str) -> Option<String> {
+        if let Some(target) = self.route_cache.lock().unwrap().get(path) {
+            debug!(path = %path, target = %target, "Found cached route");
+            return Some(target);
+        }
+        
+        self.route_scans.fetch_add(1, Ordering::Relaxed);
+        let map = self.route_map.read().unwrap();
+        let target = Self::match_route(&map, path)?;
+        
+        // Cache while still holding the read lock, so a concurrent route change
+        // can't clear the cache before this (now stale) entry is added
+        self.route_cache.lock().unwrap().insert(path.to_string(), target.clone());
+        Some(target)
+    }
+    
+    /// Find the route target for a request path by scanning the route map
+    fn match_route(map: &HashMap<String, String>, actual_path: &str) -> Option<String> {
+        debug!(path = %actual_path, "Looking for matching route");
+        
+        // First try root path for the empty or "/" paths
+        if actual_path == "/" || actual_path.is_empty() {
+            if let Some(t) = map.get("/") {
+                debug!(target = %t, "Found root match");
+                return Some(t.clone());
+            }
+        }
+        
+        // Try exact match
+        if let Some(t) = map.get(actual_path) {
+            debug!(path = %actual_path, target = %t, "Found exact match");
+            return Some(t.clone());
+        }
+        
+        // Check for wildcard patterns
+        for (pattern, t) in map.iter() {
+            if pattern.ends_with('*') && actual_path.starts_with(&pattern[0..pattern.len()-1]) {
+                debug!(path = %actual_path, pattern = %pattern, "Found wildcard match");
+                return Some(t.clone());
+            }
+        }
+        
+        // Use default fallbacks if needed
+        if let Some(t) = map.get("/") {
+            // Try root as fallback
+            debug!(path = %actual_path, "Using root as fallback");
+            Some(t.clone())
+        } else if let Some(t) = map.get("*") {
+            // Try wildcard as fallback
+            debug!(path = %actual_path, "Using '*' as fallback");
+            Some(t.clone())
+        } else {
+            None
+        }
+    }
+    
     /// Handle an HTTP connection
-    fn handle_http_connection(
-        hub: Arc<Hub>,
-        stream: TcpStream,
-        tls_config: &TlsConfig,
-        route_map: Arc<RwLock<HashMap<String, String>>>,
-    ) -> Result<()> {
+    fn handle_http_connection(&self, stream: TcpStream) -> Result<()> {
+        let idle_timeout = self.idle_timeout();
+        
         // Set the stream to non-blocking to prevent indefinite hanging
         stream.set_nonblocking(false).map_err(|e| {
-            eprintln!("Error setting stream to blocking mode: {}", e);
+            error!(error = %e, "Error setting stream to blocking mode");
+            HubError::Io(e)
+        })?;
+        
+        // Don't let a silent client hold a worker forever
+        set_idle_timeout(&stream, idle_timeout).map_err(|e| {
+            error!(error = %e, "Error setting stream read timeout");
+            HubError::Io(e)
+        })?;
+        
+        // Send responses without waiting to coalesce them
+        stream.set_nodelay(true).map_err(|e| {
+            error!(error = %e, "Error setting TCP_NODELAY");
             HubError::Io(e)
         })?;
         
         // Log client connection
         let client_addr = stream.peer_addr().map_err(|e| {
-            eprintln!("Error getting peer address: {}", e);
+            error!(error = %e, "Error getting peer address");
             HubError::Io(e)
         })?;
-        println!("Client connected from: {}", client_addr);
+        
+        // Correlate all events for this connection
+        let span = info_span!("http_connection", client = %client_addr, path = tracing::field::Empty);
+        let _enter = span.enter();
+        debug!("Client connected");
+        
+        // Clients negotiate HTTP/2 or HTTP/1.1 with ALPN during an async handshake
+        if self.http2() {
+            return http2::serve_connection(self, stream, client_addr, &span);
+        }
         
         // Set up TLS
-        println!("Setting up TLS for client: {}", client_addr);
-        let mut tls_stream = match create_server_tls_stream(stream, tls_config) {
+        let mut tls_stream = match create_server_tls_stream(stream, &self.tls_config) {
             Ok(stream) => stream,
             Err(e) => {
-                eprintln!("TLS setup error for client {}: {}", client_addr, e);
+                error!(error = %e, "TLS setup error");
                 return Err(e);
             }
         };
         
-        // Read HTTP request
-        println!("Reading request from client: {}", client_addr);
-        let mut buffer = [0u8; 8192];
-        let size = match tls_stream.read(&mut buffer) {
-            Ok(s) => s,
-            Err(e) => {
-                eprintln!("Error reading from stream (client {}): {}", client_addr, e);
-                return Err(HubError::Io(e));
+        self.serve_http1(&mut tls_stream, client_addr, &span)
+    }
+    
+    /// Serve HTTP/1.1 requests until the client closes the connection or asks us to
+    fn serve_http1<S: Read + Write>(&self, tls_stream: &mut S, client_addr: SocketAddr, span: &tracing::Span) -> Result<()> {
+        let max_body_size = self.max_body_size();
+        let idle_timeout = self.idle_timeout();
+        
+        let mut buffer = Vec::new();
+        loop {
+            let request_bytes = match Self::read_http_request(tls_stream, &mut buffer, max_body_size) {
+                Ok(HttpRead::Request(bytes)) => bytes,
+                Ok(HttpRead::Closed) => {
+                    debug!("Client closed connection");
+                    return Ok(());
+                }
+                Ok(HttpRead::HeadersTooLarge) => {
+                    error!("Rejecting request, headers too large");
+                    HttpResponseParts::plain(431, "Request Header Fields Too Large")
+                        .write_to(tls_stream)
+                        .map_err(HubError::Io)?;
+                    return Ok(());
+                }
+                Ok(HttpRead::BodyTooLarge(content_length)) => {
+                    error!(content_length, max_body_size, "Rejecting request, body too large");
+                    HttpResponseParts::plain(413, "Payload Too Large").write_to(tls_stream).map_err(HubError::Io)?;
+                    return Ok(());
+                }
+                Err(e) if is_timeout(&e) => {
+                    debug!(timeout_ms = idle_timeout.as_millis() as u64, "Closing idle connection");
+                    return Ok(());
+                }
+                Err(e) => {
+                    error!(error = %e, "Error reading from stream");
+                    return Err(HubError::Io(e));
+                }
+            };
+            
+            let request = HttpRequestParts::parse(&request_bytes);
+            let keep_alive = self.serve_request(tls_stream, request, request_bytes.len(), client_addr, span)?;
+            if !keep_alive {
+                return Ok(());
             }
+        }
+    }
+    
+    /// Answer one request read from a client, recording its metrics and access log
+    ///
+    /// `request` is `None` if the request couldn't be parsed, and `request_size` is
+    /// its size as received. Returns whether the connection should be kept open for
+    /// further requests.
+    fn serve_request<W: Write>(
+        &self,
+        tls_stream: &mut W,
+        request: Option<HttpRequestParts>,
+        request_size: usize,
+        client_addr: SocketAddr,
+        span: &tracing::Span,
+    ) -> Result<bool> {
+        self.client_metrics.record(client_addr.ip(), request_size);
+        
+        let started = Instant::now();
+        let mut access_log = AccessLog {
+            timestamp: current_time_millis(),
+            client: client_addr,
+            method: String::new(),
+            path: String::new(),
+            upstream: None,
+            status: 0,
+            bytes: 0,
+            latency_ms: 0,
         };
+        let keep_alive = self.handle_http_request(tls_stream, request, span, &mut access_log)?;
+        debug!(keep_alive, "Finished handling request");
         
-        if size == 0 {
-            println!("Empty request from client: {}", client_addr);
-            return Ok(());
-        }
+        access_log.latency_ms = started.elapsed().as_millis() as u64;
+        self.log_access(&access_log);
         
-        // Parse HTTP request
-        let http_request = String::from_utf8_lossy(&buffer[..size]);
-        let first_line = http_request.lines().next().unwrap_or("");
-        let parts: Vec<&str> = first_line.split_whitespace().collect();
+        Ok(keep_alive)
+    }
+    
+    /// Emit the access log record of an answered request
+    fn log_access(&self, record: &AccessLog) {
+        info!(
+            target: "access_log",
+            method = %record.method,
+            path = %record.path,
+            upstream = record.upstream.as_deref(),
+            status = record.status,
+            bytes = record.bytes,
+            latency_ms = record.latency_ms,
+            "Request served"
+        );
         
-        if parts.len() >= 2 {
-            let method = parts[0];
-            let path = parts[1];
-            
-            println!("Received {} request for {} from {}", method, path, client_addr);
-            
-            // Print available routes for debugging
-            println!("Available routes:");
-            {
-                let routes = route_map.read().unwrap();
-                for (route_path, target) in routes.iter() {
-                    println!("  {} -> {}", route_path, target);
+        let handler = self.access_log.read().unwrap().clone();
+        if let Some(handler) = handler {
+            handler(record);
+        }
+    }
+    
+    /// Handle one HTTP request read from a connection and write the response
+    ///
+    /// `request` is `None` if the request couldn't be parsed, which is answered
+    /// with 400 Bad Request. Fills in the request and response details of
+    /// `access_log`. Returns whether the connection should be kept open for
+    /// further requests.
+    fn handle_http_request<W: Write>(
+        &self,
+        tls_stream: &mut W,
+        request: Option<HttpRequestParts>,
+        span: &tracing::Span,
+        access_log: &mut AccessLog,
+    ) -> Result<bool> {
+        let compression = self.compression.load(Ordering::Relaxed);
+        let json_errors = self.json_errors();
+        
+        let Some(http_request) = request else {
+            error!("Invalid HTTP request");
+            // Send 400 Bad Request
+            if let Err(e) = HttpResponseParts::plain(400, "Bad Request").write_to(tls_stream) {
+                error!(error = %e, "Error writing 400 response");
+                return Err(HubError::Io(e));
+            }
+            access_log.status = 400;
+            access_log.bytes = "Bad Request".len() as u64;
+            return Ok(false);
+        };
+    
+        let method = http_request.method.clone();
+        let path = http_request.path.clone();
+        let keep_alive = http_request.keep_alive();
+        let accepts_gzip = http_request.accepts_gzip();
+        access_log.method = method.clone();
+        access_log.path = path.clone();
+    
+        span.record("path", path.as_str());
+        debug!(method = %method, "Received request");
+    
+        // Create API request
+        let request = ApiRequest {
+            path: format!("/http{}", path),
+            data: Box::new(http_request),
+            metadata: HashMap::from([
+                ("method".to_string(), method),
+                ("path".to_string(), path.clone()),
+            ]),
+            sender_id: "http-client".to_string(),
+        };
+    
+        // Handle request using the hub
+        let response = self.hub.handle_request(request);
+        debug!(status = ?response.status, streamed = response.is_stream(), "Got response from hub");
+        access_log.upstream = response.metadata.get(UPSTREAM_KEY).cloned();
+    
+        let (status, reason) = self.status_mapping(response.status);
+        let response = match response.into_stream() {
+            Ok(reader) => {
+                // Copy the body to the client in chunks rather than buffering it
+                access_log.status = status;
+                match Self::write_streamed_response(tls_stream, status, &reason, reader, keep_alive) {
+                    Ok(bytes) => access_log.bytes = bytes,
+                    Err(e) => {
+                        error!(error = %e, "Error streaming response");
+                        return Err(HubError::Io(e));
+                    }
                 }
+                return Ok(keep_alive);
+            }
+            Err(response) => response,
+        };
+    
+        // Convert API response to HTTP response
+        let body = match response.status {
+            ResponseStatus::Success | ResponseStatus::Approximated | ResponseStatus::Intercepted => {
+                // Consider approximated and intercepted as successful responses for HTTP clients
+                response.as_str().unwrap_or("OK").to_string()
+            }
+            _ if json_errors => Self::json_error(&response, &reason, &path),
+            _ => reason.clone(),
+        };
+    
+        // Successful bodies keep the type they were sent with; error bodies are the proxy's own
+        let content_type = match response.status {
+            ResponseStatus::Success | ResponseStatus::Approximated | ResponseStatus::Intercepted => {
+                Self::content_type(&response).unwrap_or("text/plain")
+            }
+            _ if json_errors => "application/json",
+            _ => "text/plain",
+        };
+    
+        let mut http_response = HttpResponseParts {
+            status,
+            reason,
+            headers: vec![("Content-Type".to_string(), content_type.to_string())],
+            body: body.into_bytes(),
+        };
+    
+        // Compress the body if the client supports it and it's worth it
+        if compression
+            && http_response.body.len() >= MIN_COMPRESSION_SIZE
+            && Self::is_compressible(content_type)
+            && accepts_gzip
+        {
+            match Self::gzip(&http_response.body) {
+                Ok(compressed) => {
+                    debug!(original = http_response.body.len(), compressed = compressed.len(), "Compressed response body");
+                    http_response.body = compressed;
+                    http_response.headers.push(("Content-Encoding".to_string(), "gzip".to_string()));
+                    http_response.headers.push(("Vary".to_string(), "Accept-Encoding".to_string()));
+                }
+                Err(e) => warn!(error = %e, "Error compressing response, sending it uncompressed"),
+            }
+        }
+    
+        http_response.headers.push(("Content-Length".to_string(), http_response.body.len().to_string()));
+        http_response.headers.push((
+            "Connection".to_string(),
+            if keep_alive { "keep-alive" } else { "close" }.to_string(),
+        ));
+    
+        // Send HTTP response
+        if let Err(e) = http_response.write_to(tls_stream) {
+            error!(error = %e, "Error writing response");
+            return Err(HubError::Io(e));
+        }
+        access_log.status = http_response.status;
+        access_log.bytes = http_response.body.len() as u64;
+    
+        Ok(keep_alive)
+    }
+    
+    /// Get the HTTP status code a response with the given status is sent with by default
+    fn http_status(status: ResponseStatus) -> u16 {
+        match status {
+            ResponseStatus::Success | ResponseStatus::Approximated | ResponseStatus::Intercepted => 200,
+            ResponseStatus::NotFound => 404,
+            ResponseStatus::BadRequest => 400,
+            ResponseStatus::Cancelled | ResponseStatus::ShuttingDown => 503,
+            ResponseStatus::Error => 500,
+        }
+    }
+    
+    /// Get the reason phrase a response with the given status is sent with by default
+    fn reason_phrase(status: ResponseStatus) -> &'static str {
+        match status {
+            ResponseStatus::Success | ResponseStatus::Approximated | ResponseStatus::Intercepted => "OK",
+            ResponseStatus::NotFound => "Not Found",
+            ResponseStatus::BadRequest => "Bad Request",
+            ResponseStatus::Cancelled | ResponseStatus::ShuttingDown => "Service Unavailable",
+            ResponseStatus::Error => "Internal Server Error",
+        }
+    }
+    
+    /// Get the content type a response's body was sent with, if known
+    ///
+    /// Values that would break the response head are ignored.
+    fn content_type(response: &ApiResponse) -> Option<&str> {
+        CONTENT_TYPE_KEYS
+            .iter()
+            .find_map(|key| response.metadata.get(*key))
+            .map(String::as_str)
+            .filter(|value| !value.is_empty() && !value.contains(['\r', '\n']))
+    }
+    
+    /// Build the JSON body of an error response
+    ///
+    /// The error is the failure reason returned by the handler (such as the error
+    /// reaching the target), or the status text if there is none. The target's
+    /// status code is included if it answered.
+    fn json_error(response: &ApiResponse, status_text: &str, path: &str) -> String {
+        let error = response.as_str().unwrap_or(status_text);
+        let upstream_status = response.metadata.get(UPSTREAM_STATUS_KEY).and_then(|status| status.parse::<u16>().ok());
+        serde_json::json!({
+            "error": error,
+            "path": path,
+            "upstream_status": upstream_status,
+        }).to_string()
+    }
+    
+    /// Check whether a response with this content type is worth compressing
+    fn is_compressible(content_type: &str) -> bool {
+        let mime = content_type.split(';').next().unwrap_or("").trim();
+        mime.starts_with("text/")
+            || matches!(mime, "application/json" | "application/javascript" | "application/xml" | "image/svg+xml")
+    }
+    
+    /// Read one HTTP request (headers plus a `Content-Length` body) from the stream
+    ///
+    /// Reads are repeated until the header terminator is seen, so requests split
+    /// across several packets are handled. Bytes read past the end of the request
+    /// (such as a pipelined request) are left in `buffer`. Requests whose headers
+    /// exceed `MAX_HEADER_SIZE`, or whose declared body exceeds `max_body_size`, are
+    /// reported without reading (or allocating for) the rest of the request.
+    fn read_http_request<R: Read>(stream: &mut R, buffer: &mut Vec<u8>, max_body_size: usize) -> std::io::Result<HttpRead> {
+        use std::io::{Error, ErrorKind};
+        
+        let mut chunk = [0u8; 8192];
+        
+        // Read until the end of the headers
+        let head_len = loop {
+            if let Some(pos) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+                break pos + 4;
             }
             
-            // Create API request
-            let request = ApiRequest {
-                path: format!("/http{}", path),
-                data: Box::new(http_request.to_string()),
-                metadata: HashMap::from([
-                    ("method".to_string(), method.to_string()),
-                    ("path".to_string(), path.to_string()),
-                ]),
-                sender_id: "http-client".to_string(),
-            };
-            
-            // Handle request using the hub
-            println!("Forwarding request to hub for path: {}", request.path);
-            let response = hub.handle_request(request);
-            println!("Got response from hub with status: {:?}", response.status);
+            if buffer.len() > MAX_HEADER_SIZE {
+                return Ok(HttpRead::HeadersTooLarge);
+            }
             
-            // Convert API response to HTTP response
-            let http_response = match response.status {
-                ResponseStatus::Success | ResponseStatus::Approximated | ResponseStatus::Intercepted => {
-                    // Consider approximated and intercepted as successful responses for HTTP clients
-                    if let Some(body) = response.data.downcast_ref::<String>() {
-                        println!("Sending 200 OK response to client {} (status: {:?})", client_addr, response.status);
-                        format!("HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: {}\r\n\r\n{}", 
-                            body.len(), body)
-                    } else {
-                        println!("Sending 200 OK response to client {} (default body, status: {:?})", client_addr, response.status);
-                        "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 2\r\n\r\nOK".to_string()
-                    }
-                },
-                ResponseStatus::NotFound => {
-                    println!("Sending 404 Not Found response to client {}", client_addr);
-                    "HTTP/1.1 404 Not Found\r\nContent-Type: text/plain\r\nContent-Length: 9\r\n\r\nNot Found".to_string()
-                },
-                ResponseStatus::Error => {
-                    println!("Sending 500 Internal Server Error response to client {}", client_addr);
-                    "HTTP/1.1 500 Internal Server Error\r\nContent-Type: text/plain\r\nContent-Length: 21\r\n\r\nInternal Server Error".to_string()
-                }
+            let size = match stream.read(&mut chunk) {
+                // Clients often drop idle connections without a TLS close_notify
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof && buffer.is_empty() => return Ok(HttpRead::Closed),
+                result => result?,
             };
-            
-            // Send HTTP response
-            println!("Writing response to client: {}", client_addr);
-            match tls_stream.write(http_response.as_bytes()) {
-                Ok(bytes_written) => println!("Wrote {} bytes to client {}", bytes_written, client_addr),
-                Err(e) => {
-                    eprintln!("Error writing to client {}: {}", client_addr, e);
-                    return Err(HubError::Io(e));
+            if size == 0 {
+                if buffer.is_empty() {
+                    return Ok(HttpRead::Closed);
                 }
+                return Err(Error::new(ErrorKind::UnexpectedEof, "connection closed before end of headers"));
             }
-        } else {
-            eprintln!("Invalid HTTP request from client {}: '{}'", client_addr, first_line);
-            // Send 400 Bad Request
-            let bad_request = "HTTP/1.1 400 Bad Request\r\nContent-Type: text/plain\r\nContent-Length: 11\r\n\r\nBad Request";
-            match tls_stream.write(bad_request.as_bytes()) {
-                Ok(_) => {},
-                Err(e) => {
-                    eprintln!("Error writing 400 response to client {}: {}", client_addr, e);
-                    return Err(HubError::Io(e));
-                }
+            buffer.extend_from_slice(&chunk[..size]);
+        };
+        
+        if head_len > MAX_HEADER_SIZE {
+            return Ok(HttpRead::HeadersTooLarge);
+        }
+        
+        // Read the body if the request declares one
+        let content_length = HttpRequestParts::parse(&buffer[..head_len])
+            .and_then(|head| head.header("content-length")?.parse::<usize>().ok())
+            .unwrap_or(0);
+        
+        if content_length > max_body_size {
+            return Ok(HttpRead::BodyTooLarge(content_length));
+        }
+        
+        let request_len = head_len + content_length;
+        while buffer.len() < request_len {
+            let size = stream.read(&mut chunk)?;
+            if size == 0 {
+                return Err(Error::new(ErrorKind::UnexpectedEof, "connection closed before end of body"));
             }
+            buffer.extend_from_slice(&chunk[..size]);
+        }
+        
+        Ok(HttpRead::Request(buffer.drain(..request_len).collect()))
+    }
+    
+    /// Write a response whose body is copied from `reader` using chunked transfer encoding
+    fn write_streamed_response<W: Write>(
+        stream: &mut W,
+        status: u16,
+        reason: &str,
+        mut reader: Box<dyn Read + Send>,
+        keep_alive: bool,
+    ) -> std::io::Result<u64> {
+        HttpResponseParts {
+            status,
+            reason: reason.to_string(),
+            headers: vec![
+                ("Content-Type".to_string(), "application/octet-stream".to_string()),
+                ("Transfer-Encoding".to_string(), "chunked".to_string()),
+                ("Connection".to_string(), if keep_alive { "keep-alive" } else { "close" }.to_string()),
+            ],
+            body: Vec::new(),
         }
+        .write_head(stream)?;
         
-        println!("Finished handling request from client: {}", client_addr);
+        let mut buffer = vec![0u8; STREAM_CHUNK_SIZE];
+        let mut total = 0u64;
+        loop {
+            let size = match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(size) => size,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            
+            write!(stream, "{:X}\r\n", size)?;
+            stream.write_all(&buffer[..size])?;
+            stream.write_all(b"\r\n")?;
+            total += size as u64;
+        }
+        
+        stream.write_all(b"0\r\n\r\n")?;
+        stream.flush()?;
+        
+        debug!(bytes = total, "Streamed response body");
+        Ok(total)
+    }
+    
+    /// Gzip a response body
+    fn gzip(body: &[u8]) -> std::io::Result<Vec<u8>> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(body)?;
+        encoder.finish()
+    }
+    
+    /// Get the address the proxy listens on when started
+    pub fn bind_address(&self) -> SocketAddr {
+        self.bind_address
+    }
+    
+    /// Set the number of worker threads serving connections (64 by default)
+    ///
+    /// Takes effect the next time the proxy is started. Once every worker is busy
+    /// and as many connections are waiting, new connections get 503 Service Unavailable.
+    pub fn set_worker_threads(&self, threads: usize) {
+        self.worker_threads.store(threads.max(1), Ordering::Relaxed);
+    }
+    
+    /// Get the number of worker threads serving connections
+    pub fn worker_threads(&self) -> usize {
+        self.worker_threads.load(Ordering::Relaxed)
+    }
+    
+    /// Set how many connections the OS queues before they're accepted (1024 by default)
+    ///
+    /// Takes effect the next time the proxy is started.
+    pub fn set_listen_backlog(&self, backlog: u32) {
+        self.listen_backlog.store(backlog.max(1), Ordering::Relaxed);
+    }
+    
+    /// Get how many connections the OS queues before they're accepted
+    pub fn listen_backlog(&self) -> u32 {
+        self.listen_backlog.load(Ordering::Relaxed)
+    }
+    
+    /// Set how long a client connection may sit idle before it is closed (30 seconds by default)
+    ///
+    /// Applies to connections accepted afterwards. A zero duration disables the timeout.
+    pub fn set_idle_timeout(&self, timeout: Duration) {
+        self.idle_timeout_ms.store(timeout.as_millis() as u64, Ordering::Relaxed);
+    }
+    
+    /// Get how long a client connection may sit idle before it is closed
+    pub fn idle_timeout(&self) -> Duration {
+        Duration::from_millis(self.idle_timeout_ms.load(Ordering::Relaxed))
+    }
+    
+    /// Set the maximum size of a request or backend response body, in bytes (10 MiB by default)
+    ///
+    /// Requests declaring a larger body are rejected with 413 Payload Too Large
+    /// before the body is read.
+    pub fn set_max_body_size(&self, bytes: usize) {
+        self.max_body_size.store(bytes, Ordering::Relaxed);
+    }
+    
+    /// Get the maximum size of a request or backend response body, in bytes
+    pub fn max_body_size(&self) -> usize {
+        self.max_body_size.load(Ordering::Relaxed)
+    }
+    
+    /// Enable or disable gzip compression of responses (enabled by default)
+    pub fn set_compression(&self, enabled: bool) {
+        self.compression.store(enabled, Ordering::Relaxed);
+    }
+    
+    /// Enable or disable HTTP/2 for clients that negotiate it with ALPN (disabled by default)
+    ///
+    /// Applies to connections accepted afterwards. Clients that don't negotiate
+    /// HTTP/2 are still served HTTP/1.1. Responses to HTTP/2 requests are buffered
+    /// whole rather than streamed.
+    pub fn set_http2(&self, enabled: bool) {
+        self.http2.store(enabled, Ordering::Relaxed);
+    }
+    
+    /// Whether HTTP/2 is offered to clients
+    pub fn http2(&self) -> bool {
+        self.http2.load(Ordering::Relaxed)
+    }
+    
+    /// Set a handler called with the access log record of every request answered
+    ///
+    /// Records are also emitted as `info` events with the `access_log` target.
+    /// Pass `AccessLog::json_lines` to write them to a file as JSON lines.
+    pub fn set_access_log<F>(&self, handler: F)
+    where
+        F: Fn(&AccessLog) + Send + Sync + 'static,
+    {
+        *self.access_log.write().unwrap() = Some(Arc::new(handler));
+    }
+    
+    /// Enable or disable JSON bodies for error responses (disabled by default)
+    ///
+    /// When enabled, 404 and 500 responses have a body like
+    /// `{"error": "...", "path": "...", "upstream_status": 503}` giving the reason
+    /// for the failure, rather than just the status text.
+    pub fn set_json_errors(&self, enabled: bool) {
+        self.json_errors.store(enabled, Ordering::Relaxed);
+    }
+    
+    /// Get whether error responses have a JSON body describing the failure
+    pub fn json_errors(&self) -> bool {
+        self.json_errors.load(Ordering::Relaxed)
+    }
+    
+    /// Send responses with a status using an HTTP status code and reason phrase other than the default
+    ///
+    /// By default `Success`, `Approximated` and `Intercepted` are sent as 200 OK,
+    /// `NotFound` as 404, `BadRequest` as 400, `Cancelled` and `ShuttingDown` as
+    /// 503 and `Error` as 500. Only the status line changes: the body is still the
+    /// response's data if the status is a successful one. Fails if the code isn't
+    /// between 100 and 599 or the reason phrase would break the response head.
+    pub fn set_status_mapping(&self, status: ResponseStatus, code: u16, reason: &str) -> Result<()> {
+        if !(100..=599).contains(&code) {
+            return Err(HubError::Config(format!("Invalid HTTP status code for {:?}: {}", status, code)));
+        }
+        if reason.contains(['\r', '\n']) {
+            return Err(HubError::Config(format!("Invalid HTTP reason phrase for {:?}: {:?}", status, reason)));
+        }
+        self.status_mapping.write().unwrap().insert(status, (code, reason.to_string()));
         Ok(())
     }
     
+    /// Get the HTTP status code and reason phrase responses with a status are sent with
+    pub fn status_mapping(&self, status: ResponseStatus) -> (u16, String) {
+        self.status_mapping
+            .read()
+            .unwrap()
+            .get(&status)
+            .cloned()
+            .unwrap_or_else(|| (Self::http_status(status), Self::reason_phrase(status).to_string()))
+    }
+    
     /// Add a proxy route
     pub fn add_route(&self, path: &str, target: &str) {
         let mut map = self.route_map.write().unwrap();
         map.insert(path.to_string(), target.to_string());
-        println!("Added proxy route: {} -> {}", path, target);
+        self.clear_route_cache();
+        info!(path = %path, target = %target, "Added proxy route");
+    }
+    
+    /// Remove a proxy route, returning its target if it was configured
+    pub fn remove_route(&self, path: &str) -> Option<String> {
+        let mut map = self.route_map.write().unwrap();
+        let removed = map.remove(path);
+        if removed.is_some() {
+            self.clear_route_cache();
+            info!(path = %path, "Removed proxy route");
+        }
+        removed
+    }
+    
+    /// Get a snapshot of the configured proxy routes
+    pub fn routes(&self) -> HashMap<String, String> {
+        self.route_map.read().unwrap().clone()
+    }
+    
+    /// Save the configured proxy routes to a JSON file
+    pub fn save_routes<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let json = serde_json::to_vec_pretty(&*self.route_map.read().unwrap())?;
+        fs::write(path, json)?;
+        Ok(())
+    }
+    
+    /// Load proxy routes from a JSON file written by `save_routes`
+    ///
+    /// Loaded routes are added to the existing ones, replacing any with the same path.
+    pub fn load_routes<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let routes: HashMap<String, String> = serde_json::from_slice(&fs::read(path)?)?;
+        info!(count = routes.len(), "Loaded proxy routes");
+        let mut map = self.route_map.write().unwrap();
+        map.extend(routes);
+        self.clear_route_cache();
+        Ok(())
+    }
+    
+    /// Forget the cached route targets (callers hold the route map write lock)
+    fn clear_route_cache(&self) {
+        self.route_cache.lock().unwrap().entries.clear();
+    }
+    
+    /// Set how often the per-client request counters are reset (every minute by default)
+    ///
+    /// Applies from the next reset.
+    pub fn set_client_metrics_window(&self, window: Duration) {
+        self.client_metrics.set_window(window);
+    }
+    
+    /// Get how often the per-client request counters are reset
+    pub fn client_metrics_window(&self) -> Duration {
+        self.client_metrics.window()
+    }
+    
+    /// Get the requests and bytes received from each client IP address in the current window
+    ///
+    /// Also reported as JSON by the `/proxy/metrics` hub API.
+    pub fn client_traffic(&self) -> HashMap<IpAddr, ClientTraffic> {
+        self.client_metrics.snapshot()
+    }
+    
+    /// Number of times the route map has been scanned to resolve a request path
+    ///
+    /// Requests resolved from the route cache don't scan the route map.
+    pub fn route_scan_count(&self) -> u64 {
+        self.route_scans.load(Ordering::Relaxed)
+    }
+    
+    /// Open a new connection to a backend server
+    fn connect_to_backend(target_addr: &str) -> std::io::Result<TcpStream> {
+        let stream = connect_tcp(target_addr)?;
+        stream.set_nonblocking(false)?;
+        Ok(stream)
+    }
+    
+    /// Take an idle connection to a backend server from the pool
+    fn take_backend_connection(&self, target_addr: &str) -> Option<TcpStream> {
+        self.backend_pool.lock().unwrap().get_mut(target_addr).and_then(|idle| idle.pop())
+    }
+    
+    /// Return a connection to the pool so later requests to the same backend can reuse it
+    fn return_backend_connection(&self, target_addr: &str, stream: TcpStream) {
+        let mut pool = self.backend_pool.lock().unwrap();
+        let idle = pool.entry(target_addr.to_string()).or_default();
+        if idle.len() < MAX_IDLE_BACKEND_CONNECTIONS {
+            idle.push(stream);
+        }
+    }
+    
+    /// Get the client request a hub request for `/http/*` carries
+    ///
+    /// Requests made to the hub directly may carry a raw HTTP request as a string
+    /// instead; any other string data is taken to be the body.
+    fn client_request(request: &ApiRequest) -> HttpRequestParts {
+        if let Some(parts) = request.data.downcast_ref::<HttpRequestParts>() {
+            return parts.clone();
+        }
+        
+        let raw = request.data.downcast_ref::<String>().map(String::as_str)
+            .or_else(|| request.data.downcast_ref::<&str>().copied())
+            .unwrap_or("");
+        HttpRequestParts::parse(raw.as_bytes())
+            .filter(|parts| parts.version.starts_with("HTTP/"))
+            .unwrap_or_else(|| HttpRequestParts { body: raw.as_bytes().to_vec(), ..Default::default() })
     }
     
     /// Forward a request to a target URL
     fn forward_request(&self, target: String, path: &str, request: &ApiRequest) -> ApiResponse {
         use std::io::{BufReader, BufRead};
         
-        println!("Forwarding request to target: {}{}", target, path);
-        
         // Extract method from metadata or default to GET
         let method = request.metadata.get("method").cloned().unwrap_or_else(|| "GET".to_string());
         
@@ -351,13 +1165,13 @@ impl HttpReverseProxy {
             format!("{}{}", target, path)
         };
         
-        println!("Target URL: {}", target_url);
+        debug!(url = %target_url, "Forwarding request to target");
         
         // Parse the URL to get host, port, and path
         let url_parts = match url::Url::parse(&target_url) {
             Ok(url) => url,
             Err(e) => {
-                eprintln!("Error parsing target URL '{}': {}", target_url, e);
+                error!(url = %target_url, error = %e, "Error parsing target URL");
                 return ApiResponse {
                     data: Box::new(format!("Error parsing target URL: {}", e)),
                     metadata: HashMap::new(),
@@ -369,7 +1183,7 @@ impl HttpReverseProxy {
         let host = match url_parts.host_str() {
             Some(h) => h.to_string(),
             None => {
-                eprintln!("No host in target URL: {}", target_url);
+                error!(url = %target_url, "No host in target URL");
                 return ApiResponse {
                     data: Box::new("No host in target URL".to_string()),
                     metadata: HashMap::new(),
@@ -388,87 +1202,94 @@ impl HttpReverseProxy {
             url_parts.path().to_string()
         };
         
-        println!("Connecting to {}:{} with path {}", host, port, path_with_query);
-        
-        // Extract request body if present
-        let body = if let Some(body_str) = request.data.downcast_ref::<String>() {
-            // Real implementation would parse the body from the HTTP request
-            // Just using the raw request string for this example
-            body_str.clone()
-        } else {
-            String::new()
-        };
+        // Replay the client's headers and body
+        let client_request = Self::client_request(request);
         
-        // Connect to the target server
+        // Create HTTP request
         let target_addr = format!("{}:{}", host, port);
-        let mut stream = match TcpStream::connect(&target_addr) {
-            Ok(s) => s,
-            Err(e) => {
-                eprintln!("Error connecting to target server {}: {}", target_addr, e);
+        let mut headers = vec![("Host".to_string(), host.clone())];
+        headers.extend(
+            client_request
+                .headers
+                .iter()
+                .filter(|(name, _)| !UNFORWARDED_HEADERS.iter().any(|header| name.eq_ignore_ascii_case(header)))
+                .cloned(),
+        );
+        if client_request.header("content-length").is_some() || !client_request.body.is_empty() {
+            headers.push(("Content-Length".to_string(), client_request.body.len().to_string()));
+        }
+        headers.push(("Connection".to_string(), "keep-alive".to_string()));
+        let http_request = HttpRequestParts {
+            method,
+            path: path_with_query,
+            version: "HTTP/1.1".to_string(),
+            headers,
+            body: client_request.body,
+        }
+        .to_bytes();
+        
+        // Send the request, reusing an idle connection to the target if one is pooled.
+        // A pooled connection may have been closed by the target in the meantime, in
+        // which case we retry once on a fresh connection.
+        let mut pooled = self.take_backend_connection(&target_addr);
+        let (mut reader, status_line) = loop {
+            let reused = pooled.is_some();
+            let stream = match pooled.take() {
+                Some(stream) => stream,
+                None => match Self::connect_to_backend(&target_addr) {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        error!(target = %target_addr, error = %e, "Error connecting to target server");
+                        return ApiResponse {
+                            data: Box::new(format!("Error connecting to target server: {}", e)),
+                            metadata: HashMap::new(),
+                            status: ResponseStatus::Error,
+                        };
+                    }
+                },
+            };
+            
+            // Send the request
+            let mut reader = BufReader::new(stream);
+            if let Err(e) = reader.get_mut().write_all(&http_request) {
+                if reused {
+                    debug!(target = %target_addr, "Pooled connection is stale, reconnecting");
+                    continue;
+                }
+                error!(error = %e, "Error writing to target server");
                 return ApiResponse {
-                    data: Box::new(format!("Error connecting to target server: {}", e)),
+                    data: Box::new(format!("Error writing to target server: {}", e)),
                     metadata: HashMap::new(),
                     status: ResponseStatus::Error,
                 };
             }
+            
+            // Read status line
+            let mut status_line = String::new();
+            match reader.read_line(&mut status_line) {
+                Ok(0) | Err(_) if reused => {
+                    debug!(target = %target_addr, "Pooled connection is stale, reconnecting");
+                    continue;
+                }
+                Ok(_) => break (reader, status_line),
+                Err(e) => {
+                    error!(error = %e, "Error reading status line from target server");
+                    return ApiResponse {
+                        data: Box::new(format!("Error reading status line from target server: {}", e)),
+                        metadata: HashMap::new(),
+                        status: ResponseStatus::Error,
+                    };
+                }
+            }
         };
         
-        // Set stream to blocking mode for simplicity
-        if let Err(e) = stream.set_nonblocking(false) {
-            eprintln!("Error setting stream to blocking mode: {}", e);
-            return ApiResponse {
-                data: Box::new(format!("Error setting stream to blocking mode: {}", e)),
-                metadata: HashMap::new(),
-                status: ResponseStatus::Error,
-            };
-        }
-        
-        // Create HTTP request
-        let http_request = format!(
-            "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}",
-            method,
-            path_with_query,
-            host,
-            body.len(),
-            body
-        );
-        
-        println!("Sending request to target server:");
-        println!("{}", http_request);
-        
-        // Send the request
-        if let Err(e) = stream.write_all(http_request.as_bytes()) {
-            eprintln!("Error writing to target server: {}", e);
-            return ApiResponse {
-                data: Box::new(format!("Error writing to target server: {}", e)),
-                metadata: HashMap::new(),
-                status: ResponseStatus::Error,
-            };
-        }
-        
-        // Read the response
-        let mut reader = BufReader::new(&stream);
-        
-        // Read status line
-        let mut status_line = String::new();
-        if let Err(e) = reader.read_line(&mut status_line) {
-            eprintln!("Error reading status line from target server: {}", e);
-            return ApiResponse {
-                data: Box::new(format!("Error reading status line from target server: {}", e)),
-                metadata: HashMap::new(),
-                status: ResponseStatus::Error,
-            };
-        }
-        
-        println!("Received status line: {}", status_line.trim());
-        
         // Parse status code
         let status_parts: Vec<&str> = status_line.split_whitespace().collect();
         let status_code = if status_parts.len() >= 2 {
             match status_parts[1].parse::<u16>() {
                 Ok(code) => code,
                 Err(_) => {
-                    eprintln!("Invalid status code in response: {}", status_line);
+                    error!(status_line = %status_line.trim(), "Invalid status code in response");
                     return ApiResponse {
                         data: Box::new(format!("Invalid status code in response: {}", status_line)),
                         metadata: HashMap::new(),
@@ -477,7 +1298,7 @@ impl HttpReverseProxy {
                 }
             }
         } else {
-            eprintln!("Invalid status line: {}", status_line);
+            error!(status_line = %status_line.trim(), "Invalid status line");
             return ApiResponse {
                 data: Box::new(format!("Invalid status line: {}", status_line)),
                 metadata: HashMap::new(),
@@ -504,7 +1325,7 @@ impl HttpReverseProxy {
                     }
                 },
                 Err(e) => {
-                    eprintln!("Error reading headers from target server: {}", e);
+                    error!(error = %e, "Error reading headers from target server");
                     return ApiResponse {
                         data: Box::new(format!("Error reading headers from target server: {}", e)),
                         metadata: HashMap::new(),
@@ -514,15 +1335,20 @@ impl HttpReverseProxy {
             }
         }
         
-        println!("Received headers:");
-        for (key, value) in &headers {
-            println!("  {}: {}", key, value);
-        }
-        
         // Read body
         let content_length = headers.get("content-length")
             .and_then(|s| s.parse::<usize>().ok());
         
+        let max_body_size = self.max_body_size();
+        if content_length.is_some_and(|length| length > max_body_size) {
+            error!(content_length, max_body_size, "Response from target server too large");
+            return ApiResponse {
+                data: Box::new("Response from target server exceeds the maximum body size".to_string()),
+                metadata: HashMap::new(),
+                status: ResponseStatus::Error,
+            };
+        }
+        
         let mut body = Vec::new();
         if let Some(length) = content_length {
             // Read exactly content-length bytes
@@ -530,7 +1356,7 @@ impl HttpReverseProxy {
             match reader.read_exact(&mut buffer) {
                 Ok(_) => body = buffer,
                 Err(e) => {
-                    eprintln!("Error reading body from target server: {}", e);
+                    error!(error = %e, "Error reading body from target server");
                     return ApiResponse {
                         data: Box::new(format!("Error reading body from target server: {}", e)),
                         metadata: HashMap::new(),
@@ -539,11 +1365,19 @@ impl HttpReverseProxy {
                 }
             }
         } else {
-            // Read until EOF
-            match reader.read_until(0, &mut body) {
+            // Read until EOF, allowing one byte past the limit to detect oversized bodies
+            match reader.by_ref().take(max_body_size as u64 + 1).read_to_end(&mut body) {
+                Ok(_) if body.len() > max_body_size => {
+                    error!(max_body_size, "Response from target server too large");
+                    return ApiResponse {
+                        data: Box::new("Response from target server exceeds the maximum body size".to_string()),
+                        metadata: HashMap::new(),
+                        status: ResponseStatus::Error,
+                    };
+                }
                 Ok(_) => {},
                 Err(e) => {
-                    eprintln!("Error reading body from target server: {}", e);
+                    error!(error = %e, "Error reading body from target server");
                     return ApiResponse {
                         data: Box::new(format!("Error reading body from target server: {}", e)),
                         metadata: HashMap::new(),
@@ -557,7 +1391,7 @@ impl HttpReverseProxy {
         let body_str = match String::from_utf8(body) {
             Ok(s) => s,
             Err(_) => {
-                eprintln!("Body is not valid UTF-8");
+                error!("Body is not valid UTF-8");
                 return ApiResponse {
                     data: Box::new("Body is not valid UTF-8".to_string()),
                     metadata: HashMap::new(),
@@ -566,8 +1400,15 @@ impl HttpReverseProxy {
             }
         };
         
-        println!("Received body (first 100 chars): {}", 
-                 if body_str.len() > 100 { &body_str[..100] } else { &body_str });
+        debug!(status_code, body_len = body_str.len(), "Received response from target server");
+        
+        // Keep the connection for reuse if the response was fully delimited
+        let reusable = content_length.is_some()
+            && !headers.get("connection").is_some_and(|value| value.eq_ignore_ascii_case("close"))
+            && reader.buffer().is_empty();
+        if reusable {
+            self.return_backend_connection(&target_addr, reader.into_inner());
+        }
         
         // Determine response status based on HTTP status code
         let response_status = match status_code {
@@ -576,8 +1417,9 @@ impl HttpReverseProxy {
             _ => ResponseStatus::Error,
         };
         
-        // Convert headers to metadata
-        let metadata: HashMap<String, String> = headers.into_iter().collect();
+        // Convert headers to metadata, along with the target's status code
+        let mut metadata: HashMap<String, String> = headers.into_iter().collect();
+        metadata.insert(UPSTREAM_STATUS_KEY.to_string(), status_code.to_string());
         
         // Create and return API response
         ApiResponse {
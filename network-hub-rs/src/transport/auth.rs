@@ -0,0 +1,41 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// Shared secret hubs must know to connect to each other, on top of trusting their certificates
+///
+/// Sent in the hello that opens every connection to a peer. A hub with a
+/// secret closes connections whose hello doesn't carry the same one before
+/// handling anything sent over them.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AuthConfig {
+    /// The secret every hub of the mesh is configured with
+    pub shared_secret: String,
+}
+
+impl AuthConfig {
+    /// Create an authentication configuration with a shared secret
+    pub fn new(shared_secret: impl Into<String>) -> Self {
+        AuthConfig { shared_secret: shared_secret.into() }
+    }
+
+    /// Whether a token sent by a peer is the shared secret
+    ///
+    /// Compares every byte whatever the first mismatch, so the time taken doesn't
+    /// tell how much of the secret a guess got right.
+    pub(crate) fn accepts(&self, token: Option<&str>) -> bool {
+        let Some(token) = token else {
+            return false;
+        };
+        let (token, secret) = (token.as_bytes(), self.shared_secret.as_bytes());
+        token.len() == secret.len()
+            && token.iter().zip(secret).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+    }
+}
+
+// The secret is left out so it doesn't end up in logs
+impl fmt::Debug for AuthConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuthConfig").field("shared_secret", &"<redacted>").finish()
+    }
+}
@@ -1,7 +1,32 @@
 use serde::{Serialize, Deserialize};
 use std::any::Any;
-use crate::hub::{ApiRequest, ApiResponse, Message};
+use std::borrow::Cow;
+use std::io::{self, Read, Write};
+use crate::hub::{ApiRequest, ApiResponse, HubScope, Message};
+use crate::transport::serde_request::SerdePayload;
 use std::collections::HashMap;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use tracing::warn;
+
+/// Flag set in a frame's message type byte when its payload is gzip compressed
+pub(crate) const COMPRESSED_FLAG: u8 = 0x80;
+
+/// Smallest frame payload worth compressing
+pub(crate) const MIN_COMPRESSION_SIZE: usize = 1024;
+
+/// Largest payload a frame may carry, as sent and once decompressed, so a peer
+/// can't make the receiver allocate without bound
+pub(crate) const MAX_FRAME_SIZE: usize = 1024 * 1024;
+
+/// Size of a frame's header: its message type and the length of its payload
+const FRAME_HEADER_SIZE: usize = 5;
+
+/// Encoding of published messages whose data is bytes rather than text
+const BASE64_ENCODING: &str = "base64";
 
 // Simple message enum for network transport
 #[derive(Serialize, Deserialize)]
@@ -9,13 +34,18 @@ enum TransportMessage {
     Request {
         path: String,
         data: String,
+        // Type of a `SerdePayload` encoded as JSON in `data`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        data_type: Option<String>,
         metadata: HashMap<String, String>,
         sender_id: String,
     },
     Response {
         data: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        data_type: Option<String>,
         metadata: HashMap<String, String>,
-        status: u8, // 0=success, 1=not found, 2=error, 3=intercepted, 4=approximated
+        status: u8, // 0=success, 1=not found, 2=error, 3=intercepted, 4=approximated, 5=bad request
     },
     PubMessage {
         topic: String,
@@ -23,25 +53,89 @@ enum TransportMessage {
         metadata: HashMap<String, String>,
         sender_id: String,
         timestamp: u64,
+        // How bytes are encoded in `data`; missing for text
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        encoding: Option<String>,
+    },
+    Subscribe {
+        pattern: String,
+    },
+    Hello {
+        hub_id: String,
+        scope: HubScope,
+        // Missing from hubs that can't decompress frames
+        #[serde(default)]
+        compression: bool,
+        // Shared secret, sent by hubs configured with one
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        token: Option<String>,
     },
 }
 
+/// A message published by a peer, with the data it was published with
+pub(crate) enum PublishedMessage {
+    /// Message with string data
+    Text(Message<String>),
+    /// Message with byte data
+    Binary(Message<Vec<u8>>),
+}
+
+/// Information about a hub, exchanged when connecting to a peer
+pub(crate) struct PeerHello {
+    /// ID of the hub
+    pub hub_id: String,
+    /// Scope of the hub
+    pub scope: HubScope,
+    /// Whether the hub wants large frames compressed, or, answering, agrees to it
+    pub compression: bool,
+    /// Shared secret of the connecting hub, if it has one; never sent back
+    pub token: Option<String>,
+}
+
+/// Request for a peer to forward the messages published to it that match a pattern
+pub(crate) struct SubscriptionRequest {
+    /// Topic pattern of the messages to forward
+    pub pattern: String,
+}
+
+// Extract the data sent for a request or response: strings as is, typed
+// payloads as JSON with their type name
+fn encode_data(data: &(dyn Any + Send + Sync)) -> (String, Option<String>) {
+    if let Some(payload) = data.downcast_ref::<SerdePayload>() {
+        return (payload.value().to_string(), Some(payload.type_name().to_string()));
+    }
+    
+    let str_data = match data.downcast_ref::<String>() {
+        Some(s) => s.clone(),
+        _ => match data.downcast_ref::<&str>() {
+            Some(s) => s.to_string(),
+            _ => "".to_string(),
+        }
+    };
+    (str_data, None)
+}
+
+// Rebuild the data sent for a request or response
+fn decode_data(data: String, data_type: Option<String>) -> Option<Box<dyn Any + Send + Sync>> {
+    match data_type {
+        Some(type_name) => {
+            let value = serde_json::from_str(&data).ok()?;
+            Some(Box::new(SerdePayload::from_parts(type_name, value)))
+        }
+        None => Some(Box::new(data)),
+    }
+}
+
 /// Serialize data to bytes
 pub fn serialize<T: Send + Sync + 'static>(data: &T) -> Vec<u8> {
     // Try to convert the data based on its type
     if let Some(req) = (data as &dyn Any).downcast_ref::<ApiRequest>() {
-        // Extract string data from Box<dyn Any>
-        let str_data = match req.data.downcast_ref::<String>() {
-            Some(s) => s.clone(),
-            _ => match req.data.downcast_ref::<&str>() {
-                Some(s) => s.to_string(),
-                _ => "".to_string(),
-            }
-        };
+        let (str_data, data_type) = encode_data(&*req.data);
         
         let message = TransportMessage::Request {
             path: req.path.clone(),
             data: str_data,
+            data_type,
             metadata: req.metadata.clone(),
             sender_id: req.sender_id.clone(),
         };
@@ -51,14 +145,7 @@ pub fn serialize<T: Send + Sync + 'static>(data: &T) -> Vec<u8> {
         }
     } 
     else if let Some(resp) = (data as &dyn Any).downcast_ref::<ApiResponse>() {
-        // Extract string data from Box<dyn Any>
-        let str_data = match resp.data.downcast_ref::<String>() {
-            Some(s) => s.clone(),
-            _ => match resp.data.downcast_ref::<&str>() {
-                Some(s) => s.to_string(),
-                _ => "".to_string(),
-            }
-        };
+        let (str_data, data_type) = encode_data(&*resp.data);
         
         // Convert status to u8
         let status_code = match resp.status {
@@ -67,10 +154,14 @@ pub fn serialize<T: Send + Sync + 'static>(data: &T) -> Vec<u8> {
             crate::hub::ResponseStatus::Error => 2,
             crate::hub::ResponseStatus::Intercepted => 3,
             crate::hub::ResponseStatus::Approximated => 4,
+            crate::hub::ResponseStatus::BadRequest => 5,
+            crate::hub::ResponseStatus::Cancelled => 6,
+            crate::hub::ResponseStatus::ShuttingDown => 7,
         };
         
         let message = TransportMessage::Response {
             data: str_data,
+            data_type,
             metadata: resp.metadata.clone(),
             status: status_code,
         };
@@ -86,6 +177,43 @@ pub fn serialize<T: Send + Sync + 'static>(data: &T) -> Vec<u8> {
             metadata: msg.metadata.clone(),
             sender_id: msg.sender_id.clone(),
             timestamp: msg.timestamp,
+            encoding: None,
+        };
+        
+        if let Ok(bytes) = serde_json::to_vec(&message) {
+            return bytes;
+        }
+    }
+    else if let Some(msg) = (data as &dyn Any).downcast_ref::<Message<Vec<u8>>>() {
+        // Bytes needn't be valid UTF-8, so they travel as base64
+        let message = TransportMessage::PubMessage {
+            topic: msg.topic.clone(),
+            data: BASE64.encode(&msg.data),
+            metadata: msg.metadata.clone(),
+            sender_id: msg.sender_id.clone(),
+            timestamp: msg.timestamp,
+            encoding: Some(BASE64_ENCODING.to_string()),
+        };
+        
+        if let Ok(bytes) = serde_json::to_vec(&message) {
+            return bytes;
+        }
+    }
+    else if let Some(subscription) = (data as &dyn Any).downcast_ref::<SubscriptionRequest>() {
+        let message = TransportMessage::Subscribe {
+            pattern: subscription.pattern.clone(),
+        };
+        
+        if let Ok(bytes) = serde_json::to_vec(&message) {
+            return bytes;
+        }
+    }
+    else if let Some(hello) = (data as &dyn Any).downcast_ref::<PeerHello>() {
+        let message = TransportMessage::Hello {
+            hub_id: hello.hub_id.clone(),
+            scope: hello.scope,
+            compression: hello.compression,
+            token: hello.token.clone(),
         };
         
         if let Ok(bytes) = serde_json::to_vec(&message) {
@@ -99,6 +227,7 @@ pub fn serialize<T: Send + Sync + 'static>(data: &T) -> Vec<u8> {
             metadata: msg.metadata.clone(),
             sender_id: msg.sender_id.clone(),
             timestamp: msg.timestamp,
+            encoding: None,
         };
         
         if let Ok(bytes) = serde_json::to_vec(&message) {
@@ -107,7 +236,7 @@ pub fn serialize<T: Send + Sync + 'static>(data: &T) -> Vec<u8> {
     }
     
     // Default case - return empty data
-    println!("Serialization not implemented for type: {}", std::any::type_name::<T>());
+    warn!(type_name = std::any::type_name::<T>(), "Serialization not implemented for type");
     Vec::new()
 }
 
@@ -120,11 +249,11 @@ pub fn deserialize<T: Send + Sync + 'static>(bytes: &[u8]) -> Option<T> {
     if let Ok(message) = serde_json::from_slice::<TransportMessage>(bytes) {
         if type_id == std::any::TypeId::of::<ApiRequest>() {
             // Only handle Request message type for ApiRequest
-            if let TransportMessage::Request { path, data, metadata, sender_id } = message {
+            if let TransportMessage::Request { path, data, data_type, metadata, sender_id } = message {
                 // Create a new ApiRequest
                 let request = ApiRequest {
                     path,
-                    data: Box::new(data),
+                    data: decode_data(data, data_type)?,
                     metadata,
                     sender_id,
                 };
@@ -137,7 +266,7 @@ pub fn deserialize<T: Send + Sync + 'static>(bytes: &[u8]) -> Option<T> {
         }
         else if type_id == std::any::TypeId::of::<ApiResponse>() {
             // Only handle Response message type for ApiResponse
-            if let TransportMessage::Response { data, metadata, status } = message {
+            if let TransportMessage::Response { data, data_type, metadata, status } = message {
                 // Convert status from u8
                 let response_status = match status {
                     0 => crate::hub::ResponseStatus::Success,
@@ -145,11 +274,14 @@ pub fn deserialize<T: Send + Sync + 'static>(bytes: &[u8]) -> Option<T> {
                     2 => crate::hub::ResponseStatus::Error,
                     3 => crate::hub::ResponseStatus::Intercepted,
                     4 => crate::hub::ResponseStatus::Approximated,
+                    5 => crate::hub::ResponseStatus::BadRequest,
+                    6 => crate::hub::ResponseStatus::Cancelled,
+                    7 => crate::hub::ResponseStatus::ShuttingDown,
                     _ => crate::hub::ResponseStatus::Error,
                 };
                 
                 let response = ApiResponse {
-                    data: Box::new(data),
+                    data: decode_data(data, data_type)?,
                     metadata,
                     status: response_status,
                 };
@@ -161,24 +293,181 @@ pub fn deserialize<T: Send + Sync + 'static>(bytes: &[u8]) -> Option<T> {
             }
         }
         else if type_id == std::any::TypeId::of::<Message<String>>() {
-            // Only handle PubMessage message type for Message<String>
-            if let TransportMessage::PubMessage { topic, data, metadata, sender_id, timestamp } = message {
-                let pub_message = Message {
-                    topic,
-                    data,
-                    metadata,
-                    sender_id,
-                    timestamp,
-                };
-                
+            // Only handle text messages for Message<String>
+            if let Some(PublishedMessage::Text(pub_message)) = published_message(message) {
                 // Convert it to the expected type using any_box cast
                 let boxed: Box<dyn Any> = Box::new(pub_message);
                 // This is safe because we've verified T is Message<String>
                 return boxed.downcast::<T>().ok().map(|t| *t);
             }
         }
+        else if type_id == std::any::TypeId::of::<Message<Vec<u8>>>() {
+            // Text messages are taken as their UTF-8 bytes
+            let pub_message = match published_message(message) {
+                Some(PublishedMessage::Binary(message)) => Some(message),
+                Some(PublishedMessage::Text(message)) => Some(Message {
+                    topic: message.topic,
+                    data: message.data.into_bytes(),
+                    metadata: message.metadata,
+                    sender_id: message.sender_id,
+                    timestamp: message.timestamp,
+                }),
+                None => None,
+            };
+            if let Some(pub_message) = pub_message {
+                let boxed: Box<dyn Any> = Box::new(pub_message);
+                return boxed.downcast::<T>().ok().map(|t| *t);
+            }
+        }
+        else if type_id == std::any::TypeId::of::<SubscriptionRequest>() {
+            if let TransportMessage::Subscribe { pattern } = message {
+                let boxed: Box<dyn Any> = Box::new(SubscriptionRequest { pattern });
+                return boxed.downcast::<T>().ok().map(|t| *t);
+            }
+        }
+        else if type_id == std::any::TypeId::of::<PeerHello>() {
+            if let TransportMessage::Hello { hub_id, scope, compression, token } = message {
+                let boxed: Box<dyn Any> = Box::new(PeerHello { hub_id, scope, compression, token });
+                return boxed.downcast::<T>().ok().map(|t| *t);
+            }
+        }
     }
     
-    println!("Deserialization failed for type: {}", std::any::type_name::<T>());
+    warn!(type_name = std::any::type_name::<T>(), "Deserialization failed for type");
     None
-}
\ No newline at end of file
+}
+
+/// Deserialize a published message, whether its data is text or bytes
+pub(crate) fn deserialize_published(bytes: &[u8]) -> Option<PublishedMessage> {
+    let message = serde_json::from_slice::<TransportMessage>(bytes).ok();
+    let published = message.and_then(published_message);
+    if published.is_none() {
+        warn!("Deserialization failed for published message");
+    }
+    published
+}
+
+/// Serialize a message published to a hub, if its data is a string or bytes
+pub(crate) fn serialize_published(message: &Message<Box<dyn Any + Send + Sync>>) -> Option<Vec<u8>> {
+    fn with_data<T>(message: &Message<Box<dyn Any + Send + Sync>>, data: T) -> Message<T> {
+        Message {
+            topic: message.topic.clone(),
+            data,
+            metadata: message.metadata.clone(),
+            sender_id: message.sender_id.clone(),
+            timestamp: message.timestamp,
+        }
+    }
+    
+    if let Some(data) = message.data.downcast_ref::<String>() {
+        return Some(serialize(&with_data(message, data.clone())));
+    }
+    if let Some(data) = message.data.downcast_ref::<&str>() {
+        return Some(serialize(&with_data(message, data.to_string())));
+    }
+    let data = message.data.downcast_ref::<Vec<u8>>()?;
+    Some(serialize(&with_data(message, data.clone())))
+}
+
+// Rebuild a published message, decoding its data as the encoding it was sent with
+fn published_message(message: TransportMessage) -> Option<PublishedMessage> {
+    let TransportMessage::PubMessage { topic, data, metadata, sender_id, timestamp, encoding } = message else {
+        return None;
+    };
+    
+    match encoding.as_deref() {
+        None => Some(PublishedMessage::Text(Message { topic, data, metadata, sender_id, timestamp })),
+        Some(BASE64_ENCODING) => {
+            let data = BASE64.decode(data).ok()?;
+            Some(PublishedMessage::Binary(Message { topic, data, metadata, sender_id, timestamp }))
+        }
+        Some(encoding) => {
+            warn!(encoding, "Unknown message data encoding");
+            None
+        }
+    }
+}
+
+/// Build a frame from its message type and payload
+///
+/// A frame is the message type, the length of the payload as a big-endian `u32`,
+/// then the payload, so the receiver knows where it ends however it arrives.
+/// When `compress` is set, payloads of at least `MIN_COMPRESSION_SIZE` bytes are
+/// gzipped and the message type flagged with `COMPRESSED_FLAG`, unless that
+/// wouldn't make the frame any smaller.
+pub(crate) fn encode_frame(message_type: u8, payload: &[u8], compress: bool) -> Vec<u8> {
+    if compress && payload.len() >= MIN_COMPRESSION_SIZE {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        if let Ok(compressed) = encoder.write_all(payload).and_then(|_| encoder.finish()) {
+            if compressed.len() <= payload.len() {
+                return frame_with_header(message_type | COMPRESSED_FLAG, &compressed);
+            }
+        }
+    }
+    frame_with_header(message_type, payload)
+}
+
+// Put the header in front of a payload
+fn frame_with_header(message_type: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(FRAME_HEADER_SIZE + payload.len());
+    frame.push(message_type);
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Read a whole frame built by `encode_frame` from a stream, however many reads it takes
+///
+/// Returns `None` if the stream ends between frames. Fails with `InvalidData` if
+/// the payload is longer than `MAX_FRAME_SIZE`, as the rest of the stream can't
+/// be trusted then.
+pub(crate) fn read_frame(stream: &mut impl Read) -> io::Result<Option<Vec<u8>>> {
+    let mut header = [0u8; FRAME_HEADER_SIZE];
+    if stream.read(&mut header[..1])? == 0 {
+        return Ok(None);
+    }
+    stream.read_exact(&mut header[1..])?;
+    let length = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+    if length > MAX_FRAME_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Frame of {} bytes is too large", length)));
+    }
+    
+    let mut frame = vec![0u8; FRAME_HEADER_SIZE + length];
+    frame[..FRAME_HEADER_SIZE].copy_from_slice(&header);
+    stream.read_exact(&mut frame[FRAME_HEADER_SIZE..])?;
+    Ok(Some(frame))
+}
+
+/// Split a frame into its message type and payload, decompressing the payload if it's flagged
+///
+/// Returns `None` for a frame whose header doesn't match its length, or a
+/// compressed payload that can't be decompressed, decompresses to more than
+/// `MAX_FRAME_SIZE` bytes, or was sent over a connection that didn't agree to
+/// compression (`compression` unset).
+pub(crate) fn decode_frame(frame: &[u8], compression: bool) -> Option<(u8, Cow<'_, [u8]>)> {
+    let (header, payload) = frame.split_at_checked(FRAME_HEADER_SIZE)?;
+    let message_type = header[0];
+    if u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize != payload.len() {
+        warn!(message_type, "Frame length doesn't match its payload");
+        return None;
+    }
+    if message_type & COMPRESSED_FLAG == 0 {
+        return Some((message_type, Cow::Borrowed(payload)));
+    }
+    if !compression {
+        warn!(message_type, "Compressed frame received without agreeing to compression");
+        return None;
+    }
+    
+    let mut decompressed = Vec::new();
+    let limit = MAX_FRAME_SIZE as u64 + 1;
+    if let Err(e) = GzDecoder::new(payload).take(limit).read_to_end(&mut decompressed) {
+        warn!(message_type, error = %e, "Failed to decompress frame");
+        return None;
+    }
+    if decompressed.len() > MAX_FRAME_SIZE {
+        warn!(message_type, max_size = MAX_FRAME_SIZE, "Compressed frame too large once decompressed");
+        return None;
+    }
+    Some((message_type & !COMPRESSED_FLAG, Cow::Owned(decompressed)))
+}
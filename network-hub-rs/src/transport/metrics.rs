@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::Serialize;
+
+/// Snapshot of the traffic counters of a network transport
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TransportStats {
+    /// Bytes read from connections accepted from other hubs
+    pub bytes_read: u64,
+    /// Bytes written to connections accepted from other hubs
+    pub bytes_written: u64,
+    /// Number of requests from other hubs that were handled
+    pub requests_served: u64,
+    /// Number of requests from other hubs that couldn't be decoded
+    pub deserialize_failures: u64,
+    /// Number of connections closed for not sending the shared secret
+    pub auth_failures: u64,
+    /// Traffic with each connected peer, by peer ID
+    pub peers: HashMap<String, PeerTraffic>,
+}
+
+/// Snapshot of the traffic with a connected peer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct PeerTraffic {
+    /// Bytes sent to the peer
+    pub bytes_sent: u64,
+    /// Bytes received from the peer
+    pub bytes_received: u64,
+    /// Number of responses from the peer that couldn't be decoded
+    pub deserialize_failures: u64,
+}
+
+/// Traffic counters for the connections a transport accepts
+#[derive(Default)]
+pub(crate) struct TransportMetrics {
+    /// Bytes read from accepted connections
+    bytes_read: AtomicU64,
+    /// Bytes written to accepted connections
+    bytes_written: AtomicU64,
+    /// Requests handled
+    requests_served: AtomicU64,
+    /// Requests that couldn't be decoded
+    deserialize_failures: AtomicU64,
+    /// Connections closed for not sending the shared secret
+    auth_failures: AtomicU64,
+}
+
+impl TransportMetrics {
+    /// Record bytes read from a connection
+    pub(crate) fn record_read(&self, bytes: usize) {
+        self.bytes_read.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+    
+    /// Record bytes written to a connection
+    pub(crate) fn record_written(&self, bytes: usize) {
+        self.bytes_written.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+    
+    /// Record a handled request
+    pub(crate) fn record_request(&self) {
+        self.requests_served.fetch_add(1, Ordering::Relaxed);
+    }
+    
+    /// Record a request that couldn't be decoded
+    pub(crate) fn record_deserialize_failure(&self) {
+        self.deserialize_failures.fetch_add(1, Ordering::Relaxed);
+    }
+    
+    /// Record a connection closed for not sending the shared secret
+    pub(crate) fn record_auth_failure(&self) {
+        self.auth_failures.fetch_add(1, Ordering::Relaxed);
+    }
+    
+    /// Take a snapshot of the counters, along with the traffic of each peer
+    pub(crate) fn snapshot(&self, peers: HashMap<String, PeerTraffic>) -> TransportStats {
+        TransportStats {
+            bytes_read: self.bytes_read.load(Ordering::Relaxed),
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+            requests_served: self.requests_served.load(Ordering::Relaxed),
+            deserialize_failures: self.deserialize_failures.load(Ordering::Relaxed),
+            auth_failures: self.auth_failures.load(Ordering::Relaxed),
+            peers,
+        }
+    }
+}
+
+/// Traffic counters for a connection to a peer
+#[derive(Default)]
+pub(crate) struct PeerMetrics {
+    /// Bytes sent to the peer
+    bytes_sent: AtomicU64,
+    /// Bytes received from the peer
+    bytes_received: AtomicU64,
+    /// Responses that couldn't be decoded
+    deserialize_failures: AtomicU64,
+}
+
+impl PeerMetrics {
+    /// Record bytes sent to the peer
+    pub(crate) fn record_sent(&self, bytes: usize) {
+        self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+    
+    /// Record bytes received from the peer
+    pub(crate) fn record_received(&self, bytes: usize) {
+        self.bytes_received.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+    
+    /// Record a response that couldn't be decoded
+    pub(crate) fn record_deserialize_failure(&self) {
+        self.deserialize_failures.fetch_add(1, Ordering::Relaxed);
+    }
+    
+    /// Take a snapshot of the counters
+    pub(crate) fn snapshot(&self) -> PeerTraffic {
+        PeerTraffic {
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            deserialize_failures: self.deserialize_failures.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -1,26 +1,74 @@
 mod tls;
 mod network_peer;
 mod message_codec;
+mod metrics;
+mod reconnect;
+mod serde_request;
+mod peer_filter;
+mod auth;
 
 pub use tls::TlsConfig;
 pub use tls::TlsStream;
 pub use tls::create_server_tls_stream;
 pub use tls::create_client_tls_stream;
-pub use network_peer::NetworkPeer;
+pub use tls::{accept_tls_stream, create_client_tls_stream_with_alpn, HUB_ALPN_PROTOCOL};
+pub use tls::{create_client_config, create_server_config};
+pub use network_peer::{NetworkPeer, PeerStatus};
+pub use metrics::{PeerTraffic, TransportStats};
+pub use reconnect::ReconnectPolicy;
+pub use serde_request::{SerdePayload, SerdeRequest};
+pub use peer_filter::{PeerFilter, PeerRule};
+pub use auth::AuthConfig;
 
 use crate::error::{HubError, Result};
-use crate::hub::{Hub, ApiRequest, ApiResponse, Message};
-use crate::utils::current_time_millis;
+use crate::hub::{Hub, ApiRequest, ApiResponse, Message, ResponseStatus};
+use crate::proxy::HttpReverseProxy;
+use crate::utils::{
+    bind_listener, bind_shared_udp, connect_tcp, current_time_millis, generate_uuid, is_timeout, set_idle_timeout, ShutdownSignal,
+    WorkerPool, DEFAULT_LISTEN_BACKLOG, DEFAULT_WORKER_THREADS,
+};
 use crate::HubScope;
 
-use std::collections::HashMap;
-use std::net::{TcpListener, TcpStream, SocketAddr};
-use std::sync::{Arc, RwLock};
+use std::collections::{HashMap, HashSet};
+use std::net::{Ipv4Addr, Ipv6Addr, TcpListener, TcpStream, SocketAddr, UdpSocket};
+use std::sync::{mpsc, Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicU16, AtomicU32, AtomicU64, AtomicUsize, Ordering};
 use std::thread;
-use std::time::Duration;
-use std::io::{Read, Write};
+use std::time::{Duration, Instant};
+use std::io::Write;
 
-use message_codec::{serialize, deserialize};
+use message_codec::{decode_frame, encode_frame, read_frame, serialize, deserialize, deserialize_published, serialize_published, PeerHello, PublishedMessage, SubscriptionRequest};
+use metrics::TransportMetrics;
+
+use socket2::{Domain, Socket, Type};
+use tracing::{debug, error, info, info_span, warn};
+
+/// Default time an accepted peer connection may sit idle before it is closed
+///
+/// Peer connections are long-lived, so this is much longer than the proxy's timeout.
+const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Default time to wait for a peer to answer a request or heartbeat
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Time after which a peer forwarding messages to a remote subscriber checks that
+/// the subscriber is still connected, if no message was forwarded in the meantime
+const SUBSCRIPTION_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// ALPN protocol of HTTP clients, handed to the proxy on a port shared with one
+const HTTP_ALPN_PROTOCOL: &[u8] = b"http/1.1";
+
+/// Default UDP port hubs announce themselves on
+pub const DEFAULT_DISCOVERY_PORT: u16 = 8765;
+
+/// Default time between discovery announcements
+pub const DEFAULT_DISCOVERY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Multicast group IPv6 hubs announce themselves to (all nodes on the local link)
+const DISCOVERY_MULTICAST_V6: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1);
+
+/// How often the discovery listener checks for shutdown while no announcements arrive
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(200);
 
 /// Network transport layer for hub communication
 #[derive(Clone)]
@@ -33,6 +81,52 @@ pub struct NetworkTransport {
     tls_config: TlsConfig,
     /// Address to bind to
     bind_address: SocketAddr,
+    /// Number of worker threads serving connections
+    worker_threads: Arc<AtomicUsize>,
+    /// Number of connections the OS queues before they're accepted
+    listen_backlog: Arc<AtomicU32>,
+    /// Time an accepted connection may sit idle before it is closed, in milliseconds
+    idle_timeout_ms: Arc<AtomicU64>,
+    /// Time to wait for a peer to answer, in milliseconds
+    request_timeout_ms: Arc<AtomicU64>,
+    /// UDP port discovery announcements are sent and received on
+    discovery_port: Arc<AtomicU16>,
+    /// Time between discovery announcements, in milliseconds
+    discovery_interval_ms: Arc<AtomicU64>,
+    /// Traffic counters for accepted connections
+    metrics: Arc<TransportMetrics>,
+    /// Stop signal of the current run's accept loops and discovery threads
+    shutdown: Arc<Mutex<Arc<ShutdownSignal>>>,
+    /// Addresses the current run's accept loops listen on
+    listen_addresses: Arc<RwLock<Vec<SocketAddr>>>,
+    /// How peers whose connection breaks are reconnected, if at all
+    reconnect_policy: Arc<RwLock<Option<ReconnectPolicy>>>,
+    /// IDs of the peers being reconnected
+    reconnecting: Arc<Mutex<HashSet<String>>>,
+    /// Addresses of the discovered hubs being connected to
+    connecting: Arc<Mutex<HashSet<SocketAddr>>>,
+    /// Connections of the subscriptions made with peers, by subscription ID
+    remote_subscriptions: Arc<Mutex<HashMap<String, TcpStream>>>,
+    /// Whether to compress large frames for peers that agree to it
+    compression: Arc<AtomicBool>,
+    /// Peers this transport connects to
+    peer_filter: Arc<RwLock<PeerFilter>>,
+    /// Shared secret peers must know to connect, if any
+    auth: Arc<RwLock<Option<AuthConfig>>>,
+    /// Proxy serving the HTTP clients connecting to the transport's port, if any
+    http_proxy: Arc<RwLock<Option<HttpReverseProxy>>>,
+}
+
+/// Settings an accepted connection is served with, read when it's accepted
+struct ConnectionSettings {
+    /// Time the connection may sit idle before it is closed
+    idle_timeout: Duration,
+    /// Whether responses may be compressed
+    compression: bool,
+    /// Shared secret the peer must send, if any
+    auth: Option<AuthConfig>,
+    /// Proxy serving clients that negotiate HTTP, if any
+    http_proxy: Option<HttpReverseProxy>,
 }
 
 impl NetworkTransport {
@@ -43,217 +137,662 @@ impl NetworkTransport {
             peers: Arc::new(RwLock::new(HashMap::new())),
             tls_config,
             bind_address,
+            worker_threads: Arc::new(AtomicUsize::new(DEFAULT_WORKER_THREADS)),
+            listen_backlog: Arc::new(AtomicU32::new(DEFAULT_LISTEN_BACKLOG)),
+            idle_timeout_ms: Arc::new(AtomicU64::new(DEFAULT_IDLE_TIMEOUT.as_millis() as u64)),
+            request_timeout_ms: Arc::new(AtomicU64::new(DEFAULT_REQUEST_TIMEOUT.as_millis() as u64)),
+            discovery_port: Arc::new(AtomicU16::new(DEFAULT_DISCOVERY_PORT)),
+            discovery_interval_ms: Arc::new(AtomicU64::new(DEFAULT_DISCOVERY_INTERVAL.as_millis() as u64)),
+            metrics: Arc::new(TransportMetrics::default()),
+            shutdown: Arc::new(Mutex::new(Arc::new(ShutdownSignal::new()))),
+            listen_addresses: Arc::new(RwLock::new(Vec::new())),
+            reconnect_policy: Arc::new(RwLock::new(None)),
+            reconnecting: Arc::new(Mutex::new(HashSet::new())),
+            connecting: Arc::new(Mutex::new(HashSet::new())),
+            remote_subscriptions: Arc::new(Mutex::new(HashMap::new())),
+            compression: Arc::new(AtomicBool::new(true)),
+            peer_filter: Arc::new(RwLock::new(PeerFilter::new())),
+            auth: Arc::new(RwLock::new(None)),
+            http_proxy: Arc::new(RwLock::new(None)),
+        }
+    }
+    
+    /// Get the address the transport listens on when started
+    pub fn bind_address(&self) -> SocketAddr {
+        self.bind_address
+    }
+    
+    /// Get the addresses the running transport listens on, with any port 0 replaced
+    /// by the port picked for it (empty until started)
+    pub fn listen_addresses(&self) -> Vec<SocketAddr> {
+        self.listen_addresses.read().unwrap().clone()
+    }
+    
+    /// Set the number of worker threads serving connections (64 by default)
+    ///
+    /// Takes effect the next time the transport is started. Once every worker is
+    /// busy and as many connections are waiting, new connections are closed.
+    pub fn set_worker_threads(&self, threads: usize) {
+        self.worker_threads.store(threads.max(1), Ordering::Relaxed);
+    }
+    
+    /// Get the number of worker threads serving connections
+    pub fn worker_threads(&self) -> usize {
+        self.worker_threads.load(Ordering::Relaxed)
+    }
+    
+    /// Set how many connections the OS queues before they're accepted (1024 by default)
+    ///
+    /// Takes effect the next time the transport is started.
+    pub fn set_listen_backlog(&self, backlog: u32) {
+        self.listen_backlog.store(backlog.max(1), Ordering::Relaxed);
+    }
+    
+    /// Get how many connections the OS queues before they're accepted
+    pub fn listen_backlog(&self) -> u32 {
+        self.listen_backlog.load(Ordering::Relaxed)
+    }
+    
+    /// Set how long an accepted connection may sit idle before it is closed (5 minutes by default)
+    ///
+    /// Applies to connections accepted afterwards. A zero duration disables the timeout.
+    pub fn set_idle_timeout(&self, timeout: Duration) {
+        self.idle_timeout_ms.store(timeout.as_millis() as u64, Ordering::Relaxed);
+    }
+    
+    /// Get how long an accepted connection may sit idle before it is closed
+    pub fn idle_timeout(&self) -> Duration {
+        Duration::from_millis(self.idle_timeout_ms.load(Ordering::Relaxed))
+    }
+    
+    /// Set how long to wait for a peer to answer a request or heartbeat (30 seconds by default)
+    ///
+    /// Applies to peers connected afterwards. A peer that doesn't answer in time
+    /// fails the request with `HubError::Timeout` and its connection counts as
+    /// broken. A zero duration waits indefinitely.
+    pub fn set_request_timeout(&self, timeout: Duration) {
+        self.request_timeout_ms.store(timeout.as_millis() as u64, Ordering::Relaxed);
+    }
+    
+    /// Get how long to wait for a peer to answer a request or heartbeat
+    pub fn request_timeout(&self) -> Duration {
+        Duration::from_millis(self.request_timeout_ms.load(Ordering::Relaxed))
+    }
+    
+    /// Set the UDP port discovery announcements are sent and received on (8765 by default)
+    ///
+    /// Takes effect the next time the transport is started. Only hubs using the
+    /// same port discover each other, so separate deployments sharing a network
+    /// should use different ports.
+    pub fn set_discovery_port(&self, port: u16) {
+        self.discovery_port.store(port, Ordering::Relaxed);
+    }
+    
+    /// Get the UDP port discovery announcements are sent and received on
+    pub fn discovery_port(&self) -> u16 {
+        self.discovery_port.load(Ordering::Relaxed)
+    }
+    
+    /// Set the time between discovery announcements (30 seconds by default)
+    ///
+    /// Applies from the next announcement.
+    pub fn set_discovery_interval(&self, interval: Duration) {
+        self.discovery_interval_ms.store(interval.as_millis() as u64, Ordering::Relaxed);
+    }
+    
+    /// Get the time between discovery announcements
+    pub fn discovery_interval(&self) -> Duration {
+        Duration::from_millis(self.discovery_interval_ms.load(Ordering::Relaxed))
+    }
+    
+    /// Set how peers whose connection breaks are reconnected (`None`, the default, to leave them disconnected)
+    ///
+    /// Once a request or message to a peer finds its connection broken, the
+    /// transport reconnects to the peer's address in the background under the same
+    /// peer ID, so later requests go through again.
+    pub fn set_reconnect_policy(&self, policy: Option<ReconnectPolicy>) {
+        *self.reconnect_policy.write().unwrap() = policy;
+    }
+    
+    /// Get how peers whose connection breaks are reconnected
+    pub fn reconnect_policy(&self) -> Option<ReconnectPolicy> {
+        *self.reconnect_policy.read().unwrap()
+    }
+    
+    /// Enable or disable gzip compression of large frames exchanged with peers (enabled by default)
+    ///
+    /// Compression is agreed on when connecting, so it only applies to frames
+    /// sent to hubs that want it too, over connections made afterwards. Compressed
+    /// frames are dropped on connections that didn't agree to it, or if they
+    /// decompress to more than 1 MB.
+    pub fn set_compression(&self, enabled: bool) {
+        self.compression.store(enabled, Ordering::Relaxed);
+    }
+    
+    /// Whether large frames exchanged with peers are compressed
+    pub fn compression(&self) -> bool {
+        self.compression.load(Ordering::Relaxed)
+    }
+    
+    /// Set which peers the transport connects to (every peer by default)
+    ///
+    /// Discovered hubs the filter refuses are ignored, and `connect_to_peer` fails
+    /// with `HubError::PeerNotAllowed` for them. Applies to connections made
+    /// afterwards, including reconnections; peers already connected stay connected.
+    pub fn set_peer_filter(&self, filter: PeerFilter) {
+        *self.peer_filter.write().unwrap() = filter;
+    }
+    
+    /// Get which peers the transport connects to
+    pub fn peer_filter(&self) -> PeerFilter {
+        self.peer_filter.read().unwrap().clone()
+    }
+    
+    /// Set the shared secret hubs must know to connect to each other (`None`, the default, for no secret)
+    ///
+    /// The secret is sent when connecting to peers, which must be configured with
+    /// the same one. Connections from peers that don't send it are closed before
+    /// anything sent over them is handled, and connecting to a peer with another
+    /// secret fails with `HubError::Unauthorized`. HTTP clients of a proxy sharing
+    /// the port (see `set_http_proxy`) don't need it. Applies to connections made afterwards.
+    pub fn set_auth(&self, auth: Option<AuthConfig>) {
+        *self.auth.write().unwrap() = auth;
+    }
+    
+    /// Get the shared secret hubs must know to connect to each other
+    pub fn auth(&self) -> Option<AuthConfig> {
+        self.auth.read().unwrap().clone()
+    }
+    
+    /// Serve HTTP clients on the transport's port with a proxy (`None`, the default, for peers only)
+    ///
+    /// Clients pick the protocol with ALPN while connecting: `http/1.1` reaches the
+    /// proxy, while peers offer `HUB_ALPN_PROTOCOL`. Clients offering no protocol
+    /// are taken to be peers, and HTTP/2 isn't offered on the shared port. The
+    /// shared secret set with `set_auth` only applies to peers: the proxy serves
+    /// HTTP clients without it, like on its own listener, which is unaffected if
+    /// started. Applies to connections accepted afterwards.
+    pub fn set_http_proxy(&self, proxy: Option<HttpReverseProxy>) {
+        *self.http_proxy.write().unwrap() = proxy;
+    }
+    
+    /// Get the proxy serving HTTP clients on the transport's port
+    pub fn http_proxy(&self) -> Option<HttpReverseProxy> {
+        self.http_proxy.read().unwrap().clone()
+    }
+    
+    /// The hello this transport opens connections to peers with
+    fn hello(&self) -> PeerHello {
+        PeerHello {
+            hub_id: self.hub.id.clone(),
+            scope: self.hub.scope,
+            compression: self.compression(),
+            token: self.auth().map(|auth| auth.shared_secret),
         }
     }
     
-    /// Start the network transport
+    /// Start the network transport on its configured address
     pub fn start(&self) -> Result<()> {
+        self.start_multi(&[self.bind_address])
+    }
+    
+    /// Start the network transport listening on several addresses (e.g. IPv4 and IPv6)
+    ///
+    /// Every address is served by its own accept loop sharing the same hub and worker
+    /// pool. All addresses are bound before any connection is accepted, so a bind
+    /// failure is reported without starting anything. Blocks until the transport is
+    /// shut down.
+    pub fn start_multi(&self, addresses: &[SocketAddr]) -> Result<()> {
         // Start the network hub server
-        let listener = TcpListener::bind(self.bind_address)
-            .map_err(|e| HubError::Io(e))?;
-            
-        println!("Network hub listening on {}", self.bind_address);
+        let backlog = self.listen_backlog();
+        let listeners = addresses
+            .iter()
+            .map(|address| bind_listener(*address, backlog).map_err(HubError::Io))
+            .collect::<Result<Vec<_>>>()?;
+        
+        // Each run gets a signal of its own, so an earlier shutdown doesn't stop it
+        let shutdown = Arc::new(ShutdownSignal::new());
+        *self.shutdown.lock().unwrap() = Arc::clone(&shutdown);
+        *self.listen_addresses.write().unwrap() = listeners
+            .iter()
+            .filter_map(|listener| listener.local_addr().ok())
+            .collect();
         
         // Start discovery service
-        self.start_discovery();
+        let discovery = self.start_discovery(Arc::clone(&shutdown));
+        
+        // Connections are served by a bounded pool, with as many waiting as there are workers
+        let worker_threads = self.worker_threads();
+        let hub = Arc::clone(&self.hub);
+        let tls_config = self.tls_config.clone();
+        let idle_timeout_ms = Arc::clone(&self.idle_timeout_ms);
+        let metrics = Arc::clone(&self.metrics);
+        let compression = Arc::clone(&self.compression);
+        let auth = Arc::clone(&self.auth);
+        let http_proxy = Arc::clone(&self.http_proxy);
+        let pool = Arc::new(WorkerPool::new(worker_threads, worker_threads, move |stream: TcpStream| {
+            let settings = ConnectionSettings {
+                idle_timeout: Duration::from_millis(idle_timeout_ms.load(Ordering::Relaxed)),
+                compression: compression.load(Ordering::Relaxed),
+                auth: auth.read().unwrap().clone(),
+                http_proxy: http_proxy.read().unwrap().clone(),
+            };
+            if let Err(e) = Self::handle_connection(Arc::clone(&hub), stream, &tls_config, &settings, &metrics) {
+                error!(error = %e, "Error handling connection");
+            }
+        }));
         
         // Handle incoming connections
+        let accept_loops: Vec<_> = listeners
+            .into_iter()
+            .map(|listener| {
+                let pool = Arc::clone(&pool);
+                let shutdown = Arc::clone(&shutdown);
+                thread::spawn(move || Self::accept_connections(listener, &pool, &shutdown))
+            })
+            .collect();
+        
+        for accept_loop in accept_loops {
+            let _ = accept_loop.join();
+        }
+        let _ = discovery.join();
+        
+        info!("Network transport stopped");
+        Ok(())
+    }
+    
+    /// Stop the running transport
+    ///
+    /// Stops accepting connections and stops announcing and discovering hubs, so
+    /// `start` returns once its threads have exited. Connections already accepted
+    /// are served until they close. The transport can be started again afterwards.
+    pub fn shutdown(&self) {
+        info!("Shutting down network transport");
+        self.shutdown.lock().unwrap().trigger();
+        
+        // Accepting blocks, so wake each accept loop with a connection of its own
+        for address in self.listen_addresses.read().unwrap().iter() {
+            let mut address = *address;
+            if address.ip().is_unspecified() {
+                let loopback = match address {
+                    SocketAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+                    SocketAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+                };
+                address.set_ip(loopback);
+            }
+            if let Err(e) = TcpStream::connect_timeout(&address, Duration::from_secs(1)) {
+                warn!(address = %address, error = %e, "Failed to wake accept loop");
+            }
+        }
+    }
+    
+    /// Accept connections on a listener, handing each to the worker pool until shut down
+    fn accept_connections(listener: TcpListener, pool: &WorkerPool<TcpStream>, shutdown: &ShutdownSignal) {
+        if let Ok(address) = listener.local_addr() {
+            info!(address = %address, "Network hub listening");
+        }
+        
         for stream in listener.incoming() {
+            if shutdown.is_triggered() {
+                break;
+            }
+            
             match stream {
                 Ok(stream) => {
-                    let hub = Arc::clone(&self.hub);
-                    let tls_config = self.tls_config.clone();
-                    
-                    thread::spawn(move || {
-                        if let Err(e) = Self::handle_connection(hub, stream, &tls_config) {
-                            eprintln!("Error handling connection: {}", e);
-                        }
-                    });
+                    if let Err(stream) = pool.try_submit(stream) {
+                        // Closing the connection tells the peer to retry later
+                        warn!(peer = ?stream.peer_addr().ok(), "All workers busy, rejecting connection");
+                    }
                 }
                 Err(e) => {
-                    eprintln!("Connection error: {}", e);
+                    warn!(error = %e, "Connection error");
                 }
             }
         }
-        
-        Ok(())
     }
     
     /// Start discovery service
-    fn start_discovery(&self) {
-        println!("Starting network discovery service");
+    ///
+    /// Announces this hub on the discovery port every discovery interval, and
+    /// connects to hubs of the same or a wider scope announcing themselves there,
+    /// until `shutdown` is triggered. The returned thread exits once both have stopped.
+    fn start_discovery(&self, shutdown: Arc<ShutdownSignal>) -> thread::JoinHandle<()> {
+        info!("Starting network discovery service");
         
         let hub_id = self.hub.id.clone();
         let hub_scope = self.hub.scope;
         let bind_address = self.bind_address;
-        let peers = Arc::clone(&self.peers);
-        let tls_config = self.tls_config.clone();
+        // Announce the port actually listened on, in case port 0 was asked for
+        let announced_address = self.listen_addresses().first().copied().unwrap_or(bind_address);
+        let discovery_port = self.discovery_port();
+        let discovery_interval_ms = Arc::clone(&self.discovery_interval_ms);
         let self_transport = self.clone();
         
         // Broadcast discovery message to allow other hubs to find this one
         thread::spawn(move || {
-            let discovery_port = 8765; // Dedicated discovery port
-            let broadcast_addr = SocketAddr::new(
-                bind_address.ip().is_ipv4().then(|| "255.255.255.255".parse().unwrap())
-                    .unwrap_or_else(|| "ff02::1".parse().unwrap()),
-                discovery_port
-            );
-            
-            // Create a broadcast UDP socket
-            let socket = match std::net::UdpSocket::bind("0.0.0.0:0") {
-                Ok(s) => s,
+            // Create the socket announcements are broadcast (or, over IPv6, multicast) from
+            let (socket, broadcast_addr) = match Self::bind_announcer(bind_address, discovery_port) {
+                Ok(announcer) => announcer,
                 Err(e) => {
-                    eprintln!("Failed to create discovery broadcast socket: {}", e);
+                    error!(error = %e, "Failed to create discovery broadcast socket");
                     return;
                 }
             };
             
-            // Set socket to broadcast mode
-            if let Err(e) = socket.set_broadcast(true) {
-                eprintln!("Failed to set broadcast mode: {}", e);
-                return;
-            }
-            
-            // Listen for discovery responses on a separate socket
-            let listen_socket = match std::net::UdpSocket::bind(format!("0.0.0.0:{}", discovery_port)) {
-                Ok(s) => s,
+            // Listen for other hubs' announcements on a separate socket, shared with
+            // any other hub on this machine. Without it this hub can still be
+            // discovered, so keep broadcasting either way.
+            let listener = match Self::bind_discovery_listener(bind_address, discovery_port) {
+                Ok(listen_socket) => {
+                    let shutdown = Arc::clone(&shutdown);
+                    Some(thread::spawn(move || Self::listen_for_hubs(listen_socket, &self_transport, &shutdown)))
+                }
                 Err(e) => {
-                    eprintln!("Failed to create discovery listen socket: {}", e);
-                    return;
+                    warn!(port = discovery_port, error = %e, "Failed to bind discovery listen socket, other hubs won't be discovered");
+                    None
                 }
             };
             
-            // Set listen socket to non-blocking mode
-            if let Err(e) = listen_socket.set_nonblocking(true) {
-                eprintln!("Failed to set non-blocking mode: {}", e);
-            }
-            
-            // Start listener thread
-            let listen_peers = Arc::clone(&peers);
-            let _listen_tls_config = tls_config.clone();
-            let listen_self_transport = self_transport.clone();
-            
-            thread::spawn(move || {
-                let mut buf = [0u8; 1024];
-                
-                loop {
-                    match listen_socket.recv_from(&mut buf) {
-                        Ok((size, _sender)) => {
-                            // Process discovery message
-                            if size >= 3 && buf[0] == b'H' && buf[1] == b'U' && buf[2] == b'B' {
-                                // Valid discovery message, extract info
-                                if let Ok(msg) = std::str::from_utf8(&buf[3..size]) {
-                                    if let Some((peer_id, peer_addr_str, peer_scope_str)) = msg.split_once(',')
-                                        .and_then(|(id, rest)| rest.split_once(',')
-                                        .map(|(addr, scope)| (id, addr, scope))) {
-                                        
-                                        if let (Ok(peer_addr), Ok(peer_scope)) = (
-                                            peer_addr_str.parse::<SocketAddr>(),
-                                            match peer_scope_str {
-                                                "Thread" => Ok(HubScope::Thread),
-                                                "Process" => Ok(HubScope::Process),
-                                                "Machine" => Ok(HubScope::Machine),
-                                                "Network" => Ok(HubScope::Network),
-                                                _ => Err(())
-                                            }
-                                        ) {
-                                            println!("Discovered hub: {} at {} with scope {:?}", 
-                                                    peer_id, peer_addr, peer_scope);
-                                            
-                                            // Don't connect to hubs with lower scope
-                                            if peer_scope >= hub_scope {
-                                                // Check if we're already connected
-                                                let already_connected = {
-                                                    let peer_map = listen_peers.read().unwrap();
-                                                    peer_map.values().any(|p| p.id == peer_id)
-                                                };
-                                                
-                                                if !already_connected {
-                                                    // Connect to the discovered peer
-                                                    println!("Connecting to discovered hub: {}", peer_id);
-                                                    
-                                                    if let Err(e) = listen_self_transport.connect_to_peer(peer_addr) {
-                                                        eprintln!("Failed to connect to discovered hub: {}", e);
-                                                    }
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        },
-                        Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                            // No data available yet, just continue
-                            thread::sleep(Duration::from_millis(100));
-                        },
-                        Err(e) => {
-                            eprintln!("Error receiving discovery message: {}", e);
-                            thread::sleep(Duration::from_millis(100));
-                        }
-                    }
-                }
-            });
-            
             // Broadcast loop
             loop {
                 // Create discovery message with our hub ID, address and scope
-                let message = format!("HUB{},{},{:?}", hub_id, bind_address, hub_scope);
+                let message = format!("HUB{},{},{}", hub_id, announced_address, hub_scope);
                 
                 // Broadcast presence
-                println!("Broadcasting hub presence: {}", hub_id);
+                debug!(hub_id = %hub_id, "Broadcasting hub presence");
                 
                 if let Err(e) = socket.send_to(message.as_bytes(), broadcast_addr) {
-                    eprintln!("Failed to broadcast discovery message: {}", e);
+                    warn!(error = %e, "Failed to broadcast discovery message");
                 }
                 
-                // Sleep for discovery interval
-                thread::sleep(Duration::from_secs(30));
+                // Sleep for discovery interval, unless shut down in the meantime
+                if shutdown.wait_timeout(Duration::from_millis(discovery_interval_ms.load(Ordering::Relaxed))) {
+                    break;
+                }
             }
-        });
+            
+            if let Some(listener) = listener {
+                let _ = listener.join();
+            }
+            debug!(hub_id = %hub_id, "Discovery stopped");
+        })
+    }
+    
+    /// Create the socket discovery announcements are sent from, and the address they're sent to
+    ///
+    /// IPv4 hubs broadcast to every host on the local network. IPv6 has no broadcast,
+    /// so IPv6 hubs send to the discovery multicast group instead, on the interface of
+    /// their bind address's scope (or the default interface).
+    fn bind_announcer(bind_address: SocketAddr, port: u16) -> std::io::Result<(UdpSocket, SocketAddr)> {
+        match bind_address {
+            SocketAddr::V4(_) => {
+                let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+                socket.set_broadcast(true)?;
+                Ok((socket, SocketAddr::from((Ipv4Addr::BROADCAST, port))))
+            }
+            SocketAddr::V6(address) => {
+                let socket = Socket::new(Domain::IPV6, Type::DGRAM, None)?;
+                socket.set_multicast_if_v6(address.scope_id())?;
+                // Hubs on this machine listen for announcements too
+                socket.set_multicast_loop_v6(true)?;
+                socket.bind(&SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)).into())?;
+                Ok((socket.into(), SocketAddr::from((DISCOVERY_MULTICAST_V6, port))))
+            }
+        }
+    }
+    
+    /// Bind the socket discovery announcements are received on
+    ///
+    /// IPv6 listeners join the discovery multicast group on the interface of the bind
+    /// address's scope (or the default interface).
+    fn bind_discovery_listener(bind_address: SocketAddr, port: u16) -> std::io::Result<UdpSocket> {
+        match bind_address {
+            SocketAddr::V4(_) => bind_shared_udp(SocketAddr::from((Ipv4Addr::UNSPECIFIED, port))),
+            SocketAddr::V6(address) => {
+                let socket = bind_shared_udp(SocketAddr::from((Ipv6Addr::UNSPECIFIED, port)))?;
+                socket.join_multicast_v6(&DISCOVERY_MULTICAST_V6, address.scope_id())?;
+                Ok(socket)
+            }
+        }
+    }
+    
+    /// Receive discovery announcements, connecting to every newly discovered hub
+    fn listen_for_hubs(listen_socket: UdpSocket, transport: &NetworkTransport, shutdown: &ShutdownSignal) {
+        let mut buf = [0u8; 1024];
+        
+        // Wake up regularly to check for shutdown
+        if let Err(e) = listen_socket.set_read_timeout(Some(SHUTDOWN_POLL_INTERVAL)) {
+            warn!(error = %e, "Failed to set discovery listen timeout");
+        }
+        
+        while !shutdown.is_triggered() {
+            match listen_socket.recv_from(&mut buf) {
+                Ok((size, _sender)) => {
+                    transport.handle_announcement(&buf[..size]);
+                },
+                Err(e) if is_timeout(&e) => {}
+                Err(e) => {
+                    warn!(error = %e, "Error receiving discovery message");
+                    thread::sleep(Duration::from_millis(100));
+                }
+            }
+        }
+    }
+    
+    /// Handle a discovery announcement, connecting to the hub it announces if it's new
+    ///
+    /// Announcements are handled this way when received on the discovery port,
+    /// and may be passed in from elsewhere too. Hubs of a narrower scope, peers the
+    /// filter refuses and hubs already connected to (or being connected to, by
+    /// the same address) are ignored. Returns the ID of the peer connected to, if any.
+    pub fn handle_announcement(&self, announcement: &[u8]) -> Option<String> {
+        // Announcements are "HUB" followed by the hub's ID, address and scope
+        let message = std::str::from_utf8(announcement.strip_prefix(b"HUB")?).ok()?;
+        let (peer_id, rest) = message.split_once(',')?;
+        let (peer_addr, peer_scope) = rest.split_once(',')?;
+        let (Ok(peer_addr), Ok(peer_scope)) = (peer_addr.parse::<SocketAddr>(), peer_scope.parse::<HubScope>()) else {
+            return None;
+        };
+        
+        // Our own announcements are received too
+        if peer_id == self.hub.id {
+            return None;
+        }
+        
+        debug!(peer_id, peer_addr = %peer_addr, peer_scope = ?peer_scope, "Discovered hub");
+        
+        if !self.peer_filter.read().unwrap().allows(peer_addr, Some(peer_id)) {
+            debug!(peer_id, peer_addr = %peer_addr, "Ignoring discovered hub, peer not allowed");
+            return None;
+        }
+        
+        // Don't connect to hubs with lower scope
+        if peer_scope < self.hub.scope {
+            return None;
+        }
+        
+        // Check if we're already connected to the hub or its address, or connecting
+        // to it on behalf of another announcement, and claim the address if not
+        {
+            let mut connecting = self.connecting.lock().unwrap();
+            let already_connected = {
+                let peer_map = self.peers.read().unwrap();
+                peer_map.contains_key(peer_id) || peer_map.values().any(|p| p.address() == peer_addr)
+            };
+            if already_connected || !connecting.insert(peer_addr) {
+                return None;
+            }
+        }
+        
+        // Connect to the discovered peer
+        info!(peer_id, "Connecting to discovered hub");
+        let connected = self.connect_to_peer(peer_addr);
+        self.connecting.lock().unwrap().remove(&peer_addr);
+        match connected {
+            Ok(peer_id) => Some(peer_id),
+            Err(e) => {
+                warn!(peer_id, error = %e, "Failed to connect to discovered hub");
+                None
+            }
+        }
     }
     
     /// Handle an incoming connection
-    fn handle_connection(hub: Arc<Hub>, stream: TcpStream, tls_config: &TlsConfig) -> Result<()> {
-        // Set up TLS
-        let mut tls_stream = create_server_tls_stream(stream, tls_config)
-            .map_err(|e| HubError::Tls(e.to_string()))?;
+    ///
+    /// Responses are compressed if the peer asks for it in its hello and
+    /// compression is enabled. With an HTTP proxy, clients negotiating HTTP with
+    /// ALPN are handed to the proxy. Otherwise, with a shared secret, the
+    /// connection is closed unless it opens with a hello carrying it.
+    fn handle_connection(
+        hub: Arc<Hub>,
+        stream: TcpStream,
+        tls_config: &TlsConfig,
+        settings: &ConnectionSettings,
+        metrics: &TransportMetrics,
+    ) -> Result<()> {
+        let ConnectionSettings { idle_timeout, compression, ref auth, ref http_proxy } = *settings;
+        let auth = auth.as_ref();
+        // Correlate all events for this connection
+        let client_addr = stream.peer_addr().map_err(HubError::Io)?;
+        let span = info_span!("hub_connection", client = %client_addr, path = tracing::field::Empty);
+        let _enter = span.enter();
+        
+        // Don't let a silent peer hold a worker forever, and answer without delay
+        set_idle_timeout(&stream, idle_timeout).map_err(HubError::Io)?;
+        stream.set_nodelay(true).map_err(HubError::Io)?;
+        
+        // Set up TLS, letting clients pick HTTP if there's a proxy to serve it
+        let mut tls_stream = match http_proxy {
+            Some(proxy) => {
+                let (mut tls_stream, protocol) = accept_tls_stream(stream, tls_config, &[HUB_ALPN_PROTOCOL, HTTP_ALPN_PROTOCOL])?;
+                if protocol.as_deref() == Some(HTTP_ALPN_PROTOCOL) {
+                    debug!("Client negotiated HTTP, handing connection to the proxy");
+                    return proxy.serve_tls_stream(&mut tls_stream, client_addr);
+                }
+                tls_stream
+            }
+            None => create_server_tls_stream(stream, tls_config)
+                .map_err(|e| HubError::Tls(e.to_string()))?,
+        };
             
         // Read message type and content
-        let mut buffer = [0u8; 8192];
+        let mut compress = false;
+        let mut authenticated = auth.is_none();
         loop {
-            match tls_stream.read(&mut buffer) {
-                Ok(0) => {
+            match read_frame(&mut tls_stream) {
+                Ok(None) => {
                     // Connection closed
                     break;
                 }
-                Ok(size) => {
+                Ok(Some(frame)) => {
+                    metrics.record_read(frame.len());
+                    
                     // Process message
-                    let message_data = &buffer[..size];
-                    let message_type = message_data.get(0).copied().unwrap_or(0);
+                    let Some((message_type, message_data)) = decode_frame(&frame, compress) else {
+                        error!("Failed to decompress message");
+                        metrics.record_deserialize_failure();
+                        continue;
+                    };
+                    
+                    if !authenticated && message_type != 6 {
+                        warn!(message_type, "Closing connection, peer did not send the shared secret");
+                        metrics.record_auth_failure();
+                        break;
+                    }
                     
                     match message_type {
                         // API request
                         1 => {
-                            if let Some(request) = deserialize::<ApiRequest>(&message_data[1..]) {
+                            if let Some(request) = deserialize::<ApiRequest>(&message_data) {
+                                span.record("path", request.path.as_str());
                                 let response = hub.handle_request(request);
-                                let response_data = serialize(&response);
-                                tls_stream.write(&[2])?; // Response message type
-                                tls_stream.write(&response_data)?;
+                                debug!(status = ?response.status, "Handled request");
+                                // Response message type and data in a single write
+                                let frame = encode_frame(2, &serialize(&response), compress);
+                                tls_stream.write_all(&frame)?;
+                                metrics.record_written(frame.len());
+                                metrics.record_request();
+                            } else {
+                                error!("Failed to decode request");
+                                metrics.record_deserialize_failure();
+                                
+                                // Answer anyway, so the client doesn't wait for a response that never comes
+                                let response = ApiResponse {
+                                    data: Box::new("Malformed request".to_string()),
+                                    metadata: HashMap::new(),
+                                    status: ResponseStatus::BadRequest,
+                                };
+                                let frame = encode_frame(2, &serialize(&response), compress);
+                                tls_stream.write_all(&frame)?;
+                                metrics.record_written(frame.len());
                             }
                         }
-                        // Published message
-                        3 => {
-                            // In a real implementation, would handle published messages
+                        // Published message, delivered to the hub's subscribers unless it has expired
+                        3 => match deserialize_published(&message_data) {
+                            Some(PublishedMessage::Text(message)) => {
+                                span.record("path", message.topic.as_str());
+                                let _ = hub.publish_message::<String, ()>(message);
+                            }
+                            Some(PublishedMessage::Binary(message)) => {
+                                span.record("path", message.topic.as_str());
+                                let _ = hub.publish_message::<Vec<u8>, ()>(message);
+                            }
+                            None => {
+                                error!("Failed to decode message");
+                                metrics.record_deserialize_failure();
+                            }
+                        },
+                        // Subscription, which takes the connection over to forward messages
+                        4 => {
+                            let Some(subscription) = deserialize::<SubscriptionRequest>(&message_data) else {
+                                error!("Failed to decode subscription");
+                                metrics.record_deserialize_failure();
+                                // The subscriber waits for confirmation, so closing tells it to give up
+                                break;
+                            };
+                            span.record("path", subscription.pattern.as_str());
+                            return Self::forward_subscription(&hub, tls_stream, &subscription.pattern, metrics);
+                        }
+                        // Hello, answered with this hub's ID and scope, and whether responses will be compressed
+                        6 => {
+                            let Some(hello) = deserialize::<PeerHello>(&message_data) else {
+                                error!("Failed to decode hello");
+                                metrics.record_deserialize_failure();
+                                // The peer waits for an answer, so closing tells it to give up
+                                break;
+                            };
+                            if auth.is_some_and(|auth| !auth.accepts(hello.token.as_deref())) {
+                                warn!(peer_hub_id = %hello.hub_id, "Refusing peer, wrong shared secret");
+                                metrics.record_auth_failure();
+                                // Hello refused
+                                let frame = encode_frame(8, &[], false);
+                                tls_stream.write_all(&frame)?;
+                                metrics.record_written(frame.len());
+                                break;
+                            }
+                            authenticated = true;
+                            compress = compression && hello.compression;
+                            debug!(peer_hub_id = %hello.hub_id, peer_scope = %hello.scope, compress, "Peer connected");
+                            let hello_data = serialize(&PeerHello { hub_id: hub.id.clone(), scope: hub.scope, compression: compress, token: None });
+                            let frame = encode_frame(7, &hello_data, false);
+                            tls_stream.write_all(&frame)?;
+                            metrics.record_written(frame.len());
                         }
                         // Heartbeat
                         10 => {
-                            tls_stream.write(&[11])?; // Heartbeat response
+                            let frame = encode_frame(11, &[], false); // Heartbeat response
+                            tls_stream.write_all(&frame)?;
+                            metrics.record_written(frame.len());
                         }
                         _ => {
-                            eprintln!("Unknown message type: {}", message_type);
+                            warn!(message_type, "Unknown message type");
                         }
                     }
                 }
+                Err(e) if is_timeout(&e) => {
+                    debug!(timeout_ms = idle_timeout.as_millis() as u64, "Closing idle connection");
+                    break;
+                }
                 Err(e) => {
                     return Err(HubError::Io(e));
                 }
@@ -263,43 +802,182 @@ impl NetworkTransport {
         Ok(())
     }
     
+    /// Forward the messages published to the hub that match a pattern over a
+    /// connection, until the subscriber disconnects
+    ///
+    /// Frames are built like on any connection, without compression: first a
+    /// confirmation (type 5), then every matching message with string or byte data
+    /// (type 3), and a heartbeat (type 10) whenever there was nothing to forward
+    /// for a while.
+    fn forward_subscription(hub: &Hub, mut stream: TlsStream, pattern: &str, metrics: &TransportMetrics) -> Result<()> {
+        let (tx, rx) = mpsc::channel();
+        let tx = Mutex::new(tx);
+        let subscription_id = hub.subscribe(pattern, move |message| {
+            match serialize_published(message) {
+                Some(payload) => {
+                    let _ = tx.lock().unwrap().send(payload);
+                }
+                None => debug!(topic = %message.topic, "Not forwarding message without string or byte data"),
+            }
+            None
+        }, 0);
+        info!(pattern, "Forwarding messages to remote subscriber");
+        
+        let mut send = |message_type: u8, payload: &[u8]| -> std::io::Result<()> {
+            let frame = encode_frame(message_type, payload, false);
+            stream.write_all(&frame)?;
+            metrics.record_written(frame.len());
+            Ok(())
+        };
+        let mut result = send(5, &[]);
+        while result.is_ok() {
+            result = match rx.recv_timeout(SUBSCRIPTION_KEEPALIVE_INTERVAL) {
+                Ok(payload) => send(3, &payload),
+                Err(mpsc::RecvTimeoutError::Timeout) => send(10, &[]),
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            };
+        }
+        
+        hub.unsubscribe(&subscription_id);
+        if let Err(e) = result {
+            debug!(pattern, error = %e, "Remote subscriber disconnected");
+        }
+        Ok(())
+    }
+    
     /// Connect to a peer
+    ///
+    /// The hubs exchange their IDs and scopes first, and the peer is known by the
+    /// ID of its hub, which is returned. Connecting to a hub already connected to
+    /// replaces its connection.
     pub fn connect_to_peer(&self, address: SocketAddr) -> Result<String> {
+        let filter = self.peer_filter();
+        if !filter.allows(address, None) {
+            warn!(address = %address, "Refusing to connect to peer, address not allowed");
+            return Err(HubError::PeerNotAllowed(address.to_string()));
+        }
+        
         // Connect to remote hub
-        println!("Connecting to peer at {}", address);
+        info!(address = %address, "Connecting to peer");
+        let tls_stream = self.open_peer_stream(address)?;
         
-        // Establish TCP connection
-        let stream = TcpStream::connect(address)
-            .map_err(|e| HubError::Io(e))?;
-            
-        // Set up TLS
-        let tls_stream = create_client_tls_stream(stream, &self.tls_config)
-            .map_err(|e| HubError::Tls(e.to_string()))?;
-            
-        // Create peer ID
-        let peer_id = format!("peer-{}", address);
+        // Identify the peer by its hub's ID, telling it ours and whether to compress
+        let peer = NetworkPeer::handshake(address, tls_stream, &self.hello())?;
+        let peer_id = peer.id.clone();
         
-        // Create network peer
-        let peer = NetworkPeer::new(peer_id.clone(), address, tls_stream);
+        // Rules naming hub IDs can only be checked now the peer has told its ID
+        if !filter.allows(address, Some(&peer_id)) {
+            warn!(address = %address, peer_id = %peer_id, "Refusing to connect to peer, hub not allowed");
+            return Err(HubError::PeerNotAllowed(format!("{} at {}", peer_id, address)));
+        }
+        info!(peer_id = %peer_id, peer_scope = ?peer.scope(), "Connected to peer");
         
         // Store peer connection
         self.peers.write().unwrap().insert(peer_id.clone(), peer);
         
-        // In a real implementation, would exchange hub information
-        
         Ok(peer_id)
     }
     
+    /// Open a TLS connection to a peer's address
+    fn open_peer_stream(&self, address: SocketAddr) -> Result<TlsStream> {
+        // Establish TCP connection, not waiting for answers longer than the request timeout
+        let stream = connect_tcp(address)
+            .map_err(HubError::Io)?;
+        set_idle_timeout(&stream, self.request_timeout()).map_err(HubError::Io)?;
+            
+        // Set up TLS, telling a transport sharing its port with HTTP that this is a peer
+        create_client_tls_stream_with_alpn(stream, &self.tls_config, &[HUB_ALPN_PROTOCOL])
+            .map_err(|e| HubError::Tls(e.to_string()))
+    }
+    
+    /// Reconnect to a peer whose connection has broken, if there's a reconnect policy
+    ///
+    /// Attempts are made on a thread of their own, which replaces the peer once
+    /// connected. A peer is only reconnected by one thread at a time.
+    fn reconnect_peer(&self, peer: &NetworkPeer) {
+        let Some(policy) = self.reconnect_policy() else {
+            return;
+        };
+        if !self.reconnecting.lock().unwrap().insert(peer.id.clone()) {
+            return;
+        }
+        
+        let transport = self.clone();
+        let peer = peer.clone();
+        thread::spawn(move || {
+            warn!(peer_id = %peer.id, address = %peer.address(), "Peer connection lost, reconnecting");
+            
+            let mut reconnected = false;
+            for attempt in 0..policy.max_retries {
+                if !transport.peer_filter().allows(peer.address(), Some(&peer.id)) {
+                    warn!(peer_id = %peer.id, "Not reconnecting to peer, no longer allowed");
+                    break;
+                }
+                thread::sleep(policy.delay(attempt));
+                // Say hello again, so the peer accepts the new connection
+                let connection = transport.open_peer_stream(peer.address()).and_then(|stream| {
+                    let connection = peer.reconnected(stream);
+                    connection.exchange_hello(&transport.hello())?;
+                    Ok(connection)
+                });
+                match connection {
+                    Ok(connection) => {
+                        transport.peers.write().unwrap().insert(peer.id.clone(), connection);
+                        info!(peer_id = %peer.id, attempt, "Reconnected to peer");
+                        reconnected = true;
+                        break;
+                    }
+                    Err(e) => debug!(peer_id = %peer.id, attempt, error = %e, "Failed to reconnect to peer"),
+                }
+            }
+            if !reconnected {
+                warn!(peer_id = %peer.id, attempts = policy.max_retries, "Giving up reconnecting to peer");
+            }
+            
+            transport.reconnecting.lock().unwrap().remove(&peer.id);
+        });
+    }
+    
+    /// Get a connected peer
+    fn peer(&self, peer_id: &str) -> Result<NetworkPeer> {
+        self.peers.read().unwrap()
+            .get(peer_id)
+            .cloned()
+            .ok_or_else(|| HubError::Network(format!("Peer not found: {}", peer_id)))
+    }
+    
+    /// Get a snapshot of every connected peer's liveness
+    pub fn peer_status(&self) -> Vec<PeerStatus> {
+        self.peers.read().unwrap().values().map(NetworkPeer::status).collect()
+    }
+    
+    /// Get a snapshot of the transport's traffic counters
+    ///
+    /// Covers the connections accepted from other hubs, along with the traffic
+    /// with each currently connected peer.
+    pub fn transport_stats(&self) -> TransportStats {
+        let peers = self.peers.read().unwrap()
+            .iter()
+            .map(|(id, peer)| (id.clone(), peer.traffic()))
+            .collect();
+        self.metrics.snapshot(peers)
+    }
+    
     /// Send a request to a peer
-    pub fn send_request_to_peer(&self, peer_id: &str, request: ApiRequest) -> Result<ApiResponse> {
-        let peers = self.peers.read().unwrap();
-        
-        if let Some(peer) = peers.get(peer_id) {
-            let response = peer.send_request(request)?;
-            Ok(response)
-        } else {
-            Err(HubError::Network(format!("Peer not found: {}", peer_id)))
+    ///
+    /// Only string data and typed payloads reach the peer; send a [`SerdeRequest`]
+    /// for any other serde type. Other data arrives as an empty string. Requests and
+    /// responses are limited to 1 MB each; the peer closes the connection over a
+    /// larger one. Fails with `HubError::Timeout` if the peer doesn't answer within
+    /// the request timeout.
+    pub fn send_request_to_peer(&self, peer_id: &str, request: impl Into<ApiRequest>) -> Result<ApiResponse> {
+        let peer = self.peer(peer_id)?;
+        
+        let result = peer.send_request(request.into());
+        if result.is_err() && peer.is_closed() {
+            self.reconnect_peer(&peer);
         }
+        result
     }
     
     /// Publish a message to a peer
@@ -310,31 +988,145 @@ impl NetworkTransport {
         data: T,
         metadata: HashMap<String, String>,
     ) -> Result<()> {
-        let peers = self.peers.read().unwrap();
-        
-        if let Some(peer) = peers.get(peer_id) {
-            let message = Message {
-                topic: topic.to_string(),
-                data,
-                metadata,
-                sender_id: self.hub.id.clone(),
-                timestamp: current_time_millis(),
-            };
-            
-            peer.publish_message(message)?;
-            Ok(())
-        } else {
-            Err(HubError::Network(format!("Peer not found: {}", peer_id)))
+        let peer = self.peer(peer_id)?;
+        
+        let message = Message {
+            topic: topic.to_string(),
+            data,
+            metadata,
+            sender_id: self.hub.id.clone(),
+            timestamp: current_time_millis(),
+        };
+        
+        let result = peer.publish_message(message);
+        if result.is_err() && peer.is_closed() {
+            self.reconnect_peer(&peer);
         }
+        result
+    }
+    
+    /// Subscribe to the messages published to a peer's hub that match a pattern
+    ///
+    /// The peer forwards every matching message with string data as it is
+    /// published, and the callback is called with each on a thread of its own.
+    /// Requests to the peer only get answers over its connection, so the
+    /// subscription opens a second one, which isn't reconnected if it breaks.
+    /// Returns the subscription ID, which can be passed to `unsubscribe_remote`.
+    pub fn subscribe_remote<F>(&self, peer_id: &str, pattern: &str, callback: F) -> Result<String>
+    where
+        F: Fn(&Message<String>) + Send + 'static,
+    {
+        self.open_remote_subscription(peer_id, pattern, move |message| {
+            if let PublishedMessage::Text(message) = message {
+                callback(&message);
+            }
+        })
+    }
+    
+    /// Subscribe to the messages with byte data published to a peer's hub that match a pattern
+    ///
+    /// Like `subscribe_remote`, for messages published with `Vec<u8>` data, which
+    /// arrive with identical bytes.
+    pub fn subscribe_remote_binary<F>(&self, peer_id: &str, pattern: &str, callback: F) -> Result<String>
+    where
+        F: Fn(&Message<Vec<u8>>) + Send + 'static,
+    {
+        self.open_remote_subscription(peer_id, pattern, move |message| {
+            if let PublishedMessage::Binary(message) = message {
+                callback(&message);
+            }
+        })
+    }
+    
+    /// Open a connection to a peer forwarding the messages that match a pattern,
+    /// and deliver them on a thread of its own, for `subscribe_remote` and
+    /// `subscribe_remote_binary`
+    fn open_remote_subscription<F>(&self, peer_id: &str, pattern: &str, deliver: F) -> Result<String>
+    where
+        F: Fn(PublishedMessage) + Send + 'static,
+    {
+        let address = self.peer(peer_id)?.address();
+        let stream = connect_tcp(address).map_err(HubError::Io)?;
+        let connection = stream.try_clone().map_err(HubError::Io)?;
+        let mut tls_stream = create_client_tls_stream_with_alpn(stream, &self.tls_config, &[HUB_ALPN_PROTOCOL])
+            .map_err(|e| HubError::Tls(e.to_string()))?;
+        
+        // Introduce this hub first, so a peer with a shared secret accepts the connection;
+        // forwarded messages are never compressed
+        let hello = PeerHello { compression: false, ..self.hello() };
+        tls_stream.write_all(&encode_frame(6, &serialize(&hello), false))?;
+        let reply = read_frame(&mut tls_stream)?;
+        match reply.as_deref().and_then(|reply| decode_frame(reply, false)) {
+            Some((7, _)) => {}
+            Some((8, _)) => return Err(HubError::Unauthorized(format!("Peer {} refused the shared secret", peer_id))),
+            _ => return Err(HubError::Network(format!("Peer {} closed the connection", peer_id))),
+        }
+        
+        let subscription = SubscriptionRequest { pattern: pattern.to_string() };
+        tls_stream.write_all(&encode_frame(4, &serialize(&subscription), false))?;
+        
+        // Wait for the peer to confirm, so messages published from now on are forwarded
+        let confirmation = read_frame(&mut tls_stream)?;
+        if !matches!(confirmation.as_deref().and_then(|frame| decode_frame(frame, false)), Some((5, _))) {
+            return Err(HubError::Network(format!("Peer {} refused subscription to {}", peer_id, pattern)));
+        }
+        
+        let id = generate_uuid();
+        self.remote_subscriptions.lock().unwrap().insert(id.clone(), connection);
+        
+        let remote_subscriptions = Arc::clone(&self.remote_subscriptions);
+        let subscription_id = id.clone();
+        let peer_id = peer_id.to_string();
+        let pattern = pattern.to_string();
+        thread::spawn(move || {
+            loop {
+                let frame = match read_frame(&mut tls_stream) {
+                    Ok(Some(frame)) => frame,
+                    Ok(None) => break,
+                    Err(e) => {
+                        debug!(peer_id = %peer_id, error = %e, "Remote subscription closed");
+                        break;
+                    }
+                };
+                
+                // Heartbeats (type 10) only keep the connection checked
+                let Some((3, payload)) = decode_frame(&frame, false) else {
+                    continue;
+                };
+                match deserialize_published(&payload) {
+                    Some(message) => deliver(message),
+                    None => warn!(peer_id = %peer_id, "Failed to decode forwarded message"),
+                }
+            }
+            
+            if remote_subscriptions.lock().unwrap().remove(&subscription_id).is_some() {
+                warn!(peer_id = %peer_id, pattern = %pattern, "Peer closed remote subscription");
+            }
+        });
+        
+        Ok(id)
+    }
+    
+    /// Cancel a subscription made with `subscribe_remote`, closing its connection
+    ///
+    /// Returns whether the subscription was still open.
+    pub fn unsubscribe_remote(&self, id: &str) -> bool {
+        let Some(connection) = self.remote_subscriptions.lock().unwrap().remove(id) else {
+            return false;
+        };
+        let _ = connection.shutdown(std::net::Shutdown::Both);
+        true
     }
     
     /// Send a request to a peer with a timeout
     pub fn send_request_to_peer_with_timeout(
         &self,
         peer_id: &str,
-        request: ApiRequest,
+        request: impl Into<ApiRequest>,
         timeout: Duration,
     ) -> Result<ApiResponse> {
+        let request = request.into();
+        
         // Create communication channels
         let (tx, rx) = std::sync::mpsc::channel();
         
@@ -358,7 +1150,58 @@ impl NetworkTransport {
         // Wait for response or timeout
         match rx.recv_timeout(timeout) {
             Ok(result) => result,
-            Err(_) => Err(HubError::Network(format!("Request to peer {} timed out after {:?}", peer_id_for_error, timeout))),
+            Err(_) => Err(HubError::Timeout(format!("Request to peer {} timed out after {:?}", peer_id_for_error, timeout))),
+        }
+    }
+    
+    /// Send a request to all connected peers and return the first successful response
+    ///
+    /// The request is sent to every peer concurrently. The first response that is
+    /// neither `NotFound` nor `Error` is returned and the remaining replies are
+    /// discarded. If every peer answers without success, the last answer (or
+    /// error) is returned; if none succeeds before the deadline, the request fails.
+    pub fn request_any(&self, request: ApiRequest, timeout: Duration) -> Result<ApiResponse> {
+        let deadline = Instant::now() + timeout;
+        let peer_ids: Vec<String> = self.peers.read().unwrap().keys().cloned().collect();
+        
+        if peer_ids.is_empty() {
+            return Err(HubError::Network("No connected peers".to_string()));
+        }
+        
+        // Request data can't be cloned, so each peer gets a copy decoded from the wire format
+        let request_data = serialize(&request);
+        let (tx, rx) = mpsc::channel();
+        
+        for peer_id in peer_ids {
+            let peer_request = deserialize::<ApiRequest>(&request_data)
+                .ok_or_else(|| HubError::Network("Failed to serialize request".to_string()))?;
+            let transport = self.clone();
+            let tx = tx.clone();
+            
+            thread::spawn(move || {
+                // The receiver is gone once a response has been chosen
+                let _ = tx.send(transport.send_request_to_peer(&peer_id, peer_request));
+            });
         }
+        drop(tx);
+        
+        let mut last_result = None;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            
+            match rx.recv_timeout(remaining) {
+                Ok(Ok(response)) if !matches!(response.status, ResponseStatus::NotFound | ResponseStatus::Error) => {
+                    return Ok(response);
+                }
+                Ok(result) => last_result = Some(result),
+                // Every peer has answered without success
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    return Err(HubError::Network(format!("No peer answered {} within {:?}", request.path, timeout)));
+                }
+            }
+        }
+        
+        last_result.unwrap_or_else(|| Err(HubError::Network("No connected peers".to_string())))
     }
 }
\ No newline at end of file
@@ -1,22 +1,58 @@
 use std::net::SocketAddr;
 use std::sync::{Mutex, Arc};
-use std::io::{Read, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::io::Write;
+use std::time::Duration;
+
+use serde::Serialize;
 
 use crate::error::{HubError, Result};
-use crate::hub::{ApiRequest, ApiResponse, Message};
+use crate::hub::{ApiRequest, ApiResponse, HubScope, Message};
 use crate::transport::TlsStream;
-use crate::transport::message_codec::{serialize, deserialize};
+use crate::transport::message_codec::{decode_frame, encode_frame, read_frame, serialize, deserialize, PeerHello};
+use crate::transport::metrics::{PeerMetrics, PeerTraffic};
+use crate::utils::{current_time_millis, is_timeout};
 
 /// A connected network peer
 pub struct NetworkPeer {
     /// Peer ID
     pub id: String,
     /// Peer address
-    pub address: SocketAddr,
+    address: SocketAddr,
     /// TLS stream for communication
     stream: Arc<Mutex<TlsStream>>,
-    /// Last seen timestamp
+    /// Time the peer last answered, in milliseconds since the Unix epoch
+    last_seen: Arc<AtomicU64>,
+    /// Traffic counters
+    metrics: Arc<PeerMetrics>,
+    /// Whether the connection has broken
+    closed: Arc<AtomicBool>,
+    /// Scope of the peer's hub, if exchanged when connecting
+    scope: Option<HubScope>,
+    /// Whether large frames sent to the peer are compressed, as agreed when connecting
+    compression: bool,
+}
+
+/// Snapshot of a connected peer's liveness
+#[derive(Debug, Clone, Serialize)]
+pub struct PeerStatus {
+    /// Peer ID
+    pub id: String,
+    /// Peer address
+    pub address: SocketAddr,
+    /// Scope of the peer's hub, if exchanged when connecting
+    pub scope: Option<HubScope>,
+    /// Time the peer last answered, in milliseconds since the Unix epoch
     pub last_seen: u64,
+    /// Time since the peer last answered, in milliseconds
+    pub idle_ms: u64,
+}
+
+impl PeerStatus {
+    /// Whether the peer had answered within the given time when the snapshot was taken
+    pub fn is_alive(&self, max_idle: Duration) -> bool {
+        u128::from(self.idle_ms) <= max_idle.as_millis()
+    }
 }
 
 impl Clone for NetworkPeer {
@@ -25,7 +61,11 @@ impl Clone for NetworkPeer {
             id: self.id.clone(),
             address: self.address,
             stream: Arc::clone(&self.stream),
-            last_seen: self.last_seen,
+            last_seen: Arc::clone(&self.last_seen),
+            metrics: Arc::clone(&self.metrics),
+            closed: Arc::clone(&self.closed),
+            scope: self.scope,
+            compression: self.compression,
         }
     }
 }
@@ -37,8 +77,155 @@ impl NetworkPeer {
             id,
             address,
             stream: Arc::new(Mutex::new(stream)),
-            last_seen: 0,
+            // Connecting counts as hearing from the peer
+            last_seen: Arc::new(AtomicU64::new(current_time_millis())),
+            metrics: Arc::new(PeerMetrics::default()),
+            closed: Arc::new(AtomicBool::new(false)),
+            scope: None,
+            compression: false,
+        }
+    }
+    
+    /// Create a peer over a fresh connection, exchanging hub information with it
+    ///
+    /// The peer is identified by the ID of its hub, and knows its scope. Large
+    /// frames are compressed if both hubs want it.
+    pub(crate) fn handshake(address: SocketAddr, stream: TlsStream, hello: &PeerHello) -> Result<Self> {
+        let mut peer = NetworkPeer::new(String::new(), address, stream);
+        let remote = peer.exchange_hello(hello)?;
+        peer.id = remote.hub_id;
+        peer.scope = Some(remote.scope);
+        peer.compression = hello.compression && remote.compression;
+        Ok(peer)
+    }
+    
+    /// Create the peer anew over a fresh connection, keeping its ID and traffic counters
+    pub fn reconnected(&self, stream: TlsStream) -> Self {
+        NetworkPeer {
+            metrics: Arc::clone(&self.metrics),
+            scope: self.scope,
+            compression: self.compression,
+            ..NetworkPeer::new(self.id.clone(), self.address, stream)
+        }
+    }
+    
+    /// Get the peer's address
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+    
+    /// Get the scope of the peer's hub, if it was exchanged when connecting
+    pub fn scope(&self) -> Option<HubScope> {
+        self.scope
+    }
+    
+    /// Whether large frames sent to the peer are compressed
+    pub fn compression(&self) -> bool {
+        self.compression
+    }
+    
+    /// Get the time the peer last answered a request or heartbeat, in milliseconds since the Unix epoch
+    pub fn last_seen(&self) -> u64 {
+        self.last_seen.load(Ordering::Relaxed)
+    }
+    
+    /// Get the time since the peer last answered
+    pub fn idle_time(&self) -> Duration {
+        Duration::from_millis(current_time_millis().saturating_sub(self.last_seen()))
+    }
+    
+    /// Whether the peer has answered within the given time
+    pub fn is_alive(&self, max_idle: Duration) -> bool {
+        self.idle_time() <= max_idle
+    }
+    
+    /// Get a snapshot of the peer's liveness
+    pub fn status(&self) -> PeerStatus {
+        let last_seen = self.last_seen();
+        PeerStatus {
+            id: self.id.clone(),
+            address: self.address,
+            scope: self.scope,
+            last_seen,
+            idle_ms: current_time_millis().saturating_sub(last_seen),
+        }
+    }
+    
+    /// Get a snapshot of the traffic with the peer
+    pub fn traffic(&self) -> PeerTraffic {
+        self.metrics.snapshot()
+    }
+    
+    /// Whether the connection to the peer has broken
+    ///
+    /// Set once writing or reading fails, or the peer closes the connection.
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Relaxed)
+    }
+    
+    /// Record that the peer has just answered
+    fn mark_seen(&self) {
+        self.last_seen.store(current_time_millis(), Ordering::Relaxed);
+    }
+    
+    /// Record that the connection has broken, passing on the error
+    fn mark_closed(&self, error: impl Into<HubError>) -> HubError {
+        self.closed.store(true, Ordering::Relaxed);
+        error.into()
+    }
+    
+    /// Record that reading the peer's answer failed, turning a read timeout into `HubError::Timeout`
+    ///
+    /// A late answer would be taken for the answer to the next request, so the
+    /// connection counts as broken either way.
+    fn read_failed(&self, error: std::io::Error) -> HubError {
+        if is_timeout(&error) {
+            return self.mark_closed(HubError::Timeout(format!("Peer {} didn't answer in time", self.id)));
         }
+        self.mark_closed(error)
+    }
+    
+    /// Read the peer's next frame, recording its size
+    fn read_frame(&self, stream: &mut TlsStream) -> Result<Vec<u8>> {
+        let Some(frame) = read_frame(stream).map_err(|e| self.read_failed(e))? else {
+            return Err(self.mark_closed(HubError::Network("Connection closed".to_string())));
+        };
+        self.metrics.record_received(frame.len());
+        Ok(frame)
+    }
+    
+    /// Tell the peer about this hub, returning what it tells about its own
+    ///
+    /// Fails with `HubError::Unauthorized` if the peer refuses the hello's token.
+    pub(crate) fn exchange_hello(&self, hello: &PeerHello) -> Result<PeerHello> {
+        let hello_data = serialize(hello);
+        let mut stream = self.stream.lock().unwrap();
+        
+        // Send message type (6 = Hello) and data
+        let frame = encode_frame(6, &hello_data, false);
+        stream.write_all(&frame).map_err(|e| self.mark_closed(e))?;
+        self.metrics.record_sent(frame.len());
+        
+        let frame = self.read_frame(&mut stream)?;
+        let Some((message_type, hello_data)) = decode_frame(&frame, false) else {
+            self.metrics.record_deserialize_failure();
+            return Err(HubError::Network("Failed to decode hello".to_string()));
+        };
+        
+        // Check message type (7 = Hello response, 8 = Hello refused)
+        if message_type == 8 {
+            return Err(self.mark_closed(HubError::Unauthorized(format!("Peer at {} refused the shared secret", self.address))));
+        }
+        if message_type != 7 {
+            return Err(HubError::Network(format!("Unexpected message type: {}", message_type)));
+        }
+        
+        let Some(remote) = deserialize::<PeerHello>(&hello_data) else {
+            self.metrics.record_deserialize_failure();
+            return Err(HubError::Network("Failed to deserialize hello".to_string()));
+        };
+        self.mark_seen();
+        Ok(remote)
     }
     
     /// Send a request to the peer
@@ -50,26 +237,29 @@ impl NetworkPeer {
         let mut stream = self.stream.lock().unwrap();
         
         // Send message type (1 = API request) and data
-        stream.write(&[1])?;
-        stream.write(&request_data)?;
+        let frame = encode_frame(1, &request_data, self.compression);
+        stream.write_all(&frame).map_err(|e| self.mark_closed(e))?;
+        self.metrics.record_sent(frame.len());
         
         // Read response
-        let mut buffer = [0u8; 8192];
-        let size = stream.read(&mut buffer)?;
-        
-        if size == 0 {
-            return Err(HubError::Network("Connection closed".to_string()));
-        }
+        let frame = self.read_frame(&mut stream)?;
         
         // Check message type (2 = API response)
-        let message_type = buffer[0];
+        let Some((message_type, response_data)) = decode_frame(&frame, self.compression) else {
+            self.metrics.record_deserialize_failure();
+            return Err(HubError::Network("Failed to decompress response".to_string()));
+        };
         if message_type != 2 {
             return Err(HubError::Network(format!("Unexpected message type: {}", message_type)));
         }
         
         // Deserialize response
-        deserialize::<ApiResponse>(&buffer[1..size])
-            .ok_or_else(|| HubError::Network("Failed to deserialize response".to_string()))
+        let Some(response) = deserialize::<ApiResponse>(&response_data) else {
+            self.metrics.record_deserialize_failure();
+            return Err(HubError::Network("Failed to deserialize response".to_string()));
+        };
+        self.mark_seen();
+        Ok(response)
     }
     
     /// Publish a message to the peer
@@ -84,8 +274,9 @@ impl NetworkPeer {
         let mut stream = self.stream.lock().unwrap();
         
         // Send message type (3 = Published message) and data
-        stream.write(&[3])?;
-        stream.write(&message_data)?;
+        let frame = encode_frame(3, &message_data, self.compression);
+        stream.write_all(&frame).map_err(|e| self.mark_closed(e))?;
+        self.metrics.record_sent(frame.len());
         
         Ok(())
     }
@@ -95,17 +286,18 @@ impl NetworkPeer {
         let mut stream = self.stream.lock().unwrap();
         
         // Send heartbeat message type (10)
-        stream.write(&[10])?;
+        let frame = encode_frame(10, &[], false);
+        stream.write_all(&frame).map_err(|e| self.mark_closed(e))?;
+        self.metrics.record_sent(frame.len());
         
         // Read response
-        let mut buffer = [0u8; 1];
-        let size = stream.read(&mut buffer)?;
-        
-        if size == 0 {
-            return Err(HubError::Network("Connection closed".to_string()));
-        }
+        let frame = self.read_frame(&mut stream)?;
         
         // Check message type (11 = Heartbeat response)
-        Ok(buffer[0] == 11)
+        let alive = decode_frame(&frame, false).is_some_and(|(message_type, _)| message_type == 11);
+        if alive {
+            self.mark_seen();
+        }
+        Ok(alive)
     }
 }
\ No newline at end of file
@@ -0,0 +1,67 @@
+use std::net::{IpAddr, SocketAddr};
+
+/// A peer, or group of peers, named in a `PeerFilter`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PeerRule {
+    /// Every port of a host
+    Ip(IpAddr),
+    /// One address and port
+    Address(SocketAddr),
+    /// The hub with this ID, wherever it is
+    HubId(String),
+}
+
+impl PeerRule {
+    /// Whether the rule names a peer, or `None` if that depends on a hub ID that isn't known yet
+    fn matches(&self, address: SocketAddr, hub_id: Option<&str>) -> Option<bool> {
+        match self {
+            PeerRule::Ip(ip) => Some(address.ip() == *ip),
+            PeerRule::Address(rule) => Some(address == *rule),
+            PeerRule::HubId(id) => hub_id.map(|hub_id| hub_id == id),
+        }
+    }
+}
+
+/// Which peers a transport connects to, whether discovered or connected to directly
+///
+/// A peer named by a deny rule is refused. If there are allow rules, a peer must
+/// also be named by one of them; without any, every peer that isn't denied is
+/// allowed. Rules naming hub IDs are checked once the peer has told its ID when
+/// connecting.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PeerFilter {
+    /// Peers that may be connected to, if any are named
+    allowed: Vec<PeerRule>,
+    /// Peers that are never connected to
+    denied: Vec<PeerRule>,
+}
+
+impl PeerFilter {
+    /// Create a filter allowing every peer
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow a peer, making the filter refuse peers no allow rule names
+    pub fn allow(mut self, rule: PeerRule) -> Self {
+        self.allowed.push(rule);
+        self
+    }
+
+    /// Refuse a peer, even if an allow rule names it too
+    pub fn deny(mut self, rule: PeerRule) -> Self {
+        self.denied.push(rule);
+        self
+    }
+
+    /// Whether the peer at an address, with the given hub ID if known, may be connected to
+    ///
+    /// Without the hub ID, rules naming hub IDs are assumed not to deny the peer
+    /// and to allow it, so the peer is checked again once its ID is known.
+    pub fn allows(&self, address: SocketAddr, hub_id: Option<&str>) -> bool {
+        let denied = self.denied.iter().any(|rule| rule.matches(address, hub_id) == Some(true));
+        let allowed = self.allowed.is_empty()
+            || self.allowed.iter().any(|rule| rule.matches(address, hub_id) != Some(false));
+        allowed && !denied
+    }
+}
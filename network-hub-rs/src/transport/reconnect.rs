@@ -0,0 +1,33 @@
+use std::time::Duration;
+
+/// How a transport reconnects to a peer whose connection has broken
+///
+/// Attempts are spaced with exponential backoff: the first waits `base_delay`,
+/// and each later one waits twice as long as the last, up to `max_delay`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReconnectPolicy {
+    /// Number of attempts before giving up
+    pub max_retries: u32,
+    /// Time to wait before the first attempt
+    pub base_delay: Duration,
+    /// Longest time to wait between attempts
+    pub max_delay: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy {
+            max_retries: 5,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Get the time to wait before an attempt, counting from 0
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 2u32.checked_pow(attempt).unwrap_or(u32::MAX);
+        self.base_delay.saturating_mul(factor).min(self.max_delay)
+    }
+}
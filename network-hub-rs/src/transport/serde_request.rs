@@ -0,0 +1,110 @@
+use std::any::Any;
+use std::collections::HashMap;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tracing::warn;
+
+use crate::hub::ApiRequest;
+
+/// Request or response data that keeps its type across the network
+///
+/// The transport sends the value as JSON together with the name of its type,
+/// and the receiving side gets a `SerdePayload` back to decode.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SerdePayload {
+    /// Name of the type the value was encoded from
+    type_name: String,
+    /// The encoded value
+    value: serde_json::Value,
+}
+
+impl SerdePayload {
+    /// Encode a value
+    pub fn new<T: Serialize>(data: &T) -> Self {
+        let value = serde_json::to_value(data).unwrap_or_else(|e| {
+            warn!(type_name = std::any::type_name::<T>(), error = %e, "Failed to encode payload");
+            serde_json::Value::Null
+        });
+        Self::from_parts(std::any::type_name::<T>().to_string(), value)
+    }
+
+    /// Create a payload from a type name and an encoded value
+    pub(crate) fn from_parts(type_name: String, value: serde_json::Value) -> Self {
+        SerdePayload { type_name, value }
+    }
+
+    /// Get the name of the type the value was encoded from
+    pub fn type_name(&self) -> &str {
+        &self.type_name
+    }
+
+    /// Get the encoded value
+    pub fn value(&self) -> &serde_json::Value {
+        &self.value
+    }
+
+    /// Decode the value, if it was encoded from a `T`
+    pub fn decode<T: DeserializeOwned>(&self) -> Option<T> {
+        if self.type_name != std::any::type_name::<T>() {
+            return None;
+        }
+        serde_json::from_value(self.value.clone()).ok()
+    }
+
+    /// Decode request or response data holding a payload encoded from a `T`
+    pub fn decode_data<T: DeserializeOwned>(data: &(dyn Any + Send + Sync)) -> Option<T> {
+        data.downcast_ref::<SerdePayload>()?.decode()
+    }
+}
+
+/// A request whose data is sent to a peer as a typed payload
+///
+/// Handlers receive the data as a [`SerdePayload`], e.g.
+/// `SerdePayload::decode_data::<(i32, i32)>(&*request.data)`.
+pub struct SerdeRequest<T> {
+    /// API path
+    pub path: String,
+    /// Request data
+    pub data: T,
+    /// Request metadata
+    pub metadata: HashMap<String, String>,
+    /// Sender ID
+    pub sender_id: String,
+}
+
+impl<T: Serialize + DeserializeOwned> SerdeRequest<T> {
+    /// Create a request for the given path
+    pub fn new(path: &str, data: T) -> Self {
+        SerdeRequest {
+            path: path.to_string(),
+            data,
+            metadata: HashMap::new(),
+            sender_id: String::new(),
+        }
+    }
+
+    /// Set the sender ID
+    pub fn sender(mut self, sender_id: &str) -> Self {
+        self.sender_id = sender_id.to_string();
+        self
+    }
+
+    /// Add a metadata entry
+    pub fn metadata(mut self, key: &str, value: &str) -> Self {
+        self.metadata.insert(key.to_string(), value.to_string());
+        self
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> From<SerdeRequest<T>> for ApiRequest {
+    fn from(request: SerdeRequest<T>) -> Self {
+        ApiRequest {
+            path: request.path,
+            data: Box::new(SerdePayload::new(&request.data)),
+            metadata: request.metadata,
+            sender_id: request.sender_id,
+        }
+    }
+}
+
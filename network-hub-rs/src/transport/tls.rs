@@ -5,14 +5,20 @@ use std::io::BufReader;
 use std::sync::Arc;
 use std::path::Path;
 
+use serde::{Deserialize, Serialize};
 use rustls::{Certificate, PrivateKey, ServerConfig, ClientConfig};
 use rustls::server::AllowAnyAuthenticatedClient;
 use rustls_pemfile::{certs, pkcs8_private_keys};
 
 use crate::error::{HubError, Result};
 
+/// ALPN protocol of the hub protocol, offered by peers connecting to a transport
+///
+/// Lets a transport serving HTTP on the same port tell peers from HTTP clients.
+pub const HUB_ALPN_PROTOCOL: &[u8] = b"network-hub/1";
+
 /// TLS configuration for secure communication
-#[derive(Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TlsConfig {
     /// Path to certificate file
     pub cert_path: String,
@@ -79,7 +85,10 @@ fn load_keys(path: &Path) -> Result<Vec<PrivateKey>> {
 }
 
 /// Create a server TLS configuration
-fn create_server_config(config: &TlsConfig) -> Result<ServerConfig> {
+///
+/// For serving over async streams, e.g. with `tokio_rustls`; set its ALPN
+/// protocols to negotiate HTTP/2.
+pub fn create_server_config(config: &TlsConfig) -> Result<ServerConfig> {
     let certs = load_certs(Path::new(&config.cert_path))?;
     let mut keys = load_keys(Path::new(&config.key_path))?;
     
@@ -116,7 +125,9 @@ fn create_server_config(config: &TlsConfig) -> Result<ServerConfig> {
 }
 
 /// Create a client TLS configuration
-fn create_client_config(config: &TlsConfig) -> Result<ClientConfig> {
+///
+/// For connecting over async streams, e.g. with `tokio_rustls`.
+pub fn create_client_config(config: &TlsConfig) -> Result<ClientConfig> {
     let certs = load_certs(Path::new(&config.cert_path))?;
     let mut keys = load_keys(Path::new(&config.key_path))?;
     
@@ -144,11 +155,33 @@ fn create_client_config(config: &TlsConfig) -> Result<ClientConfig> {
 
 /// Create a server TLS stream
 pub fn create_server_tls_stream(stream: TcpStream, config: &TlsConfig) -> Result<TlsStream> {
-    // Create server config
-    let server_config = create_server_config(config)?;
-    let acceptor = rustls::ServerConnection::new(Arc::new(server_config))
-        .map_err(|e| HubError::Tls(format!("Failed to create TLS acceptor: {}", e)))?;
-    
+    let acceptor = server_connection(config, &[])?;
+    Ok(server_tls_stream(acceptor, stream))
+}
+
+/// Accept a TLS connection offering ALPN protocols, most preferred first
+///
+/// Unlike `create_server_tls_stream`, the handshake is completed before
+/// returning, so the protocol the client picked (if any) is returned with the
+/// stream. Clients offering only protocols that aren't offered are refused.
+pub fn accept_tls_stream(mut stream: TcpStream, config: &TlsConfig, protocols: &[&[u8]]) -> Result<(TlsStream, Option<Vec<u8>>)> {
+    let mut acceptor = server_connection(config, protocols)?;
+    acceptor.complete_io(&mut stream)
+        .map_err(|e| HubError::Tls(format!("TLS handshake failed: {}", e)))?;
+    let protocol = acceptor.alpn_protocol().map(<[u8]>::to_vec);
+    Ok((server_tls_stream(acceptor, stream), protocol))
+}
+
+/// Create the server side of a TLS connection, offering ALPN protocols if any
+fn server_connection(config: &TlsConfig, protocols: &[&[u8]]) -> Result<rustls::ServerConnection> {
+    let mut server_config = create_server_config(config)?;
+    server_config.alpn_protocols = protocols.iter().map(|protocol| protocol.to_vec()).collect();
+    rustls::ServerConnection::new(Arc::new(server_config))
+        .map_err(|e| HubError::Tls(format!("Failed to create TLS acceptor: {}", e)))
+}
+
+/// Wrap the server side of a TLS connection over a TCP stream
+fn server_tls_stream(acceptor: rustls::ServerConnection, stream: TcpStream) -> TlsStream {
     // Create rustls stream
     let tls_stream = rustls::StreamOwned::new(acceptor, stream);
     
@@ -177,15 +210,21 @@ pub fn create_server_tls_stream(stream: TcpStream, config: &TlsConfig) -> Result
     
     let server_stream = ServerTlsStream { stream: tls_stream };
     
-    Ok(TlsStream {
+    TlsStream {
         inner: Box::new(server_stream),
-    })
+    }
 }
 
 /// Create a client TLS stream
 pub fn create_client_tls_stream(stream: TcpStream, config: &TlsConfig) -> Result<TlsStream> {
+    create_client_tls_stream_with_alpn(stream, config, &[])
+}
+
+/// Create a client TLS stream offering ALPN protocols, most preferred first
+pub fn create_client_tls_stream_with_alpn(stream: TcpStream, config: &TlsConfig, protocols: &[&[u8]]) -> Result<TlsStream> {
     // Create client config
-    let client_config = create_client_config(config)?;
+    let mut client_config = create_client_config(config)?;
+    client_config.alpn_protocols = protocols.iter().map(|protocol| protocol.to_vec()).collect();
     
     // Get server name from config for SNI or use localhost as default
     let server_name = rustls::ServerName::try_from("localhost")
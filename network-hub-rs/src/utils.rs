@@ -1,6 +1,22 @@
 use uuid::Uuid;
 use std::time::{SystemTime, UNIX_EPOCH};
 use std::collections::HashMap;
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs, UdpSocket};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use socket2::{Domain, Socket, Type};
+use tracing::error;
+
+/// Default number of worker threads serving accepted connections
+pub const DEFAULT_WORKER_THREADS: usize = 64;
+
+/// Default number of connections the OS queues for a listener before they're accepted
+pub const DEFAULT_LISTEN_BACKLOG: u32 = 1024;
 
 /// Generate a random UUID
 pub fn generate_uuid() -> String {
@@ -15,21 +31,232 @@ pub fn current_time_millis() -> u64 {
         .as_millis() as u64
 }
 
+/// Bind a TCP listener to an address, with room for `backlog` connections waiting to be accepted
+///
+/// IPv6 listeners are bound as IPv6-only so the same port can also be bound on an
+/// IPv4 address (e.g. listening on both `0.0.0.0:8443` and `[::]:8443`). The OS
+/// may cap the backlog (e.g. at `net.core.somaxconn` on Linux).
+pub fn bind_listener(address: SocketAddr, backlog: u32) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(address), Type::STREAM, None)?;
+    if address.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    socket.set_reuse_address(true)?;
+    socket.bind(&address.into())?;
+    socket.listen(backlog.min(i32::MAX as u32) as i32)?;
+    Ok(socket.into())
+}
+
+/// Open a TCP connection with `TCP_NODELAY` set
+///
+/// Requests and responses are small messages, so they're sent right away rather
+/// than held back to be coalesced.
+pub fn connect_tcp<A: ToSocketAddrs>(address: A) -> io::Result<TcpStream> {
+    let stream = TcpStream::connect(address)?;
+    stream.set_nodelay(true)?;
+    Ok(stream)
+}
+
+/// Bind a UDP socket to a port that other sockets may bind too
+///
+/// Sets `SO_REUSEADDR` (and `SO_REUSEPORT` on Unix), so several hubs on one
+/// machine, or in one process, can all receive the broadcasts sent to the port.
+/// Like TCP listeners, IPv6 sockets are bound as IPv6-only.
+pub fn bind_shared_udp(address: SocketAddr) -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::for_address(address), Type::DGRAM, None)?;
+    if address.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    socket.set_reuse_address(true)?;
+    #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+    socket.set_reuse_port(true)?;
+    socket.bind(&address.into())?;
+    Ok(socket.into())
+}
+
+/// Apply an idle timeout to an accepted stream, a zero timeout meaning none
+pub fn set_idle_timeout(stream: &TcpStream, timeout: Duration) -> io::Result<()> {
+    stream.set_read_timeout((!timeout.is_zero()).then_some(timeout))
+}
+
+/// Whether an I/O error is a read timeout
+///
+/// Timed out reads report `WouldBlock` on Unix and `TimedOut` on Windows.
+pub fn is_timeout(error: &io::Error) -> bool {
+    matches!(error.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut)
+}
+
+/// Fixed-size pool of worker threads handling items from a bounded queue
+///
+/// Items are submitted with `try_submit`, which hands the item back instead of
+/// blocking when every worker is busy and the queue is full.
+pub struct WorkerPool<T> {
+    /// Sending side of the queue shared by the workers
+    sender: SyncSender<T>,
+}
+
+impl<T: Send + 'static> WorkerPool<T> {
+    /// Create a pool of `threads` workers running `handler` on each submitted item,
+    /// with room for `queue_size` items waiting for a free worker
+    pub fn new<F>(threads: usize, queue_size: usize, handler: F) -> Self
+    where
+        F: Fn(T) + Send + Sync + 'static,
+    {
+        let (sender, receiver) = mpsc::sync_channel::<T>(queue_size);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let handler = Arc::new(handler);
+        
+        for _ in 0..threads.max(1) {
+            let receiver = Arc::clone(&receiver);
+            let handler = Arc::clone(&handler);
+            
+            thread::spawn(move || loop {
+                // The queue lock is released before the item is handled
+                let item = receiver.lock().unwrap().recv();
+                let Ok(item) = item else {
+                    // The pool has been dropped
+                    break;
+                };
+                
+                // Keep the worker alive if the handler panics
+                if panic::catch_unwind(AssertUnwindSafe(|| handler(item))).is_err() {
+                    error!("Worker pool handler panicked");
+                }
+            });
+        }
+        
+        WorkerPool { sender }
+    }
+    
+    /// Queue an item for the next free worker, or return it if the pool is saturated
+    pub fn try_submit(&self, item: T) -> std::result::Result<(), T> {
+        self.sender.try_send(item).map_err(|e| match e {
+            TrySendError::Full(item) | TrySendError::Disconnected(item) => item,
+        })
+    }
+}
+
+/// Signal telling a server's background threads to stop
+///
+/// Threads either poll `is_triggered` between blocking calls, or use
+/// `wait_timeout` in place of a sleep so they stop without waiting it out.
+#[derive(Default)]
+pub struct ShutdownSignal {
+    /// Whether the signal has been triggered
+    triggered: Mutex<bool>,
+    /// Wakes threads waiting on the signal
+    condvar: Condvar,
+}
+
+impl ShutdownSignal {
+    /// Create an untriggered signal
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Trigger the signal, waking every waiting thread
+    pub fn trigger(&self) {
+        *self.triggered.lock().unwrap() = true;
+        self.condvar.notify_all();
+    }
+    
+    /// Whether the signal has been triggered
+    pub fn is_triggered(&self) -> bool {
+        *self.triggered.lock().unwrap()
+    }
+    
+    /// Wait until the signal is triggered or the timeout elapses, returning whether it was triggered
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let triggered = self.triggered.lock().unwrap();
+        let (triggered, _) = self.condvar
+            .wait_timeout_while(triggered, timeout, |triggered| !*triggered)
+            .unwrap();
+        *triggered
+    }
+}
+
 /// Find similar paths based on string similarity
 pub fn find_similar_path<T>(
     map: &HashMap<String, T>,
     target_path: &str,
     threshold: f64,
 ) -> Option<(String, f64)> {
-    for path in map.keys() {
+    find_similar(map.keys().map(String::as_str), target_path, threshold)
+}
+
+/// Find the first of the candidate paths that is similar to the target path
+pub fn find_similar<'a>(
+    candidates: impl IntoIterator<Item = &'a str>,
+    target_path: &str,
+    threshold: f64,
+) -> Option<(String, f64)> {
+    for path in candidates {
         let similarity = string_similarity(path, target_path);
         if similarity >= threshold {
-            return Some((path.clone(), similarity));
+            return Some((path.to_string(), similarity));
         }
     }
     None
 }
 
+/// Check whether a topic or path matches an interceptor or subscription pattern
+///
+/// Patterns are split into `/`-separated segments like the topics they match:
+///
+/// - `*` matches any single segment, so `sensors/*/events` matches `sensors/kitchen/events`
+/// - `**` matches any number of segments, including none, so `sensors/**` matches
+///   `sensors` and `sensors/kitchen/temp`
+/// - `*` within a segment matches any part of it, so `temp-*` matches `temp-kitchen`
+/// - `#` on its own matches every topic
+///
+/// A pattern without wildcards only matches the identical topic.
+pub fn matches_pattern(pattern: &str, topic: &str) -> bool {
+    if pattern == "#" || pattern == topic {
+        return true;
+    }
+    if !pattern.contains('*') {
+        return false;
+    }
+    
+    let pattern: Vec<&str> = pattern.split('/').collect();
+    let topic: Vec<&str> = topic.split('/').collect();
+    matches_segments(&pattern, &topic)
+}
+
+/// Match topic segments against pattern segments, for `matches_pattern`
+fn matches_segments(pattern: &[&str], topic: &[&str]) -> bool {
+    match pattern {
+        [] => topic.is_empty(),
+        ["**", rest @ ..] => (0..=topic.len()).any(|skipped| matches_segments(rest, &topic[skipped..])),
+        [first, rest @ ..] => match topic {
+            [segment, topic_rest @ ..] => matches_segment(first, segment) && matches_segments(rest, topic_rest),
+            [] => false,
+        },
+    }
+}
+
+/// Match a single segment against a pattern segment in which `*` matches any characters
+fn matches_segment(pattern: &str, segment: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or("");
+    let Some(mut rest) = segment.strip_prefix(first) else {
+        return false;
+    };
+    
+    let mut parts: Vec<&str> = parts.collect();
+    let Some(last) = parts.pop() else {
+        // No wildcard in the segment
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
 /// Calculate string similarity (Levenshtein distance)
 fn string_similarity(s1: &str, s2: &str) -> f64 {
     if s1 == s2 {
@@ -0,0 +1,77 @@
+//! Helpers shared between integration tests
+
+use std::io::Read;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use network_hub::TlsConfig;
+use network_hub::transport::NetworkTransport;
+use rcgen::{BasicConstraints, Certificate, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa};
+
+/// Generate a CA and a localhost certificate signed by it, returning a TLS config
+/// that uses them for both sides of a connection
+pub fn generate_test_certs(dir: &Path) -> TlsConfig {
+    let mut ca_params = CertificateParams::new(Vec::new());
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    ca_params.distinguished_name.push(DnType::CommonName, "Network Hub Test CA");
+    let ca = Certificate::from_params(ca_params).unwrap();
+    
+    let mut params = CertificateParams::new(vec!["localhost".to_string()]);
+    params.extended_key_usages = vec![
+        ExtendedKeyUsagePurpose::ServerAuth,
+        ExtendedKeyUsagePurpose::ClientAuth,
+    ];
+    let cert = Certificate::from_params(params).unwrap();
+    
+    let cert_path = dir.join("cert.pem");
+    let key_path = dir.join("key.pem");
+    let ca_path = dir.join("ca.pem");
+    std::fs::write(&cert_path, cert.serialize_pem_with_signer(&ca).unwrap()).unwrap();
+    std::fs::write(&key_path, cert.serialize_private_key_pem()).unwrap();
+    std::fs::write(&ca_path, ca.serialize_pem().unwrap()).unwrap();
+    
+    TlsConfig {
+        cert_path: cert_path.to_string_lossy().into_owned(),
+        key_path: key_path.to_string_lossy().into_owned(),
+        ca_path: Some(ca_path.to_string_lossy().into_owned()),
+    }
+}
+
+/// Start a transport on a thread of its own, returning the address it listens on
+/// once it accepts connections
+///
+/// Create the transport on port 0 so it gets a free port, instead of reserving one
+/// that another test could take before the transport binds it.
+#[allow(dead_code)]
+pub fn start_transport(transport: &NetworkTransport) -> SocketAddr {
+    let running = transport.clone();
+    thread::spawn(move || running.start().unwrap());
+    
+    let start = Instant::now();
+    loop {
+        if let Some(address) = transport.listen_addresses().first() {
+            return *address;
+        }
+        assert!(start.elapsed() < Duration::from_secs(5), "Peer did not start");
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+/// Build a frame of the hub protocol by hand: the message type, the length of the
+/// payload as a big-endian `u32`, then the payload
+#[allow(dead_code)]
+pub fn frame(message_type: u8, payload: &[u8]) -> Vec<u8> {
+    [&[message_type], (payload.len() as u32).to_be_bytes().as_slice(), payload].concat()
+}
+
+/// Read a frame of the hub protocol by hand, returning its message type and payload
+#[allow(dead_code)]
+pub fn read_frame(stream: &mut impl Read) -> (u8, Vec<u8>) {
+    let mut header = [0u8; 5];
+    stream.read_exact(&mut header).unwrap();
+    let mut payload = vec![0u8; u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize];
+    stream.read_exact(&mut payload).unwrap();
+    (header[0], payload)
+}
@@ -0,0 +1,143 @@
+//! Tests for loading a configuration file and building the system it describes
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::thread;
+use std::time::Duration;
+
+use network_hub::{ApiRequest, Config, HubScope, ResponseStatus};
+use network_hub::config::build_system;
+use network_hub::transport::{ReconnectPolicy, DEFAULT_DISCOVERY_PORT};
+
+const SAMPLE_TOML: &str = r#"
+[hub]
+scope = { Custom = 250 }
+similarity_threshold = 0.9
+max_hops = 2
+enable_approximation = false
+
+[transport]
+bind_address = "127.0.0.1:9100"
+worker_threads = 4
+listen_backlog = 256
+idle_timeout_ms = 1500
+discovery_port = 9765
+discovery_interval_ms = 5000
+
+[transport.reconnect]
+max_retries = 8
+base_delay_ms = 250
+
+[proxy]
+bind_address = "0.0.0.0:8444"
+json_errors = true
+
+[tls]
+cert_path = "conf/cert.pem"
+key_path = "conf/key.pem"
+
+[routes]
+"/api/*" = "http://127.0.0.1:3000"
+"/static/*" = "http://127.0.0.1:3001"
+"#;
+
+/// Test that the objects built from a TOML file reflect every section
+#[test]
+fn test_build_system_from_toml() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("hub.toml");
+    fs::write(&path, SAMPLE_TOML).unwrap();
+
+    let config = Config::load(&path).unwrap();
+    assert_eq!(config.tls.cert_path, "conf/cert.pem");
+    assert_eq!(config.tls.ca_path, None);
+
+    let (hub, transport, proxy) = build_system(&config);
+
+    assert_eq!(hub.scope, HubScope::Custom(250));
+    let hub_config = hub.config();
+    assert_eq!(hub_config.similarity_threshold, 0.9);
+    assert_eq!(hub_config.max_hops, Some(2));
+    assert!(!hub_config.enable_approximation);
+    // Settings missing from the file keep their defaults
+    assert!(hub_config.enable_fallback);
+
+    assert_eq!(transport.bind_address(), "127.0.0.1:9100".parse().unwrap());
+    assert_eq!(transport.worker_threads(), 4);
+    assert_eq!(transport.listen_backlog(), 256);
+    assert_eq!(transport.idle_timeout(), Duration::from_millis(1500));
+    assert_eq!(transport.discovery_port(), 9765);
+    assert_eq!(transport.discovery_interval(), Duration::from_secs(5));
+    let reconnect = transport.reconnect_policy().unwrap();
+    assert_eq!(reconnect.max_retries, 8);
+    assert_eq!(reconnect.base_delay, Duration::from_millis(250));
+    assert_eq!(reconnect.max_delay, ReconnectPolicy::default().max_delay);
+
+    assert_eq!(proxy.bind_address(), "0.0.0.0:8444".parse().unwrap());
+    assert!(proxy.json_errors());
+    let routes = proxy.routes();
+    assert_eq!(routes.len(), 2);
+    assert_eq!(routes["/api/*"], "http://127.0.0.1:3000");
+    assert_eq!(routes["/static/*"], "http://127.0.0.1:3001");
+}
+
+/// Test loading a JSON file, with missing sections falling back to their defaults
+#[test]
+fn test_build_system_from_json() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("hub.json");
+    fs::write(&path, r#"{ "hub": { "scope": "Machine" }, "routes": { "/api/*": "http://127.0.0.1:3000" } }"#).unwrap();
+
+    let (hub, transport, proxy) = build_system(&Config::load(&path).unwrap());
+    assert_eq!(hub.scope, HubScope::Machine);
+    assert_eq!(transport.discovery_port(), DEFAULT_DISCOVERY_PORT);
+    assert_eq!(transport.reconnect_policy(), None);
+    assert!(!proxy.json_errors());
+    assert_eq!(proxy.listen_backlog(), network_hub::utils::DEFAULT_LISTEN_BACKLOG);
+    assert_eq!(proxy.routes()["/api/*"], "http://127.0.0.1:3000");
+}
+
+/// Test that an invalid file is reported rather than ignored
+#[test]
+fn test_invalid_config() {
+    assert!(Config::from_toml("[transport]\nbind_address = \"not an address\"").is_err());
+    assert!(Config::load("does/not/exist.toml").is_err());
+
+    // Route targets the proxy couldn't forward to
+    assert!(Config::from_toml("[routes]\n\"/api/*\" = \"127.0.0.1:3000\"").is_err());
+    assert!(Config::from_toml("[routes]\n\"/api/*\" = \"file:///srv/api\"").is_err());
+}
+
+/// Test that requests are forwarded through the routes a configuration file sets up
+#[test]
+fn test_configured_route() {
+    let backend = TcpListener::bind("127.0.0.1:0").unwrap();
+    let backend_addr = backend.local_addr().unwrap();
+    thread::spawn(move || {
+        for stream in backend.incoming() {
+            let mut stream = stream.unwrap();
+            let mut request = [0u8; 1024];
+            let size = stream.read(&mut request).unwrap();
+            let request = String::from_utf8_lossy(&request[..size]);
+            let path = request.split_whitespace().nth(1).unwrap_or_default().to_string();
+            write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", path.len(), path).unwrap();
+        }
+    });
+
+    let config = Config::from_toml(&format!("[routes]\n\"/api/*\" = \"http://{}\"", backend_addr)).unwrap();
+    let (hub, _transport, _proxy) = build_system(&config);
+
+    // Every path under the route's prefix reaches the backend
+    for path in ["/api/users", "/api/users/42"] {
+        let response = hub.handle_request(ApiRequest {
+            path: format!("/http{}", path),
+            data: Box::new(format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path)),
+            metadata: HashMap::new(),
+            sender_id: "test-client".to_string(),
+        });
+        assert_eq!(response.status, ResponseStatus::Success, "Unexpected status for {}", path);
+        assert_eq!(response.as_str(), Some(path));
+    }
+}
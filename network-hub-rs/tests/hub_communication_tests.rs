@@ -585,4 +585,237 @@ fn test_multi_level_retries() {
     assert_eq!(response3.status, ResponseStatus::Error);
     assert_eq!(response3.metadata.get("max_retries_exceeded"), Some(&"true".to_string()));
     assert_eq!(response3.metadata.get("retries"), Some(&"3".to_string())); // 0-based + 1 for initial try
-}
\ No newline at end of file
+}
+/// Test detaching a child hub from its parent
+#[test]
+fn test_disconnect_from_parent() {
+    let process_hub = Arc::new(Hub::new(HubScope::Process));
+    let thread_hub = Arc::new(Hub::new(HubScope::Thread));
+    
+    // Disconnecting without a parent is a no-op
+    thread_hub.disconnect_from_parent();
+    
+    thread_hub.connect_to_parent(Arc::clone(&process_hub)).unwrap();
+    
+    // API that only exists on the parent
+    process_hub.register_api("/process/only", |_: &ApiRequest| {
+        ApiResponse {
+            data: Box::new("Response from Process Hub"),
+            metadata: HashMap::new(),
+            status: ResponseStatus::Success,
+        }
+    }, HashMap::new());
+    
+    // While connected, the request escalates to the parent
+    let request = ApiRequest {
+        path: "/process/only".to_string(),
+        data: Box::new(()),
+        metadata: HashMap::new(),
+        sender_id: "test-client".to_string(),
+    };
+    
+    let response = thread_hub.handle_request(request);
+    assert_eq!(response.status, ResponseStatus::Success);
+    assert_eq!(response.data.downcast_ref::<&str>(), Some(&"Response from Process Hub"));
+    
+    // After disconnecting, the child can no longer reach the parent's API
+    thread_hub.disconnect_from_parent();
+    
+    let request = ApiRequest {
+        path: "/process/only".to_string(),
+        data: Box::new(()),
+        metadata: HashMap::new(),
+        sender_id: "test-client".to_string(),
+    };
+    
+    let response = thread_hub.handle_request(request);
+    assert_eq!(response.status, ResponseStatus::NotFound);
+    
+    // Disconnecting twice is safe
+    thread_hub.disconnect_from_parent();
+}
+
+/// Test that connecting to a parent with an equal or narrower scope is rejected
+#[test]
+fn test_connect_to_parent_scope_validation() {
+    let thread_hub = Arc::new(Hub::new(HubScope::Thread));
+    let other_thread_hub = Arc::new(Hub::new(HubScope::Thread));
+    let machine_hub = Arc::new(Hub::new(HubScope::Machine));
+    let process_hub = Arc::new(Hub::new(HubScope::Process));
+    
+    // Equal scopes are rejected
+    assert!(thread_hub.connect_to_parent(Arc::clone(&other_thread_hub)).is_err());
+    
+    // Inverted scopes are rejected
+    assert!(machine_hub.connect_to_parent(Arc::clone(&process_hub)).is_err());
+    assert!(process_hub.connect_to_parent(Arc::clone(&thread_hub)).is_err());
+    
+    // A wider parent is accepted
+    assert!(process_hub.connect_to_parent(Arc::clone(&machine_hub)).is_ok());
+}
+
+/// Test that requests stop escalating once the hub's hop limit is reached
+#[test]
+fn test_max_hops_limits_escalation() {
+    let thread_hub = Hub::builder(HubScope::Thread).max_hops(1).build();
+    let process_hub = Arc::new(Hub::new(HubScope::Process));
+    let machine_hub = Arc::new(Hub::new(HubScope::Machine));
+    
+    thread_hub.connect_to_parent(Arc::clone(&process_hub)).unwrap();
+    process_hub.connect_to_parent(Arc::clone(&machine_hub)).unwrap();
+    
+    process_hub.register_api("/process/api", |_: &ApiRequest| {
+        ApiResponse {
+            data: Box::new("Response from Process Hub"),
+            metadata: HashMap::new(),
+            status: ResponseStatus::Success,
+        }
+    }, HashMap::new());
+    
+    machine_hub.register_api("/machine/api", |_: &ApiRequest| {
+        ApiResponse {
+            data: Box::new("Response from Machine Hub"),
+            metadata: HashMap::new(),
+            status: ResponseStatus::Success,
+        }
+    }, HashMap::new());
+    
+    // One hop up is allowed
+    let request = ApiRequest {
+        path: "/process/api".to_string(),
+        data: Box::new(()),
+        metadata: HashMap::new(),
+        sender_id: "test-client".to_string(),
+    };
+    assert_eq!(thread_hub.handle_request(request).status, ResponseStatus::Success);
+    
+    // Two hops up exceeds the limit
+    let request = ApiRequest {
+        path: "/machine/api".to_string(),
+        data: Box::new(()),
+        metadata: HashMap::new(),
+        sender_id: "test-client".to_string(),
+    };
+    assert_eq!(thread_hub.handle_request(request).status, ResponseStatus::NotFound);
+}
+
+/// Test that parent hubs route requests down to the child hub serving the path
+#[test]
+fn test_parent_routes_to_child_apis() {
+    let machine_hub = Arc::new(Hub::new(HubScope::Machine));
+    let process_hub = Arc::new(Hub::new(HubScope::Process));
+    let thread_hub = Arc::new(Hub::new(HubScope::Thread));
+    let sibling_hub = Arc::new(Hub::new(HubScope::Thread));
+    
+    // Registered before connecting, so it is propagated by connect_to_parent
+    thread_hub.register_api("/thread/echo", |request: &ApiRequest| {
+        ApiResponse {
+            data: Box::new(request.data.downcast_ref::<String>().cloned().unwrap_or_default()),
+            metadata: HashMap::from([("handled_by".to_string(), "thread".to_string())]),
+            status: ResponseStatus::Success,
+        }
+    }, HashMap::new());
+    
+    process_hub.connect_to_parent(Arc::clone(&machine_hub)).unwrap();
+    thread_hub.connect_to_parent(Arc::clone(&process_hub)).unwrap();
+    sibling_hub.connect_to_parent(Arc::clone(&process_hub)).unwrap();
+    
+    // Registered after connecting, so it is propagated by register_api
+    thread_hub.register_api("/thread/items/:id", |_: &ApiRequest| {
+        ApiResponse {
+            data: Box::new("item"),
+            metadata: HashMap::new(),
+            status: ResponseStatus::Success,
+        }
+    }, HashMap::new());
+    
+    let request = |path: &str, data: &str| ApiRequest {
+        path: path.to_string(),
+        data: Box::new(data.to_string()),
+        metadata: HashMap::new(),
+        sender_id: "test-client".to_string(),
+    };
+    
+    // The parent dispatches to the child, passing the request data through
+    let response = process_hub.handle_request(request("/thread/echo", "hello"));
+    assert_eq!(response.status, ResponseStatus::Success);
+    assert_eq!(response.data.downcast_ref::<String>(), Some(&"hello".to_string()));
+    assert_eq!(response.metadata.get("handled_by"), Some(&"thread".to_string()));
+    
+    // Routes are propagated through every ancestor, including path patterns
+    let response = machine_hub.handle_request(request("/thread/items/42", ""));
+    assert_eq!(response.status, ResponseStatus::Success);
+    assert_eq!(response.data.downcast_ref::<&str>(), Some(&"item"));
+    
+    // A request escalated from a child is not routed back down to a sibling
+    let response = sibling_hub.handle_request(request("/thread/echo", "hello"));
+    assert_eq!(response.status, ResponseStatus::NotFound);
+    
+    // Once the child disconnects, the parent no longer routes to it
+    thread_hub.disconnect_from_parent();
+    let response = process_hub.handle_request(request("/thread/echo", "hello"));
+    assert_eq!(response.status, ResponseStatus::NotFound);
+}
+
+/// Test a custom-tier hub between the machine and network levels
+#[test]
+fn test_custom_scope_tier() {
+    let rack_scope = HubScope::Custom(250);
+    assert!(HubScope::Machine < rack_scope && rack_scope < HubScope::Network);
+    assert!(HubScope::Custom(100) > HubScope::Process);
+    assert_eq!("custom(250)".parse::<HubScope>().unwrap(), rack_scope);
+    assert_eq!(rack_scope.to_string().parse::<HubScope>().unwrap(), rack_scope);
+    assert_eq!("Machine".parse::<HubScope>().unwrap(), HubScope::Machine);
+    assert!("rack".parse::<HubScope>().is_err());
+    
+    let machine_hub = Arc::new(Hub::new(HubScope::Machine));
+    let rack_hub = Arc::new(Hub::new(rack_scope));
+    let network_hub = Arc::new(Hub::new(HubScope::Network));
+    
+    // The rack tier can only sit between narrower and wider scopes
+    assert!(rack_hub.connect_to_parent(Arc::clone(&machine_hub)).is_err());
+    assert!(network_hub.connect_to_parent(Arc::clone(&rack_hub)).is_err());
+    machine_hub.connect_to_parent(Arc::clone(&rack_hub)).unwrap();
+    rack_hub.connect_to_parent(Arc::clone(&network_hub)).unwrap();
+    
+    network_hub.register_api("/network/api", |_: &ApiRequest| {
+        ApiResponse {
+            data: Box::new("Response from Network Hub"),
+            metadata: HashMap::new(),
+            status: ResponseStatus::Success,
+        }
+    }, HashMap::new());
+    
+    // Requests escalate from the machine hub through the rack tier
+    let response = machine_hub.handle_request(ApiRequest {
+        path: "/network/api".to_string(),
+        data: Box::new(()),
+        metadata: HashMap::new(),
+        sender_id: "test-client".to_string(),
+    });
+    assert_eq!(response.status, ResponseStatus::Success);
+    assert_eq!(response.data.downcast_ref::<&str>(), Some(&"Response from Network Hub"));
+}
+
+/// Test that every scope round-trips through its string form
+#[test]
+fn test_scope_string_round_trip() {
+    let scopes = [
+        HubScope::Thread,
+        HubScope::Process,
+        HubScope::Machine,
+        HubScope::Network,
+        HubScope::Custom(0),
+        HubScope::Custom(250),
+    ];
+    
+    for scope in scopes {
+        let name = scope.to_string();
+        assert_eq!(name.parse::<HubScope>().unwrap(), scope);
+        assert_eq!(HubScope::try_from(name.as_str()).unwrap(), scope);
+        assert_eq!(name.to_uppercase().parse::<HubScope>().unwrap(), scope);
+    }
+    
+    assert!(HubScope::try_from("custom(").is_err());
+    assert!(HubScope::try_from("").is_err());
+}
@@ -252,7 +252,7 @@ fn test_request_escalation_and_routing() {
     
     // Test 2: Routing - Request to thread hub API from process hub
     // This tests that requests that should route to child hubs are correctly delegated
-    // Since we uncommented the register_remote_api in our fix, APIs should be propagated upward
+    // (registering an API records a remote route to the thread hub in the process hub)
     let thread_request = ApiRequest {
         path: "/thread/local_api".to_string(),
         data: Box::new(()),
@@ -262,8 +262,8 @@ fn test_request_escalation_and_routing() {
     
     let response = process_hub.handle_request(thread_request);
     assert_eq!(response.status, ResponseStatus::Success);
-    // The response should match our remote API registration format in register_remote_api
-    assert!(response.data.downcast_ref::<String>().unwrap().contains("Remote API from hub"));
+    // The response comes from the thread hub's own handler
+    assert_eq!(response.data.downcast_ref::<&str>(), Some(&"thread hub response"));
     println!("Request routing to child hub successful");
     });
 }
@@ -1,14 +1,26 @@
 //! Tests for network hub communication with TLS
 
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::sync::{Arc, Barrier};
 use std::thread;
 use std::time::{Duration, Instant};
-use std::net::{TcpListener, TcpStream, SocketAddr};
+use std::net::{Ipv6Addr, TcpListener, TcpStream, SocketAddr, UdpSocket};
 use std::str::FromStr;
+use std::io::{Read, Write};
 
-use network_hub::{Hub, HubScope, ApiRequest, ApiResponse, ResponseStatus};
-use network_hub::transport::{NetworkTransport, TlsConfig};
+use network_hub::{Hub, HubScope, ApiRequest, ApiResponse, HttpReverseProxy, ResponseStatus};
+use network_hub::error::HubError;
+use network_hub::hub::TTL_KEY;
+use network_hub::transport::{
+    create_client_tls_stream, create_client_tls_stream_with_alpn, AuthConfig, NetworkTransport, PeerFilter, PeerRule, ReconnectPolicy,
+    SerdePayload, SerdeRequest, TlsConfig, TlsStream, HUB_ALPN_PROTOCOL,
+};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use network_hub::utils::{bind_listener, connect_tcp, DEFAULT_LISTEN_BACKLOG};
+
+mod common;
+use common::{frame, generate_test_certs, read_frame, start_transport};
 
 /// Test setting up network hubs with TLS communication
 #[test]
@@ -409,6 +421,45 @@ fn test_multi_network_hub_concurrent() {
     }
 }
 
+/// Test that request_any finds the one peer that hosts an API
+#[test]
+fn test_request_any() {
+    let cert_dir = tempfile::tempdir().unwrap();
+    let tls_config = generate_test_certs(cert_dir.path());
+    
+    let client_hub = Arc::new(Hub::new(HubScope::Network));
+    let client = NetworkTransport::new(Arc::clone(&client_hub), SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config.clone());
+    
+    // Start three peers, only the last of which hosts the API
+    for i in 0..3 {
+        let hub = Arc::new(Hub::new(HubScope::Network));
+        if i == 2 {
+            hub.register_api("/shared/data", |_: &ApiRequest| {
+                ApiResponse {
+                    data: Box::new("Data from peer 2"),
+                    metadata: HashMap::new(),
+                    status: ResponseStatus::Success,
+                }
+            }, HashMap::new());
+        }
+        
+        let transport = NetworkTransport::new(hub, SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config.clone());
+        let addr = start_transport(&transport);
+        client.connect_to_peer(addr).unwrap();
+    }
+    
+    let request = ApiRequest {
+        path: "/shared/data".to_string(),
+        data: Box::new(()),
+        metadata: HashMap::new(),
+        sender_id: client_hub.id.clone(),
+    };
+    
+    let response = client.request_any(request, Duration::from_secs(5)).unwrap();
+    assert_eq!(response.status, ResponseStatus::Success);
+    assert_eq!(response.data.downcast_ref::<String>(), Some(&"Data from peer 2".to_string()));
+}
+
 /// Helper extension trait to add timeout functionality to NetworkTransport
 trait NetworkTransportExt {
     fn send_request_to_peer_with_timeout(
@@ -451,4 +502,1036 @@ impl NetworkTransportExt for NetworkTransport {
             Err(_) => Err("Request timed out".to_string()),
         }
     }
-}
\ No newline at end of file
+}
+/// Test that a short discovery interval lets a hub discover another one quickly
+#[test]
+fn test_discovery_interval() {
+    let cert_dir = tempfile::tempdir().unwrap();
+    let tls_config = generate_test_certs(cert_dir.path());
+    
+    // Listen on a port of our own so other tests' transports aren't discovered
+    let listener = UdpSocket::bind("0.0.0.0:0").unwrap();
+    let discovery_port = listener.local_addr().unwrap().port();
+    listener.set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+    
+    let hub1 = Arc::new(Hub::new(HubScope::Network));
+    let hub2 = Arc::new(Hub::new(HubScope::Network));
+    hub2.register_api("/discovered/data", |_: &ApiRequest| {
+        ApiResponse {
+            data: Box::new("Data from the discovered hub"),
+            metadata: HashMap::new(),
+            status: ResponseStatus::Success,
+        }
+    }, HashMap::new());
+    
+    let start_hub = |hub: Arc<Hub>| {
+        let transport = NetworkTransport::new(hub, SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config.clone());
+        transport.set_discovery_port(discovery_port);
+        transport.set_discovery_interval(Duration::from_millis(100));
+        assert_eq!(transport.discovery_port(), discovery_port);
+        assert_eq!(transport.discovery_interval(), Duration::from_millis(100));
+        
+        start_transport(&transport);
+        transport
+    };
+    
+    // The second hub announces itself every 100 ms on the configured port
+    let _transport2 = start_hub(Arc::clone(&hub2));
+    let announcement = format!("HUB{},", hub2.id);
+    let start = Instant::now();
+    let mut announcements = 0;
+    let mut buf = [0u8; 1024];
+    while announcements < 3 {
+        assert!(start.elapsed() < Duration::from_secs(1), "Only {} announcements received", announcements);
+        if let Ok(size) = listener.recv(&mut buf) {
+            if buf[..size].starts_with(announcement.as_bytes()) {
+                announcements += 1;
+            }
+        }
+    }
+    drop(listener);
+    
+    // A hub started afterwards discovers it from its next announcement
+    let transport1 = start_hub(hub1);
+    let request = || ApiRequest {
+        path: "/discovered/data".to_string(),
+        data: Box::new(()),
+        metadata: HashMap::new(),
+        sender_id: "test-client".to_string(),
+    };
+    
+    let start = Instant::now();
+    let response = loop {
+        if let Ok(response) = transport1.request_any(request(), Duration::from_secs(1)) {
+            break response;
+        }
+        assert!(start.elapsed() < Duration::from_secs(2), "Second hub was not discovered");
+        thread::sleep(Duration::from_millis(50));
+    };
+    assert_eq!(response.status, ResponseStatus::Success);
+    assert_eq!(response.data.downcast_ref::<String>(), Some(&"Data from the discovered hub".to_string()));
+}
+
+/// Test that two transports in one process share the discovery port and discover each other
+#[test]
+fn test_discovery_shared_port() {
+    let cert_dir = tempfile::tempdir().unwrap();
+    let tls_config = generate_test_certs(cert_dir.path());
+    let discovery_port = UdpSocket::bind("0.0.0.0:0").unwrap().local_addr().unwrap().port();
+    
+    let transports: Vec<_> = (0..2)
+        .map(|i| {
+            let hub = Arc::new(Hub::new(HubScope::Network));
+            hub.register_api(&format!("/shared/hub{}", i), move |_: &ApiRequest| {
+                ApiResponse {
+                    data: Box::new(format!("Data from hub {}", i)),
+                    metadata: HashMap::new(),
+                    status: ResponseStatus::Success,
+                }
+            }, HashMap::new());
+            
+            let transport = NetworkTransport::new(hub, SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config.clone());
+            transport.set_discovery_port(discovery_port);
+            transport.set_discovery_interval(Duration::from_millis(100));
+            
+            start_transport(&transport);
+            transport
+        })
+        .collect();
+    
+    // Each transport reaches the other's API through the peer it discovered
+    for (i, transport) in transports.iter().enumerate() {
+        let other = 1 - i;
+        let start = Instant::now();
+        let response = loop {
+            let request = ApiRequest {
+                path: format!("/shared/hub{}", other),
+                data: Box::new(()),
+                metadata: HashMap::new(),
+                sender_id: "test-client".to_string(),
+            };
+            if let Ok(response) = transport.request_any(request, Duration::from_secs(1)) {
+                break response;
+            }
+            assert!(start.elapsed() < Duration::from_secs(3), "Hub {} did not discover hub {}", i, other);
+            thread::sleep(Duration::from_millis(50));
+        };
+        assert_eq!(response.status, ResponseStatus::Success);
+        assert_eq!(response.data.downcast_ref::<String>(), Some(&format!("Data from hub {}", other)));
+    }
+}
+
+/// Whether multicast to the IPv6 all-nodes group reaches this machine, which needs
+/// a multicast-capable interface
+fn ipv6_multicast_supported() -> bool {
+    let group = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1);
+    let probe = || -> std::io::Result<bool> {
+        let receiver = UdpSocket::bind("[::]:0")?;
+        receiver.join_multicast_v6(&group, 0)?;
+        receiver.set_read_timeout(Some(Duration::from_millis(200)))?;
+        let port = receiver.local_addr()?.port();
+        
+        UdpSocket::bind("[::]:0")?.send_to(b"probe", SocketAddr::from((group, port)))?;
+        Ok(receiver.recv(&mut [0u8; 16]).is_ok())
+    };
+    TcpListener::bind("[::1]:0").is_ok() && probe().unwrap_or(false)
+}
+
+/// Test that IPv6 hubs discover each other over multicast
+#[test]
+fn test_discovery_ipv6_multicast() {
+    if !ipv6_multicast_supported() {
+        println!("Skipping test: IPv6 multicast is not supported here");
+        return;
+    }
+    
+    let cert_dir = tempfile::tempdir().unwrap();
+    let tls_config = generate_test_certs(cert_dir.path());
+    let discovery_port = UdpSocket::bind("[::]:0").unwrap().local_addr().unwrap().port();
+    
+    let hub1 = Arc::new(Hub::new(HubScope::Network));
+    let hub2 = Arc::new(Hub::new(HubScope::Network));
+    hub2.register_api("/ipv6/data", |_: &ApiRequest| {
+        ApiResponse {
+            data: Box::new("Data over IPv6"),
+            metadata: HashMap::new(),
+            status: ResponseStatus::Success,
+        }
+    }, HashMap::new());
+    
+    let transports: Vec<_> = [hub1, hub2]
+        .into_iter()
+        .map(|hub| {
+            let transport = NetworkTransport::new(hub, SocketAddr::from_str("[::1]:0").unwrap(), tls_config.clone());
+            transport.set_discovery_port(discovery_port);
+            transport.set_discovery_interval(Duration::from_millis(100));
+            
+            start_transport(&transport);
+            transport
+        })
+        .collect();
+    
+    let start = Instant::now();
+    let response = loop {
+        let request = ApiRequest {
+            path: "/ipv6/data".to_string(),
+            data: Box::new(()),
+            metadata: HashMap::new(),
+            sender_id: "test-client".to_string(),
+        };
+        if let Ok(response) = transports[0].request_any(request, Duration::from_secs(1)) {
+            break response;
+        }
+        assert!(start.elapsed() < Duration::from_secs(3), "IPv6 hub was not discovered");
+        thread::sleep(Duration::from_millis(50));
+    };
+    assert_eq!(response.status, ResponseStatus::Success);
+    assert_eq!(response.data.downcast_ref::<String>(), Some(&"Data over IPv6".to_string()));
+}
+
+/// Test that discovery and direct connections skip peers the transport's peer filter refuses
+#[test]
+fn test_peer_filter() {
+    let cert_dir = tempfile::tempdir().unwrap();
+    let tls_config = generate_test_certs(cert_dir.path());
+    let discovery_port = UdpSocket::bind("0.0.0.0:0").unwrap().local_addr().unwrap().port();
+    
+    // Two hubs announcing themselves, one of which the client refuses
+    let servers: Vec<_> = (0..2)
+        .map(|_| {
+            let hub = Arc::new(Hub::new(HubScope::Network));
+            let transport = NetworkTransport::new(Arc::clone(&hub), SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config.clone());
+            transport.set_discovery_port(discovery_port);
+            transport.set_discovery_interval(Duration::from_millis(50));
+            (hub.id.clone(), start_transport(&transport))
+        })
+        .collect();
+    let (denied_id, denied_addr) = servers[0].clone();
+    let (allowed_id, allowed_addr) = servers[1].clone();
+    
+    let client = NetworkTransport::new(Arc::new(Hub::new(HubScope::Network)), SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config);
+    client.set_discovery_port(discovery_port);
+    client.set_discovery_interval(Duration::from_millis(50));
+    client.set_peer_filter(PeerFilter::new().deny(PeerRule::Address(denied_addr)));
+    start_transport(&client);
+    
+    let start = Instant::now();
+    while !client.peer_status().iter().any(|peer| peer.id == allowed_id) {
+        assert!(start.elapsed() < Duration::from_secs(3), "Allowed hub was not discovered");
+        thread::sleep(Duration::from_millis(20));
+    }
+    // Give the denied hub a few more announcements to be connected to
+    thread::sleep(Duration::from_millis(300));
+    assert!(client.peer_status().iter().all(|peer| peer.id != denied_id && peer.address != denied_addr));
+    
+    assert!(matches!(client.connect_to_peer(denied_addr), Err(HubError::PeerNotAllowed(_))));
+    
+    // Hub IDs are checked once the peer has told its ID
+    client.set_peer_filter(PeerFilter::new().allow(PeerRule::HubId(allowed_id.clone())));
+    assert!(matches!(client.connect_to_peer(denied_addr), Err(HubError::PeerNotAllowed(_))));
+    assert_eq!(client.connect_to_peer(allowed_addr).unwrap(), allowed_id);
+    assert!(client.peer_status().iter().all(|peer| peer.id != denied_id));
+    
+    client.shutdown();
+}
+
+/// Test that a hub announced twice at once is connected to only once
+#[test]
+fn test_concurrent_discovery() {
+    let cert_dir = tempfile::tempdir().unwrap();
+    let tls_config = generate_test_certs(cert_dir.path());
+    
+    let server_hub = Arc::new(Hub::new(HubScope::Network));
+    let server = NetworkTransport::new(Arc::clone(&server_hub), SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config.clone());
+    let server_addr = start_transport(&server);
+    
+    let client_addr = SocketAddr::from_str("127.0.0.1:0").unwrap();
+    let client_hub = Arc::new(Hub::new(HubScope::Network));
+    let client = NetworkTransport::new(Arc::clone(&client_hub), client_addr, tls_config);
+    
+    // The same announcement, handled by two threads at once
+    let announcement = format!("HUB{},{},{}", server_hub.id, server_addr, HubScope::Network);
+    let barrier = Arc::new(Barrier::new(2));
+    let handles: Vec<_> = (0..2)
+        .map(|_| {
+            let client = client.clone();
+            let barrier = Arc::clone(&barrier);
+            let announcement = announcement.clone();
+            thread::spawn(move || {
+                barrier.wait();
+                client.handle_announcement(announcement.as_bytes())
+            })
+        })
+        .collect();
+    let connected: Vec<String> = handles.into_iter().filter_map(|handle| handle.join().unwrap()).collect();
+    assert_eq!(connected, vec![server_hub.id.clone()]);
+    assert_eq!(client.peer_status().len(), 1);
+    
+    // Once connected, the hub is ignored however often it's announced
+    assert_eq!(client.handle_announcement(announcement.as_bytes()), None);
+    assert_eq!(client.peer_status().len(), 1);
+    
+    // As are malformed announcements and our own
+    assert_eq!(client.handle_announcement(b"HUB"), None);
+    assert_eq!(client.handle_announcement(format!("HUBother,{},Galaxy", server_addr).as_bytes()), None);
+    assert_eq!(client.handle_announcement(format!("HUB{},{},Network", client_hub.id, client_addr).as_bytes()), None);
+    
+    server.shutdown();
+}
+
+/// Test that peers and HTTP clients share a transport's port, told apart with ALPN
+#[test]
+fn test_alpn_shared_port() {
+    let cert_dir = tempfile::tempdir().unwrap();
+    let tls_config = generate_test_certs(cert_dir.path());
+    
+    let server_hub = Arc::new(Hub::new(HubScope::Network));
+    server_hub.register_api("/http/status", |_: &ApiRequest| ApiResponse {
+        data: Box::new("ok over http".to_string()),
+        metadata: HashMap::new(),
+        status: ResponseStatus::Success,
+    }, HashMap::new());
+    server_hub.register_api("/status", |_: &ApiRequest| ApiResponse {
+        data: Box::new("ok over the hub protocol".to_string()),
+        metadata: HashMap::new(),
+        status: ResponseStatus::Success,
+    }, HashMap::new());
+    let server = NetworkTransport::new(Arc::clone(&server_hub), SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config.clone());
+    let proxy = HttpReverseProxy::new(Arc::clone(&server_hub), SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config.clone());
+    assert!(server.http_proxy().is_none());
+    server.set_http_proxy(Some(proxy));
+    let server_addr = start_transport(&server);
+    
+    // HTTP clients reach the proxy
+    let stream = TcpStream::connect(server_addr).unwrap();
+    let mut tls_stream = create_client_tls_stream_with_alpn(stream, &tls_config, &[b"http/1.1"]).unwrap();
+    tls_stream.write_all(b"GET /status HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+    let mut response = Vec::new();
+    let _ = tls_stream.read_to_end(&mut response);
+    let response = String::from_utf8(response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "Unexpected response: {}", response);
+    assert!(response.ends_with("ok over http"), "Unexpected response: {}", response);
+    
+    // Peers offer the hub protocol
+    let client = NetworkTransport::new(Arc::new(Hub::new(HubScope::Network)), SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config.clone());
+    let peer_id = client.connect_to_peer(server_addr).unwrap();
+    assert_eq!(peer_id, server_hub.id);
+    let response = client.send_request_to_peer(&peer_id, ApiRequest {
+        path: "/status".to_string(),
+        data: Box::new(()),
+        metadata: HashMap::new(),
+        sender_id: "client".to_string(),
+    }).unwrap();
+    assert_eq!(response.as_str(), Some("ok over the hub protocol"));
+    
+    // Clients offering no protocol are taken to be peers, as are those offering the hub protocol explicitly
+    for protocols in [&[][..], &[HUB_ALPN_PROTOCOL][..]] {
+        let stream = TcpStream::connect(server_addr).unwrap();
+        let mut tls_stream = create_client_tls_stream_with_alpn(stream, &tls_config, protocols).unwrap();
+        tls_stream.write_all(&frame(10, &[])).unwrap();
+        assert_eq!(read_frame(&mut tls_stream).0, 11, "Heartbeat not answered offering {:?}", protocols);
+    }
+    
+    // The shared secret only applies to peers, not to HTTP clients of the proxy
+    server.set_auth(Some(AuthConfig::new("mesh-secret")));
+    let stream = TcpStream::connect(server_addr).unwrap();
+    let mut tls_stream = create_client_tls_stream_with_alpn(stream, &tls_config, &[b"http/1.1"]).unwrap();
+    tls_stream.write_all(b"GET /status HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+    let mut response = Vec::new();
+    let _ = tls_stream.read_to_end(&mut response);
+    let response = String::from_utf8(response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "Unexpected response: {}", response);
+    
+    let outsider = NetworkTransport::new(Arc::new(Hub::new(HubScope::Network)), SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config.clone());
+    assert!(matches!(outsider.connect_to_peer(server_addr), Err(HubError::Unauthorized(_))));
+    let stream = TcpStream::connect(server_addr).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut tls_stream = create_client_tls_stream_with_alpn(stream, &tls_config, &[HUB_ALPN_PROTOCOL]).unwrap();
+    tls_stream.write_all(&frame(10, &[])).unwrap();
+    let mut reply = [0u8; 1];
+    assert_eq!(tls_stream.read(&mut reply).unwrap_or(0), 0, "Heartbeat answered without the secret");
+    
+    let member = NetworkTransport::new(Arc::new(Hub::new(HubScope::Network)), SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config.clone());
+    member.set_auth(Some(AuthConfig::new("mesh-secret")));
+    assert_eq!(member.connect_to_peer(server_addr).unwrap(), server_hub.id);
+    assert_eq!(server.transport_stats().auth_failures, 2);
+    
+    server.shutdown();
+}
+
+/// Test that a peer shows as alive after answering a request and stale once idle
+#[test]
+fn test_peer_status() {
+    let cert_dir = tempfile::tempdir().unwrap();
+    let tls_config = generate_test_certs(cert_dir.path());
+    
+    let server_hub = Arc::new(Hub::new(HubScope::Network));
+    server_hub.register_api("/status/ping", |_: &ApiRequest| {
+        ApiResponse {
+            data: Box::new("pong"),
+            metadata: HashMap::new(),
+            status: ResponseStatus::Success,
+        }
+    }, HashMap::new());
+    let server = NetworkTransport::new(server_hub, SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config.clone());
+    let addr = start_transport(&server);
+    
+    let client_hub = Arc::new(Hub::new(HubScope::Network));
+    let client = NetworkTransport::new(Arc::clone(&client_hub), SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config);
+    assert!(client.peer_status().is_empty());
+    let peer_id = client.connect_to_peer(addr).unwrap();
+    
+    let max_idle = Duration::from_millis(100);
+    thread::sleep(max_idle * 2);
+    assert!(!client.peer_status()[0].is_alive(max_idle));
+    
+    // Answering a request refreshes the peer
+    let request = ApiRequest {
+        path: "/status/ping".to_string(),
+        data: Box::new(()),
+        metadata: HashMap::new(),
+        sender_id: client_hub.id.clone(),
+    };
+    let response = client.send_request_to_peer(&peer_id, request).unwrap();
+    assert_eq!(response.status, ResponseStatus::Success);
+    
+    let status = client.peer_status();
+    assert_eq!(status.len(), 1);
+    assert_eq!(status[0].id, peer_id);
+    assert_eq!(status[0].address, addr);
+    assert!(status[0].is_alive(max_idle), "Peer idle for {} ms", status[0].idle_ms);
+    
+    // ...until it has been idle for longer than the window
+    thread::sleep(max_idle * 2);
+    let status = client.peer_status();
+    assert!(!status[0].is_alive(max_idle));
+    assert!(status[0].idle_ms >= 200);
+}
+
+/// Test that a transport counts the requests it serves and the bytes exchanged
+#[test]
+fn test_transport_stats() {
+    let cert_dir = tempfile::tempdir().unwrap();
+    let tls_config = generate_test_certs(cert_dir.path());
+    
+    let server_hub = Arc::new(Hub::new(HubScope::Network));
+    server_hub.register_api("/stats/echo", |_: &ApiRequest| {
+        ApiResponse {
+            data: Box::new("echo".to_string()),
+            metadata: HashMap::new(),
+            status: ResponseStatus::Success,
+        }
+    }, HashMap::new());
+    let server = NetworkTransport::new(server_hub, SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config.clone());
+    let addr = start_transport(&server);
+    
+    let client_hub = Arc::new(Hub::new(HubScope::Network));
+    let client = NetworkTransport::new(Arc::clone(&client_hub), SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config);
+    let peer_id = client.connect_to_peer(addr).unwrap();
+    
+    for _ in 0..2 {
+        let request = ApiRequest {
+            path: "/stats/echo".to_string(),
+            data: Box::new(()),
+            metadata: HashMap::new(),
+            sender_id: client_hub.id.clone(),
+        };
+        let response = client.send_request_to_peer(&peer_id, request).unwrap();
+        assert_eq!(response.status, ResponseStatus::Success);
+    }
+    
+    // The server counts a request once its response is written, which may be just
+    // after the client has read it
+    let start = Instant::now();
+    while server.transport_stats().requests_served < 2 {
+        assert!(start.elapsed() < Duration::from_secs(5), "Requests were not counted");
+        thread::sleep(Duration::from_millis(10));
+    }
+    
+    let stats = server.transport_stats();
+    assert_eq!(stats.requests_served, 2);
+    assert_eq!(stats.deserialize_failures, 0);
+    assert!(stats.bytes_read > 0);
+    assert!(stats.bytes_written > 0);
+    
+    // The client counts the same traffic from its side
+    let traffic = client.transport_stats().peers[&peer_id];
+    assert_eq!(traffic.bytes_sent, stats.bytes_read);
+    assert_eq!(traffic.bytes_received, stats.bytes_written);
+}
+
+/// Test that a malformed request gets an error response instead of leaving the client waiting
+#[test]
+fn test_malformed_request() {
+    let cert_dir = tempfile::tempdir().unwrap();
+    let tls_config = generate_test_certs(cert_dir.path());
+    
+    let server_hub = Arc::new(Hub::new(HubScope::Network));
+    let server = NetworkTransport::new(server_hub, SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config.clone());
+    let addr = start_transport(&server);
+    
+    let stream = TcpStream::connect(addr).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut tls_stream = create_client_tls_stream(stream, &tls_config).unwrap();
+    
+    // API request message type followed by bytes that aren't a request
+    tls_stream.write_all(&frame(1, &[0xde, 0xad, 0xbe, 0xef])).unwrap();
+    
+    let (message_type, payload) = read_frame(&mut tls_stream);
+    assert_eq!(message_type, 2, "Expected an API response");
+    let response: serde_json::Value = serde_json::from_slice(&payload).unwrap();
+    assert_eq!(response["Response"]["status"], 5);
+    
+    // The connection stays usable
+    tls_stream.write_all(&frame(10, &[])).unwrap();
+    assert_eq!(read_frame(&mut tls_stream).0, 11);
+    
+    assert_eq!(server.transport_stats().deserialize_failures, 1);
+    assert_eq!(server.transport_stats().requests_served, 0);
+}
+
+/// Test that only peers knowing the shared secret can connect and make requests
+#[test]
+fn test_shared_secret() {
+    let cert_dir = tempfile::tempdir().unwrap();
+    let tls_config = generate_test_certs(cert_dir.path());
+    
+    let server_hub = Arc::new(Hub::new(HubScope::Network));
+    let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let counter = Arc::clone(&calls);
+    server_hub.register_api("/mesh/data", move |_: &ApiRequest| {
+        counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        ApiResponse {
+            data: Box::new("Data for members".to_string()),
+            metadata: HashMap::new(),
+            status: ResponseStatus::Success,
+        }
+    }, HashMap::new());
+    let server = NetworkTransport::new(server_hub, SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config.clone());
+    server.set_auth(Some(AuthConfig::new("mesh-secret")));
+    let addr = start_transport(&server);
+    
+    let client = |auth: Option<AuthConfig>| {
+        let transport = NetworkTransport::new(Arc::new(Hub::new(HubScope::Network)), SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config.clone());
+        transport.set_auth(auth);
+        transport
+    };
+    let request = || ApiRequest {
+        path: "/mesh/data".to_string(),
+        data: Box::new(()),
+        metadata: HashMap::new(),
+        sender_id: "test-client".to_string(),
+    };
+    
+    // The right secret is accepted
+    let member = client(Some(AuthConfig::new("mesh-secret")));
+    let peer_id = member.connect_to_peer(addr).unwrap();
+    let response = member.send_request_to_peer(&peer_id, request()).unwrap();
+    assert_eq!(response.data.downcast_ref::<String>().unwrap(), "Data for members");
+    
+    // A wrong or missing secret is refused
+    let outsider = client(Some(AuthConfig::new("guess")));
+    assert!(matches!(outsider.connect_to_peer(addr), Err(HubError::Unauthorized(_))));
+    assert!(outsider.peer_status().is_empty());
+    assert!(matches!(client(None).connect_to_peer(addr), Err(HubError::Unauthorized(_))));
+    
+    // A connection sending a request without saying hello is closed unanswered
+    let stream = TcpStream::connect(addr).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut tls_stream = create_client_tls_stream(stream, &tls_config).unwrap();
+    let request = serde_json::json!({
+        "Request": { "path": "/mesh/data", "data": "", "metadata": {}, "sender_id": "intruder" }
+    });
+    tls_stream.write_all(&frame(1, &serde_json::to_vec(&request).unwrap())).unwrap();
+    let mut buffer = [0u8; 1024];
+    assert_eq!(tls_stream.read(&mut buffer).unwrap_or(0), 0);
+    
+    assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+    assert_eq!(server.transport_stats().auth_failures, 3);
+    assert!(format!("{:?}", server.auth()).contains("<redacted>"));
+}
+
+/// Test that typed request and response data survive the trip to a peer
+#[test]
+fn test_serde_request() {
+    let cert_dir = tempfile::tempdir().unwrap();
+    let tls_config = generate_test_certs(cert_dir.path());
+    
+    let server_hub = Arc::new(Hub::new(HubScope::Network));
+    server_hub.register_api("/math/add", |request: &ApiRequest| {
+        match SerdePayload::decode_data::<(i32, i32)>(&*request.data) {
+            Some((a, b)) => ApiResponse {
+                data: Box::new(SerdePayload::new(&(a + b))),
+                metadata: HashMap::new(),
+                status: ResponseStatus::Success,
+            },
+            None => ApiResponse {
+                data: Box::new("Expected two numbers".to_string()),
+                metadata: HashMap::new(),
+                status: ResponseStatus::Error,
+            },
+        }
+    }, HashMap::new());
+    let server = NetworkTransport::new(server_hub, SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config.clone());
+    let addr = start_transport(&server);
+    
+    let client_hub = Arc::new(Hub::new(HubScope::Network));
+    let client = NetworkTransport::new(Arc::clone(&client_hub), SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config);
+    let peer_id = client.connect_to_peer(addr).unwrap();
+    
+    let request = SerdeRequest::new("/math/add", (3, 4)).sender(&client_hub.id);
+    let response = client.send_request_to_peer(&peer_id, request).unwrap();
+    assert_eq!(response.status, ResponseStatus::Success);
+    assert_eq!(SerdePayload::decode_data::<i32>(&*response.data), Some(7));
+    
+    // A payload of another type isn't mistaken for the expected one
+    let request = SerdeRequest::new("/math/add", (3i64, 4i64)).sender(&client_hub.id);
+    let response = client.send_request_to_peer(&peer_id, request).unwrap();
+    assert_eq!(response.status, ResponseStatus::Error);
+    assert_eq!(response.data.downcast_ref::<String>().map(String::as_str), Some("Expected two numbers"));
+}
+
+/// Test that messages published to a peer reach its subscribers
+#[test]
+fn test_remote_message_delivery() {
+    let cert_dir = tempfile::tempdir().unwrap();
+    let tls_config = generate_test_certs(cert_dir.path());
+    
+    let server_hub = Arc::new(Hub::new(HubScope::Network));
+    let (tx, rx) = std::sync::mpsc::channel();
+    let tx = std::sync::Mutex::new(tx);
+    server_hub.subscribe("alerts", move |message| {
+        let data = message.data.downcast_ref::<String>().cloned().unwrap_or_default();
+        let _ = tx.lock().unwrap().send((data, message.metadata.get(TTL_KEY).cloned()));
+        None
+    }, 0);
+    let server = NetworkTransport::new(server_hub, SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config.clone());
+    let addr = start_transport(&server);
+    
+    let client = NetworkTransport::new(Arc::new(Hub::new(HubScope::Network)), SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config);
+    let peer_id = client.connect_to_peer(addr).unwrap();
+    
+    let metadata = HashMap::from([(TTL_KEY.to_string(), "60000".to_string())]);
+    client.publish_to_peer(&peer_id, "alerts", "overheat".to_string(), metadata).unwrap();
+    
+    let (data, ttl) = rx.recv_timeout(Duration::from_secs(5)).expect("Message was not delivered");
+    assert_eq!(data, "overheat");
+    assert_eq!(ttl.as_deref(), Some("60000"));
+}
+
+/// Test that messages published to a peer with byte data arrive with identical bytes
+#[test]
+fn test_binary_message_delivery() {
+    let cert_dir = tempfile::tempdir().unwrap();
+    let tls_config = generate_test_certs(cert_dir.path());
+    
+    let server_hub = Arc::new(Hub::new(HubScope::Network));
+    let (tx, rx) = std::sync::mpsc::channel();
+    let tx = std::sync::Mutex::new(tx);
+    server_hub.subscribe("blobs", move |message| {
+        let _ = tx.lock().unwrap().send(message.data.downcast_ref::<Vec<u8>>().cloned());
+        None
+    }, 0);
+    let server = NetworkTransport::new(server_hub, SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config.clone());
+    let addr = start_transport(&server);
+    
+    let client = NetworkTransport::new(Arc::new(Hub::new(HubScope::Network)), SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config);
+    let peer_id = client.connect_to_peer(addr).unwrap();
+    
+    // Not valid UTF-8, so it can't travel as text
+    let bytes = vec![0u8, 0xff, b'\n', 0x80, b'"', 0xc3, 0x28, 0x00];
+    client.publish_to_peer(&peer_id, "blobs", bytes.clone(), HashMap::new()).unwrap();
+    
+    let data = rx.recv_timeout(Duration::from_secs(5)).expect("Message was not delivered");
+    assert_eq!(data, Some(bytes));
+    
+    // Far larger than a single read once base64 encoded
+    let bytes: Vec<u8> = (0..64 * 1024).map(|i| (i % 251) as u8).collect();
+    client.publish_to_peer(&peer_id, "blobs", bytes.clone(), HashMap::new()).unwrap();
+    
+    let data = rx.recv_timeout(Duration::from_secs(5)).expect("Large message was not delivered");
+    assert_eq!(data, Some(bytes));
+}
+
+/// Test that shutting a transport down stops its listener and discovery announcements
+#[test]
+fn test_shutdown_stops_discovery() {
+    let cert_dir = tempfile::tempdir().unwrap();
+    let tls_config = generate_test_certs(cert_dir.path());
+    
+    // Count announcements on a port of our own
+    let counter = UdpSocket::bind("0.0.0.0:0").unwrap();
+    let discovery_port = counter.local_addr().unwrap().port();
+    counter.set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+    
+    let hub = Arc::new(Hub::new(HubScope::Network));
+    let announcement = format!("HUB{},", hub.id);
+    let transport = NetworkTransport::new(hub, SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config);
+    transport.set_discovery_port(discovery_port);
+    transport.set_discovery_interval(Duration::from_millis(50));
+    
+    // Started by hand rather than with `start_transport`, to check what `start` returns
+    let (stopped_tx, stopped_rx) = std::sync::mpsc::channel();
+    let server = transport.clone();
+    thread::spawn(move || {
+        let _ = stopped_tx.send(server.start());
+    });
+    let start = Instant::now();
+    let addr = loop {
+        if let Some(address) = transport.listen_addresses().first() {
+            break *address;
+        }
+        assert!(start.elapsed() < Duration::from_secs(5), "Transport did not start");
+        thread::sleep(Duration::from_millis(20));
+    };
+    
+    let count_announcements = |duration: Duration| {
+        let start = Instant::now();
+        let mut buf = [0u8; 1024];
+        let mut announcements = 0;
+        while start.elapsed() < duration {
+            if let Ok(size) = counter.recv(&mut buf) {
+                if buf[..size].starts_with(announcement.as_bytes()) {
+                    announcements += 1;
+                }
+            }
+        }
+        announcements
+    };
+    assert!(count_announcements(Duration::from_millis(300)) > 0, "Transport never announced itself");
+    
+    transport.shutdown();
+    let result = stopped_rx.recv_timeout(Duration::from_secs(5)).expect("Transport did not stop");
+    assert!(result.is_ok());
+    assert!(TcpStream::connect(addr).is_err(), "Listener still accepting connections");
+    
+    // Drop announcements sent before the shutdown, then expect silence
+    counter.set_nonblocking(true).unwrap();
+    while counter.recv(&mut [0u8; 1024]).is_ok() {}
+    counter.set_nonblocking(false).unwrap();
+    assert_eq!(count_announcements(Duration::from_millis(300)), 0);
+}
+
+/// Test that hub connections are opened with TCP_NODELAY, on a listener with an explicit backlog
+#[test]
+fn test_tcp_nodelay() {
+    let listener = bind_listener("127.0.0.1:0".parse().unwrap(), 16).unwrap();
+    let addr = listener.local_addr().unwrap();
+    
+    let stream = connect_tcp(addr).unwrap();
+    assert!(socket2::SockRef::from(&stream).nodelay().unwrap());
+    
+    // A plain connection doesn't have it, so the check above is meaningful
+    assert!(!socket2::SockRef::from(&TcpStream::connect(addr).unwrap()).nodelay().unwrap());
+    
+    let transport = NetworkTransport::new(Arc::new(Hub::new(HubScope::Network)), addr, TlsConfig::default());
+    assert_eq!(transport.listen_backlog(), DEFAULT_LISTEN_BACKLOG);
+    transport.set_listen_backlog(64);
+    assert_eq!(transport.listen_backlog(), 64);
+}
+
+/// Test that a peer whose connection drops is reconnected under the same ID once its listener is back
+#[test]
+fn test_peer_reconnects() {
+    let cert_dir = tempfile::tempdir().unwrap();
+    let tls_config = generate_test_certs(cert_dir.path());
+    
+    let policy = ReconnectPolicy {
+        max_retries: 50,
+        base_delay: Duration::from_millis(20),
+        max_delay: Duration::from_millis(100),
+    };
+    let delays: Vec<_> = (0..5).map(|attempt| policy.delay(attempt).as_millis()).collect();
+    assert_eq!(delays, [20, 40, 80, 100, 100]);
+    
+    let server_hub = Arc::new(Hub::new(HubScope::Network));
+    server_hub.register_api("/reconnect/ping", |_: &ApiRequest| {
+        ApiResponse {
+            data: Box::new("pong"),
+            metadata: HashMap::new(),
+            status: ResponseStatus::Success,
+        }
+    }, HashMap::new());
+    let server = NetworkTransport::new(server_hub, SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config.clone());
+    // Close the accepted connection soon after the listener is shut down
+    server.set_idle_timeout(Duration::from_millis(200));
+    let addr = start_transport(&server);
+    
+    let client_hub = Arc::new(Hub::new(HubScope::Network));
+    let client = NetworkTransport::new(Arc::clone(&client_hub), SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config);
+    assert_eq!(client.reconnect_policy(), None);
+    client.set_reconnect_policy(Some(policy));
+    let peer_id = client.connect_to_peer(addr).unwrap();
+    
+    let ping = || ApiRequest {
+        path: "/reconnect/ping".to_string(),
+        data: Box::new(()),
+        metadata: HashMap::new(),
+        sender_id: client_hub.id.clone(),
+    };
+    assert_eq!(client.send_request_to_peer(&peer_id, ping()).unwrap().status, ResponseStatus::Success);
+    
+    // Drop the listener and wait for the idle connection to be closed
+    server.shutdown();
+    thread::sleep(Duration::from_millis(400));
+    assert!(client.send_request_to_peer(&peer_id, ping()).is_err());
+    
+    // Attempts fail while the listener is down, then succeed once it's back on the same port
+    thread::sleep(Duration::from_millis(200));
+    let running = server.clone();
+    thread::spawn(move || running.start_multi(&[addr]).unwrap());
+    
+    let start = Instant::now();
+    loop {
+        if let Ok(response) = client.send_request_to_peer(&peer_id, ping()) {
+            assert_eq!(response.status, ResponseStatus::Success);
+            break;
+        }
+        assert!(start.elapsed() < Duration::from_secs(5), "Peer did not reconnect");
+        thread::sleep(Duration::from_millis(50));
+    }
+    
+    let status = client.peer_status();
+    assert_eq!(status.len(), 1);
+    assert_eq!(status[0].id, peer_id);
+    assert_eq!(status[0].address, addr);
+}
+
+/// Test that a hub subscribed to a peer's topics receives the messages published there
+#[test]
+fn test_remote_subscription() {
+    let cert_dir = tempfile::tempdir().unwrap();
+    let tls_config = generate_test_certs(cert_dir.path());
+    
+    let hub_b = Arc::new(Hub::new(HubScope::Network));
+    let server = NetworkTransport::new(Arc::clone(&hub_b), SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config.clone());
+    let addr = start_transport(&server);
+    
+    let hub_a = Arc::new(Hub::new(HubScope::Network));
+    let transport_a = NetworkTransport::new(Arc::clone(&hub_a), SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config);
+    let peer_id = transport_a.connect_to_peer(addr).unwrap();
+    assert!(transport_a.subscribe_remote("peer-unknown", "events/*", |_| {}).is_err());
+    
+    let (tx, rx) = std::sync::mpsc::channel();
+    let subscription = transport_a.subscribe_remote(&peer_id, "events/*", move |message| {
+        let _ = tx.send((message.topic.clone(), message.data.clone(), message.sender_id.clone()));
+    }).unwrap();
+    assert_eq!(hub_b.subscribed_patterns(), vec!["events/*"]);
+    
+    // Only matching messages with string data are forwarded, in the order they're published
+    hub_b.publish::<_, ()>("events/started", "first".to_string(), HashMap::new());
+    hub_b.publish::<_, ()>("alerts/overheat", "unrelated".to_string(), HashMap::new());
+    hub_b.publish::<_, ()>("events/count", 3, HashMap::new());
+    hub_b.publish::<_, ()>("events/stopped", "second", HashMap::new());
+    
+    let received: Vec<_> = (0..2)
+        .map(|_| rx.recv_timeout(Duration::from_secs(5)).expect("Message was not forwarded"))
+        .collect();
+    assert_eq!(received, vec![
+        ("events/started".to_string(), "first".to_string(), hub_b.id.clone()),
+        ("events/stopped".to_string(), "second".to_string(), hub_b.id.clone()),
+    ]);
+    
+    // Requests to the peer still get their answers over the peer connection
+    let request = ApiRequest {
+        path: "/missing".to_string(),
+        data: Box::new(()),
+        metadata: HashMap::new(),
+        sender_id: hub_a.id.clone(),
+    };
+    assert_eq!(transport_a.send_request_to_peer(&peer_id, request).unwrap().status, ResponseStatus::NotFound);
+    
+    // Messages with byte data reach binary subscriptions with identical bytes, however large
+    let (binary_tx, binary_rx) = std::sync::mpsc::channel();
+    let binary_subscription = transport_a.subscribe_remote_binary(&peer_id, "events/*", move |message| {
+        let _ = binary_tx.send((message.topic.clone(), message.data.clone()));
+    }).unwrap();
+    let bytes: Vec<u8> = (0..64 * 1024).map(|i| (i % 251) as u8).collect();
+    hub_b.publish::<_, ()>("events/snapshot", bytes.clone(), HashMap::new());
+    hub_b.publish::<_, ()>("events/label", "text".to_string(), HashMap::new());
+    
+    let received = binary_rx.recv_timeout(Duration::from_secs(5)).expect("Binary message was not forwarded");
+    assert_eq!(received, ("events/snapshot".to_string(), bytes));
+    assert_eq!(rx.recv_timeout(Duration::from_secs(5)).unwrap().1, "text");
+    assert!(binary_rx.recv_timeout(Duration::from_millis(300)).is_err());
+    assert!(transport_a.unsubscribe_remote(&binary_subscription));
+    
+    assert!(transport_a.unsubscribe_remote(&subscription));
+    assert!(!transport_a.unsubscribe_remote(&subscription));
+    hub_b.publish::<_, ()>("events/restarted", "third".to_string(), HashMap::new());
+    assert!(rx.recv_timeout(Duration::from_millis(300)).is_err());
+}
+
+/// Test that connecting to a peer exchanges hub IDs and scopes, keying the peer by its hub's ID
+#[test]
+fn test_peer_handshake() {
+    let cert_dir = tempfile::tempdir().unwrap();
+    let tls_config = generate_test_certs(cert_dir.path());
+    
+    let server_hub = Arc::new(Hub::new(HubScope::Network));
+    let server = NetworkTransport::new(Arc::clone(&server_hub), SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config.clone());
+    let addr = start_transport(&server);
+    
+    let client = NetworkTransport::new(Arc::new(Hub::new(HubScope::Machine)), SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config);
+    let peer_id = client.connect_to_peer(addr).unwrap();
+    assert_eq!(peer_id, server_hub.id);
+    assert_ne!(peer_id, format!("peer-{}", addr));
+    
+    let status = client.peer_status();
+    assert_eq!(status.len(), 1);
+    assert_eq!(status[0].id, server_hub.id);
+    assert_eq!(status[0].scope, Some(HubScope::Network));
+    
+    // Connecting again replaces the connection to the same hub
+    assert_eq!(client.connect_to_peer(addr).unwrap(), server_hub.id);
+    assert_eq!(client.peer_status().len(), 1);
+}
+
+/// Test that large frames are compressed when both hubs agree to it
+#[test]
+fn test_frame_compression() {
+    let cert_dir = tempfile::tempdir().unwrap();
+    let tls_config = generate_test_certs(cert_dir.path());
+    
+    let server_hub = Arc::new(Hub::new(HubScope::Network));
+    server_hub.register_api("/echo", |request: &ApiRequest| {
+        ApiResponse {
+            data: Box::new(request.data.downcast_ref::<String>().cloned().unwrap_or_default()),
+            metadata: HashMap::new(),
+            status: ResponseStatus::Success,
+        }
+    }, HashMap::new());
+    let server = NetworkTransport::new(Arc::clone(&server_hub), SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config.clone());
+    assert!(server.compression());
+    let server_stats = server.clone();
+    let addr = start_transport(&server);
+    
+    // Far larger than a single read when uncompressed, both ways
+    let payload = "network hub payload ".repeat(2000);
+    let client = NetworkTransport::new(Arc::new(Hub::new(HubScope::Machine)), SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config.clone());
+    let peer_id = client.connect_to_peer(addr).unwrap();
+    let response = client.send_request_to_peer(&peer_id, ApiRequest {
+        path: "/echo".to_string(),
+        data: Box::new(payload.clone()),
+        metadata: HashMap::new(),
+        sender_id: "test".to_string(),
+    }).unwrap();
+    assert_eq!(response.status, ResponseStatus::Success);
+    assert_eq!(response.data.downcast_ref::<String>(), Some(&payload));
+    
+    let traffic = client.transport_stats().peers[&peer_id];
+    assert!(traffic.bytes_sent < payload.len() as u64 / 10, "Request not compressed: {} bytes", traffic.bytes_sent);
+    assert!(traffic.bytes_received < payload.len() as u64 / 10, "Response not compressed: {} bytes", traffic.bytes_received);
+    assert!(server_stats.transport_stats().bytes_written < payload.len() as u64 / 10);
+    
+    // Payloads that don't compress well still arrive whole, however many reads they take
+    let payload: String = (0..1000).map(|_| uuid::Uuid::new_v4().to_string()).collect();
+    let response = client.send_request_to_peer(&peer_id, ApiRequest {
+        path: "/echo".to_string(),
+        data: Box::new(payload.clone()),
+        metadata: HashMap::new(),
+        sender_id: "test".to_string(),
+    }).unwrap();
+    assert_eq!(response.data.downcast_ref::<String>(), Some(&payload));
+    
+    // A hub that doesn't want compression sends and receives frames as they are
+    let payload = "network hub payload ".repeat(1000);
+    let plain_client = NetworkTransport::new(Arc::new(Hub::new(HubScope::Machine)), SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config);
+    plain_client.set_compression(false);
+    let peer_id = plain_client.connect_to_peer(addr).unwrap();
+    let response = plain_client.send_request_to_peer(&peer_id, ApiRequest {
+        path: "/echo".to_string(),
+        data: Box::new(payload.clone()),
+        metadata: HashMap::new(),
+        sender_id: "test".to_string(),
+    }).unwrap();
+    assert_eq!(response.status, ResponseStatus::Success);
+    assert_eq!(response.data.downcast_ref::<String>(), Some(&payload));
+    
+    let traffic = plain_client.transport_stats().peers[&peer_id];
+    assert!(traffic.bytes_sent > payload.len() as u64);
+    assert!(traffic.bytes_received > payload.len() as u64);
+    
+    // Frames larger than the limit close the connection rather than being read
+    let too_large = "x".repeat(2 * 1024 * 1024);
+    let result = plain_client.send_request_to_peer(&peer_id, ApiRequest {
+        path: "/echo".to_string(),
+        data: Box::new(too_large),
+        metadata: HashMap::new(),
+        sender_id: "test".to_string(),
+    });
+    assert!(result.is_err());
+}
+
+/// Test that compressed frames are only decompressed once agreed to, and only up to a limit
+#[test]
+fn test_compressed_frame_limits() {
+    let cert_dir = tempfile::tempdir().unwrap();
+    let tls_config = generate_test_certs(cert_dir.path());
+    
+    let server = NetworkTransport::new(Arc::new(Hub::new(HubScope::Network)), SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config.clone());
+    let addr = start_transport(&server);
+    
+    // Megabytes of zeros, compressed into a request frame of a few kilobytes
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(&vec![0u8; 4 * 1024 * 1024]).unwrap();
+    let bomb = frame(1 | 0x80, &encoder.finish().unwrap());
+    assert!(bomb.len() < 8192);
+    
+    let stream = TcpStream::connect(addr).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut tls_stream = create_client_tls_stream(stream, &tls_config).unwrap();
+    let send_bomb = |tls_stream: &mut TlsStream| {
+        tls_stream.write_all(&bomb).unwrap();
+        // Dropped without an answer, so the connection still answers heartbeats
+        thread::sleep(Duration::from_millis(100));
+        tls_stream.write_all(&frame(10, &[])).unwrap();
+        assert_eq!(read_frame(tls_stream).0, 11);
+    };
+    
+    // Before a hello asks for compression, compressed frames aren't decompressed at all
+    send_bomb(&mut tls_stream);
+    assert_eq!(server.transport_stats().deserialize_failures, 1);
+    
+    // Once it has, frames decompressing to more than the limit are refused too
+    let hello = br#"{"Hello":{"hub_id":"raw-client","scope":"Network","compression":true}}"#;
+    tls_stream.write_all(&frame(6, hello)).unwrap();
+    assert_eq!(read_frame(&mut tls_stream).0, 7);
+    send_bomb(&mut tls_stream);
+    assert_eq!(server.transport_stats().deserialize_failures, 2);
+}
+
+/// Test that a request to a peer that never answers times out without a thread of its own
+#[test]
+fn test_peer_request_timeout() {
+    let cert_dir = tempfile::tempdir().unwrap();
+    let tls_config = generate_test_certs(cert_dir.path());
+    
+    let server_hub = Arc::new(Hub::new(HubScope::Network));
+    server_hub.register_api("/hang", |_: &ApiRequest| {
+        thread::sleep(Duration::from_secs(10));
+        ApiResponse {
+            data: Box::new("Too late".to_string()),
+            metadata: HashMap::new(),
+            status: ResponseStatus::Success,
+        }
+    }, HashMap::new());
+    let server = NetworkTransport::new(Arc::clone(&server_hub), SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config.clone());
+    let addr = start_transport(&server);
+    
+    let client = NetworkTransport::new(Arc::new(Hub::new(HubScope::Machine)), SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config);
+    assert_eq!(client.request_timeout(), Duration::from_secs(30));
+    client.set_request_timeout(Duration::from_millis(300));
+    let peer_id = client.connect_to_peer(addr).unwrap();
+    
+    let start = Instant::now();
+    let result = client.send_request_to_peer(&peer_id, ApiRequest {
+        path: "/hang".to_string(),
+        data: Box::new(()),
+        metadata: HashMap::new(),
+        sender_id: "test".to_string(),
+    });
+    let elapsed = start.elapsed();
+    assert!(matches!(result, Err(HubError::Timeout(_))), "Unexpected result: {:?}", result.map(|r| r.status));
+    assert!(elapsed >= Duration::from_millis(300) && elapsed < Duration::from_secs(2), "Timed out after {:?}", elapsed);
+}
@@ -1,15 +1,26 @@
 //! Tests for the HTTP reverse proxy functionality
 
 use std::collections::HashMap;
-use std::net::SocketAddr;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
 use std::str::FromStr;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
 
-use network_hub::{Hub, HubScope, HttpReverseProxy, TlsConfig, ApiRequest, ResponseStatus};
+use network_hub::{Hub, HubScope, HttpReverseProxy, TlsConfig, ApiRequest, ApiResponse, ResponseStatus};
+use network_hub::error::HubError;
+use network_hub::proxy::{AccessLog, HttpRequestParts, HttpResponseParts};
+use network_hub::transport::{create_client_config, create_client_tls_stream};
+use flate2::read::GzDecoder;
+use tracing_test::traced_test;
 
-/// Test proxy route configuration - this test passes because http_tests is mocking the response
-/// To make this test pass, update assert_eq!(response.status, ResponseStatus::NotFound) to match
-/// what's actually happening, or fix the implementation
+mod common;
+use common::generate_test_certs;
+
+/// Test proxy route configuration - requests under `/http/` reach the proxy's wildcard API
+/// and are forwarded to the configured route target
 #[test]
 fn test_proxy_route_configuration() {
     // Create a hub
@@ -83,12 +94,9 @@ fn test_proxy_route_configuration() {
         println!("Response is not a String");
     }
     
-    // For this test to pass until we fix the implementation:
-    assert_eq!(response.status, ResponseStatus::NotFound, "Expected NotFound response status");
-    
-    println!("\nNOTE: This test passes with NotFound status because the proxy implementation");
-    println!("has a placeholder that doesn't match the test expectations. Fix either the test");
-    println!("or the implementation according to your requirements.");
+    // The `/http/*` API matches, so the request is forwarded instead of being reported
+    // as NotFound. Whether the target answers depends on network access.
+    assert_ne!(response.status, ResponseStatus::NotFound, "Request was not routed to the proxy handler");
     
     // Original expectations (commented out for now):
     // assert_eq!(response.status, ResponseStatus::Success, "Response status was wrong");
@@ -99,4 +107,1161 @@ fn test_proxy_route_configuration() {
     // } else {
     //     panic!("Response data is not a String");
     // }
-}
\ No newline at end of file
+}
+
+/// Test that a malformed HTTP request is answered with 400 and logged as an error
+#[test]
+#[traced_test]
+fn test_bad_request_emits_error_event() {
+    let cert_dir = tempfile::tempdir().unwrap();
+    let tls_config = generate_test_certs(cert_dir.path());
+    
+    let hub = Arc::new(Hub::new(HubScope::Network));
+    let bind_addr = SocketAddr::from_str("127.0.0.1:0").unwrap();
+    let proxy = HttpReverseProxy::new(Arc::clone(&hub), bind_addr, tls_config.clone());
+    
+    // Accept the connection on this thread so its events are captured by the test
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    
+    let client = thread::spawn(move || {
+        let stream = TcpStream::connect(addr).unwrap();
+        let mut tls_stream = create_client_tls_stream(stream, &tls_config).unwrap();
+        tls_stream.write_all(b"GARBAGE\r\n\r\n").unwrap();
+        
+        let mut buffer = [0u8; 1024];
+        let size = tls_stream.read(&mut buffer).unwrap();
+        String::from_utf8_lossy(&buffer[..size]).into_owned()
+    });
+    
+    let (stream, _) = listener.accept().unwrap();
+    proxy.handle_connection(stream).unwrap();
+    
+    let response = client.join().unwrap();
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request"), "Unexpected response: {}", response);
+    
+    // The error is logged inside the connection span
+    assert!(logs_contain("ERROR"));
+    assert!(logs_contain("Invalid HTTP request"));
+    assert!(logs_contain("http_connection{client=127.0.0.1"));
+}
+
+/// Reader that produces a fixed number of bytes and records the largest read it served
+struct GeneratedBody {
+    remaining: usize,
+    largest_read: Arc<AtomicUsize>,
+}
+
+impl Read for GeneratedBody {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let size = buf.len().min(self.remaining);
+        buf[..size].fill(b'x');
+        self.remaining -= size;
+        self.largest_read.fetch_max(size, Ordering::SeqCst);
+        Ok(size)
+    }
+}
+
+/// Test that a streamed response body is sent to the client in bounded chunks
+#[test]
+fn test_streamed_response() {
+    const BODY_SIZE: usize = 10 * 1024 * 1024;
+    const MAX_CHUNK: usize = 64 * 1024;
+    
+    let cert_dir = tempfile::tempdir().unwrap();
+    let tls_config = generate_test_certs(cert_dir.path());
+    
+    let hub = Arc::new(Hub::new(HubScope::Network));
+    let largest_read = Arc::new(AtomicUsize::new(0));
+    let handler_largest_read = Arc::clone(&largest_read);
+    hub.register_api("/http/download", move |_: &ApiRequest| {
+        ApiResponse::stream(Box::new(GeneratedBody {
+            remaining: BODY_SIZE,
+            largest_read: Arc::clone(&handler_largest_read),
+        }))
+    }, HashMap::new());
+    
+    let bind_addr = SocketAddr::from_str("127.0.0.1:0").unwrap();
+    let proxy = HttpReverseProxy::new(Arc::clone(&hub), bind_addr, tls_config.clone());
+    
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        proxy.handle_connection(stream).unwrap();
+    });
+    
+    let stream = TcpStream::connect(addr).unwrap();
+    let mut tls_stream = create_client_tls_stream(stream, &tls_config).unwrap();
+    tls_stream.write_all(b"GET /download HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    let mut reader = BufReader::new(tls_stream);
+    
+    // Status line and headers
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    assert_eq!(line.trim_end(), "HTTP/1.1 200 OK");
+    let mut chunked = false;
+    loop {
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        if line.trim_end().is_empty() {
+            break;
+        }
+        if line.to_lowercase().starts_with("transfer-encoding: chunked") {
+            chunked = true;
+        }
+    }
+    assert!(chunked, "Expected a chunked response");
+    
+    // Decode the chunks without holding the whole body
+    let mut received = 0;
+    loop {
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        let size = usize::from_str_radix(line.trim_end(), 16).unwrap();
+        if size == 0 {
+            break;
+        }
+        assert!(size <= MAX_CHUNK, "Chunk of {} bytes exceeds the expected bound", size);
+        
+        let mut chunk = vec![0u8; size];
+        reader.read_exact(&mut chunk).unwrap();
+        assert!(chunk.iter().all(|&b| b == b'x'));
+        received += size;
+        
+        let mut terminator = [0u8; 2];
+        reader.read_exact(&mut terminator).unwrap();
+        assert_eq!(&terminator, b"\r\n");
+    }
+    
+    drop(reader);
+    server.join().unwrap();
+    assert_eq!(received, BODY_SIZE);
+    assert!(largest_read.load(Ordering::SeqCst) <= MAX_CHUNK);
+}
+
+/// Read an HTTP response with a `Content-Length` body, returning the status line,
+/// the headers (with lowercase names) and the raw body
+fn read_raw_http_response<R: BufRead>(reader: &mut R) -> (String, HashMap<String, String>, Vec<u8>) {
+    let mut status_line = String::new();
+    reader.read_line(&mut status_line).unwrap();
+    
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if line.trim_end().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+    
+    let content_length = headers.get("content-length").map_or(0, |value| value.parse().unwrap());
+    let mut body = vec![0u8; content_length];
+    reader.read_exact(&mut body).unwrap();
+    (status_line.trim_end().to_string(), headers, body)
+}
+
+/// Read an HTTP response with a `Content-Length` text body, returning the status line and body
+fn read_http_response<R: BufRead>(reader: &mut R) -> (String, String) {
+    let (status_line, _, body) = read_raw_http_response(reader);
+    (status_line, String::from_utf8(body).unwrap())
+}
+
+/// Test that a request split across several writes is still parsed correctly
+#[test]
+fn test_request_split_across_writes() {
+    let cert_dir = tempfile::tempdir().unwrap();
+    let tls_config = generate_test_certs(cert_dir.path());
+    
+    // Echo the request back so the test can check nothing was lost
+    let hub = Arc::new(Hub::new(HubScope::Network));
+    hub.register_api("/http/echo", |request: &ApiRequest| {
+        let http_request = request.data.downcast_ref::<HttpRequestParts>().unwrap();
+        ApiResponse {
+            data: Box::new(String::from_utf8(http_request.to_bytes()).unwrap()),
+            metadata: HashMap::new(),
+            status: ResponseStatus::Success,
+        }
+    }, HashMap::new());
+    
+    let bind_addr = SocketAddr::from_str("127.0.0.1:0").unwrap();
+    let proxy = HttpReverseProxy::new(Arc::clone(&hub), bind_addr, tls_config.clone());
+    
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        proxy.handle_connection(stream).unwrap();
+    });
+    
+    let stream = TcpStream::connect(addr).unwrap();
+    let mut tls_stream = create_client_tls_stream(stream, &tls_config).unwrap();
+    
+    // Split the request line, the headers and the body across writes
+    tls_stream.write_all(b"POST /ec").unwrap();
+    thread::sleep(Duration::from_millis(100));
+    tls_stream.write_all(b"ho HTTP/1.1\r\nHost: localhost\r\nContent-Length: 10\r\n").unwrap();
+    thread::sleep(Duration::from_millis(100));
+    tls_stream.write_all(b"\r\nhello").unwrap();
+    thread::sleep(Duration::from_millis(100));
+    tls_stream.write_all(b" body").unwrap();
+    
+    let mut reader = BufReader::new(tls_stream);
+    let (status_line, body) = read_http_response(&mut reader);
+    drop(reader);
+    server.join().unwrap();
+    
+    assert_eq!(status_line, "HTTP/1.1 200 OK");
+    assert!(body.starts_with("POST /echo HTTP/1.1\r\n"), "Unexpected body: {}", body);
+    assert!(body.ends_with("\r\n\r\nhello body"), "Unexpected body: {}", body);
+}
+
+/// Test that pipelined requests on one keep-alive connection each get a response
+#[test]
+fn test_keep_alive_pipelined_requests() {
+    let cert_dir = tempfile::tempdir().unwrap();
+    let tls_config = generate_test_certs(cert_dir.path());
+    
+    let hub = Arc::new(Hub::new(HubScope::Network));
+    for name in ["first", "second"] {
+        hub.register_api(&format!("/http/{}", name), move |_: &ApiRequest| {
+            ApiResponse {
+                data: Box::new(format!("{} response", name)),
+                metadata: HashMap::new(),
+                status: ResponseStatus::Success,
+            }
+        }, HashMap::new());
+    }
+    
+    let bind_addr = SocketAddr::from_str("127.0.0.1:0").unwrap();
+    let proxy = HttpReverseProxy::new(Arc::clone(&hub), bind_addr, tls_config.clone());
+    
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        proxy.handle_connection(stream).unwrap();
+    });
+    
+    let stream = TcpStream::connect(addr).unwrap();
+    let mut tls_stream = create_client_tls_stream(stream, &tls_config).unwrap();
+    
+    // Both requests are sent before reading any response
+    tls_stream.write_all(concat!(
+        "GET /first HTTP/1.1\r\nHost: localhost\r\n\r\n",
+        "GET /second HTTP/1.1\r\nHost: localhost\r\n\r\n",
+    ).as_bytes()).unwrap();
+    
+    let mut reader = BufReader::new(tls_stream);
+    assert_eq!(read_http_response(&mut reader), ("HTTP/1.1 200 OK".to_string(), "first response".to_string()));
+    assert_eq!(read_http_response(&mut reader), ("HTTP/1.1 200 OK".to_string(), "second response".to_string()));
+    
+    // The connection stays open until the client asks to close it
+    reader.get_mut().write_all(b"GET /first HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+    assert_eq!(read_http_response(&mut reader).1, "first response");
+    server.join().unwrap();
+}
+
+/// Test that large text responses are gzip-encoded for clients that accept it
+#[test]
+fn test_gzip_response_compression() {
+    let cert_dir = tempfile::tempdir().unwrap();
+    let tls_config = generate_test_certs(cert_dir.path());
+    
+    let original = "The quick brown fox jumps over the lazy dog. ".repeat(1000);
+    let hub = Arc::new(Hub::new(HubScope::Network));
+    let body = original.clone();
+    hub.register_api("/http/text", move |_: &ApiRequest| {
+        ApiResponse {
+            data: Box::new(body.clone()),
+            metadata: HashMap::new(),
+            status: ResponseStatus::Success,
+        }
+    }, HashMap::new());
+    
+    let bind_addr = SocketAddr::from_str("127.0.0.1:0").unwrap();
+    let proxy = HttpReverseProxy::new(Arc::clone(&hub), bind_addr, tls_config.clone());
+    
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server_proxy = proxy.clone();
+    let server = thread::spawn(move || {
+        for _ in 0..2 {
+            let (stream, _) = listener.accept().unwrap();
+            server_proxy.handle_connection(stream).unwrap();
+        }
+    });
+    
+    let request = |compression: bool| {
+        proxy.set_compression(compression);
+        let stream = TcpStream::connect(addr).unwrap();
+        let mut tls_stream = create_client_tls_stream(stream, &tls_config).unwrap();
+        tls_stream.write_all(
+            b"GET /text HTTP/1.1\r\nHost: localhost\r\nAccept-Encoding: gzip, deflate\r\nConnection: close\r\n\r\n"
+        ).unwrap();
+        read_raw_http_response(&mut BufReader::new(tls_stream))
+    };
+    
+    // The body comes back compressed and decompresses to the original
+    let (status_line, headers, body) = request(true);
+    assert_eq!(status_line, "HTTP/1.1 200 OK");
+    assert_eq!(headers.get("content-encoding").map(String::as_str), Some("gzip"));
+    assert!(body.len() < original.len());
+    let mut decompressed = String::new();
+    GzDecoder::new(&body[..]).read_to_string(&mut decompressed).unwrap();
+    assert_eq!(decompressed, original);
+    
+    // With compression disabled the body is sent as-is
+    let (_, headers, body) = request(false);
+    assert!(!headers.contains_key("content-encoding"));
+    assert_eq!(String::from_utf8(body).unwrap(), original);
+    
+    server.join().unwrap();
+}
+
+/// Test that a proxy started on several addresses accepts connections on all of them
+#[test]
+fn test_start_multi_listens_on_all_addresses() {
+    let cert_dir = tempfile::tempdir().unwrap();
+    let tls_config = generate_test_certs(cert_dir.path());
+    
+    let hub = Arc::new(Hub::new(HubScope::Network));
+    hub.register_api("/http/ping", |_: &ApiRequest| {
+        ApiResponse {
+            data: Box::new("pong".to_string()),
+            metadata: HashMap::new(),
+            status: ResponseStatus::Success,
+        }
+    }, HashMap::new());
+    
+    // Pick two free loopback ports
+    let free_address = || TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let addresses = [free_address(), free_address()];
+    
+    let proxy = HttpReverseProxy::new(Arc::clone(&hub), addresses[0], tls_config.clone());
+    thread::spawn(move || proxy.start_multi(&addresses).unwrap());
+    
+    for address in addresses {
+        // Wait for the listener to come up
+        let stream = (0..50)
+            .find_map(|_| TcpStream::connect(address).ok().or_else(|| {
+                thread::sleep(Duration::from_millis(20));
+                None
+            }))
+            .unwrap_or_else(|| panic!("Proxy is not listening on {}", address));
+        
+        let mut tls_stream = create_client_tls_stream(stream, &tls_config).unwrap();
+        tls_stream.write_all(b"GET /ping HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+        let response = read_http_response(&mut BufReader::new(tls_stream));
+        assert_eq!(response, ("HTTP/1.1 200 OK".to_string(), "pong".to_string()));
+    }
+}
+
+/// Test that routes saved to disk are restored into a fresh proxy
+#[test]
+fn test_save_and_load_routes() {
+    let tls_config = TlsConfig {
+        cert_path: "certs/cert.pem".to_string(),
+        key_path: "certs/key.pem".to_string(),
+        ca_path: None,
+    };
+    let bind_addr = SocketAddr::from_str("127.0.0.1:0").unwrap();
+    
+    let proxy = HttpReverseProxy::new(Arc::new(Hub::new(HubScope::Network)), bind_addr, tls_config.clone());
+    proxy.add_route("/api", "https://api.example.com");
+    proxy.add_route("/static/*", "https://static.example.com");
+    
+    let dir = tempfile::tempdir().unwrap();
+    let routes_path = dir.path().join("routes.json");
+    proxy.save_routes(&routes_path).unwrap();
+    
+    let restored = HttpReverseProxy::new(Arc::new(Hub::new(HubScope::Network)), bind_addr, tls_config);
+    assert!(restored.routes().is_empty());
+    restored.load_routes(&routes_path).unwrap();
+    
+    assert_eq!(restored.routes(), proxy.routes());
+}
+
+/// Test that repeated requests for a path reuse the resolved route until the routes change
+#[test]
+fn test_route_cache() {
+    let tls_config = TlsConfig {
+        cert_path: "certs/cert.pem".to_string(),
+        key_path: "certs/key.pem".to_string(),
+        ca_path: None,
+    };
+    let hub = Arc::new(Hub::new(HubScope::Network));
+    let proxy = HttpReverseProxy::new(Arc::clone(&hub), SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config);
+    
+    // Point the route at a closed local port; only route resolution matters here
+    let closed_port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    proxy.add_route("/static/*", &format!("http://127.0.0.1:{}", closed_port));
+    
+    let send = || {
+        hub.handle_request(ApiRequest {
+            path: "/http/static/app.css".to_string(),
+            data: Box::new(String::new()),
+            metadata: HashMap::from([
+                ("method".to_string(), "GET".to_string()),
+                ("path".to_string(), "/static/app.css".to_string()),
+            ]),
+            sender_id: "test-client".to_string(),
+        })
+    };
+    
+    send();
+    assert_eq!(proxy.route_scan_count(), 1);
+    
+    // The second request is resolved from the cache
+    send();
+    assert_eq!(proxy.route_scan_count(), 1);
+    
+    // Changing the routes invalidates the cache
+    proxy.add_route("/api", "https://api.example.com");
+    send();
+    assert_eq!(proxy.route_scan_count(), 2);
+}
+
+/// Test that requests sent to the proxy's HTTP handler without a path in their
+/// metadata take it from the request path, however short
+#[test]
+fn test_short_http_paths() {
+    let tls_config = TlsConfig {
+        cert_path: "certs/cert.pem".to_string(),
+        key_path: "certs/key.pem".to_string(),
+        ca_path: None,
+    };
+    let hub = Arc::new(Hub::new(HubScope::Network));
+    let _proxy = HttpReverseProxy::new(Arc::clone(&hub), SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config);
+    
+    let send = |path: &str| {
+        hub.handle_request(ApiRequest {
+            path: path.to_string(),
+            data: Box::new(String::new()),
+            metadata: HashMap::new(),
+            sender_id: "test-client".to_string(),
+        })
+    };
+    
+    for (path, expected) in [("/http/", "/"), ("/http/a", "/a"), ("/http/a/b", "/a/b")] {
+        let response = send(path);
+        assert_eq!(response.status, ResponseStatus::NotFound, "Unexpected status for {}", path);
+        assert_eq!(response.as_str(), Some(format!("No proxy target found for path: {}", expected).as_str()));
+    }
+    
+    // Shorter than the handler's own pattern, so only reaches it by approximation
+    assert_ne!(send("/http").status, ResponseStatus::Error);
+}
+
+/// Test that connections beyond the worker pool's capacity are queued, and turned
+/// away with 503 once the queue is full, without failing any accepted connection
+#[test]
+fn test_worker_pool_saturation() {
+    let cert_dir = tempfile::tempdir().unwrap();
+    let tls_config = generate_test_certs(cert_dir.path());
+    
+    let hub = Arc::new(Hub::new(HubScope::Network));
+    hub.register_api("/http/ping", |_: &ApiRequest| {
+        ApiResponse {
+            data: Box::new("pong".to_string()),
+            metadata: HashMap::new(),
+            status: ResponseStatus::Success,
+        }
+    }, HashMap::new());
+    
+    let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let proxy = HttpReverseProxy::new(Arc::clone(&hub), addr, tls_config.clone());
+    proxy.set_worker_threads(2);
+    thread::spawn(move || proxy.start().unwrap());
+    
+    let connect = || {
+        (0..50)
+            .find_map(|_| TcpStream::connect(addr).ok().or_else(|| {
+                thread::sleep(Duration::from_millis(20));
+                None
+            }))
+            .expect("Proxy is not listening")
+    };
+    let ping = |stream: TcpStream, keep_alive: bool| {
+        let mut tls_stream = create_client_tls_stream(stream, &tls_config).unwrap();
+        let connection = if keep_alive { "keep-alive" } else { "close" };
+        write!(tls_stream, "GET /ping HTTP/1.1\r\nHost: localhost\r\nConnection: {}\r\n\r\n", connection).unwrap();
+        let mut reader = BufReader::new(tls_stream);
+        let (status_line, _) = read_http_response(&mut reader);
+        (status_line, reader)
+    };
+    
+    // Keep-alive connections occupy both workers
+    let busy: Vec<_> = (0..2)
+        .map(|_| {
+            let (status_line, reader) = ping(connect(), true);
+            assert_eq!(status_line, "HTTP/1.1 200 OK");
+            reader
+        })
+        .collect();
+    
+    // These wait in the queue for a free worker
+    let queued: Vec<_> = (0..2).map(|_| connect()).collect();
+    thread::sleep(Duration::from_millis(100));
+    
+    // With every worker busy and the queue full, the next connection is turned away
+    assert_eq!(ping(connect(), false).0, "HTTP/1.1 503 Service Unavailable");
+    
+    // Once the workers are free, the queued connections are served normally
+    drop(busy);
+    for stream in queued {
+        assert_eq!(ping(stream, false).0, "HTTP/1.1 200 OK");
+    }
+}
+
+/// Test that request bodies over the maximum size are rejected before they are read
+#[test]
+fn test_max_body_size() {
+    let cert_dir = tempfile::tempdir().unwrap();
+    let tls_config = generate_test_certs(cert_dir.path());
+    
+    let hub = Arc::new(Hub::new(HubScope::Network));
+    hub.register_api("/http/upload", |request: &ApiRequest| {
+        let length = request.data.downcast_ref::<String>().map(|raw| raw.len()).unwrap_or_default();
+        ApiResponse {
+            data: Box::new(format!("received {} bytes", length)),
+            metadata: HashMap::new(),
+            status: ResponseStatus::Success,
+        }
+    }, HashMap::new());
+    
+    let bind_addr = SocketAddr::from_str("127.0.0.1:0").unwrap();
+    let proxy = HttpReverseProxy::new(Arc::clone(&hub), bind_addr, tls_config.clone());
+    proxy.set_max_body_size(1024);
+    assert_eq!(proxy.max_body_size(), 1024);
+    
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        for _ in 0..2 {
+            let (stream, _) = listener.accept().unwrap();
+            proxy.handle_connection(stream).unwrap();
+        }
+    });
+    
+    // A body just under the limit is handled
+    let stream = TcpStream::connect(addr).unwrap();
+    let mut tls_stream = create_client_tls_stream(stream, &tls_config).unwrap();
+    let body = "x".repeat(1023);
+    tls_stream.write_all(format!(
+        "POST /upload HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body,
+    ).as_bytes()).unwrap();
+    let mut reader = BufReader::new(tls_stream);
+    let (status_line, response_body) = read_http_response(&mut reader);
+    assert_eq!(status_line, "HTTP/1.1 200 OK");
+    assert!(response_body.starts_with("received "), "Unexpected body: {}", response_body);
+    drop(reader);
+    
+    // A body just over the limit is rejected without the body being sent
+    let stream = TcpStream::connect(addr).unwrap();
+    let mut tls_stream = create_client_tls_stream(stream, &tls_config).unwrap();
+    tls_stream.write_all(b"POST /upload HTTP/1.1\r\nHost: localhost\r\nContent-Length: 1025\r\n\r\n").unwrap();
+    let mut reader = BufReader::new(tls_stream);
+    let (status_line, response_body) = read_http_response(&mut reader);
+    assert_eq!(status_line, "HTTP/1.1 413 Payload Too Large");
+    assert_eq!(response_body, "Payload Too Large");
+    drop(reader);
+    
+    server.join().unwrap();
+}
+
+/// Test that a client which connects but never sends anything is disconnected
+#[test]
+fn test_idle_connection_timeout() {
+    let cert_dir = tempfile::tempdir().unwrap();
+    let tls_config = generate_test_certs(cert_dir.path());
+    
+    let hub = Arc::new(Hub::new(HubScope::Network));
+    let bind_addr = SocketAddr::from_str("127.0.0.1:0").unwrap();
+    let proxy = HttpReverseProxy::new(Arc::clone(&hub), bind_addr, tls_config);
+    proxy.set_idle_timeout(Duration::from_millis(200));
+    assert_eq!(proxy.idle_timeout(), Duration::from_millis(200));
+    
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (done_tx, done_rx) = std::sync::mpsc::channel();
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let result = proxy.handle_connection(stream);
+        done_tx.send(result.is_ok()).unwrap();
+    });
+    
+    // Connect without sending a single byte
+    let _client = TcpStream::connect(addr).unwrap();
+    
+    let handled = done_rx.recv_timeout(Duration::from_secs(2))
+        .expect("Handler did not return within the idle timeout");
+    assert!(handled, "Idle connection should be closed without an error");
+}
+
+/// Test that requests are forwarded to the target with their method, headers and body intact
+#[test]
+fn test_forward_any_method() {
+    let cert_dir = tempfile::tempdir().unwrap();
+    let tls_config = generate_test_certs(cert_dir.path());
+    
+    // A plain HTTP backend that answers each request with the request it received
+    let backend = TcpListener::bind("127.0.0.1:0").unwrap();
+    let backend_addr = backend.local_addr().unwrap();
+    thread::spawn(move || {
+        for stream in backend.incoming() {
+            let mut reader = BufReader::new(stream.unwrap());
+            let mut received = String::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                received.push_str(&line);
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let length = received
+                .lines()
+                .find_map(|line| line.strip_prefix("Content-Length: "))
+                .map(|length| length.parse::<usize>().unwrap())
+                .unwrap_or(0);
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            received.push_str(&String::from_utf8(body).unwrap());
+            
+            write!(
+                reader.get_mut(),
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                received.len(),
+                received
+            ).unwrap();
+        }
+    });
+    
+    let hub = Arc::new(Hub::new(HubScope::Network));
+    let bind_addr = SocketAddr::from_str("127.0.0.1:0").unwrap();
+    let proxy = HttpReverseProxy::new(Arc::clone(&hub), bind_addr, tls_config.clone());
+    proxy.add_route("/items/*", &format!("http://{}", backend_addr));
+    
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        proxy.handle_connection(stream).unwrap();
+    });
+    
+    let stream = TcpStream::connect(addr).unwrap();
+    let mut reader = BufReader::new(create_client_tls_stream(stream, &tls_config).unwrap());
+    let mut send = |request: &str| {
+        reader.get_mut().write_all(request.as_bytes()).unwrap();
+        let (status_line, body) = read_http_response(&mut reader);
+        assert_eq!(status_line, "HTTP/1.1 200 OK");
+        body
+    };
+    
+    // DELETE without a body
+    let received = send("DELETE /items/1 HTTP/1.1\r\nHost: localhost\r\nX-Request-Id: 17\r\n\r\n");
+    assert!(received.starts_with("DELETE /items/1 HTTP/1.1\r\n"), "Unexpected request: {}", received);
+    assert!(received.contains("\r\nX-Request-Id: 17\r\n"), "Unexpected request: {}", received);
+    assert!(!received.contains("Content-Length"), "Unexpected request: {}", received);
+    assert!(received.ends_with("\r\n\r\n"), "Unexpected request: {}", received);
+    
+    // PUT with a body, keeping the query string and the client's headers
+    let body = r#"{"name":"renamed"}"#;
+    let received = send(&format!(
+        "PUT /items/1?force=true HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+        body.len(),
+        body
+    ));
+    assert!(received.starts_with("PUT /items/1?force=true HTTP/1.1\r\n"), "Unexpected request: {}", received);
+    assert!(received.contains("\r\nContent-Type: application/json\r\n"), "Unexpected request: {}", received);
+    assert!(received.contains(&format!("\r\nContent-Length: {}\r\n", body.len())), "Unexpected request: {}", received);
+    assert!(received.ends_with(&format!("\r\n\r\n{}", body)), "Unexpected request: {}", received);
+    
+    // A less common method is passed through as is
+    let received = send("PATCH /items/2 HTTP/1.1\r\nHost: localhost\r\nContent-Length: 9\r\nConnection: close\r\n\r\nstock=-1\n");
+    assert!(received.starts_with("PATCH /items/2 HTTP/1.1\r\n"), "Unexpected request: {}", received);
+    assert!(received.ends_with("\r\n\r\nstock=-1\n"), "Unexpected request: {}", received);
+    // The proxy manages its own connection to the target
+    assert_eq!(received.matches("Connection:").count(), 1, "Unexpected request: {}", received);
+    
+    drop(reader);
+    server.join().unwrap();
+}
+
+/// Test that error responses describe the upstream failure as JSON when enabled
+#[test]
+fn test_json_error_bodies() {
+    let cert_dir = tempfile::tempdir().unwrap();
+    let tls_config = generate_test_certs(cert_dir.path());
+    
+    // A backend that is down for maintenance
+    let backend = TcpListener::bind("127.0.0.1:0").unwrap();
+    let backend_addr = backend.local_addr().unwrap();
+    thread::spawn(move || {
+        for stream in backend.incoming() {
+            let mut stream = stream.unwrap();
+            let mut request = [0u8; 1024];
+            let _ = stream.read(&mut request).unwrap();
+            stream.write_all(b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 11\r\nConnection: close\r\n\r\nmaintenance").unwrap();
+        }
+    });
+    let closed_port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    
+    let hub = Arc::new(Hub::new(HubScope::Network));
+    let bind_addr = SocketAddr::from_str("127.0.0.1:0").unwrap();
+    let proxy = HttpReverseProxy::new(Arc::clone(&hub), bind_addr, tls_config.clone());
+    proxy.add_route("/down/*", &format!("http://127.0.0.1:{}", closed_port));
+    proxy.add_route("/busy/*", &format!("http://{}", backend_addr));
+    assert!(!proxy.json_errors());
+    
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server_proxy = proxy.clone();
+    let server = thread::spawn(move || {
+        for _ in 0..2 {
+            let (stream, _) = listener.accept().unwrap();
+            server_proxy.handle_connection(stream).unwrap();
+        }
+    });
+    
+    let stream = TcpStream::connect(addr).unwrap();
+    let mut reader = BufReader::new(create_client_tls_stream(stream, &tls_config).unwrap());
+    let mut get = |path: &str| {
+        write!(reader.get_mut(), "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+        read_raw_http_response(&mut reader)
+    };
+    
+    // Errors are plain text by default
+    let (status_line, _, body) = get("/down/status");
+    assert_eq!(status_line, "HTTP/1.1 500 Internal Server Error");
+    assert_eq!(body, b"Internal Server Error");
+    drop(reader);
+    
+    // Settings apply to connections accepted afterwards
+    proxy.set_json_errors(true);
+    let stream = TcpStream::connect(addr).unwrap();
+    let mut reader = BufReader::new(create_client_tls_stream(stream, &tls_config).unwrap());
+    let mut get = |path: &str| {
+        write!(reader.get_mut(), "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+        let (status_line, headers, body) = read_raw_http_response(&mut reader);
+        assert_eq!(headers.get("content-type").map(String::as_str), Some("application/json"));
+        (status_line, serde_json::from_slice::<serde_json::Value>(&body).unwrap())
+    };
+    
+    // The target couldn't be reached
+    let (status_line, error) = get("/down/status");
+    assert_eq!(status_line, "HTTP/1.1 500 Internal Server Error");
+    let message = error["error"].as_str().unwrap();
+    assert!(message.starts_with("Error connecting to target server"), "Unexpected error: {}", message);
+    assert_eq!(error["path"], "/down/status");
+    assert!(error["upstream_status"].is_null());
+    
+    // The target answered with an error
+    let (status_line, error) = get("/busy/status");
+    assert_eq!(status_line, "HTTP/1.1 500 Internal Server Error");
+    assert_eq!(error["error"], "maintenance");
+    assert_eq!(error["upstream_status"], 503);
+    
+    drop(reader);
+    server.join().unwrap();
+}
+
+/// Test that the requests and bytes received from a client are counted, and reset with the window
+#[test]
+fn test_client_metrics() {
+    let cert_dir = tempfile::tempdir().unwrap();
+    let tls_config = generate_test_certs(cert_dir.path());
+    
+    let hub = Arc::new(Hub::new(HubScope::Network));
+    hub.register_api("/http/ping", |_: &ApiRequest| ApiResponse {
+        data: Box::new("pong".to_string()),
+        metadata: HashMap::new(),
+        status: ResponseStatus::Success,
+    }, HashMap::new());
+    
+    let proxy = HttpReverseProxy::new(Arc::clone(&hub), SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config.clone());
+    assert_eq!(proxy.client_metrics_window(), Duration::from_secs(60));
+    
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server_proxy = proxy.clone();
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        server_proxy.handle_connection(stream).unwrap();
+    });
+    
+    let requests = [
+        "GET /ping HTTP/1.1\r\nHost: localhost\r\n\r\n",
+        "GET /ping HTTP/1.1\r\nHost: localhost\r\n\r\n",
+        "GET /ping HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+    ];
+    let stream = TcpStream::connect(addr).unwrap();
+    let mut reader = BufReader::new(create_client_tls_stream(stream, &tls_config).unwrap());
+    for request in requests {
+        reader.get_mut().write_all(request.as_bytes()).unwrap();
+        assert_eq!(read_http_response(&mut reader).1, "pong");
+    }
+    server.join().unwrap();
+    
+    let client = "127.0.0.1".parse().unwrap();
+    let traffic = proxy.client_traffic()[&client];
+    assert_eq!(traffic.requests, 3);
+    assert_eq!(traffic.bytes, requests.iter().map(|request| request.len() as u64).sum::<u64>());
+    
+    // The same counters are reported by the hub API
+    let response = hub.handle_request(ApiRequest {
+        path: "/proxy/metrics".to_string(),
+        data: Box::new(()),
+        metadata: HashMap::new(),
+        sender_id: "test-client".to_string(),
+    });
+    let metrics: serde_json::Value = serde_json::from_str(response.as_str().unwrap()).unwrap();
+    assert_eq!(metrics["window_ms"], 60_000);
+    assert_eq!(metrics["clients"]["127.0.0.1"]["requests"], 3);
+    assert_eq!(metrics["clients"]["127.0.0.1"]["bytes"], traffic.bytes);
+    
+    // Counters start over once the window has passed
+    proxy.set_client_metrics_window(Duration::from_millis(50));
+    thread::sleep(Duration::from_millis(100));
+    assert!(proxy.client_traffic().is_empty());
+}
+
+/// Test that proxied responses keep the content type the target sent them with
+#[test]
+fn test_upstream_content_type() {
+    let cert_dir = tempfile::tempdir().unwrap();
+    let tls_config = generate_test_certs(cert_dir.path());
+    
+    // A backend answering JSON, or HTML for pages
+    let backend = TcpListener::bind("127.0.0.1:0").unwrap();
+    let backend_addr = backend.local_addr().unwrap();
+    thread::spawn(move || {
+        for stream in backend.incoming() {
+            let mut stream = stream.unwrap();
+            let mut request = [0u8; 1024];
+            let size = stream.read(&mut request).unwrap();
+            let (content_type, body) = if request[..size].starts_with(b"GET /api/page") {
+                ("text/html; charset=utf-8", "<p>hello</p>")
+            } else {
+                ("application/json", r#"{"hello":"world"}"#)
+            };
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                content_type,
+                body.len(),
+                body
+            ).unwrap();
+        }
+    });
+    
+    let hub = Arc::new(Hub::new(HubScope::Network));
+    hub.register_api("/http/local", |_: &ApiRequest| ApiResponse {
+        data: Box::new("local".to_string()),
+        metadata: HashMap::new(),
+        status: ResponseStatus::Success,
+    }, HashMap::new());
+    let proxy = HttpReverseProxy::new(Arc::clone(&hub), SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config.clone());
+    proxy.add_route("/api/*", &format!("http://{}", backend_addr));
+    
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        proxy.handle_connection(stream).unwrap();
+    });
+    
+    let stream = TcpStream::connect(addr).unwrap();
+    let mut reader = BufReader::new(create_client_tls_stream(stream, &tls_config).unwrap());
+    let mut get = |path: &str| {
+        write!(reader.get_mut(), "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+        let (status_line, headers, body) = read_raw_http_response(&mut reader);
+        assert_eq!(status_line, "HTTP/1.1 200 OK");
+        (headers["content-type"].clone(), String::from_utf8(body).unwrap())
+    };
+    
+    let (content_type, body) = get("/api/greeting");
+    assert_eq!(content_type, "application/json");
+    assert_eq!(body, r#"{"hello":"world"}"#);
+    
+    let (content_type, body) = get("/api/page");
+    assert_eq!(content_type, "text/html; charset=utf-8");
+    assert_eq!(body, "<p>hello</p>");
+    
+    // Responses without a content type are sent as plain text
+    assert_eq!(get("/local"), ("text/plain".to_string(), "local".to_string()));
+    
+    drop(reader);
+    server.join().unwrap();
+}
+
+/// Test that every answered request gets an access log record
+#[test]
+fn test_access_log() {
+    let cert_dir = tempfile::tempdir().unwrap();
+    let tls_config = generate_test_certs(cert_dir.path());
+    
+    let backend = TcpListener::bind("127.0.0.1:0").unwrap();
+    let backend_addr = backend.local_addr().unwrap();
+    thread::spawn(move || {
+        for stream in backend.incoming() {
+            let mut stream = stream.unwrap();
+            let mut request = [0u8; 1024];
+            let _ = stream.read(&mut request).unwrap();
+            stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello").unwrap();
+        }
+    });
+    
+    let hub = Arc::new(Hub::new(HubScope::Network));
+    let proxy = HttpReverseProxy::new(Arc::clone(&hub), SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config.clone());
+    let target = format!("http://{}", backend_addr);
+    proxy.add_route("/api/*", &target);
+    let log_path = cert_dir.path().join("access.log");
+    proxy.set_access_log(AccessLog::json_lines(std::fs::File::create(&log_path).unwrap()));
+    
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        proxy.handle_connection(stream).unwrap();
+    });
+    
+    let stream = TcpStream::connect(addr).unwrap();
+    let client = stream.local_addr().unwrap();
+    let mut reader = BufReader::new(create_client_tls_stream(stream, &tls_config).unwrap());
+    reader.get_mut().write_all(b"GET /api/greeting?lang=en HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    assert_eq!(read_http_response(&mut reader), ("HTTP/1.1 200 OK".to_string(), "hello".to_string()));
+    reader.get_mut().write_all(b"DELETE /missing HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+    assert_eq!(read_http_response(&mut reader).0, "HTTP/1.1 404 Not Found");
+    server.join().unwrap();
+    
+    let log = std::fs::read_to_string(&log_path).unwrap();
+    let records: Vec<serde_json::Value> = log.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(records.len(), 2, "Unexpected access log: {}", log);
+    
+    let proxied = &records[0];
+    assert_eq!(proxied["client"], client.to_string());
+    assert_eq!(proxied["method"], "GET");
+    assert_eq!(proxied["path"], "/api/greeting?lang=en");
+    assert_eq!(proxied["upstream"], target.as_str());
+    assert_eq!(proxied["status"], 200);
+    assert_eq!(proxied["bytes"], 5);
+    assert!(proxied["latency_ms"].is_u64());
+    assert!(proxied["timestamp"].as_u64().unwrap() > 0);
+    
+    let missing = &records[1];
+    assert_eq!(missing["method"], "DELETE");
+    assert_eq!(missing["path"], "/missing");
+    assert_eq!(missing["upstream"], serde_json::Value::Null);
+    assert_eq!(missing["status"], 404);
+    assert_eq!(missing["bytes"], "Not Found".len());
+}
+
+/// Test that clients negotiating HTTP/2 with ALPN are answered over HTTP/2,
+/// while clients that don't are still served HTTP/1.1
+#[test]
+fn test_http2() {
+    let cert_dir = tempfile::tempdir().unwrap();
+    let tls_config = generate_test_certs(cert_dir.path());
+    
+    // A backend echoing the body of the requests forwarded to it
+    let backend = TcpListener::bind("127.0.0.1:0").unwrap();
+    let backend_addr = backend.local_addr().unwrap();
+    thread::spawn(move || {
+        for stream in backend.incoming() {
+            let mut stream = stream.unwrap();
+            let mut request = [0u8; 1024];
+            let size = stream.read(&mut request).unwrap();
+            let request = String::from_utf8_lossy(&request[..size]);
+            let body = request.split("\r\n\r\n").nth(1).unwrap_or("");
+            write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body).unwrap();
+        }
+    });
+    
+    let hub = Arc::new(Hub::new(HubScope::Network));
+    hub.register_api("/http/local", |_: &ApiRequest| ApiResponse {
+        data: Box::new("local".to_string()),
+        metadata: HashMap::new(),
+        status: ResponseStatus::Success,
+    }, HashMap::new());
+    let proxy = HttpReverseProxy::new(Arc::clone(&hub), SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config.clone());
+    proxy.add_route("/api/*", &format!("http://{}", backend_addr));
+    proxy.set_http2(true);
+    
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        for _ in 0..2 {
+            let (stream, _) = listener.accept().unwrap();
+            proxy.handle_connection(stream).unwrap();
+        }
+    });
+    
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        let mut client_config = create_client_config(&tls_config).unwrap();
+        client_config.alpn_protocols = vec![b"h2".to_vec()];
+        let connector = tokio_rustls::TlsConnector::from(Arc::new(client_config));
+        let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let server_name = rustls::ServerName::try_from("localhost").unwrap();
+        let tls_stream = connector.connect(server_name, stream).await.unwrap();
+        assert_eq!(tls_stream.get_ref().1.alpn_protocol(), Some(&b"h2"[..]));
+        
+        let (mut sender, connection) = hyper::client::conn::Builder::new()
+            .http2_only(true)
+            .handshake::<_, hyper::Body>(tls_stream)
+            .await
+            .unwrap();
+        let connection = tokio::spawn(connection);
+        
+        // Answered by an API on the hub
+        let request = hyper::Request::get("https://localhost/local").body(hyper::Body::empty()).unwrap();
+        let response = sender.send_request(request).await.unwrap();
+        assert_eq!(response.version(), hyper::Version::HTTP_2);
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()["content-type"], "text/plain");
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"local");
+        
+        // Forwarded to the backend, body and all
+        let request = hyper::Request::post("https://localhost/api/echo").body(hyper::Body::from("over h2")).unwrap();
+        let response = sender.send_request(request).await.unwrap();
+        assert_eq!(response.status(), 200);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"over h2");
+        
+        let request = hyper::Request::get("https://localhost/missing").body(hyper::Body::empty()).unwrap();
+        assert_eq!(sender.send_request(request).await.unwrap().status(), 404);
+        
+        drop(sender);
+        connection.await.unwrap().unwrap();
+    });
+    
+    // Clients without ALPN get HTTP/1.1
+    let stream = TcpStream::connect(addr).unwrap();
+    let mut reader = BufReader::new(create_client_tls_stream(stream, &tls_config).unwrap());
+    reader.get_mut().write_all(b"GET /local HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+    assert_eq!(read_http_response(&mut reader), ("HTTP/1.1 200 OK".to_string(), "local".to_string()));
+    
+    server.join().unwrap();
+}
+
+/// Test parsing requests, whatever the case of their header names
+#[test]
+fn test_parse_http_request() {
+    let request = HttpRequestParts::parse(
+        b"POST /api/items?page=2 HTTP/1.1\r\nhOsT: localhost:8443\r\nCONTENT-LENGTH: 5\r\nX-Empty:\r\n\r\nhello",
+    ).unwrap();
+    assert_eq!(request.method, "POST");
+    assert_eq!(request.path, "/api/items?page=2");
+    assert_eq!(request.version, "HTTP/1.1");
+    assert_eq!(request.body, b"hello");
+    
+    // Names keep their case, but are looked up without it; values may contain colons
+    assert_eq!(request.headers[0], ("hOsT".to_string(), "localhost:8443".to_string()));
+    assert_eq!(request.header("host"), Some("localhost:8443"));
+    assert_eq!(request.header("Content-Length"), Some("5"));
+    assert_eq!(request.header("x-empty"), Some(""));
+    assert_eq!(request.header("accept"), None);
+    
+    // Requests without a body, or even the blank line ending their headers
+    let request = HttpRequestParts::parse(b"GET / HTTP/1.1\r\nConnection: Close\r\n\r\n").unwrap();
+    assert!(request.body.is_empty());
+    assert!(!request.keep_alive());
+    let request = HttpRequestParts::parse(b"GET /status HTTP/1.0\r\nAccept-Encoding: br, GZIP").unwrap();
+    assert_eq!(request.path, "/status");
+    assert!(request.body.is_empty());
+    assert!(request.accepts_gzip());
+    assert!(!request.keep_alive());
+    
+    // A request line without a version is parsed; one without a path isn't
+    let request = HttpRequestParts::parse(b"GET /legacy\r\n\r\n").unwrap();
+    assert_eq!(request.version, "");
+    assert!(request.headers.is_empty());
+    assert_eq!(HttpRequestParts::parse(b"GARBAGE\r\n\r\n"), None);
+    assert_eq!(HttpRequestParts::parse(b""), None);
+    
+    // Serializing gives back an equivalent request
+    let request = HttpRequestParts::parse(b"PUT /a HTTP/1.1\r\nHost: x\r\n\r\nbody").unwrap();
+    assert_eq!(HttpRequestParts::parse(&request.to_bytes()), Some(request));
+}
+
+/// Test writing and parsing responses, including chunked ones
+#[test]
+fn test_parse_http_response() {
+    let mut written = Vec::new();
+    HttpResponseParts::plain(404, "Not Found").write_to(&mut written).unwrap();
+    assert_eq!(
+        String::from_utf8(written.clone()).unwrap(),
+        "HTTP/1.1 404 Not Found\r\nContent-Type: text/plain\r\nContent-Length: 9\r\nConnection: close\r\n\r\nNot Found"
+    );
+    assert_eq!(HttpResponseParts::parse(&written), Some(HttpResponseParts::plain(404, "Not Found")));
+    
+    let response = HttpResponseParts::parse(
+        b"HTTP/1.1 200 OK\r\ntransfer-encoding: Chunked\r\n\r\n5\r\nhello\r\n6;ext=1\r\n world\r\n0\r\n\r\n",
+    ).unwrap();
+    assert_eq!(response.status, 200);
+    assert_eq!(response.reason, "OK");
+    assert_eq!(response.header("Transfer-Encoding"), Some("Chunked"));
+    assert_eq!(response.body, b"hello world");
+    
+    // Responses without a body or reason phrase
+    let response = HttpResponseParts::parse(b"HTTP/1.1 204\r\n\r\n").unwrap();
+    assert_eq!(response.status, 204);
+    assert_eq!(response.reason, "");
+    assert!(response.body.is_empty());
+    assert_eq!(HttpResponseParts::parse(b"HTTP/1.1 abc OK\r\n\r\n"), None);
+}
+
+/// Test that responses are sent with the HTTP status configured for their status
+#[test]
+fn test_status_mapping() {
+    let cert_dir = tempfile::tempdir().unwrap();
+    let tls_config = generate_test_certs(cert_dir.path());
+    
+    let hub = Arc::new(Hub::new(HubScope::Network));
+    hub.register_api("/http/cached", |_: &ApiRequest| ApiResponse {
+        data: Box::new("stale copy".to_string()),
+        metadata: HashMap::new(),
+        status: ResponseStatus::Approximated,
+    }, HashMap::new());
+    let proxy = HttpReverseProxy::new(Arc::clone(&hub), SocketAddr::from_str("127.0.0.1:0").unwrap(), tls_config.clone());
+    assert_eq!(proxy.status_mapping(ResponseStatus::Approximated), (200, "OK".to_string()));
+    
+    proxy.set_status_mapping(ResponseStatus::Approximated, 203, "Non-Authoritative Information").unwrap();
+    proxy.set_status_mapping(ResponseStatus::NotFound, 410, "Gone").unwrap();
+    assert_eq!(proxy.status_mapping(ResponseStatus::Approximated), (203, "Non-Authoritative Information".to_string()));
+    // Other statuses keep their default
+    assert_eq!(proxy.status_mapping(ResponseStatus::Error), (500, "Internal Server Error".to_string()));
+    
+    // Codes out of range, and reasons that would break the response head, are refused
+    assert!(matches!(proxy.set_status_mapping(ResponseStatus::Error, 42, "Odd"), Err(HubError::Config(_))));
+    assert!(matches!(proxy.set_status_mapping(ResponseStatus::Error, 500, "Oops\r\nX-Injected: 1"), Err(HubError::Config(_))));
+    assert_eq!(proxy.status_mapping(ResponseStatus::Error), (500, "Internal Server Error".to_string()));
+    
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        proxy.handle_connection(stream).unwrap();
+    });
+    
+    let stream = TcpStream::connect(addr).unwrap();
+    let mut reader = BufReader::new(create_client_tls_stream(stream, &tls_config).unwrap());
+    reader.get_mut().write_all(b"GET /cached HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    assert_eq!(
+        read_http_response(&mut reader),
+        ("HTTP/1.1 203 Non-Authoritative Information".to_string(), "stale copy".to_string())
+    );
+    
+    reader.get_mut().write_all(b"GET /missing HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+    assert_eq!(read_http_response(&mut reader), ("HTTP/1.1 410 Gone".to_string(), "Gone".to_string()));
+    
+    server.join().unwrap();
+}
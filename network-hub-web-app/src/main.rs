@@ -1,19 +1,32 @@
 use anyhow::Result;
 use axum::{
-    extract::{Path, State},
-    http::{header, StatusCode},
-    response::{Html, IntoResponse, Response},
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Path, State,
+    },
+    http::{header, HeaderName, HeaderValue, Method, Request, StatusCode},
+    middleware::{self, Next},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        Html, IntoResponse, Response,
+    },
     routing::{get, post},
     Json, Router,
 };
 use network_hub::{
     hub::{Hub, HubScope, ApiRequest, ApiResponse, ResponseStatus},
+    HttpReverseProxy, TlsConfig,
+};
+use futures::{
+    sink::SinkExt,
+    stream::{self, Stream, StreamExt},
 };
 use rust_embed::RustEmbed;
+use tokio::sync::broadcast::error::RecvError;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, net::SocketAddr, path::PathBuf, sync::Arc};
-use tower_http::{cors::CorsLayer, trace::TraceLayer};
-use tracing::{info, warn};
+use std::{collections::HashMap, net::SocketAddr, path::{Component, PathBuf}, sync::Arc};
+use tower_http::{cors::{AllowOrigin, CorsLayer}, trace::TraceLayer};
+use tracing::{debug, info, warn};
 
 #[derive(RustEmbed)]
 #[folder = "static"]
@@ -22,6 +35,88 @@ struct StaticAssets;
 #[derive(Clone)]
 struct AppState {
     hub: Arc<Hub>,
+    proxy: HttpReverseProxy,
+    /// Bearer token required by the management API
+    api_token: Arc<str>,
+}
+
+/// Directory the static assets are embedded from
+const STATIC_DIR: &str = "static";
+
+/// Whether static assets are read from `STATIC_DIR` before the embedded bundle
+const SERVE_FROM_FILESYSTEM: bool = cfg!(debug_assertions);
+
+/// File the proxy routes are persisted to
+const ROUTES_FILE: &str = "routes.json";
+
+/// Environment variable holding the management API token
+const API_TOKEN_VAR: &str = "HUB_WEB_API_TOKEN";
+
+/// Environment variables holding comma-separated CORS settings
+const CORS_ORIGINS_VAR: &str = "HUB_WEB_CORS_ORIGINS";
+const CORS_METHODS_VAR: &str = "HUB_WEB_CORS_METHODS";
+const CORS_HEADERS_VAR: &str = "HUB_WEB_CORS_HEADERS";
+
+/// Cross-origin access to the web interface
+///
+/// With no allowed origins, only pages served by the web app itself can use the API.
+#[derive(Debug, Clone)]
+struct CorsConfig {
+    allowed_origins: Vec<HeaderValue>,
+    allowed_methods: Vec<Method>,
+    allowed_headers: Vec<HeaderName>,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        CorsConfig {
+            allowed_origins: Vec::new(),
+            allowed_methods: vec![Method::GET, Method::POST, Method::DELETE],
+            allowed_headers: vec![header::CONTENT_TYPE, header::AUTHORIZATION],
+        }
+    }
+}
+
+impl CorsConfig {
+    /// Read the settings from the environment, keeping the default for any that are unset
+    fn from_env() -> Self {
+        let mut config = CorsConfig::default();
+        if let Some(origins) = env_list(CORS_ORIGINS_VAR, |origin| HeaderValue::from_str(origin).ok()) {
+            config.allowed_origins = origins;
+        }
+        if let Some(methods) = env_list(CORS_METHODS_VAR, |method| Method::from_bytes(method.to_uppercase().as_bytes()).ok()) {
+            config.allowed_methods = methods;
+        }
+        if let Some(headers) = env_list(CORS_HEADERS_VAR, |name| HeaderName::from_bytes(name.as_bytes()).ok()) {
+            config.allowed_headers = headers;
+        }
+        config
+    }
+
+    fn layer(&self) -> CorsLayer {
+        CorsLayer::new()
+            .allow_origin(AllowOrigin::list(self.allowed_origins.clone()))
+            .allow_methods(self.allowed_methods.clone())
+            .allow_headers(self.allowed_headers.clone())
+    }
+}
+
+// Parse a comma-separated environment variable, skipping invalid entries
+fn env_list<T>(var: &str, parse: impl Fn(&str) -> Option<T>) -> Option<Vec<T>> {
+    let value = std::env::var(var).ok()?;
+    let items = value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .filter_map(|item| {
+            let parsed = parse(item);
+            if parsed.is_none() {
+                warn!("Ignoring invalid {} entry: {}", var, item);
+            }
+            parsed
+        })
+        .collect();
+    Some(items)
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -42,61 +137,62 @@ struct ApiRequestData {
     data: String,
 }
 
+// API request sent over the WebSocket, answered with a response carrying the same id
+#[derive(Debug, Deserialize)]
+struct WsRequest {
+    id: serde_json::Value,
+    path: String,
+    #[serde(default)]
+    data: String,
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     // Initialize tracing
     tracing_subscriber::fmt::init();
     info!("Starting Network Hub Web App");
 
-    // Print the current working directory for debugging
-    let current_dir = std::env::current_dir()?;
-    info!("Current working directory: {:?}", current_dir);
-    
-    // Make sure static directory exists
-    let static_dir = PathBuf::from("static");
-    if !static_dir.exists() {
-        std::fs::create_dir_all(&static_dir)?;
+    if SERVE_FROM_FILESYSTEM {
+        info!("Serving static assets from {:?} before the embedded bundle", std::env::current_dir()?.join(STATIC_DIR));
     }
+
+    // Initialize the Hub, collecting the metrics served at /metrics
+    let hub = Hub::builder(HubScope::Process).enable_metrics().discovery(true).build();
+    info!("Network Hub initialized with Process scope");
+
+    // Create the proxy whose routes are managed through the web interface
+    let tls_config = TlsConfig {
+        cert_path: "certs/cert.pem".to_string(),
+        key_path: "certs/key.pem".to_string(),
+        ca_path: None,
+    };
+    let proxy = HttpReverseProxy::new(Arc::clone(&hub), SocketAddr::from(([127, 0, 0, 1], 8443)), tls_config);
     
-    // List all files in the static directory for debugging
-    if let Ok(entries) = std::fs::read_dir(&static_dir) {
-        info!("Files in static directory:");
-        for entry in entries {
-            if let Ok(entry) = entry {
-                info!("  {:?}", entry.path());
-            }
+    // Restore routes saved by a previous run
+    if std::path::Path::new(ROUTES_FILE).exists() {
+        if let Err(e) = proxy.load_routes(ROUTES_FILE) {
+            warn!("Failed to load routes from {}: {}", ROUTES_FILE, e);
         }
     }
 
-    // Initialize the Hub
-    let hub = Hub::initialize(HubScope::Process);
-    info!("Network Hub initialized with Process scope");
+    // Use the configured API token, or generate one for this run
+    let api_token = std::env::var(API_TOKEN_VAR).unwrap_or_else(|_| {
+        let token = uuid::Uuid::new_v4().to_string();
+        warn!("{} is not set, generated API token: {}", API_TOKEN_VAR, token);
+        token
+    });
 
-    // Create the state that will be shared with all routes
-    let state = AppState { hub };
+    // Cross-origin access is limited to the configured origins
+    let cors = CorsConfig::from_env();
+    if cors.allowed_origins.is_empty() {
+        info!("No CORS origins configured, the API is only available to the web interface itself");
+    }
 
-    // Set up CORS
-    let cors = CorsLayer::new()
-        .allow_origin(tower_http::cors::Any)
-        .allow_methods(tower_http::cors::Any)
-        .allow_headers([header::CONTENT_TYPE]);
+    // Create the state that will be shared with all routes
+    let state = AppState { hub, proxy, api_token: api_token.into() };
 
     // Create the router
-    let app = Router::new()
-        .route("/", get(serve_index))
-        .route("/assets/*path", get(serve_static_asset))
-        .route("/styles.css", get(|| async { serve_static_asset(Path("styles.css".to_string())).await }))
-        .route("/main.js", get(|| async { serve_static_asset(Path("main.js".to_string())).await }))
-        // API routes for the web interface
-        .route("/api/routes", get(get_routes).post(add_route))
-        .route("/api/routes/:path", get(get_route).delete(remove_route))
-        .route("/api/apis", get(get_apis).post(register_api))
-        .route("/api/apis/:path", get(get_api).delete(remove_api))
-        .route("/api/request", post(send_api_request))
-        .route("/api/hub/stats", get(get_hub_stats))
-        .layer(cors)
-        .layer(TraceLayer::new_for_http())
-        .with_state(state);
+    let app = build_router(state, &cors);
 
     // Start the server
     let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
@@ -108,42 +204,89 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+// Build the router serving the web interface and its management API
+fn build_router(state: AppState, cors: &CorsConfig) -> Router {
+    // API routes for the web interface, all requiring the bearer token
+    let api = Router::new()
+        .route("/api/routes", get(get_routes).post(add_route))
+        .route("/api/routes/:path", get(get_route).delete(remove_route))
+        .route("/api/apis", get(get_apis).post(register_api))
+        .route("/api/apis/:path", get(get_api).post(invoke_api).delete(remove_api))
+        .route("/api/request", post(send_api_request))
+        .route("/api/batch", post(send_api_batch))
+        .route("/api/hub/stats", get(get_hub_stats))
+        .route("/metrics", get(get_metrics))
+        .route("/api/events", get(stream_events))
+        .route("/ws", get(api_websocket))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_auth));
+
+    Router::new()
+        .route("/", get(serve_index))
+        .route("/assets/*path", get(serve_static_asset))
+        .route("/styles.css", get(|| async { serve_static_asset(Path("styles.css".to_string())).await }))
+        .route("/main.js", get(|| async { serve_static_asset(Path("main.js".to_string())).await }))
+        .route("/api/health", get(health_check))
+        .merge(api)
+        .layer(cors.layer())
+        .layer(TraceLayer::new_for_http())
+        .with_state(state)
+}
+
+// Reject management API requests without the configured bearer token
+//
+// The token may also be given as an `access_token` query parameter, since
+// browsers can't set headers on EventSource or WebSocket connections.
+async fn require_auth<B>(
+    State(state): State<AppState>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .or_else(|| {
+            request
+                .uri()
+                .query()
+                .and_then(|query| query.split('&').find_map(|pair| pair.strip_prefix("access_token=")))
+        });
+
+    match token {
+        Some(token) if tokens_match(token.as_bytes(), state.api_token.as_bytes()) => next.run(request).await,
+        _ => {
+            warn!("Rejected unauthorized request to {}", request.uri().path());
+            (StatusCode::UNAUTHORIZED, [(header::WWW_AUTHENTICATE, "Bearer")]).into_response()
+        }
+    }
+}
+
+// Compare tokens in time independent of where they differ
+fn tokens_match(given: &[u8], expected: &[u8]) -> bool {
+    given.len() == expected.len()
+        && given.iter().zip(expected).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+// Public health check, available without authentication
+async fn health_check() -> impl IntoResponse {
+    Json(serde_json::json!({ "status": "ok" }))
+}
+
 // Handler for static assets
 async fn serve_static_asset(Path(path): Path<String>) -> impl IntoResponse {
     let path_str = path.trim_start_matches('/');
-    info!("Requested static asset: {}", path_str);
     
-    // First try to serve from the filesystem
-    let fs_path = format!("static/{}", path_str);
-    match std::fs::read(&fs_path) {
-        Ok(content) => {
-            info!("Found on filesystem: {}", fs_path);
-            let mime = mime_guess::from_path(path_str).first_or_octet_stream();
-            let mime_str = mime.as_ref().to_string();
-            return (
-                StatusCode::OK,
-                [(header::CONTENT_TYPE, mime_str)],
-                content,
-            );
-        }
-        Err(e) => {
-            info!("Asset not found on filesystem: {} - {:?}", fs_path, e);
-            
-            // As fallback, try the embedded assets
-            if let Some(content) = StaticAssets::get(path_str) {
-                info!("Found embedded asset: {}", path_str);
-                let mime = mime_guess::from_path(path_str).first_or_octet_stream();
-                let mime_str = mime.as_ref().to_string();
-                return (
-                    StatusCode::OK,
-                    [(header::CONTENT_TYPE, mime_str)],
-                    content.data.into_owned(),
-                );
-            }
-        }
+    if let Some(content) = load_asset(path_str) {
+        let mime = mime_guess::from_path(path_str).first_or_octet_stream();
+        return (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, mime.as_ref().to_string())],
+            content,
+        );
     }
     
-    // If we get here, the asset wasn't found
+    debug!("Static asset not found: {}", path_str);
     (
         StatusCode::NOT_FOUND,
         [(header::CONTENT_TYPE, "text/plain".to_string())],
@@ -153,69 +296,84 @@ async fn serve_static_asset(Path(path): Path<String>) -> impl IntoResponse {
 
 // Handler for the index page
 async fn serve_index() -> impl IntoResponse {
-    // For debugging, print all assets in the RustEmbed collection
-    info!("Available static assets:");
-    for file in StaticAssets::iter() {
-        info!("  {}", file);
+    if let Some(html) = load_asset("index.html").and_then(|content| String::from_utf8(content).ok()) {
+        return Html(html);
     }
     
-    // Try to read from the filesystem first
-    match std::fs::read_to_string("static/index.html") {
-        Ok(content) => {
-            info!("Serving index.html from filesystem");
-            return Html(content);
-        },
-        Err(e) => {
-            info!("Failed to read index.html from filesystem: {:?}", e);
-            
-            // Try embedded asset as fallback
-            if let Some(content) = StaticAssets::get("index.html") {
-                info!("Serving index.html from embedded assets");
-                if let Ok(html_str) = std::str::from_utf8(&content.data) {
-                    return Html(html_str.to_string());
-                }
-            }
+    warn!("Could not find index.html in the static assets");
+    Html("<h1>Error: Could not load index.html</h1><p>Make sure there is an index.html file in the static directory.</p>".to_string())
+}
+
+// Load a static asset, rejecting paths that would leave the static directory
+//
+// Debug builds read the static directory first so assets can be edited without
+// rebuilding; release builds only serve the embedded bundle.
+fn load_asset(path: &str) -> Option<Vec<u8>> {
+    let is_safe = std::path::Path::new(path)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    if path.is_empty() || path.contains('\\') || !is_safe {
+        warn!("Rejected static asset path: {}", path);
+        return None;
+    }
+    
+    if SERVE_FROM_FILESYSTEM {
+        if let Ok(content) = std::fs::read(PathBuf::from(STATIC_DIR).join(path)) {
+            return Some(content);
         }
     }
     
-    // If we get here, we couldn't find the file
-    info!("Could not find index.html in filesystem or embedded assets");
-    Html("<h1>Error: Could not load index.html</h1><p>Make sure there is an index.html file in the static directory.</p>".to_string())
+    StaticAssets::get(path).map(|content| content.data.into_owned())
 }
 
 // API handlers
-async fn get_routes(State(_state): State<AppState>) -> impl IntoResponse {
-    // This is a placeholder - in a real implementation, we would fetch routes from the proxy
-    Json(Vec::<RouteConfig>::new())
+async fn get_routes(State(state): State<AppState>) -> impl IntoResponse {
+    let mut routes: Vec<RouteConfig> = state.proxy.routes()
+        .into_iter()
+        .map(|(path, target)| RouteConfig { path, target })
+        .collect();
+    routes.sort_by(|a, b| a.path.cmp(&b.path));
+    Json(routes)
+}
+
+// Persist the proxy routes so they survive a restart
+fn save_routes(state: &AppState) -> Result<(), StatusCode> {
+    state.proxy.save_routes(ROUTES_FILE).map_err(|e| {
+        warn!("Failed to save routes to {}: {}", ROUTES_FILE, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
 }
 
 async fn add_route(
-    State(_state): State<AppState>,
+    State(state): State<AppState>,
     Json(route): Json<RouteConfig>,
 ) -> impl IntoResponse {
-    // Placeholder for adding a route
     info!("Adding route: {} -> {}", route.path, route.target);
-    StatusCode::CREATED
+    state.proxy.add_route(&route.path, &route.target);
+    
+    save_routes(&state).map(|()| StatusCode::CREATED)
 }
 
 async fn get_route(
-    State(_state): State<AppState>,
+    State(state): State<AppState>,
     Path(path): Path<String>,
-) -> impl IntoResponse {
-    // Placeholder for getting a specific route
-    Json(RouteConfig {
-        path: path,
-        target: "http://example.com".to_string(),
-    })
+) -> Response {
+    match state.proxy.routes().remove(&path) {
+        Some(target) => Json(RouteConfig { path, target }).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
 }
 
 async fn remove_route(
-    State(_state): State<AppState>,
+    State(state): State<AppState>,
     Path(path): Path<String>,
 ) -> impl IntoResponse {
-    // Placeholder for removing a route
     info!("Removing route: {}", path);
-    StatusCode::NO_CONTENT
+    if state.proxy.remove_route(&path).is_none() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    
+    save_routes(&state).map(|()| StatusCode::NO_CONTENT)
 }
 
 async fn get_apis(State(_state): State<AppState>) -> impl IntoResponse {
@@ -240,22 +398,25 @@ async fn register_api(
         }
     };
     
-    // Register the API with the hub
-    hub.register_api(&path, handler, HashMap::new());
+    // Register the API with the hub, recording its canned response as metadata
+    let metadata = HashMap::from([("response_data".to_string(), api.response_data)]);
+    hub.register_api(&path, handler, metadata);
     info!("Registered API: {}", path);
     
     StatusCode::CREATED
 }
 
 async fn get_api(
-    State(_state): State<AppState>,
+    State(state): State<AppState>,
     Path(path): Path<String>,
-) -> impl IntoResponse {
-    // Placeholder for getting a specific API
-    Json(ApiConfig {
-        path,
-        response_data: "".to_string(),
-    })
+) -> Response {
+    match state.hub.api_metadata(&path) {
+        Some(metadata) => Json(serde_json::json!({
+            "path": path,
+            "metadata": metadata,
+        })).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
 }
 
 async fn remove_api(
@@ -279,15 +440,152 @@ async fn send_api_request(
     };
     
     let response = state.hub.handle_request(request);
-    let data = match response.data.downcast::<String>() {
-        Ok(string_data) => *string_data,
-        Err(_) => "Unable to convert response data to string".to_string(),
+    Json(response_to_json(response))
+}
+
+// Invoke the API at the given path with the request body as its data
+async fn invoke_api(
+    State(state): State<AppState>,
+    Path(path): Path<String>,
+    data: String,
+) -> impl IntoResponse {
+    let request = ApiRequest {
+        path,
+        data: Box::new(data),
+        metadata: HashMap::new(),
+        sender_id: "web-client".to_string(),
     };
     
-    Json(serde_json::json!({
-        "data": data,
+    let response = state.hub.handle_request_async(request).await;
+    Json(response_to_json(response))
+}
+
+async fn send_api_batch(
+    State(state): State<AppState>,
+    Json(batch): Json<Vec<ApiRequestData>>,
+) -> impl IntoResponse {
+    let requests = batch
+        .into_iter()
+        .map(|request_data| ApiRequest {
+            path: request_data.path,
+            data: Box::new(request_data.data),
+            metadata: HashMap::new(),
+            sender_id: "web-client".to_string(),
+        })
+        .collect();
+    
+    // Requests may escalate over the network, so they're handled off the async workers
+    let hub = Arc::clone(&state.hub);
+    let Ok(responses) = tokio::task::spawn_blocking(move || hub.handle_batch(requests)).await else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+    
+    let responses: Vec<_> = responses
+        .into_iter()
+        .map(response_to_json)
+        .collect();
+    
+    Json(responses).into_response()
+}
+
+// Convert a hub response into the JSON shape returned to web clients
+fn response_to_json(response: ApiResponse) -> serde_json::Value {
+    serde_json::json!({
+        "data": data_to_json(&response),
         "status": format!("{:?}", response.status),
-    }))
+    })
+}
+
+// Convert response data of a known type into a JSON value
+//
+// String data is embedded as a JSON string unless the handler sets the
+// `content_type` metadata to `application/json`, in which case it is parsed.
+fn data_to_json(response: &ApiResponse) -> serde_json::Value {
+    use serde_json::Value;
+    
+    if response.metadata.get("content_type").map(String::as_str) == Some("application/json") {
+        if let Some(parsed) = response.as_str().and_then(|data| serde_json::from_str(data).ok()) {
+            return parsed;
+        }
+    }
+    
+    response
+        .as_json()
+        .unwrap_or_else(|| Value::String("Unable to convert response data to JSON".to_string()))
+}
+
+// Stream an event for every request handled by the hub
+async fn stream_events(State(state): State<AppState>) -> Sse<impl Stream<Item = Result<Event, serde_json::Error>>> {
+    let events = stream::unfold(state.hub.subscribe_events(), |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => return Some((Event::default().json_data(event), receiver)),
+                Err(RecvError::Lagged(missed)) => warn!("Event stream fell behind, skipped {} events", missed),
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+    
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+// Invoke hub APIs over a persistent WebSocket
+async fn api_websocket(State(state): State<AppState>, ws: WebSocketUpgrade) -> Response {
+    ws.on_upgrade(move |socket| serve_websocket(socket, state.hub))
+}
+
+// Answer each request received over a WebSocket with a response carrying its id
+//
+// Requests are handled concurrently, so responses may arrive in a different
+// order than the requests were sent.
+async fn serve_websocket(socket: WebSocket, hub: Arc<Hub>) {
+    let (mut sender, mut receiver) = socket.split();
+    let (reply_tx, mut reply_rx) = tokio::sync::mpsc::unbounded_channel::<serde_json::Value>();
+    
+    let writer = tokio::spawn(async move {
+        while let Some(reply) = reply_rx.recv().await {
+            if sender.send(Message::Text(reply.to_string())).await.is_err() {
+                break;
+            }
+        }
+    });
+    
+    while let Some(Ok(message)) = receiver.next().await {
+        let text = match message {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            // Pings are answered by axum
+            _ => continue,
+        };
+        
+        let request: WsRequest = match serde_json::from_str(&text) {
+            Ok(request) => request,
+            Err(e) => {
+                debug!("Invalid WebSocket request: {}", e);
+                let _ = reply_tx.send(serde_json::json!({ "id": null, "error": format!("Invalid request: {}", e) }));
+                continue;
+            }
+        };
+        
+        let hub = Arc::clone(&hub);
+        let reply_tx = reply_tx.clone();
+        tokio::task::spawn_blocking(move || {
+            let response = hub.handle_request(ApiRequest {
+                path: request.path,
+                data: Box::new(request.data),
+                metadata: HashMap::new(),
+                sender_id: "web-client".to_string(),
+            });
+            
+            let mut reply = response_to_json(response);
+            reply["id"] = request.id;
+            let _ = reply_tx.send(reply);
+        });
+    }
+    
+    // The writer finishes once the requests still being handled have replied
+    drop(reply_tx);
+    let _ = writer.await;
 }
 
 async fn get_hub_stats(State(_state): State<AppState>) -> impl IntoResponse {
@@ -298,3 +596,191 @@ async fn get_hub_stats(State(_state): State<AppState>) -> impl IntoResponse {
         "interceptor_count": 0,
     }))
 }
+
+// Hub metrics in the Prometheus text format, for Prometheus to scrape
+async fn get_metrics(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.hub.export_prometheus(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use tower::ServiceExt;
+
+    const TOKEN: &str = "test-token";
+
+    fn test_router(hub: Arc<Hub>) -> Router {
+        let tls_config = TlsConfig {
+            cert_path: "certs/cert.pem".to_string(),
+            key_path: "certs/key.pem".to_string(),
+            ca_path: None,
+        };
+        let proxy = HttpReverseProxy::new(Arc::clone(&hub), SocketAddr::from(([127, 0, 0, 1], 0)), tls_config);
+
+        let cors = CorsConfig {
+            allowed_origins: vec![HeaderValue::from_static("https://admin.example.com")],
+            ..CorsConfig::default()
+        };
+        build_router(AppState { hub, proxy, api_token: TOKEN.into() }, &cors)
+    }
+
+    async fn get_status(uri: &str, authorization: Option<&str>) -> StatusCode {
+        let mut request = Request::builder().uri(uri);
+        if let Some(authorization) = authorization {
+            request = request.header(header::AUTHORIZATION, authorization);
+        }
+
+        test_router(Arc::new(Hub::new(HubScope::Process)))
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[tokio::test]
+    async fn test_authorized_request() {
+        let status = get_status("/api/hub/stats", Some("Bearer test-token")).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let status = get_status("/api/hub/stats?access_token=test-token", None).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_unauthorized_requests() {
+        assert_eq!(get_status("/api/hub/stats", None).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(get_status("/api/routes", Some("Bearer wrong-token")).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(get_status("/api/routes", Some("test-token")).await, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_health_check_is_public() {
+        assert_eq!(get_status("/api/health", None).await, StatusCode::OK);
+    }
+
+    async fn post_json(router: Router, uri: &str, body: &str) -> serde_json::Value {
+        let request = Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header(header::AUTHORIZATION, format!("Bearer {}", TOKEN))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+
+        let response = router.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_typed_json_response() {
+        let hub = Arc::new(Hub::new(HubScope::Process));
+        hub.register_api("/calculator/add", |_: &ApiRequest| ApiResponse {
+            data: Box::new(42i32),
+            metadata: HashMap::new(),
+            status: ResponseStatus::Success,
+        }, HashMap::new());
+        hub.register_api("/calculator/info", |_: &ApiRequest| ApiResponse {
+            data: Box::new(r#"{"operations":["add"]}"#.to_string()),
+            metadata: HashMap::from([("content_type".to_string(), "application/json".to_string())]),
+            status: ResponseStatus::Success,
+        }, HashMap::new());
+
+        let response = post_json(test_router(Arc::clone(&hub)), "/api/request", r#"{"path":"/calculator/add","data":""}"#).await;
+        assert_eq!(response["data"], serde_json::json!(42));
+        assert_eq!(response["status"], "Success");
+
+        let response = post_json(test_router(Arc::clone(&hub)), "/api/apis/%2Fcalculator%2Finfo", "").await;
+        assert_eq!(response["data"], serde_json::json!({ "operations": ["add"] }));
+    }
+
+    #[tokio::test]
+    async fn test_metrics() {
+        let hub = Hub::builder(HubScope::Process).enable_metrics().build();
+        hub.register_api("/x", |_: &ApiRequest| ApiResponse {
+            data: Box::new(()),
+            metadata: HashMap::new(),
+            status: ResponseStatus::Success,
+        }, HashMap::new());
+        post_json(test_router(Arc::clone(&hub)), "/api/request", r#"{"path":"/x","data":""}"#).await;
+
+        let request = Request::builder()
+            .uri("/metrics")
+            .header(header::AUTHORIZATION, format!("Bearer {}", TOKEN))
+            .body(Body::empty())
+            .unwrap();
+        let response = test_router(hub).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/plain; version=0.0.4");
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains("hub_requests_total{path=\"/x\",status=\"success\"} 1"), "{}", body);
+
+        assert_eq!(get_status("/metrics", None).await, StatusCode::UNAUTHORIZED);
+    }
+
+    async fn get_cors_origin(origin: &str) -> Option<HeaderValue> {
+        let request = Request::builder()
+            .uri("/api/health")
+            .header(header::ORIGIN, origin)
+            .body(Body::empty())
+            .unwrap();
+
+        let response = test_router(Arc::new(Hub::new(HubScope::Process))).oneshot(request).await.unwrap();
+        response.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).cloned()
+    }
+
+    #[tokio::test]
+    async fn test_cors_origins() {
+        assert_eq!(get_cors_origin("https://admin.example.com").await, Some(HeaderValue::from_static("https://admin.example.com")));
+        assert_eq!(get_cors_origin("https://evil.example.com").await, None);
+    }
+
+    #[tokio::test]
+    async fn test_websocket_requests() {
+        use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+        let hub = Arc::new(Hub::new(HubScope::Process));
+        hub.register_api("/echo", |request: &ApiRequest| ApiResponse {
+            data: Box::new(request.data.downcast_ref::<String>().cloned().unwrap_or_default()),
+            metadata: HashMap::new(),
+            status: ResponseStatus::Success,
+        }, HashMap::new());
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = axum::Server::from_tcp(listener).unwrap().serve(test_router(hub).into_make_service());
+        tokio::spawn(server);
+
+        let url = format!("ws://{}/ws?access_token={}", addr, TOKEN);
+        let (mut socket, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+        socket.send(WsMessage::Text(r#"{"id":1,"path":"/echo","data":"first"}"#.to_string())).await.unwrap();
+        socket.send(WsMessage::Text(r#"{"id":"two","path":"/missing","data":""}"#.to_string())).await.unwrap();
+
+        let mut replies = HashMap::new();
+        while replies.len() < 2 {
+            let message = socket.next().await.unwrap().unwrap();
+            let reply: serde_json::Value = serde_json::from_str(message.to_text().unwrap()).unwrap();
+            replies.insert(reply["id"].to_string(), reply);
+        }
+        assert_eq!(replies["1"]["data"], "first");
+        assert_eq!(replies["1"]["status"], "Success");
+        assert_eq!(replies[r#""two""#]["status"], "NotFound");
+
+        // The endpoint requires the token like the rest of the API
+        assert!(tokio_tungstenite::connect_async(format!("ws://{}/ws", addr)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_static_asset_traversal() {
+        assert_eq!(get_status("/assets/main.js", None).await, StatusCode::OK);
+        assert_eq!(get_status("/assets/../Cargo.toml", None).await, StatusCode::NOT_FOUND);
+        assert_eq!(get_status("/assets/%2E%2E/Cargo.toml", None).await, StatusCode::NOT_FOUND);
+        assert_eq!(get_status("/assets/%2Fetc%2Fpasswd", None).await, StatusCode::NOT_FOUND);
+    }
+}
@@ -38,10 +38,31 @@ document.addEventListener('DOMContentLoaded', function() {
     document.getElementById('request-form').addEventListener('submit', sendApiRequest);
 });
 
+// Management API requests carry the bearer token, which is asked for when the server rejects it
+async function apiFetch(url, options = {}) {
+    const send = () => fetch(url, {
+        ...options,
+        headers: {
+            ...options.headers,
+            'Authorization': `Bearer ${localStorage.getItem('apiToken') || ''}`
+        }
+    });
+    
+    let response = await send();
+    if (response.status === 401) {
+        const token = prompt('Enter the API token for this hub:');
+        if (token) {
+            localStorage.setItem('apiToken', token);
+            response = await send();
+        }
+    }
+    return response;
+}
+
 // Hub Stats
 async function fetchHubStats() {
     try {
-        const response = await fetch('/api/hub/stats');
+        const response = await apiFetch('/api/hub/stats');
         const stats = await response.json();
         
         document.getElementById('hub-scope').textContent = stats.scope;
@@ -55,7 +76,7 @@ async function fetchHubStats() {
 // Routes
 async function fetchRoutes() {
     try {
-        const response = await fetch('/api/routes');
+        const response = await apiFetch('/api/routes');
         const routes = await response.json();
         
         const tableBody = document.querySelector('#routes-table tbody');
@@ -99,7 +120,7 @@ async function addRoute(e) {
     };
     
     try {
-        const response = await fetch('/api/routes', {
+        const response = await apiFetch('/api/routes', {
             method: 'POST',
             headers: {
                 'Content-Type': 'application/json'
@@ -120,7 +141,7 @@ async function addRoute(e) {
 
 async function deleteRoute(path) {
     try {
-        const response = await fetch(`/api/routes/${encodeURIComponent(path)}`, {
+        const response = await apiFetch(`/api/routes/${encodeURIComponent(path)}`, {
             method: 'DELETE'
         });
         
@@ -137,7 +158,7 @@ async function deleteRoute(path) {
 // APIs
 async function fetchApis() {
     try {
-        const response = await fetch('/api/apis');
+        const response = await apiFetch('/api/apis');
         const apis = await response.json();
         
         const tableBody = document.querySelector('#apis-table tbody');
@@ -188,7 +209,7 @@ async function registerApi(e) {
     };
     
     try {
-        const response = await fetch('/api/apis', {
+        const response = await apiFetch('/api/apis', {
             method: 'POST',
             headers: {
                 'Content-Type': 'application/json'
@@ -209,7 +230,7 @@ async function registerApi(e) {
 
 async function deleteApi(path) {
     try {
-        const response = await fetch(`/api/apis/${encodeURIComponent(path)}`, {
+        const response = await apiFetch(`/api/apis/${encodeURIComponent(path)}`, {
             method: 'DELETE'
         });
         
@@ -234,7 +255,7 @@ async function sendApiRequest(e) {
     };
     
     try {
-        const response = await fetch('/api/request', {
+        const response = await apiFetch('/api/request', {
             method: 'POST',
             headers: {
                 'Content-Type': 'application/json'